@@ -0,0 +1,46 @@
+//! Captures build information exposed by the `/version` endpoint.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    // Rebuild when a commit is checked out or created.
+    let git_dir = Path::new("../../.git");
+    for path in ["HEAD", "refs"] {
+        let path = git_dir.join(path);
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    if let Some(sha) = git_sha() {
+        println!("cargo:rustc-env=YY_GIT_SHA={sha}");
+    }
+
+    // Reproducible builds pin the timestamp via SOURCE_DATE_EPOCH.
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("the system time is before the Unix epoch")
+                .as_secs()
+        });
+    println!("cargo:rustc-env=YY_BUILD_TIMESTAMP={timestamp}");
+}
+
+/// Gets the SHA of the checked out commit, if built from a git repository.
+fn git_sha() -> Option<String> {
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let sha = String::from_utf8(output.stdout).ok()?;
+    Some(sha.trim().to_string())
+}
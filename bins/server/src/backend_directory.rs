@@ -0,0 +1,59 @@
+//! Contains the directory of the registered backends, as reported to operators.
+
+use crate::circuit_breaker::{CircuitBreaker, CircuitBreakers};
+use backend_traits::{Backend, BackendPriority};
+use std::sync::Arc;
+
+/// The registered backends along with their circuit breakers.
+#[derive(Default)]
+pub struct BackendDirectory {
+    /// The backends, in the order they are served in.
+    backends: Vec<BackendDescription>,
+    /// The circuit breakers shared with the distributions.
+    breakers: Arc<CircuitBreakers>,
+}
+
+/// Describes a registered backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackendDescription {
+    /// The tag of the backend.
+    pub tag: String,
+    /// The name of the backend implementation, e.g. `S3`, if known.
+    pub name: Option<&'static str>,
+    /// The version of the backend implementation, if known.
+    pub version: Option<&'static str>,
+    /// The priority of the backend; lower values are served first.
+    pub priority: BackendPriority,
+}
+
+impl BackendDirectory {
+    pub fn new(backends: &[Backend], breakers: Arc<CircuitBreakers>) -> Self {
+        Self {
+            backends: backends
+                .iter()
+                .map(|backend| BackendDescription {
+                    tag: backend.tag().to_string(),
+                    name: backend.backend_name(),
+                    version: backend.backend_version(),
+                    priority: backend.priority(),
+                })
+                .collect(),
+            breakers,
+        }
+    }
+
+    /// Gets the registered backends, in the order they are served in.
+    pub fn backends(&self) -> &[BackendDescription] {
+        &self.backends
+    }
+
+    /// Determines whether a backend with the specified tag is registered.
+    pub fn contains(&self, tag: &str) -> bool {
+        self.backends.iter().any(|backend| backend.tag == tag)
+    }
+
+    /// Gets the circuit breaker of the backend with the specified tag, if any.
+    pub fn breaker(&self, tag: &str) -> Option<&CircuitBreaker> {
+        self.breakers.get(tag)
+    }
+}
@@ -1,21 +1,45 @@
+use crate::backend_directory::BackendDirectory;
+use crate::circuit_breaker::{
+    CircuitBreaker, CircuitBreakerPolicy, CircuitBreakers, CircuitPermit,
+};
+use crate::receipts::DistributionRecords;
+use app_config::distribution::DeleteBehavior;
 use app_config::AppConfig;
 use backend_traits::{
-    Backend, BackendCommand, BackendCommandSender, BackendRegistration, RegisterBackendError,
-    TryCreateFromConfig,
+    Backend, BackendCommand, BackendCommandSender, BackendHealth, BackendRegistration,
+    BackendResult, DistributeEarly, DistributionError, DistributionOutcome, HealthCheckError,
+    ReceiveTimeout, RegisterBackendError, StoredDigest, TryCreateFromConfig,
 };
-use file_distribution::FileProvider;
+use file_distribution::{BoxedFileReader, FileHashes, FileProvider, WriteSummary};
+use futures::future::join_all;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, StreamExt};
+use metrics::backend::{BackendMetrics, VerificationResult};
 use rendezvous::RendezvousGuard;
+use shortguid::ShortGuid;
 use std::cell::Cell;
-use tokio::sync::mpsc;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::task::{JoinError, JoinHandle};
-use tracing::{debug, error, info, warn};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, info_span, trace, warn, Instrument, Span};
+
+/// Notified of the outcome of a distribution once all backends finished.
+type Completion = oneshot::Sender<DistributionOutcome>;
 
-const EVENT_BUFFER_SIZE: usize = 64;
+/// The write summary handed to an early distribution, along with its completion channel.
+type DeferredSummary = (Arc<WriteSummary>, Option<Completion>);
 
 pub struct BackendRegistry {
     handle: JoinHandle<()>,
     sender: Cell<Option<Sender<BackendCommand>>>,
+    records: Arc<DistributionRecords>,
+    directory: Arc<BackendDirectory>,
 }
 
 impl BackendRegistry {
@@ -30,17 +54,28 @@ impl BackendRegistry {
         cleanup_rendezvous: RendezvousGuard,
         backends: Vec<Backend>,
         file_accessor: FileProvider,
+        options: DistributionOptions,
+        circuit_breaker: CircuitBreakerPolicy,
+        event_buffer_size: usize,
     ) -> Self {
-        let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
+        let (sender, receiver) = mpsc::channel(event_buffer_size);
+        let records = Arc::new(DistributionRecords::default());
+        let breakers = Arc::new(CircuitBreakers::new(&backends, circuit_breaker));
+        let directory = Arc::new(BackendDirectory::new(&backends, breakers.clone()));
         let handle = tokio::spawn(Self::handle_events(
-            backends,
+            backends.into(),
             receiver,
             cleanup_rendezvous,
             file_accessor,
+            records.clone(),
+            breakers,
+            options,
         ));
         Self {
             handle,
             sender: Cell::new(Some(sender)),
+            records,
+            directory,
         }
     }
 
@@ -48,44 +83,741 @@ impl BackendRegistry {
         self.sender.take().map(BackendCommandSender::from)
     }
 
+    /// Gets the records of the distribution outcomes.
+    pub(crate) fn distribution_records(&self) -> Arc<DistributionRecords> {
+        self.records.clone()
+    }
+
+    /// Gets the directory of the registered backends.
+    pub(crate) fn backend_directory(&self) -> Arc<BackendDirectory> {
+        self.directory.clone()
+    }
+
+    #[allow(dead_code)]
     pub async fn join(self) -> Result<(), JoinError> {
         self.handle.await
     }
 
     async fn handle_events(
-        backends: Vec<Backend>,
+        backends: Arc<[Backend]>,
         mut receiver: Receiver<BackendCommand>,
         cleanup_rendezvous: RendezvousGuard,
         file_accessor: FileProvider,
+        records: Arc<DistributionRecords>,
+        breakers: Arc<CircuitBreakers>,
+        options: DistributionOptions,
     ) {
+        let early_distribution = options.early_distribution
+            && backends
+                .iter()
+                .any(|backend| backend.early_distributor().is_some());
+
+        // Files that are distributed early, waiting for their write summary.
+        let mut pending: HashMap<ShortGuid, oneshot::Sender<DeferredSummary>> = HashMap::new();
+
+        // Distributions that are currently running.
+        let mut active: HashMap<ShortGuid, ActiveDistribution> = HashMap::new();
+
+        // All spawned tasks, drained before the event loop completes.
+        let mut tasks = JoinSet::new();
+
         while let Some(event) = receiver.recv().await {
+            active.retain(|_, distribution| !distribution.is_finished());
+            while tasks.try_join_next().is_some() {}
+
             match event {
-                BackendCommand::DistributeFile(id, summary) => {
-                    // TODO: Handle file distribution
+                BackendCommand::FileCreated(id, span) => {
+                    if !early_distribution {
+                        continue;
+                    }
+
+                    debug!(file_id = %id, "Starting early distribution of file {id}", id = id);
+                    let (sender, summary) = oneshot::channel();
+                    pending.insert(id, sender);
+                    let distribution = ActiveDistribution::spawn(
+                        &mut tasks,
+                        id,
+                        &span,
+                        Self::distribute_early(
+                            backends.clone(),
+                            id,
+                            summary,
+                            file_accessor.clone(),
+                            records.clone(),
+                            breakers.clone(),
+                            options,
+                        ),
+                    );
+                    active.insert(id, distribution);
+                }
+                BackendCommand::DistributeFile(id, summary, completion, span) => {
                     debug!(file_id = %id, "Handling distribution of file {id}", id = id);
+                    if let Some(sender) = pending.remove(&id) {
+                        records.begin(id, &summary);
+                        sender.send((summary, completion)).ok();
+                        continue;
+                    }
 
-                    // TODO: Spawn distribution tasks in background
-
-                    // TODO: Initiate tasks in priority order?
-                    for backend in &backends {
-                        match backend
-                            .distribute_file(id, summary.clone(), file_accessor.clone())
-                            .await
-                        {
-                            Ok(_) => {}
-                            Err(e) => {
-                                warn!(file_id = %id, "Failed to distribute file using backend {tag}: {error}", tag = backend.tag(), error = e);
-                            }
-                        }
+                    // Redistributing a file while it is being distributed would store it twice.
+                    if active.contains_key(&id) {
+                        info!(file_id = %id, "File {id} is already being distributed; skipping its redistribution", id = id);
+                        continue;
                     }
+
+                    records.begin(id, &summary);
+
+                    let distribution = Self::distribute_file(
+                        backends.clone(),
+                        id,
+                        summary,
+                        file_accessor.clone(),
+                        records.clone(),
+                        breakers.clone(),
+                        options,
+                        &[],
+                    )
+                    .map(|outcome| Self::complete(completion, outcome));
+                    let distribution =
+                        ActiveDistribution::spawn(&mut tasks, id, &span, distribution);
+                    active.insert(id, distribution);
+                }
+                BackendCommand::ReceiveFile(id, reply) => {
+                    debug!(file_id = %id, "Attempting to receive file {id} from the backends", id = id);
+                    tasks.spawn(
+                        Self::receive_file(backends.clone(), id, options.fallback, reply)
+                            .instrument(info_span!("receive", file_id = %id)),
+                    );
+                }
+                BackendCommand::PresignFile(id, expires_in, reply) => {
+                    debug!(file_id = %id, "Requesting a presigned URL of file {id} from the backends", id = id);
+                    tasks.spawn(
+                        Self::presign_file(backends.clone(), id, expires_in, reply)
+                            .instrument(info_span!("presign", file_id = %id)),
+                    );
+                }
+                BackendCommand::CheckHealth(timeout, reply) => {
+                    trace!("Checking the health of the backends");
+                    tasks.spawn(Self::check_health(backends.clone(), timeout, reply));
+                }
+                BackendCommand::FileRemoved(id) => {
+                    // Files removed before writing completed are never distributed;
+                    // this stops their early distribution.
+                    pending.remove(&id);
+                }
+                BackendCommand::DeleteFile(id) => {
+                    debug!(file_id = %id, "Deleting file {id} from the backends", id = id);
+                    pending.remove(&id);
+                    tasks.spawn(
+                        Self::delete_file(
+                            backends.clone(),
+                            id,
+                            active.remove(&id),
+                            options.on_delete,
+                        )
+                        .instrument(info_span!("delete", file_id = %id)),
+                    );
+                }
+                BackendCommand::LeaseExtended(id, expires) => {
+                    debug!(file_id = %id, "Extending the lease of file {id} in the backends", id = id);
+                    let distribution = active
+                        .get(&id)
+                        .map(|distribution| distribution.finished.clone());
+                    tasks.spawn(
+                        Self::extend_lease(backends.clone(), id, expires, distribution)
+                            .instrument(info_span!("extend_lease", file_id = %id)),
+                    );
                 }
             }
         }
 
-        // TODO: Wait until all currently running tasks have finished.
+        // Early distributions still waiting for their write summary will not receive it anymore.
+        drop(pending);
+
+        // Wait until all currently running tasks have finished.
+        if !tasks.is_empty() {
+            info!(
+                "Waiting for {count} backend tasks to finish before shutting down",
+                count = tasks.len()
+            );
+        }
+        while let Some(result) = tasks.join_next().await {
+            if let Err(e) = result {
+                warn!("A backend task failed during shutdown: {e}");
+            }
+        }
+
         debug!("Closing backend event loop");
         cleanup_rendezvous.completed();
     }
+
+    /// Deletes a file from all backends.
+    ///
+    /// If the file is still being distributed, the in-flight backend writes are either
+    /// completed or aborted first, depending on the configured behavior.
+    async fn delete_file(
+        backends: Arc<[Backend]>,
+        id: ShortGuid,
+        distribution: Option<ActiveDistribution>,
+        behavior: DeleteBehavior,
+    ) {
+        if let Some(distribution) = distribution {
+            if behavior == DeleteBehavior::Abort {
+                debug!(file_id = %id, "Aborting distribution of deleted file {id}", id = id);
+                distribution.cancel.cancel();
+            } else {
+                debug!(file_id = %id, "Waiting for distribution of deleted file {id} to complete", id = id);
+            }
+
+            distribution.finished.cancelled().await;
+        }
+
+        let deletions = backends.iter().map(|backend| async move {
+            if let Err(e) = backend.delete_file(id).await {
+                warn!(file_id = %id, "Failed to delete file using backend {tag}: {error}", tag = backend.tag(), error = e);
+            }
+        });
+        join_all(deletions).await;
+    }
+
+    /// Extends the lease of a file in all backends.
+    ///
+    /// A running distribution stores the file until its previous expiration date
+    /// and is therefore awaited first.
+    async fn extend_lease(
+        backends: Arc<[Backend]>,
+        id: ShortGuid,
+        expires: Instant,
+        distribution: Option<CancellationToken>,
+    ) {
+        if let Some(finished) = distribution {
+            debug!(file_id = %id, "Waiting for distribution of file {id} to complete before extending its lease", id = id);
+            finished.cancelled().await;
+        }
+
+        let extensions = backends.iter().map(|backend| async move {
+            if let Err(e) = backend.extend_lease(id, expires).await {
+                warn!(file_id = %id, "Failed to extend the lease of file using backend {tag}: {error}", tag = backend.tag(), error = e);
+            }
+        });
+        join_all(extensions).await;
+    }
+
+    /// Distributes a file that is still being written.
+    ///
+    /// Backends not depending on the file hashes start receiving the file right away and are
+    /// provided with the write summary once it is available. All other backends are served
+    /// after the file was written completely, as are early backends whose early attempt failed
+    /// or whose circuit breaker is open; those are retried as per the retry policy.
+    async fn distribute_early(
+        backends: Arc<[Backend]>,
+        id: ShortGuid,
+        summary: oneshot::Receiver<DeferredSummary>,
+        file_accessor: FileProvider,
+        records: Arc<DistributionRecords>,
+        breakers: Arc<CircuitBreakers>,
+        options: DistributionOptions,
+    ) {
+        let early_backends = backends.iter().filter_map(|backend| {
+            backend
+                .early_distributor()
+                .map(|distributor| (backend, distributor))
+        });
+
+        let attempts = join_all(early_backends.map(|(backend, distributor)| {
+            let file_accessor = file_accessor.clone();
+            let breaker = breakers.get(backend.tag());
+            async move {
+                let permit = match breaker.map(CircuitBreaker::try_acquire) {
+                    Some(None) => {
+                        debug!(file_id = %id, "Deferring backend {tag} while its circuit breaker is open", tag = backend.tag());
+                        return None;
+                    }
+                    Some(permit) => permit,
+                    None => None,
+                };
+
+                let started = Instant::now();
+                let result = distributor.distribute_early(id, file_accessor).await;
+                Some(EarlyAttempt {
+                    backend,
+                    distributor,
+                    permit,
+                    result,
+                    started,
+                })
+            }
+        }))
+        .await;
+        let attempts: Vec<_> = attempts.into_iter().flatten().collect();
+
+        let Ok((summary, completion)) = summary.await else {
+            debug!(file_id = %id, "File {id} was removed before writing completed", id = id);
+
+            // The backends may hold on to the partial data of the file.
+            let deletions = attempts.iter().map(|attempt| async move {
+                let backend = attempt.backend;
+                if let Err(e) = backend.delete_file(id).await {
+                    warn!(file_id = %id, "Failed to discard file using backend {tag}: {error}", tag = backend.tag(), error = e);
+                }
+            });
+            join_all(deletions).await;
+            return;
+        };
+
+        let mut outcome = DistributionOutcome::default();
+        let mut served = Vec::new();
+        for attempt in attempts {
+            let backend = attempt.backend;
+            let result = match attempt.result {
+                Ok(()) => {
+                    attempt
+                        .distributor
+                        .update_metadata(id, summary.clone())
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Some(permit) = attempt.permit {
+                permit.record(result.is_ok());
+            }
+
+            match result {
+                Ok(()) => {
+                    // Early distributions report no digest of the stored file.
+                    Self::verify_stored_file(backend, id, None, &summary.hashes);
+                    let succeeded =
+                        Self::record_outcome(backend, id, Ok(()), attempt.started, &records);
+                    Self::add_outcome(&mut outcome, backend, succeeded);
+                    served.push(backend.tag());
+                }
+                Err(e) => {
+                    warn!(file_id = %id, "Early distribution of file {id} using backend {tag} failed, distributing it after writing completed: {error}", tag = backend.tag(), error = e);
+                    BackendMetrics::track_retry(backend.tag());
+                }
+            }
+        }
+
+        let remaining = Self::distribute_file(
+            backends.clone(),
+            id,
+            summary,
+            file_accessor,
+            records,
+            breakers.clone(),
+            options,
+            &served,
+        )
+        .await;
+        outcome.backends.extend(remaining.backends);
+        Self::complete(completion, outcome);
+    }
+
+    /// Sends the outcome of a distribution to the completion channel, if any.
+    fn complete(completion: Option<Completion>, outcome: DistributionOutcome) {
+        if let Some(completion) = completion {
+            completion.send(outcome).ok();
+        }
+    }
+
+    /// Adds the outcome of distributing a file to a single backend.
+    fn add_outcome(outcome: &mut DistributionOutcome, backend: &Backend, succeeded: bool) {
+        let result = if succeeded {
+            BackendResult::Stored
+        } else {
+            BackendResult::Failed
+        };
+        outcome.insert(backend.tag(), result);
+    }
+
+    /// Distributes a file to all backends.
+    ///
+    /// The backends are expected to be sorted by their priority and are started in that order.
+    /// If `options.gate_by_priority` is set, backends of a lower priority are only started after
+    /// all backends of a higher priority have finished. Backends whose tag is listed in `served`
+    /// are skipped since they stored the file during its early distribution.
+    ///
+    /// Returns the outcome of the distribution to the backends that were started.
+    #[allow(clippy::too_many_arguments)]
+    async fn distribute_file(
+        backends: Arc<[Backend]>,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_accessor: FileProvider,
+        records: Arc<DistributionRecords>,
+        breakers: Arc<CircuitBreakers>,
+        options: DistributionOptions,
+        served: &[&str],
+    ) -> DistributionOutcome {
+        let mut outcome = DistributionOutcome::default();
+        let mut tasks = FuturesUnordered::new();
+        let mut current_priority = None;
+
+        let backends = backends
+            .iter()
+            .filter(|backend| !served.contains(&backend.tag()));
+
+        for backend in backends {
+            let priority = backend.priority();
+            if options.gate_by_priority && matches!(current_priority, Some(p) if p != priority) {
+                trace!(file_id = %id, "Waiting for higher-priority backends to finish before starting backend {tag}", tag = backend.tag());
+                while let Some((backend, succeeded)) = tasks.next().await {
+                    Self::add_outcome(&mut outcome, backend, succeeded);
+                }
+            }
+
+            current_priority = Some(priority);
+            tasks.push(
+                Self::distribute_to_backend(
+                    backend,
+                    id,
+                    summary.clone(),
+                    file_accessor.clone(),
+                    &records,
+                    breakers.get(backend.tag()),
+                    options.retry,
+                )
+                .map(move |succeeded| (backend, succeeded)),
+            );
+        }
+
+        while let Some((backend, succeeded)) = tasks.next().await {
+            Self::add_outcome(&mut outcome, backend, succeeded);
+        }
+        outcome
+    }
+
+    /// Replies with the reader of the first backend that provides the file.
+    ///
+    /// All backends are asked at once, each attempt bounded by the policy's backend timeout.
+    /// If no backend provides the file and at least one of them did not answer in time,
+    /// or the policy's deadline passes, [`ReceiveTimeout`] is sent.
+    async fn receive_file(
+        backends: Arc<[Backend]>,
+        id: ShortGuid,
+        policy: FallbackPolicy,
+        reply: oneshot::Sender<Result<Option<BoxedFileReader>, ReceiveTimeout>>,
+    ) {
+        let mut attempts: FuturesUnordered<_> = backends
+            .iter()
+            .map(|backend| Self::receive_from_backend(backend, id, policy))
+            .collect();
+
+        let mut timed_out = false;
+        let first_reader = async {
+            while let Some(result) = attempts.next().await {
+                match result {
+                    Ok(Some(reader)) => return Some(reader),
+                    Ok(None) => {}
+                    Err(ReceiveFailure::TimedOut) => timed_out = true,
+                    Err(ReceiveFailure::Failed) => {}
+                }
+            }
+            None
+        };
+
+        let result = match tokio::time::timeout(policy.deadline, first_reader).await {
+            Ok(Some(reader)) => Ok(Some(reader)),
+            Ok(None) if timed_out => Err(ReceiveTimeout),
+            Ok(None) => Ok(None),
+            Err(_) => {
+                warn!(file_id = %id, "The backends did not provide file {id} within {deadline:?}", deadline = policy.deadline);
+                Err(ReceiveTimeout)
+            }
+        };
+        reply.send(result).ok();
+    }
+
+    /// Attempts to read a file from a single backend, retrying failed and timed out attempts.
+    async fn receive_from_backend(
+        backend: &Backend,
+        id: ShortGuid,
+        policy: FallbackPolicy,
+    ) -> Result<Option<BoxedFileReader>, ReceiveFailure> {
+        let tag = backend.tag();
+        let mut failure = ReceiveFailure::Failed;
+        for attempt in 1..=policy.max_attempts {
+            match tokio::time::timeout(policy.backend_timeout, backend.receive_file(id)).await {
+                Ok(Ok(Some(reader))) => {
+                    debug!(file_id = %id, "Received file {id} from backend {tag}");
+                    return Ok(Some(reader));
+                }
+                Ok(Ok(None)) => return Ok(None),
+                Ok(Err(e)) => {
+                    warn!(file_id = %id, "Failed to receive file using backend {tag} (attempt {attempt} of {max_attempts}): {e}", max_attempts = policy.max_attempts);
+                    failure = ReceiveFailure::Failed;
+                }
+                Err(_) => {
+                    warn!(file_id = %id, "Backend {tag} did not provide file {id} within {timeout:?} (attempt {attempt} of {max_attempts})", timeout = policy.backend_timeout, max_attempts = policy.max_attempts);
+                    failure = ReceiveFailure::TimedOut;
+                }
+            }
+        }
+        Err(failure)
+    }
+
+    /// Replies with the presigned URL of the first backend, in order of priority,
+    /// that provides one.
+    async fn presign_file(
+        backends: Arc<[Backend]>,
+        id: ShortGuid,
+        expires_in: Duration,
+        reply: oneshot::Sender<Option<String>>,
+    ) {
+        for backend in backends.iter() {
+            match backend.presigned_url(id, expires_in).await {
+                Ok(Some(url)) => {
+                    debug!(file_id = %id, "Presigned file {id} using backend {tag}", tag = backend.tag());
+                    reply.send(Some(url)).ok();
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(file_id = %id, "Failed to presign file using backend {tag}: {error}", tag = backend.tag(), error = e);
+                }
+            }
+        }
+
+        reply.send(None).ok();
+    }
+
+    /// Checks the health of all backends concurrently, waiting at most `timeout` for each.
+    async fn check_health(
+        backends: Arc<[Backend]>,
+        timeout: Duration,
+        reply: oneshot::Sender<Vec<BackendHealth>>,
+    ) {
+        let checks = backends.iter().map(|backend| async move {
+            let error = match tokio::time::timeout(timeout, backend.health_check()).await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e),
+                Err(_) => Some(HealthCheckError::TimedOut(timeout)),
+            };
+            if let Some(e) = &error {
+                warn!("Backend {tag} is unhealthy: {e}", tag = backend.tag());
+            }
+            BackendHealth {
+                tag: backend.tag().to_string(),
+                error,
+            }
+        });
+        reply.send(join_all(checks).await).ok();
+    }
+
+    /// Distributes a file to a single backend, retrying failed attempts with an
+    /// exponential backoff as per the retry policy.
+    ///
+    /// Attempts are skipped while the backend's circuit breaker, if any, is open.
+    ///
+    /// Returns whether the backend stored the file.
+    async fn distribute_to_backend(
+        backend: &Backend,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_accessor: FileProvider,
+        records: &DistributionRecords,
+        breaker: Option<&CircuitBreaker>,
+        retry: RetryPolicy,
+    ) -> bool {
+        let started = Instant::now();
+        let mut attempt = 1;
+        let result = loop {
+            let permit = match breaker.map(CircuitBreaker::try_acquire) {
+                Some(None) => {
+                    debug!(file_id = %id, "Skipping backend {tag} while its circuit breaker is open", tag = backend.tag());
+                    BackendMetrics::track_short_circuit(backend.tag());
+                    break Err(DistributionError::CircuitOpen);
+                }
+                Some(permit) => permit,
+                None => None,
+            };
+
+            let result = backend
+                .distribute_file(id, summary.clone(), file_accessor.clone())
+                .await;
+            if let Some(permit) = permit {
+                permit.record(result.is_ok());
+            }
+
+            match result {
+                Err(e) if attempt < retry.max_attempts => {
+                    let backoff = retry.backoff(attempt);
+                    warn!(file_id = %id, "Attempt {attempt} of {max_attempts} to distribute file using backend {tag} failed, retrying in {backoff:?}: {error}", max_attempts = retry.max_attempts, tag = backend.tag(), error = e);
+                    BackendMetrics::track_retry(backend.tag());
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+
+        if result.is_err() && !matches!(result, Err(DistributionError::CircuitOpen)) {
+            error!(file_id = %id, "Giving up distributing file {id} using backend {tag} after {attempt} attempts", tag = backend.tag());
+            BackendMetrics::track_abandoned(backend.tag());
+        }
+
+        let result =
+            result.map(|digest| Self::verify_stored_file(backend, id, digest, &summary.hashes));
+        Self::record_outcome(backend, id, result, started, records)
+    }
+
+    /// Compares the digest of a file as stored by a backend against the hashes of the file.
+    ///
+    /// Mismatches hint at the file being corrupted during distribution; they are logged and
+    /// tracked, but do not fail the distribution.
+    fn verify_stored_file(
+        backend: &Backend,
+        id: ShortGuid,
+        digest: Option<StoredDigest>,
+        hashes: &FileHashes,
+    ) {
+        let tag = backend.tag();
+        let result = match digest.map(|digest| (digest, digest.matches(hashes))) {
+            Some((digest, Some(true))) => {
+                trace!(file_id = %id, "Backend {tag} stored file {id} with the expected digest {digest}");
+                VerificationResult::Verified
+            }
+            Some((digest, Some(false))) => {
+                error!(file_id = %id, "Backend {tag} stored file {id} with digest {digest}, which does not match the file; the stored copy may be corrupt");
+                VerificationResult::Mismatch
+            }
+            _ => VerificationResult::Unverified,
+        };
+        BackendMetrics::track_verification(tag, result);
+    }
+
+    /// Records the outcome of distributing a file to a backend, started at `started`.
+    ///
+    /// Returns whether the backend stored the file.
+    fn record_outcome(
+        backend: &Backend,
+        id: ShortGuid,
+        result: Result<(), DistributionError>,
+        started: Instant,
+        records: &DistributionRecords,
+    ) -> bool {
+        BackendMetrics::track_distribution(backend.tag(), &result, started.elapsed());
+        match result {
+            Ok(_) => {
+                records.record(id, backend.tag(), backend.location(id), true);
+                true
+            }
+            Err(e) => {
+                warn!(file_id = %id, "Failed to distribute file using backend {tag}: {error}", tag = backend.tag(), error = e);
+                records.record(id, backend.tag(), None, false);
+                false
+            }
+        }
+    }
+}
+
+/// The attempt to distribute a file to a backend while the file was being written.
+struct EarlyAttempt<'a> {
+    backend: &'a Backend,
+    distributor: &'a dyn DistributeEarly,
+    /// The admission of the attempt by the backend's circuit breaker, if any.
+    permit: Option<CircuitPermit<'a>>,
+    result: Result<(), DistributionError>,
+    started: Instant,
+}
+
+/// Controls how files are distributed to the backends.
+#[derive(Debug, Clone, Copy)]
+struct DistributionOptions {
+    /// Whether lower-priority backends wait for higher-priority backends to finish.
+    gate_by_priority: bool,
+    /// Whether hash-independent backends receive files while they are being written.
+    early_distribution: bool,
+    /// How to proceed with files that are deleted while being distributed.
+    on_delete: DeleteBehavior,
+    /// How failed distributions to a backend are retried.
+    retry: RetryPolicy,
+    /// How files are read back from the backends.
+    fallback: FallbackPolicy,
+}
+
+/// Controls reading files back from the backends.
+#[derive(Debug, Clone, Copy)]
+struct FallbackPolicy {
+    /// The number of attempts to read a file from each backend, including the first one.
+    max_attempts: u32,
+    /// The time to wait for each attempt.
+    backend_timeout: Duration,
+    /// The time to wait for any backend to provide the file.
+    deadline: Duration,
+}
+
+/// The reason a backend did not provide a file.
+#[derive(Debug, Clone, Copy)]
+enum ReceiveFailure {
+    /// The last attempt failed.
+    Failed,
+    /// The last attempt did not complete in time.
+    TimedOut,
+}
+
+/// Controls the retries of failed distributions to a backend.
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    /// The number of attempts before giving up, including the first one.
+    max_attempts: u32,
+    /// The delay before the first retry.
+    initial_backoff: Duration,
+    /// The upper bound of the delay between retries.
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Gets the delay after the specified (one-based) failed attempt; the delay
+    /// doubles with every attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// A running distribution of a file.
+struct ActiveDistribution {
+    /// Aborts the distribution when cancelled.
+    cancel: CancellationToken,
+    /// Cancelled by the distribution task when it finishes.
+    finished: CancellationToken,
+}
+
+impl ActiveDistribution {
+    /// Spawns a distribution task onto `tasks` that can be aborted using its cancellation token.
+    ///
+    /// The task runs in a span nested in the span of the upload, such that its logs carry
+    /// the ID of the upload request.
+    fn spawn<F>(tasks: &mut JoinSet<()>, id: ShortGuid, upload: &Span, distribution: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let cancel = CancellationToken::new();
+        let finished = CancellationToken::new();
+        let token = cancel.clone();
+        let guard = finished.clone().drop_guard();
+        let span = info_span!(parent: upload, "distribution", file_id = %id);
+        tasks.spawn(
+            async move {
+                let _guard = guard;
+                tokio::select! {
+                    _ = token.cancelled() => {
+                        debug!(file_id = %id, "Aborted distribution of file {id}", id = id);
+                    }
+                    _ = distribution => {}
+                }
+            }
+            .instrument(span),
+        );
+        Self { cancel, finished }
+    }
+
+    /// Determines whether the distribution task has finished.
+    fn is_finished(&self) -> bool {
+        self.finished.is_cancelled()
+    }
 }
 
 pub struct BackendRegistryBuilder {
@@ -113,8 +845,37 @@ impl BackendRegistryBuilder {
         }
     }
 
-    pub fn build(self) -> BackendRegistry {
-        BackendRegistry::new(self.cleanup_rendezvous, self.backends, self.file_accessor)
+    /// Builds the registry.
+    ///
+    /// The registered backends are ordered by their [priority](Backend::priority), with
+    /// backends of the same priority keeping their registration order.
+    pub fn build(mut self, config: &AppConfig) -> BackendRegistry {
+        self.backends.sort_by_key(|backend| backend.priority());
+        BackendRegistry::new(
+            self.cleanup_rendezvous,
+            self.backends,
+            self.file_accessor,
+            DistributionOptions {
+                gate_by_priority: config.distribution.gate_by_priority,
+                early_distribution: config.distribution.early_distribution,
+                on_delete: config.distribution.on_delete,
+                retry: RetryPolicy {
+                    max_attempts: config.distribution.retry.max_attempts(),
+                    initial_backoff: config.distribution.retry.initial_backoff(),
+                    max_backoff: config.distribution.retry.max_backoff(),
+                },
+                fallback: FallbackPolicy {
+                    max_attempts: config.downloads.fallback.max_attempts(),
+                    backend_timeout: config.downloads.fallback.backend_timeout(),
+                    deadline: config.downloads.fallback.deadline(),
+                },
+            },
+            CircuitBreakerPolicy {
+                failure_threshold: config.distribution.circuit_breaker.failure_threshold(),
+                cooldown: config.distribution.circuit_breaker.cooldown(),
+            },
+            config.distribution.event_buffer_size(),
+        )
     }
 
     /// Adds backends to the application.
@@ -167,24 +928,1296 @@ impl BackendRegistryBuilder {
                 backend_version = T::backend_version(),
                 plural = if backends.len() == 1 { "" } else { "s" }
             );
-                    Ok(self.add_backends_from_iter(backends))
+                    let backends = backends.into_iter().map(Backend::with_info::<T>);
+                    self.add_backends_from_iter(backends).map_err(|e| {
+                        error!(
+                            "Failed to register {backend} backends: {e}",
+                            backend = T::backend_name()
+                        );
+                        e
+                    })
                 } else {
                     Ok(self)
                 }
             }
             Err(e) => {
-                error!("Failed to initialize Memcached backends: {}", e);
+                error!(
+                    "Failed to initialize {backend} backends: {e}",
+                    backend = T::backend_name()
+                );
                 Err(e)
             }
         }
     }
 
     /// Registers multiple backends.
+    ///
+    /// Fails if a backend uses the tag of an already registered backend.
     fn add_backends_from_iter<I: IntoIterator<Item = Backend>>(
         mut self,
         backends: I,
-    ) -> BackendRegistryBuilder {
-        self.backends.extend(backends);
-        self
+    ) -> Result<BackendRegistryBuilder, RegisterBackendError> {
+        for backend in backends {
+            if self
+                .backends
+                .iter()
+                .any(|registered| registered.tag() == backend.tag())
+            {
+                return Err(RegisterBackendError::DuplicateTag(
+                    backend.tag().to_string(),
+                ));
+            }
+            self.backends.push(backend);
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use app_config::distribution::DEFAULT_EVENT_BUFFER_SIZE;
+    use axum::async_trait;
+    use backend_traits::{BackendInfo, DistributeEarly, DistributeFile, ReceiveError, ReceiveFile};
+    use file_distribution::hash::{HashBlake3, HashMd5, HashSha256};
+    use file_distribution::{
+        BoxedFileReader, BufferedFileReader, FileAccessorError, FileHashes, GetFile,
+    };
+    use rendezvous::Rendezvous;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+    use tokio::time::Instant;
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    const SINGLE_ATTEMPT: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        initial_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+    };
+
+    const SINGLE_FALLBACK: FallbackPolicy = FallbackPolicy {
+        max_attempts: 1,
+        backend_timeout: Duration::from_secs(1),
+        deadline: Duration::from_secs(5),
+    };
+
+    struct RecordingBackend {
+        tag: String,
+        events: Events,
+        delay: Duration,
+    }
+
+    impl RecordingBackend {
+        fn wrap(tag: &str, events: &Events, delay: Duration) -> Backend {
+            Backend::wrap(Self {
+                tag: tag.to_string(),
+                events: events.clone(),
+                delay,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl DistributeFile for RecordingBackend {
+        fn tag(&self) -> &str {
+            &self.tag
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("start {}", self.tag));
+            tokio::time::sleep(self.delay).await;
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("end {}", self.tag));
+            Ok(None)
+        }
+
+        async fn delete_file(&self, _id: ShortGuid) -> Result<(), DistributionError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("delete {}", self.tag));
+            Ok(())
+        }
+
+        async fn extend_lease(
+            &self,
+            _id: ShortGuid,
+            _expires: Instant,
+        ) -> Result<(), DistributionError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("extend {}", self.tag));
+            Ok(())
+        }
+    }
+
+    /// A backend providing presigned URLs of files.
+    struct PresigningBackend {
+        tag: String,
+    }
+
+    #[async_trait]
+    impl DistributeFile for PresigningBackend {
+        fn tag(&self) -> &str {
+            &self.tag
+        }
+
+        async fn presigned_url(
+            &self,
+            id: ShortGuid,
+            expires_in: Duration,
+        ) -> Result<Option<String>, backend_traits::ReceiveError> {
+            Ok(Some(format!(
+                "https://{tag}/{id}?expires={secs}",
+                tag = self.tag,
+                secs = expires_in.as_secs()
+            )))
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            Ok(None)
+        }
+    }
+
+    /// A backend failing a number of times before succeeding.
+    struct FlakyBackend {
+        tag: String,
+        failures: u32,
+        attempts: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl DistributeFile for FlakyBackend {
+        fn tag(&self) -> &str {
+            &self.tag
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            if attempt <= self.failures {
+                return Err(std::io::Error::other("transient failure").into());
+            }
+            Ok(None)
+        }
+
+        async fn delete_file(&self, _id: ShortGuid) -> Result<(), DistributionError> {
+            Ok(())
+        }
+    }
+
+    /// A backend reporting a fixed digest for every stored file.
+    struct DigestBackend {
+        tag: String,
+        digest: StoredDigest,
+    }
+
+    #[async_trait]
+    impl DistributeFile for DigestBackend {
+        fn tag(&self) -> &str {
+            &self.tag
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            Ok(Some(self.digest))
+        }
+    }
+
+    /// A backend that does not depend on the file hashes.
+    struct EarlyBackend {
+        events: Events,
+    }
+
+    #[async_trait]
+    impl DistributeFile for EarlyBackend {
+        fn tag(&self) -> &str {
+            "early"
+        }
+
+        fn early_distributor(&self) -> Option<&dyn DistributeEarly> {
+            Some(self)
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            panic!("the early backend should not be distributed to after writing completed");
+        }
+
+        async fn delete_file(&self, _id: ShortGuid) -> Result<(), DistributionError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push("early discarded".to_string());
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl DistributeEarly for EarlyBackend {
+        async fn distribute_early(
+            &self,
+            id: ShortGuid,
+            file_provider: FileProvider,
+        ) -> Result<(), DistributionError> {
+            let mut reader = file_provider.get_file(id).await?;
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).await?;
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("early received {} bytes", data.len()));
+            Ok(())
+        }
+
+        async fn update_metadata(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+        ) -> Result<(), DistributionError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push("early received hashes".to_string());
+            Ok(())
+        }
+    }
+
+    /// A backend failing early distributions, whose regular distributions fail a number
+    /// of times before succeeding.
+    struct FlakyEarlyBackend {
+        events: Events,
+        failures: u32,
+        attempts: Arc<AtomicU32>,
+    }
+
+    #[async_trait]
+    impl DistributeFile for FlakyEarlyBackend {
+        fn tag(&self) -> &str {
+            "flaky-early"
+        }
+
+        fn early_distributor(&self) -> Option<&dyn DistributeEarly> {
+            Some(self)
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            let attempt = self.attempts.fetch_add(1, Ordering::SeqCst) + 1;
+            if attempt <= self.failures {
+                return Err(std::io::Error::other("transient failure").into());
+            }
+            self.events
+                .lock()
+                .unwrap()
+                .push("flaky-early stored".to_string());
+            Ok(None)
+        }
+    }
+
+    #[async_trait]
+    impl DistributeEarly for FlakyEarlyBackend {
+        async fn distribute_early(
+            &self,
+            _id: ShortGuid,
+            _file_provider: FileProvider,
+        ) -> Result<(), DistributionError> {
+            self.events
+                .lock()
+                .unwrap()
+                .push("flaky-early failed".to_string());
+            Err(std::io::Error::other("connection reset").into())
+        }
+    }
+
+    struct SomeFile;
+
+    #[async_trait]
+    impl GetFile for SomeFile {
+        async fn get_file(&self, _id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
+            Ok(BoxedFileReader::new(BufferedFileReader::new("yeet")))
+        }
+    }
+
+    struct NoFiles;
+
+    #[async_trait]
+    impl GetFile for NoFiles {
+        async fn get_file(&self, _id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
+            Err(FileAccessorError::BackboneUnavailable)
+        }
+    }
+
+    fn backends(events: &Events) -> Arc<[Backend]> {
+        let mut backends: Vec<_> = [("cold", 10), ("hot", 0), ("warm", 5)]
+            .into_iter()
+            .map(|(tag, priority)| {
+                RecordingBackend::wrap(tag, events, Duration::from_millis(10))
+                    .with_priority(priority)
+            })
+            .collect();
+        backends.sort_by_key(|backend| backend.priority());
+        backends.into()
+    }
+
+    fn summary() -> Arc<WriteSummary> {
+        Arc::new(WriteSummary {
+            expires: Instant::now(),
+            hashes: FileHashes::new(
+                HashMd5::new().finalize(),
+                HashSha256::new().finalize(),
+                HashBlake3::new().finalize(),
+            ),
+            file_name: None,
+            file_size_bytes: 0,
+            metadata: Default::default(),
+        })
+    }
+
+    /// The backend event loop, running in the background.
+    struct EventLoop {
+        sender: Sender<BackendCommand>,
+        handle: JoinHandle<()>,
+        records: Arc<DistributionRecords>,
+        rendezvous: Rendezvous,
+    }
+
+    impl EventLoop {
+        fn spawn(backends: Vec<Backend>, options: DistributionOptions) -> Self {
+            let rendezvous = Rendezvous::new();
+            let (sender, receiver) = mpsc::channel(DEFAULT_EVENT_BUFFER_SIZE);
+            let records = Arc::new(DistributionRecords::default());
+            let handle = tokio::spawn(BackendRegistry::handle_events(
+                backends.into(),
+                receiver,
+                rendezvous.fork_guard(),
+                FileProvider::wrap(Arc::new(SomeFile)),
+                records.clone(),
+                Arc::default(),
+                options,
+            ));
+            Self {
+                sender,
+                handle,
+                records,
+                rendezvous,
+            }
+        }
+
+        async fn send(&self, command: BackendCommand) {
+            self.sender
+                .send(command)
+                .await
+                .unwrap_or_else(|_| panic!("the event loop stopped"));
+        }
+
+        async fn shut_down(self) {
+            drop(self.sender);
+            self.handle.await.expect("failed to join event loop");
+            let rendezvous = self.rendezvous;
+            tokio::task::spawn_blocking(move || rendezvous.rendezvous())
+                .await
+                .expect("failed to await the rendezvous");
+        }
+    }
+
+    #[test]
+    fn duplicate_backend_tags_are_rejected() {
+        let events = Events::default();
+        let rendezvous = Rendezvous::new();
+        let builder = BackendRegistryBuilder::new(
+            rendezvous.fork_guard(),
+            FileProvider::wrap(Arc::new(SomeFile)),
+        );
+
+        let builder = builder
+            .add_backends_from_iter([RecordingBackend::wrap("hot", &events, Duration::ZERO)])
+            .expect("distinct tags should be accepted");
+        let result = builder.add_backends_from_iter([
+            RecordingBackend::wrap("cold", &events, Duration::ZERO),
+            RecordingBackend::wrap("hot", &events, Duration::ZERO),
+        ]);
+        assert!(matches!(
+            result,
+            Err(RegisterBackendError::DuplicateTag(tag)) if tag == "hot"
+        ));
+
+        rendezvous.rendezvous();
+    }
+
+    /// A backend type failing to be created from the configuration.
+    struct UnavailableBackend;
+
+    impl BackendInfo for UnavailableBackend {
+        fn backend_name() -> &'static str {
+            "Unavailable"
+        }
+    }
+
+    impl TryCreateFromConfig for UnavailableBackend {
+        type Error = std::io::Error;
+
+        fn try_from_config(_config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+            Err(std::io::Error::other("connection refused"))
+        }
+    }
+
+    /// Collects the formatted log output.
+    #[derive(Clone, Default)]
+    struct LogOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failures_to_create_backends_name_the_backend() {
+        let output = LogOutput::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        let rendezvous = Rendezvous::new();
+        let builder = BackendRegistryBuilder::new(
+            rendezvous.fork_guard(),
+            FileProvider::wrap(Arc::new(SomeFile)),
+        );
+        let result = tracing::subscriber::with_default(subscriber, || {
+            builder.add_backends::<UnavailableBackend>(&AppConfig::default())
+        });
+        assert!(matches!(
+            result,
+            Err(RegisterBackendError::TryCreateFromConfig(_))
+        ));
+        rendezvous.rendezvous();
+
+        let logs = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("Failed to initialize Unavailable backends: connection refused"));
+        assert!(!logs.contains("Memcached"));
+    }
+
+    /// Waits until the specified event was recorded.
+    async fn wait_for(events: &Events, event: &str) {
+        let recorded = || events.lock().unwrap().iter().any(|e| e == event);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !recorded() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("timed out waiting for event {event:?}"));
+    }
+
+    async fn distribute(gate_by_priority: bool) -> Vec<String> {
+        let events = Events::default();
+        let summary = summary();
+
+        BackendRegistry::distribute_file(
+            backends(&events),
+            ShortGuid::new_random(),
+            summary,
+            FileProvider::wrap(Arc::new(NoFiles)),
+            Arc::new(DistributionRecords::default()),
+            Arc::default(),
+            DistributionOptions {
+                gate_by_priority,
+                early_distribution: false,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+            &[],
+        )
+        .await;
+
+        let events = events.lock().unwrap();
+        events.clone()
+    }
+
+    #[tokio::test]
+    async fn backends_are_started_in_priority_order() {
+        let events = distribute(false).await;
+        assert_eq!(&events[..3], ["start hot", "start warm", "start cold"]);
+    }
+
+    #[tokio::test]
+    async fn distributions_are_tracked_per_backend() {
+        distribute(false).await;
+        let metrics = metrics::Metrics::get().encode();
+        for tag in ["hot", "warm", "cold"] {
+            assert!(metrics.contains(&format!(
+                "backend_distributions_total{{backend=\"{tag}\",outcome=\"success\"}}"
+            )));
+            assert!(metrics.contains(&format!(
+                "backend_distribution_duration_seconds_total{{backend=\"{tag}\",outcome=\"success\"}}"
+            )));
+        }
+    }
+
+    #[tokio::test]
+    async fn gated_backends_wait_for_higher_priorities() {
+        let events = distribute(true).await;
+        assert_eq!(
+            events,
+            [
+                "start hot",
+                "end hot",
+                "start warm",
+                "end warm",
+                "start cold",
+                "end cold"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_independent_backends_receive_files_before_writing_completed() {
+        let events = Events::default();
+        let event_loop = EventLoop::spawn(
+            vec![
+                RecordingBackend::wrap("hot", &events, Duration::from_millis(10)),
+                Backend::wrap(EarlyBackend {
+                    events: events.clone(),
+                }),
+            ],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: true,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let id = ShortGuid::new_random();
+        event_loop
+            .send(BackendCommand::FileCreated(id, Span::none()))
+            .await;
+        wait_for(&events, "early received 4 bytes").await;
+        assert_eq!(
+            events.lock().unwrap().as_slice(),
+            ["early received 4 bytes"]
+        );
+
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                id,
+                summary(),
+                None,
+                Span::none(),
+            ))
+            .await;
+        wait_for(&events, "end hot").await;
+        wait_for(&events, "early received hashes").await;
+
+        let receipt = event_loop.records.receipt(id).expect("missing receipt");
+        assert_eq!(receipt.backends.len(), 2);
+
+        event_loop.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn failed_early_distributions_are_retried_after_writing_completed() {
+        let events = Events::default();
+        let attempts = Arc::new(AtomicU32::new(0));
+        let event_loop = EventLoop::spawn(
+            vec![Backend::wrap(FlakyEarlyBackend {
+                events: events.clone(),
+                failures: 1,
+                attempts: attempts.clone(),
+            })],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: true,
+                on_delete: DeleteBehavior::Complete,
+                retry: RetryPolicy {
+                    max_attempts: 2,
+                    ..SINGLE_ATTEMPT
+                },
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let id = ShortGuid::new_random();
+        event_loop
+            .send(BackendCommand::FileCreated(id, Span::none()))
+            .await;
+        wait_for(&events, "flaky-early failed").await;
+
+        let (completion, outcome) = oneshot::channel();
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                id,
+                summary(),
+                Some(completion),
+                Span::none(),
+            ))
+            .await;
+        let outcome = outcome.await.expect("the distribution did not complete");
+        assert_eq!(outcome.succeeded(), ["flaky-early"]);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            events.lock().unwrap().as_slice(),
+            ["flaky-early failed", "flaky-early stored"]
+        );
+
+        let receipt = event_loop.records.receipt(id).expect("missing receipt");
+        assert_eq!(receipt.backends.len(), 1);
+        assert_eq!(receipt.backends[0].tag, "flaky-early");
+
+        event_loop.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn early_distributions_of_removed_files_are_discarded() {
+        let events = Events::default();
+        let event_loop = EventLoop::spawn(
+            vec![Backend::wrap(EarlyBackend {
+                events: events.clone(),
+            })],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: true,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let id = ShortGuid::new_random();
+        event_loop
+            .send(BackendCommand::FileCreated(id, Span::none()))
+            .await;
+        wait_for(&events, "early received 4 bytes").await;
+
+        event_loop.send(BackendCommand::FileRemoved(id)).await;
+        wait_for(&events, "early discarded").await;
+        assert!(event_loop.records.receipt(id).is_none());
+
+        event_loop.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn first_presigned_url_is_used() {
+        let events = Events::default();
+        let backends: Arc<[Backend]> = Arc::new([
+            RecordingBackend::wrap("hot", &events, Duration::ZERO),
+            Backend::wrap(PresigningBackend {
+                tag: "warm".to_string(),
+            }),
+            Backend::wrap(PresigningBackend {
+                tag: "cold".to_string(),
+            }),
+        ]);
+
+        let id = ShortGuid::new_random();
+        let (sender, receiver) = oneshot::channel();
+        BackendRegistry::presign_file(backends, id, Duration::from_secs(60), sender).await;
+        assert_eq!(
+            receiver.await.expect("no reply"),
+            Some(format!("https://warm/{id}?expires=60"))
+        );
+
+        let (sender, receiver) = oneshot::channel();
+        let backends: Arc<[Backend]> =
+            Arc::new([RecordingBackend::wrap("hot", &events, Duration::ZERO)]);
+        BackendRegistry::presign_file(backends, id, Duration::from_secs(60), sender).await;
+        assert_eq!(receiver.await.expect("no reply"), None);
+    }
+
+    /// A backend serving files back after a delay, failing a number of attempts first.
+    struct ReceivingBackend {
+        tag: String,
+        delay: Duration,
+        knows_file: bool,
+        failures: AtomicU32,
+        attempts: Arc<AtomicU32>,
+    }
+
+    impl ReceivingBackend {
+        fn new(tag: &str, delay: Duration, knows_file: bool) -> Self {
+            Self {
+                tag: tag.to_string(),
+                delay,
+                knows_file,
+                failures: AtomicU32::new(0),
+                attempts: Arc::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl DistributeFile for ReceivingBackend {
+        fn tag(&self) -> &str {
+            &self.tag
+        }
+
+        fn receiver(&self) -> Option<&dyn ReceiveFile> {
+            Some(self)
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            Ok(None)
+        }
+    }
+
+    #[async_trait]
+    impl ReceiveFile for ReceivingBackend {
+        async fn receive_file(
+            &self,
+            _id: ShortGuid,
+        ) -> Result<Option<BoxedFileReader>, ReceiveError> {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            let failed = self
+                .failures
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if failed {
+                return Err(ReceiveError::Io(std::io::ErrorKind::Other.into()));
+            }
+            Ok(self
+                .knows_file
+                .then(|| BoxedFileReader::new(BufferedFileReader::new(self.tag.clone()))))
+        }
+    }
+
+    /// Reads a file back from the backends, returning the tag of the backend that provided it.
+    async fn receive(
+        backends: Vec<Backend>,
+        policy: FallbackPolicy,
+    ) -> Result<Option<String>, ReceiveTimeout> {
+        let (sender, receiver) = oneshot::channel();
+        BackendRegistry::receive_file(backends.into(), ShortGuid::new_random(), policy, sender)
+            .await;
+        let Some(mut reader) = receiver.await.expect("no reply")? else {
+            return Ok(None);
+        };
+        let mut tag = String::new();
+        reader
+            .read_to_string(&mut tag)
+            .await
+            .expect("failed to read");
+        Ok(Some(tag))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn first_backend_providing_the_file_is_used() {
+        let backends = vec![
+            Backend::wrap(ReceivingBackend::new("cold", Duration::from_secs(60), true)),
+            Backend::wrap(ReceivingBackend::new("empty", Duration::ZERO, false)),
+            Backend::wrap(ReceivingBackend::new(
+                "warm",
+                Duration::from_millis(100),
+                true,
+            )),
+        ];
+
+        let started = Instant::now();
+        let received = receive(backends, SINGLE_FALLBACK).await;
+        assert!(matches!(received, Ok(Some(tag)) if tag == "warm"));
+        assert_eq!(started.elapsed(), Duration::from_millis(100));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_backends_time_out() {
+        let backends = vec![
+            Backend::wrap(ReceivingBackend::new("cold", Duration::from_secs(60), true)),
+            Backend::wrap(ReceivingBackend::new("empty", Duration::ZERO, false)),
+        ];
+        let started = Instant::now();
+        assert!(receive(backends, SINGLE_FALLBACK).await.is_err());
+        assert_eq!(started.elapsed(), SINGLE_FALLBACK.backend_timeout);
+
+        // The deadline bounds all attempts.
+        let backends = vec![Backend::wrap(ReceivingBackend::new(
+            "cold",
+            Duration::from_secs(60),
+            true,
+        ))];
+        let policy = FallbackPolicy {
+            max_attempts: 10,
+            ..SINGLE_FALLBACK
+        };
+        let started = Instant::now();
+        assert!(receive(backends, policy).await.is_err());
+        assert_eq!(started.elapsed(), SINGLE_FALLBACK.deadline);
+
+        // Backends that answer in time but do not know the file are no timeout.
+        let backends = vec![Backend::wrap(ReceivingBackend::new(
+            "empty",
+            Duration::ZERO,
+            false,
+        ))];
+        assert!(matches!(receive(backends, SINGLE_FALLBACK).await, Ok(None)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_receives_are_retried() {
+        let flaky = ReceivingBackend {
+            failures: AtomicU32::new(1),
+            ..ReceivingBackend::new("flaky", Duration::ZERO, true)
+        };
+        let attempts = flaky.attempts.clone();
+
+        let policy = FallbackPolicy {
+            max_attempts: 2,
+            ..SINGLE_FALLBACK
+        };
+        let received = receive(vec![Backend::wrap(flaky)], policy).await;
+        assert!(matches!(received, Ok(Some(tag)) if tag == "flaky"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    /// Deletes a file while it is being distributed to a slow backend.
+    async fn delete_during_distribution(on_delete: DeleteBehavior) -> Vec<String> {
+        let events = Events::default();
+        let event_loop = EventLoop::spawn(
+            vec![RecordingBackend::wrap(
+                "slow",
+                &events,
+                Duration::from_millis(200),
+            )],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: false,
+                on_delete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let id = ShortGuid::new_random();
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                id,
+                summary(),
+                None,
+                Span::none(),
+            ))
+            .await;
+        wait_for(&events, "start slow").await;
+
+        event_loop.send(BackendCommand::DeleteFile(id)).await;
+        wait_for(&events, "delete slow").await;
+
+        event_loop.shut_down().await;
+        let events = events.lock().unwrap();
+        events.clone()
+    }
+
+    #[tokio::test]
+    async fn deleted_files_complete_distribution_before_deletion() {
+        let events = delete_during_distribution(DeleteBehavior::Complete).await;
+        assert_eq!(events, ["start slow", "end slow", "delete slow"]);
+    }
+
+    #[tokio::test]
+    async fn deleted_files_abort_distribution() {
+        let events = delete_during_distribution(DeleteBehavior::Abort).await;
+        assert_eq!(events, ["start slow", "delete slow"]);
+    }
+
+    #[tokio::test]
+    async fn leases_are_extended_after_running_distributions() {
+        let events = Events::default();
+        let event_loop = EventLoop::spawn(
+            vec![RecordingBackend::wrap(
+                "slow",
+                &events,
+                Duration::from_millis(200),
+            )],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: false,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let id = ShortGuid::new_random();
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                id,
+                summary(),
+                None,
+                Span::none(),
+            ))
+            .await;
+        wait_for(&events, "start slow").await;
+
+        let expires = Instant::now() + Duration::from_secs(60);
+        event_loop
+            .send(BackendCommand::LeaseExtended(id, expires))
+            .await;
+        wait_for(&events, "extend slow").await;
+
+        event_loop.shut_down().await;
+        let events = events.lock().unwrap();
+        assert_eq!(*events, ["start slow", "end slow", "extend slow"]);
+    }
+
+    #[tokio::test]
+    async fn running_distributions_are_not_repeated() {
+        let events = Events::default();
+        let event_loop = EventLoop::spawn(
+            vec![RecordingBackend::wrap(
+                "slow",
+                &events,
+                Duration::from_millis(100),
+            )],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: false,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let id = ShortGuid::new_random();
+        let distribute = || BackendCommand::DistributeFile(id, summary(), None, Span::none());
+        event_loop.send(distribute()).await;
+        wait_for(&events, "start slow").await;
+        event_loop.send(distribute()).await;
+        wait_for(&events, "end slow").await;
+
+        // Once finished, the file can be distributed again.
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        event_loop.send(distribute()).await;
+        event_loop.shut_down().await;
+        assert_eq!(
+            events.lock().unwrap().as_slice(),
+            ["start slow", "end slow", "start slow", "end slow"]
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_running_distributions() {
+        let events = Events::default();
+        let event_loop = EventLoop::spawn(
+            vec![RecordingBackend::wrap(
+                "slow",
+                &events,
+                Duration::from_millis(200),
+            )],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: false,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                ShortGuid::new_random(),
+                summary(),
+                None,
+                Span::none(),
+            ))
+            .await;
+        wait_for(&events, "start slow").await;
+
+        // The rendezvous only completes after the distribution finished.
+        event_loop.shut_down().await;
+        assert_eq!(
+            events.lock().unwrap().as_slice(),
+            ["start slow", "end slow"]
+        );
+    }
+
+    #[tokio::test]
+    async fn distribution_outcome_is_reported_on_completion() {
+        let flaky = |tag: &str, failures| {
+            Backend::wrap(FlakyBackend {
+                tag: tag.to_string(),
+                failures,
+                attempts: Arc::default(),
+            })
+        };
+        let event_loop = EventLoop::spawn(
+            vec![flaky("stores", 0), flaky("fails", 1)],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: false,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let (completion, outcome) = oneshot::channel();
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                ShortGuid::new_random(),
+                summary(),
+                Some(completion),
+                Span::none(),
+            ))
+            .await;
+        let outcome = outcome.await.expect("the outcome was not reported");
+        assert_eq!(outcome.succeeded(), ["stores"]);
+        assert_eq!(outcome.failed(), ["fails"]);
+        assert_eq!(outcome.backends["fails"], BackendResult::Failed);
+
+        event_loop.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn distribution_logs_are_traced_back_to_the_upload() {
+        let output = LogOutput::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _subscriber = tracing::subscriber::set_default(subscriber);
+
+        let event_loop = EventLoop::spawn(
+            vec![Backend::wrap(FlakyBackend {
+                tag: "fails".to_string(),
+                failures: 1,
+                attempts: Arc::default(),
+            })],
+            DistributionOptions {
+                gate_by_priority: false,
+                early_distribution: false,
+                on_delete: DeleteBehavior::Complete,
+                retry: SINGLE_ATTEMPT,
+                fallback: SINGLE_FALLBACK,
+            },
+        );
+
+        let (completion, outcome) = oneshot::channel();
+        let upload = info_span!("request", request_id = "upload-1");
+        event_loop
+            .send(BackendCommand::DistributeFile(
+                ShortGuid::new_random(),
+                summary(),
+                Some(completion),
+                upload,
+            ))
+            .await;
+        outcome.await.expect("the outcome was not reported");
+        event_loop.shut_down().await;
+
+        let logs = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let warning = logs
+            .lines()
+            .find(|line| line.contains("Failed to distribute file using backend fails"))
+            .expect("the failure was not logged");
+        assert!(
+            warning.contains("request{request_id=\"upload-1\"}:distribution{file_id="),
+            "the warning lacks the span of the upload: {warning}"
+        );
+    }
+
+    /// Distributes a file to a backend failing `failures` times, returning the
+    /// number of attempts and the recorded outcome.
+    async fn distribute_flaky(tag: &str, failures: u32, max_attempts: u32) -> (u32, bool) {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let backend = Backend::wrap(FlakyBackend {
+            tag: tag.to_string(),
+            failures,
+            attempts: attempts.clone(),
+        });
+        let records = Arc::new(DistributionRecords::default());
+        let id = ShortGuid::new_random();
+        records.begin(id, &summary());
+
+        BackendRegistry::distribute_to_backend(
+            &backend,
+            id,
+            summary(),
+            FileProvider::wrap(Arc::new(NoFiles)),
+            &records,
+            None,
+            RetryPolicy {
+                max_attempts,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(10),
+            },
+        )
+        .await;
+
+        let receipt = records.receipt(id).expect("missing receipt");
+        let succeeded = !receipt.backends.is_empty();
+        (attempts.load(Ordering::SeqCst), succeeded)
+    }
+
+    #[tokio::test]
+    async fn stored_digests_are_verified() {
+        let summary = summary();
+        let sha256 = summary.hashes.sha256.expect("missing hash");
+        let digests = [
+            ("verifying-intact", StoredDigest::Sha256(sha256.into())),
+            ("verifying-corrupt", StoredDigest::Md5([0; 16])),
+        ];
+
+        for (tag, digest) in digests {
+            let backend = Backend::wrap(DigestBackend {
+                tag: tag.to_string(),
+                digest,
+            });
+            let records = DistributionRecords::default();
+            let id = ShortGuid::new_random();
+            records.begin(id, &summary);
+
+            let stored = BackendRegistry::distribute_to_backend(
+                &backend,
+                id,
+                summary.clone(),
+                FileProvider::wrap(Arc::new(NoFiles)),
+                &records,
+                None,
+                SINGLE_ATTEMPT,
+            )
+            .await;
+            assert!(stored, "mismatches do not fail the distribution");
+        }
+
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics.contains(
+            "backend_distribution_verifications_total{backend=\"verifying-intact\",result=\"verified\"} 1"
+        ));
+        assert!(metrics.contains(
+            "backend_distribution_verifications_total{backend=\"verifying-corrupt\",result=\"mismatch\"} 1"
+        ));
+    }
+
+    #[test]
+    fn retry_backoff_grows_exponentially() {
+        let retry = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(3),
+        };
+        let backoffs: Vec<_> = (1..=5).map(|attempt| retry.backoff(attempt)).collect();
+        assert_eq!(
+            backoffs,
+            [
+                Duration::from_millis(500),
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(3),
+                Duration::from_secs(3),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_distributions_are_retried() {
+        let (attempts, succeeded) = distribute_flaky("flaky-retried", 2, 3).await;
+        assert_eq!(attempts, 3);
+        assert!(succeeded);
+
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics.contains("backend_distribution_retries_total{backend=\"flaky-retried\"} 2"));
+        assert!(
+            !metrics.contains("backend_distributions_abandoned_total{backend=\"flaky-retried\"}")
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn distributions_are_abandoned_after_max_attempts() {
+        let (attempts, succeeded) = distribute_flaky("flaky-abandoned", 5, 3).await;
+        assert_eq!(attempts, 3);
+        assert!(!succeeded);
+
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics
+            .contains("backend_distributions_abandoned_total{backend=\"flaky-abandoned\"} 1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn open_circuit_breakers_skip_distributions() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let backend = Backend::wrap(FlakyBackend {
+            tag: "flaky-short-circuited".to_string(),
+            failures: u32::MAX,
+            attempts: attempts.clone(),
+        });
+        let breakers = CircuitBreakers::new(
+            std::slice::from_ref(&backend),
+            CircuitBreakerPolicy {
+                failure_threshold: 2,
+                cooldown: Duration::from_secs(10),
+            },
+        );
+        let records = DistributionRecords::default();
+
+        for _ in 0..3 {
+            let succeeded = BackendRegistry::distribute_to_backend(
+                &backend,
+                ShortGuid::new_random(),
+                summary(),
+                FileProvider::wrap(Arc::new(NoFiles)),
+                &records,
+                breakers.get(backend.tag()),
+                SINGLE_ATTEMPT,
+            )
+            .await;
+            assert!(!succeeded);
+        }
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics.contains(
+            "backend_distributions_short_circuited_total{backend=\"flaky-short-circuited\"} 1"
+        ));
+        assert!(metrics.contains(
+            "backend_distributions_abandoned_total{backend=\"flaky-short-circuited\"} 2"
+        ));
     }
 }
@@ -0,0 +1,363 @@
+//! Contains the circuit breakers skipping distributions to failing backends.
+
+use backend_traits::Backend;
+use metrics::backend::{BackendMetrics, CircuitState};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Controls when the circuit breakers open and for how long.
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerPolicy {
+    /// The number of consecutive failed attempts after which a circuit breaker opens;
+    /// `0` disables the circuit breakers.
+    pub failure_threshold: u32,
+    /// The time distributions are skipped once a circuit breaker opened.
+    pub cooldown: Duration,
+}
+
+/// The circuit breakers of the backends, keyed by their tags.
+#[derive(Default)]
+pub struct CircuitBreakers(HashMap<String, CircuitBreaker>);
+
+impl CircuitBreakers {
+    /// Creates a circuit breaker for each backend, unless disabled by the policy.
+    pub fn new(backends: &[Backend], policy: CircuitBreakerPolicy) -> Self {
+        if policy.failure_threshold == 0 {
+            return Self::default();
+        }
+
+        Self(
+            backends
+                .iter()
+                .map(|backend| {
+                    let tag = backend.tag().to_string();
+                    (tag.clone(), CircuitBreaker::new(tag, policy))
+                })
+                .collect(),
+        )
+    }
+
+    /// Gets the circuit breaker of the backend with the specified tag, if any.
+    pub fn get(&self, tag: &str) -> Option<&CircuitBreaker> {
+        self.0.get(tag)
+    }
+}
+
+/// Skips distributions to a backend after consecutive failures.
+///
+/// The breaker opens after `failure_threshold` consecutive failed attempts and rejects
+/// attempts until the cooldown has passed. It then half-opens, admitting a single trial
+/// attempt: if it succeeds the breaker closes, otherwise it opens again.
+pub struct CircuitBreaker {
+    /// The tag of the backend.
+    tag: String,
+    /// When the breaker opens and for how long.
+    policy: CircuitBreakerPolicy,
+    /// The current state.
+    state: Mutex<State>,
+    /// The number of failed attempts since the service started.
+    failures_total: AtomicU64,
+}
+
+/// A snapshot of the state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitStatus {
+    /// The state of the breaker.
+    pub state: CircuitState,
+    /// The number of consecutive failed attempts while the breaker is closed.
+    pub consecutive_failures: u32,
+    /// The number of failed attempts since the service started.
+    pub failures_total: u64,
+    /// The remaining cooldown while the breaker is open.
+    pub cooldown_remaining: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Attempts are admitted; the number of consecutive failures is counted.
+    Closed { failures: u32 },
+    /// Attempts are rejected until the specified instant.
+    Open { until: Instant },
+    /// A single trial attempt is admitted.
+    HalfOpen { trial_running: bool },
+}
+
+impl CircuitBreaker {
+    fn new(tag: String, policy: CircuitBreakerPolicy) -> Self {
+        BackendMetrics::track_circuit(&tag);
+        Self {
+            tag,
+            policy,
+            state: Mutex::new(State::Closed { failures: 0 }),
+            failures_total: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets a snapshot of the state of the breaker.
+    pub fn status(&self) -> CircuitStatus {
+        let state = *self
+            .state
+            .lock()
+            .expect("failed to lock the circuit breaker");
+        let (consecutive_failures, cooldown_remaining) = match state {
+            State::Closed { failures } => (failures, None),
+            State::Open { until } => (0, Some(until.saturating_duration_since(Instant::now()))),
+            State::HalfOpen { .. } => (0, None),
+        };
+        CircuitStatus {
+            state: Self::tracked(state),
+            consecutive_failures,
+            failures_total: self.failures_total.load(Ordering::Relaxed),
+            cooldown_remaining,
+        }
+    }
+
+    /// Closes the breaker, such that distributions to the backend are attempted again
+    /// right away.
+    pub fn reset(&self) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("failed to lock the circuit breaker");
+        match *state {
+            State::Closed { .. } => *state = State::Closed { failures: 0 },
+            _ => {
+                info!("Circuit breaker of backend {tag} was reset", tag = self.tag);
+                self.transition(&mut state, State::Closed { failures: 0 });
+            }
+        }
+    }
+
+    /// Requests permission to attempt a distribution.
+    ///
+    /// Returns `None` if the attempt is rejected. The outcome of an admitted attempt
+    /// is reported using [`CircuitPermit::record`].
+    pub fn try_acquire(&self) -> Option<CircuitPermit<'_>> {
+        let mut state = self
+            .state
+            .lock()
+            .expect("failed to lock the circuit breaker");
+        match *state {
+            State::Closed { .. } => {}
+            State::Open { until } if Instant::now() >= until => {
+                info!("Testing whether backend {tag} recovered", tag = self.tag);
+                self.transition(
+                    &mut state,
+                    State::HalfOpen {
+                        trial_running: true,
+                    },
+                );
+            }
+            State::HalfOpen {
+                trial_running: false,
+            } => {
+                *state = State::HalfOpen {
+                    trial_running: true,
+                }
+            }
+            State::Open { .. } | State::HalfOpen { .. } => return None,
+        }
+
+        Some(CircuitPermit {
+            breaker: self,
+            recorded: false,
+        })
+    }
+
+    /// Updates the state with the outcome of an attempt.
+    fn record(&self, succeeded: bool) {
+        if !succeeded {
+            self.failures_total.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let mut state = self
+            .state
+            .lock()
+            .expect("failed to lock the circuit breaker");
+        match (*state, succeeded) {
+            (State::Closed { .. }, true) => *state = State::Closed { failures: 0 },
+            (_, true) => {
+                info!("Backend {tag} recovered", tag = self.tag);
+                self.transition(&mut state, State::Closed { failures: 0 });
+            }
+            (State::Closed { failures }, false) => {
+                let failures = failures + 1;
+                if failures < self.policy.failure_threshold {
+                    *state = State::Closed { failures };
+                    return;
+                }
+
+                warn!(
+                    "Skipping distributions to backend {tag} for {cooldown:?} after {failures} consecutive failures",
+                    tag = self.tag,
+                    cooldown = self.policy.cooldown
+                );
+                self.open(&mut state);
+            }
+            (State::HalfOpen { .. }, false) => {
+                warn!(
+                    "Backend {tag} did not recover, skipping distributions for {cooldown:?}",
+                    tag = self.tag,
+                    cooldown = self.policy.cooldown
+                );
+                self.open(&mut state);
+            }
+            // Attempts admitted before the breaker opened do not extend the cooldown.
+            (State::Open { .. }, false) => {}
+        }
+    }
+
+    /// Releases an admitted attempt whose outcome was not recorded, e.g. because the
+    /// distribution was aborted, allowing another trial attempt.
+    fn release(&self) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("failed to lock the circuit breaker");
+        if let State::HalfOpen {
+            trial_running: true,
+        } = *state
+        {
+            *state = State::HalfOpen {
+                trial_running: false,
+            };
+        }
+    }
+
+    fn open(&self, state: &mut State) {
+        let until = Instant::now() + self.policy.cooldown;
+        self.transition(state, State::Open { until });
+    }
+
+    fn transition(&self, state: &mut State, next: State) {
+        *state = next;
+        BackendMetrics::track_circuit_transition(&self.tag, Self::tracked(next));
+    }
+
+    fn tracked(state: State) -> CircuitState {
+        match state {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+/// Permission to attempt a distribution, granted by a [`CircuitBreaker`].
+pub struct CircuitPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    recorded: bool,
+}
+
+impl CircuitPermit<'_> {
+    /// Reports the outcome of the attempt.
+    pub fn record(mut self, succeeded: bool) {
+        self.recorded = true;
+        self.breaker.record(succeeded);
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.breaker.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: CircuitBreakerPolicy = CircuitBreakerPolicy {
+        failure_threshold: 3,
+        cooldown: Duration::from_secs(10),
+    };
+
+    fn fail(breaker: &CircuitBreaker) {
+        breaker
+            .try_acquire()
+            .expect("attempt should be admitted")
+            .record(false);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn breaker_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new("breaker-opens".to_string(), POLICY);
+        fail(&breaker);
+        fail(&breaker);
+        breaker.try_acquire().unwrap().record(true);
+
+        fail(&breaker);
+        fail(&breaker);
+        assert!(breaker.try_acquire().is_some());
+        fail(&breaker);
+        assert!(breaker.try_acquire().is_none());
+
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics.contains("backend_circuit_state{backend=\"breaker-opens\"} 1"));
+        assert!(metrics.contains(
+            "backend_circuit_transitions_total{backend=\"breaker-opens\",state=\"open\"} 1"
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn breaker_half_opens_after_the_cooldown() {
+        let breaker = CircuitBreaker::new("breaker-recovers".to_string(), POLICY);
+        for _ in 0..3 {
+            fail(&breaker);
+        }
+
+        tokio::time::sleep(POLICY.cooldown).await;
+        let trial = breaker.try_acquire().expect("trial should be admitted");
+        assert!(
+            breaker.try_acquire().is_none(),
+            "only one trial is admitted"
+        );
+
+        // A failed trial opens the breaker again.
+        trial.record(false);
+        assert!(breaker.try_acquire().is_none());
+
+        // An aborted trial admits another one.
+        tokio::time::sleep(POLICY.cooldown).await;
+        drop(breaker.try_acquire().expect("trial should be admitted"));
+        breaker
+            .try_acquire()
+            .expect("trial should be admitted")
+            .record(true);
+
+        assert!(breaker.try_acquire().is_some());
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics.contains("backend_circuit_state{backend=\"breaker-recovers\"} 0"));
+        assert!(metrics.contains(
+            "backend_circuit_transitions_total{backend=\"breaker-recovers\",state=\"half_open\"} 2"
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn breaker_reports_its_status_and_can_be_reset() {
+        let breaker = CircuitBreaker::new("breaker-resets".to_string(), POLICY);
+        fail(&breaker);
+        let status = breaker.status();
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.consecutive_failures, 1);
+
+        fail(&breaker);
+        fail(&breaker);
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        let status = breaker.status();
+        assert_eq!(status.state, CircuitState::Open);
+        assert_eq!(status.failures_total, 3);
+        assert_eq!(status.cooldown_remaining, Some(Duration::from_secs(6)));
+
+        breaker.reset();
+        assert_eq!(breaker.status().state, CircuitState::Closed);
+        assert_eq!(breaker.status().failures_total, 3);
+        assert!(breaker.try_acquire().is_some());
+    }
+}
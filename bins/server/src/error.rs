@@ -0,0 +1,382 @@
+//! The catalog of problem details returned by the API.
+//!
+//! Each [`ProblemType`] has a stable `type` URI, title and status code, so that clients
+//! can rely on the machine-readable `type` field rather than matching on strings.
+
+use app_config::errors::ErrorsConfig;
+use axum::http::StatusCode;
+use file_distribution::GetFileReaderError;
+use problemdetails::Problem;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// The default prefix of the problem `type` URIs.
+pub const DEFAULT_TYPE_URI_PREFIX: &str = "urn:yeet-yoink:problem:";
+
+/// The types of problems reported by the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemType {
+    /// The requested file is not known.
+    FileNotFound,
+    /// The requested file has expired.
+    FileExpired,
+    /// The requested file could not be accessed.
+    FileAccessFailed,
+    /// The requested file is still being written.
+    FileIncomplete,
+    /// A file could not be created for an upload.
+    FileCreationFailed,
+    /// The data of an upload could not be written to its file.
+    FileWriteFailed,
+    /// The body of an upload could not be read.
+    UploadReadFailed,
+    /// The ID of a new file is already in use.
+    FileIdConflict,
+    /// The upload does not fit into the remaining storage headroom.
+    InsufficientStorage,
+    /// The size of the upload does not match its `Content-Length` header.
+    ContentLengthMismatch,
+    /// The `Content-Encoding` of the upload is not supported.
+    UnsupportedContentEncoding,
+    /// The upload could not be decoded as per its `Content-Encoding` header.
+    InvalidContentEncoding,
+    /// The `multipart/form-data` body of an upload is invalid or lacks a file.
+    InvalidFormData,
+    /// The backends cannot keep up with new files at the moment.
+    BackendsBusy,
+    /// The service is shutting down and no longer accepts uploads.
+    ShuttingDown,
+    /// The requested lease is invalid.
+    InvalidLease,
+    /// The requested hash algorithms are invalid.
+    InvalidHashSelection,
+    /// No receipt exists for the file.
+    ReceiptNotFound,
+    /// Receipts cannot be verified because no signing key is configured.
+    ReceiptSigningDisabled,
+    /// The signature of a receipt is missing or invalid.
+    InvalidReceiptSignature,
+    /// The receipt is not yet or no longer valid.
+    ReceiptNotValid,
+    /// The request carries no valid bearer token.
+    Unauthorized,
+    /// The upload quota of the bearer token is used up.
+    QuotaExceeded,
+    /// The request handler did not complete in time.
+    RequestTimeout,
+    /// The body of an upload was not received in time.
+    UploadTimeout,
+    /// The files selected for an archive are invalid.
+    InvalidFileSelection,
+    /// The requested wait mode of an upload is invalid.
+    InvalidWaitMode,
+    /// The backends did not confirm storing the file.
+    DistributionFailed,
+    /// The `Expect` header of the request asks for an unsupported expectation.
+    UnsupportedExpectation,
+    /// The backends did not provide a file in time.
+    BackendTimeout,
+    /// The requested dry run mode of an upload is invalid.
+    InvalidDryRun,
+    /// The resumable upload session is not known.
+    UploadSessionNotFound,
+    /// The `Content-Range` of a chunk of a resumable upload is invalid.
+    InvalidContentRange,
+    /// The content type of the upload is not allowed.
+    ContentTypeNotAllowed,
+    /// The metadata attached to the upload is invalid or too large.
+    InvalidMetadata,
+    /// The maximum number of concurrent uploads is reached.
+    TooManyUploads,
+    /// The expected hash of the upload is malformed.
+    InvalidExpectedHash,
+    /// The content of the upload does not match its expected hash.
+    HashMismatch,
+    /// No backend with the requested tag is registered.
+    BackendNotFound,
+    /// Circuit breakers are disabled, hence cannot be reset.
+    CircuitBreakersDisabled,
+    /// The file ID in the request path is malformed.
+    InvalidFileId,
+}
+
+impl ProblemType {
+    /// Gets the stable identifier of the problem type.
+    pub const fn slug(&self) -> &'static str {
+        match self {
+            ProblemType::FileNotFound => "file-not-found",
+            ProblemType::FileExpired => "file-expired",
+            ProblemType::FileAccessFailed => "file-access-failed",
+            ProblemType::FileIncomplete => "file-incomplete",
+            ProblemType::FileCreationFailed => "file-creation-failed",
+            ProblemType::FileWriteFailed => "file-write-failed",
+            ProblemType::UploadReadFailed => "upload-read-failed",
+            ProblemType::FileIdConflict => "file-id-conflict",
+            ProblemType::InsufficientStorage => "insufficient-storage",
+            ProblemType::ContentLengthMismatch => "content-length-mismatch",
+            ProblemType::UnsupportedContentEncoding => "unsupported-content-encoding",
+            ProblemType::InvalidContentEncoding => "invalid-content-encoding",
+            ProblemType::InvalidFormData => "invalid-form-data",
+            ProblemType::BackendsBusy => "backends-busy",
+            ProblemType::ShuttingDown => "shutting-down",
+            ProblemType::InvalidLease => "invalid-lease",
+            ProblemType::InvalidHashSelection => "invalid-hash-selection",
+            ProblemType::ReceiptNotFound => "receipt-not-found",
+            ProblemType::ReceiptSigningDisabled => "receipt-signing-disabled",
+            ProblemType::InvalidReceiptSignature => "invalid-receipt-signature",
+            ProblemType::ReceiptNotValid => "receipt-not-valid",
+            ProblemType::Unauthorized => "unauthorized",
+            ProblemType::QuotaExceeded => "quota-exceeded",
+            ProblemType::RequestTimeout => "request-timeout",
+            ProblemType::UploadTimeout => "upload-timeout",
+            ProblemType::InvalidFileSelection => "invalid-file-selection",
+            ProblemType::InvalidWaitMode => "invalid-wait-mode",
+            ProblemType::DistributionFailed => "distribution-failed",
+            ProblemType::UnsupportedExpectation => "unsupported-expectation",
+            ProblemType::BackendTimeout => "backend-timeout",
+            ProblemType::InvalidDryRun => "invalid-dry-run",
+            ProblemType::UploadSessionNotFound => "upload-session-not-found",
+            ProblemType::InvalidContentRange => "invalid-content-range",
+            ProblemType::ContentTypeNotAllowed => "content-type-not-allowed",
+            ProblemType::InvalidMetadata => "invalid-metadata",
+            ProblemType::TooManyUploads => "too-many-uploads",
+            ProblemType::InvalidExpectedHash => "invalid-expected-hash",
+            ProblemType::HashMismatch => "hash-mismatch",
+            ProblemType::BackendNotFound => "backend-not-found",
+            ProblemType::CircuitBreakersDisabled => "circuit-breakers-disabled",
+            ProblemType::InvalidFileId => "invalid-file-id",
+        }
+    }
+
+    /// Gets the title of the problem type.
+    pub const fn title(&self) -> &'static str {
+        match self {
+            ProblemType::FileNotFound => "File not found",
+            ProblemType::FileExpired => "File expired",
+            ProblemType::FileAccessFailed => "Unable to access file",
+            ProblemType::FileIncomplete => "File incomplete",
+            ProblemType::FileCreationFailed => "Unable to create file",
+            ProblemType::FileWriteFailed => "Unable to write file",
+            ProblemType::UploadReadFailed => "Unable to read upload",
+            ProblemType::FileIdConflict => "File ID already in use",
+            ProblemType::InsufficientStorage => "Insufficient storage",
+            ProblemType::ContentLengthMismatch => "Content length mismatch",
+            ProblemType::UnsupportedContentEncoding => "Unsupported content encoding",
+            ProblemType::InvalidContentEncoding => "Invalid content encoding",
+            ProblemType::InvalidFormData => "Invalid form data",
+            ProblemType::BackendsBusy => "Backends busy",
+            ProblemType::ShuttingDown => "Shutting down",
+            ProblemType::InvalidLease => "Invalid lease",
+            ProblemType::InvalidHashSelection => "Invalid hash selection",
+            ProblemType::ReceiptNotFound => "Receipt not found",
+            ProblemType::ReceiptSigningDisabled => "Receipt signing is disabled",
+            ProblemType::InvalidReceiptSignature => "Invalid receipt signature",
+            ProblemType::ReceiptNotValid => "Receipt outside of its validity period",
+            ProblemType::Unauthorized => "Unauthorized",
+            ProblemType::QuotaExceeded => "Upload quota exceeded",
+            ProblemType::RequestTimeout => "Request timed out",
+            ProblemType::UploadTimeout => "Upload timed out",
+            ProblemType::InvalidFileSelection => "Invalid file selection",
+            ProblemType::InvalidWaitMode => "Invalid wait mode",
+            ProblemType::DistributionFailed => "Distribution failed",
+            ProblemType::UnsupportedExpectation => "Unsupported expectation",
+            ProblemType::BackendTimeout => "Backend timed out",
+            ProblemType::InvalidDryRun => "Invalid dry run",
+            ProblemType::UploadSessionNotFound => "Upload session not found",
+            ProblemType::InvalidContentRange => "Invalid content range",
+            ProblemType::ContentTypeNotAllowed => "Content type not allowed",
+            ProblemType::InvalidMetadata => "Invalid metadata",
+            ProblemType::TooManyUploads => "Too many uploads",
+            ProblemType::InvalidExpectedHash => "Invalid expected hash",
+            ProblemType::HashMismatch => "Hash mismatch",
+            ProblemType::BackendNotFound => "Backend not found",
+            ProblemType::CircuitBreakersDisabled => "Circuit breakers are disabled",
+            ProblemType::InvalidFileId => "Invalid file ID",
+        }
+    }
+
+    /// Gets the HTTP status code of the problem type.
+    pub const fn status(&self) -> StatusCode {
+        match self {
+            ProblemType::FileNotFound
+            | ProblemType::ReceiptNotFound
+            | ProblemType::UploadSessionNotFound
+            | ProblemType::BackendNotFound => StatusCode::NOT_FOUND,
+            ProblemType::FileExpired => StatusCode::GONE,
+            ProblemType::FileIncomplete | ProblemType::FileIdConflict => StatusCode::CONFLICT,
+            ProblemType::FileAccessFailed
+            | ProblemType::FileCreationFailed
+            | ProblemType::FileWriteFailed
+            | ProblemType::UploadReadFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            ProblemType::InvalidLease
+            | ProblemType::ContentLengthMismatch
+            | ProblemType::InvalidContentEncoding
+            | ProblemType::InvalidFormData
+            | ProblemType::InvalidHashSelection
+            | ProblemType::InvalidReceiptSignature
+            | ProblemType::ReceiptNotValid
+            | ProblemType::InvalidFileSelection
+            | ProblemType::InvalidWaitMode
+            | ProblemType::InvalidDryRun
+            | ProblemType::InvalidContentRange
+            | ProblemType::InvalidMetadata
+            | ProblemType::InvalidExpectedHash
+            | ProblemType::InvalidFileId => StatusCode::BAD_REQUEST,
+            ProblemType::HashMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+            ProblemType::InsufficientStorage => StatusCode::INSUFFICIENT_STORAGE,
+            ProblemType::UnsupportedContentEncoding | ProblemType::ContentTypeNotAllowed => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+            ProblemType::BackendsBusy | ProblemType::ShuttingDown | ProblemType::TooManyUploads => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            ProblemType::ReceiptSigningDisabled | ProblemType::CircuitBreakersDisabled => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            ProblemType::Unauthorized => StatusCode::UNAUTHORIZED,
+            ProblemType::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+            ProblemType::RequestTimeout | ProblemType::BackendTimeout => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            ProblemType::UploadTimeout => StatusCode::REQUEST_TIMEOUT,
+            ProblemType::DistributionFailed => StatusCode::BAD_GATEWAY,
+            ProblemType::UnsupportedExpectation => StatusCode::EXPECTATION_FAILED,
+        }
+    }
+
+    /// Gets the `type` URI of the problem type, using the prefix configured in `config`.
+    pub fn type_uri(&self, config: &ErrorsConfig) -> String {
+        let prefix = config
+            .type_uri_prefix
+            .as_deref()
+            .unwrap_or(DEFAULT_TYPE_URI_PREFIX);
+        format!("{prefix}{slug}", slug = self.slug())
+    }
+
+    /// Creates a problem of this type, to be completed with details.
+    pub fn problem(&self, config: &ErrorsConfig) -> Problem {
+        problemdetails::new(self.status())
+            .with_type(self.type_uri(config))
+            .with_title(self.title())
+    }
+}
+
+/// The shape of the problem details (RFC 7807) returned for errors, as documented in the
+/// OpenAPI document; responses are rendered from [`Problem`].
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// The URI identifying the [`ProblemType`], e.g. `urn:yeet-yoink:problem:file-not-found`.
+    #[serde(rename = "type")]
+    problem_type: String,
+    /// The title of the problem type.
+    title: String,
+    /// The HTTP status code.
+    status: u16,
+    /// A human-readable explanation of this occurrence of the problem.
+    detail: Option<String>,
+    /// The path of the resource the problem occurred on.
+    instance: Option<String>,
+}
+
+impl From<&GetFileReaderError> for ProblemType {
+    fn from(value: &GetFileReaderError) -> Self {
+        match value {
+            GetFileReaderError::UnknownFile(_) => ProblemType::FileNotFound,
+            GetFileReaderError::FileExpired(_) => ProblemType::FileExpired,
+            GetFileReaderError::FileError(_, _) => ProblemType::FileAccessFailed,
+            GetFileReaderError::BackendTimeout(_) => ProblemType::BackendTimeout,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+    use std::collections::HashSet;
+
+    const ALL: [ProblemType; 41] = [
+        ProblemType::FileNotFound,
+        ProblemType::FileExpired,
+        ProblemType::FileAccessFailed,
+        ProblemType::FileIncomplete,
+        ProblemType::FileCreationFailed,
+        ProblemType::FileWriteFailed,
+        ProblemType::UploadReadFailed,
+        ProblemType::FileIdConflict,
+        ProblemType::InsufficientStorage,
+        ProblemType::ContentLengthMismatch,
+        ProblemType::UnsupportedContentEncoding,
+        ProblemType::InvalidContentEncoding,
+        ProblemType::InvalidFormData,
+        ProblemType::BackendsBusy,
+        ProblemType::ShuttingDown,
+        ProblemType::InvalidLease,
+        ProblemType::InvalidHashSelection,
+        ProblemType::ReceiptNotFound,
+        ProblemType::ReceiptSigningDisabled,
+        ProblemType::InvalidReceiptSignature,
+        ProblemType::ReceiptNotValid,
+        ProblemType::Unauthorized,
+        ProblemType::QuotaExceeded,
+        ProblemType::RequestTimeout,
+        ProblemType::UploadTimeout,
+        ProblemType::InvalidFileSelection,
+        ProblemType::InvalidWaitMode,
+        ProblemType::DistributionFailed,
+        ProblemType::UnsupportedExpectation,
+        ProblemType::BackendTimeout,
+        ProblemType::InvalidDryRun,
+        ProblemType::UploadSessionNotFound,
+        ProblemType::InvalidContentRange,
+        ProblemType::ContentTypeNotAllowed,
+        ProblemType::InvalidMetadata,
+        ProblemType::TooManyUploads,
+        ProblemType::InvalidExpectedHash,
+        ProblemType::HashMismatch,
+        ProblemType::BackendNotFound,
+        ProblemType::CircuitBreakersDisabled,
+        ProblemType::InvalidFileId,
+    ];
+
+    #[tokio::test]
+    async fn problems_render_with_stable_type_and_status() {
+        for problem_type in ALL {
+            let response = problem_type
+                .problem(&ErrorsConfig::default())
+                .with_detail("test")
+                .into_response();
+            assert_eq!(response.status(), problem_type.status());
+
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(
+                body["type"],
+                format!("urn:yeet-yoink:problem:{slug}", slug = problem_type.slug())
+            );
+            assert_eq!(body["title"], problem_type.title());
+        }
+    }
+
+    #[test]
+    fn type_uris_use_the_configured_prefix() {
+        let config = ErrorsConfig {
+            type_uri_prefix: Some("https://example.com/problems/".to_string()),
+        };
+        assert_eq!(
+            ProblemType::FileNotFound.type_uri(&config),
+            "https://example.com/problems/file-not-found"
+        );
+        assert_eq!(
+            ProblemType::FileNotFound.type_uri(&ErrorsConfig::default()),
+            "urn:yeet-yoink:problem:file-not-found"
+        );
+    }
+
+    #[test]
+    fn problem_types_are_unique() {
+        let slugs: HashSet<_> = ALL.iter().map(ProblemType::slug).collect();
+        assert_eq!(slugs.len(), ALL.len());
+    }
+}
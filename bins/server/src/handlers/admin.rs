@@ -0,0 +1,303 @@
+//! Contains the `/admin` endpoint filters.
+
+use crate::circuit_breaker::CircuitStatus;
+use crate::error::ProblemType;
+use crate::handlers::public_path;
+use crate::AppState;
+use axum::body::HttpBody;
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use backend_traits::{BackendHealth, BackendPriority};
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::info;
+
+pub trait AdminRoutes {
+    /// Provides an API for inspecting the backends and resetting their circuit breakers.
+    ///
+    /// ```http
+    /// GET /admin/backends HTTP/1.1
+    /// POST /admin/backends/s3-1/reset HTTP/1.1
+    /// ```
+    fn map_admin_endpoints(self) -> Self;
+}
+
+impl<B> AdminRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_admin_endpoints(self) -> Self {
+        self.route("/admin/backends", get(do_list_backends))
+            .route("/admin/backends/:tag/reset", post(do_reset_circuit))
+    }
+}
+
+/// Lists the registered backends along with their health and circuit breaker state.
+///
+/// The backends are health checked on every request.
+///
+/// ```http
+/// GET /admin/backends
+/// ```
+async fn do_list_backends(State(state): State<AppState>) -> Json<BackendsResponse> {
+    let timeout = state.config.timeouts.backend_health_check_timeout();
+    let health: Option<HashMap<String, BackendHealth>> =
+        state.backbone.check_backends(timeout).await.map(|checks| {
+            checks
+                .into_iter()
+                .map(|check| (check.tag.clone(), check))
+                .collect()
+        });
+
+    let backends = state
+        .backends
+        .backends()
+        .iter()
+        .map(|backend| {
+            let health = health.as_ref().and_then(|health| health.get(&backend.tag));
+            BackendStatusResponse {
+                tag: backend.tag.clone(),
+                backend: backend.name,
+                version: backend.version,
+                priority: backend.priority,
+                healthy: health.map(BackendHealth::is_healthy),
+                health_error: health
+                    .and_then(|health| health.error.as_ref())
+                    .map(ToString::to_string),
+                circuit: state
+                    .backends
+                    .breaker(&backend.tag)
+                    .map(|breaker| breaker.status().into()),
+            }
+        })
+        .collect();
+
+    Json(BackendsResponse { backends })
+}
+
+/// Closes the circuit breaker of a backend, such that distributions to it are
+/// attempted again right away.
+///
+/// ```http
+/// POST /admin/backends/:tag/reset
+/// ```
+async fn do_reset_circuit(Path(tag): Path<String>, State(state): State<AppState>) -> Response {
+    let instance = public_path(
+        state.config.base_path(),
+        format!("/admin/backends/{tag}/reset"),
+    );
+    if !state.backends.contains(&tag) {
+        return ProblemType::BackendNotFound
+            .problem(&state.config.errors)
+            .with_detail(format!("No backend with the tag {tag} is registered"))
+            .with_instance(instance)
+            .with_value("tag", tag)
+            .into_response();
+    }
+
+    let Some(breaker) = state.backends.breaker(&tag) else {
+        return ProblemType::CircuitBreakersDisabled
+            .problem(&state.config.errors)
+            .with_detail("Circuit breakers are disabled by distribution.circuit_breaker")
+            .with_instance(instance)
+            .with_value("tag", tag)
+            .into_response();
+    };
+
+    info!("Resetting the circuit breaker of backend {tag} from API call");
+    breaker.reset();
+    let circuit = breaker.status().into();
+    Json(CircuitResetResponse { tag, circuit }).into_response()
+}
+
+#[derive(Serialize)]
+struct BackendsResponse {
+    /// The backends, in the order they are served in.
+    backends: Vec<BackendStatusResponse>,
+}
+
+#[derive(Serialize)]
+struct BackendStatusResponse {
+    /// The tag of the backend.
+    tag: String,
+    /// The name of the backend implementation, e.g. `S3`.
+    backend: Option<&'static str>,
+    /// The version of the backend implementation.
+    version: Option<&'static str>,
+    /// The priority of the backend; lower values are served first.
+    priority: BackendPriority,
+    /// Whether the backend passed its health check; `None` if the backends could not be reached.
+    healthy: Option<bool>,
+    /// The reason the backend failed its health check.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health_error: Option<String>,
+    /// The state of the circuit breaker; `None` if circuit breakers are disabled.
+    circuit: Option<CircuitResponse>,
+}
+
+#[derive(Serialize)]
+struct CircuitResetResponse {
+    /// The tag of the backend.
+    tag: String,
+    /// The state of the circuit breaker after the reset.
+    circuit: CircuitResponse,
+}
+
+#[derive(Serialize)]
+struct CircuitResponse {
+    /// The state of the breaker: `closed`, `open` or `half_open`.
+    state: String,
+    /// The number of consecutive failed distribution attempts while the breaker is closed.
+    consecutive_failures: u32,
+    /// The number of failed distribution attempts since the service started.
+    failures_total: u64,
+    /// The number of seconds distributions are still skipped while the breaker is open.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cooldown_remaining_sec: Option<u64>,
+}
+
+impl From<CircuitStatus> for CircuitResponse {
+    fn from(value: CircuitStatus) -> Self {
+        Self {
+            state: value.state.to_string(),
+            consecutive_failures: value.consecutive_failures,
+            failures_total: value.failures_total,
+            cooldown_remaining_sec: value
+                .cooldown_remaining
+                .map(|remaining| remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::await_rendezvous;
+    use crate::backend_directory::BackendDirectory;
+    use crate::circuit_breaker::{CircuitBreakerPolicy, CircuitBreakers};
+    use app_config::AppConfig;
+    use axum::async_trait;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use backend_traits::{
+        Backend, BackendCommand, BackendInfo, DistributeFile, DistributionError, HealthCheckError,
+        StoredDigest,
+    };
+    use file_distribution::{FileProvider, WriteSummary};
+    use hyper::Request;
+    use shortguid::ShortGuid;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    struct StubBackend(&'static str);
+
+    #[async_trait]
+    impl DistributeFile for StubBackend {
+        fn tag(&self) -> &str {
+            self.0
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            Ok(None)
+        }
+    }
+
+    impl BackendInfo for StubBackend {
+        fn backend_name() -> &'static str {
+            "Stub"
+        }
+
+        fn backend_version() -> &'static str {
+            "1.2.3"
+        }
+    }
+
+    async fn json(app: &Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn backends_are_listed_and_circuits_reset() {
+        let backends = [
+            Backend::wrap(StubBackend("admin-stub"))
+                .with_info::<StubBackend>()
+                .with_priority(2),
+            Backend::wrap(StubBackend("admin-failing")),
+        ];
+        let policy = CircuitBreakerPolicy {
+            failure_threshold: 1,
+            cooldown: Duration::from_secs(60),
+        };
+        let breakers = Arc::new(CircuitBreakers::new(&backends, policy));
+        let failing = breakers.get("admin-failing").unwrap();
+        failing.try_acquire().unwrap().record(false);
+
+        let (mut state, mut backend_receiver, rendezvous) =
+            AppState::for_tests(AppConfig::default());
+        state.backends = Arc::new(BackendDirectory::new(&backends, breakers));
+
+        // A stub registry answering health checks only.
+        tokio::spawn(async move {
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::CheckHealth(timeout, reply) = command {
+                    let health = |tag: &str, error| BackendHealth {
+                        tag: tag.to_string(),
+                        error,
+                    };
+                    let checks = vec![
+                        health("admin-stub", None),
+                        health("admin-failing", Some(HealthCheckError::TimedOut(timeout))),
+                    ];
+                    reply.send(checks).ok();
+                }
+            }
+        });
+
+        let app = Router::new().map_admin_endpoints().with_state(state);
+
+        let request = Request::get("/admin/backends").body(Body::empty()).unwrap();
+        let (status, body) = json(&app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        let stub = &body["backends"][0];
+        assert_eq!(stub["tag"], "admin-stub");
+        assert_eq!(stub["backend"], "Stub");
+        assert_eq!(stub["version"], "1.2.3");
+        assert_eq!(stub["priority"], 2);
+        assert_eq!(stub["healthy"], true);
+        assert_eq!(stub["circuit"]["state"], "closed");
+        let failing = &body["backends"][1];
+        assert_eq!(failing["healthy"], false);
+        assert!(failing["health_error"].is_string());
+        assert_eq!(failing["circuit"]["state"], "open");
+        assert_eq!(failing["circuit"]["failures_total"], 1);
+        assert_eq!(failing["circuit"]["cooldown_remaining_sec"], 60);
+
+        let reset = |tag: &str| {
+            Request::post(format!("/admin/backends/{tag}/reset"))
+                .body(Body::empty())
+                .unwrap()
+        };
+        let (status, body) = json(&app, reset("admin-failing")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["circuit"]["state"], "closed");
+
+        let (status, body) = json(&app, reset("unknown")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["type"], "urn:yeet-yoink:problem:backend-not-found");
+
+        drop(app);
+        await_rendezvous(rendezvous).await;
+    }
+}
@@ -0,0 +1,475 @@
+//! Contains the `/yoink?ids=...` endpoint filter.
+
+use crate::error::ProblemType;
+use crate::handlers::public_path;
+use crate::services::record_file_id;
+use crate::AppState;
+use app_config::AppConfig;
+use axum::body::{HttpBody, StreamBody};
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use backbone::Backbone;
+use file_distribution::{BoxedFileReader, FileReaderTrait};
+use futures::{stream, StreamExt};
+use metrics::transfer::{TransferMethod, TransferMetrics};
+use serde::{Deserialize, Serialize};
+use shared_files::FileSize;
+use shortguid::ShortGuid;
+use std::io::ErrorKind;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, warn};
+
+/// The maximum number of files per archive.
+const MAX_ARCHIVE_FILES: usize = 100;
+
+/// The number of bytes buffered between writing the archive and sending the response.
+const ARCHIVE_BUFFER_BYTES: usize = 64 * 1024;
+
+/// The size of tar headers and the granularity of entries.
+const BLOCK_SIZE: usize = 512;
+
+/// The maximum length of an entry name in a tar header.
+const MAX_NAME_LEN: usize = 100;
+
+/// The name of the trailing entry listing the archived and skipped files.
+const MANIFEST_ENTRY: &str = "manifest.json";
+
+pub trait ArchiveRoutes {
+    /// Provides an API for retrieving several files as a single `tar` archive.
+    ///
+    /// ```http
+    /// GET /yoink?ids=KmC6e8laTnK3dioUSMpM0Q,hT8sYm3uQ2e5lvMDRqQqzw HTTP/1.1
+    /// ```
+    ///
+    /// Each file is stored in an entry named by its ID, followed by its file name if known.
+    /// Files that are unknown, expired or still being written are skipped; the trailing
+    /// `manifest.json` entry lists the archived files and the reasons files were skipped.
+    fn map_archive_endpoint(self) -> Self;
+}
+
+impl<B> ArchiveRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_archive_endpoint(self) -> Self {
+        self.route("/yoink", get(do_yoink_archive))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryParams {
+    /// The comma-separated IDs of the files to archive.
+    ids: String,
+}
+
+/// The trailing `manifest.json` entry of an archive.
+#[derive(Debug, Default, Serialize)]
+struct ArchiveManifest {
+    /// The files stored in the archive, in order.
+    files: Vec<ArchivedFile>,
+    /// The files that were requested but not stored in the archive.
+    skipped: Vec<SkippedFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct ArchivedFile {
+    /// The ID of the file.
+    id: ShortGuid,
+    /// The name of the archive entry holding the file.
+    entry: String,
+    /// The file size in bytes.
+    file_size_bytes: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct SkippedFile {
+    /// The ID of the file.
+    id: ShortGuid,
+    /// The slug of the problem type describing why the file was skipped, e.g. `file-not-found`.
+    reason: &'static str,
+}
+
+/// Streams the requested files as a `tar` archive.
+///
+/// Files are read one after another while the archive is sent, so that only a bounded
+/// part of the archive is held in memory. The archive is written while the response body
+/// is polled, so that it is abandoned along with the connection.
+#[utoipa::path(
+    get,
+    path = "/yoink",
+    tag = "files",
+    params(("ids" = String, Query, description = "The comma-separated IDs of the files")),
+    responses(
+        (status = 200, description = "A tar archive of the files, ending with a manifest.json entry", body = Vec<u8>, content_type = "application/x-tar"),
+        (status = 400, description = "The file selection is invalid", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
+#[axum::debug_handler]
+async fn do_yoink_archive(
+    Query(query): Query<QueryParams>,
+    State(state): State<AppState>,
+) -> Response {
+    let ids = match parse_ids(&query.ids) {
+        Ok(ids) => ids,
+        Err(detail) => return map_invalid_file_selection_to_response(detail, &state.config),
+    };
+
+    TransferMetrics::track_transfer(TransferMethod::Fetch);
+
+    let (writer, reader) = tokio::io::duplex(ARCHIVE_BUFFER_BYTES);
+    let read_guard = state.shutdown.track_read();
+    let backbone = state.backbone.clone();
+    let write = async move {
+        // Keeps the download registered for the graceful shutdown until the archive is written.
+        let _read_guard = read_guard;
+        if let Err(e) = write_archive(&backbone, &ids, writer).await {
+            warn!(
+                "Failed to write the archive of {count} files: {e}",
+                count = ids.len()
+            );
+        }
+    };
+
+    // The writer yields no data of its own; it fills the buffer drained by the reader.
+    let write = stream::once(write).filter_map(|()| async { None });
+    let body = stream::select(ReaderStream::new(reader), write);
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/x-tar"),
+        (
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"yoink.tar\"",
+        ),
+    ];
+    (headers, StreamBody::new(body)).into_response()
+}
+
+/// Parses the comma-separated file IDs, dropping duplicates.
+fn parse_ids(value: &str) -> Result<Vec<ShortGuid>, String> {
+    let mut ids = Vec::new();
+    for id in value.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let id: ShortGuid = id
+            .parse()
+            .map_err(|_| format!("The file ID {id:?} is invalid"))?;
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    if ids.is_empty() {
+        return Err("No file IDs were specified".to_string());
+    }
+    if ids.len() > MAX_ARCHIVE_FILES {
+        return Err(format!(
+            "At most {MAX_ARCHIVE_FILES} files can be archived, but {count} were requested",
+            count = ids.len()
+        ));
+    }
+    Ok(ids)
+}
+
+/// Writes the files as a `tar` archive, followed by the manifest.
+async fn write_archive<W>(backbone: &Backbone, ids: &[ShortGuid], mut out: W) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut manifest = ArchiveManifest::default();
+    for &id in ids {
+        record_file_id(id);
+        let file = match backbone.get_file(id).await {
+            Ok(file) => file,
+            Err(e) => {
+                debug!(file_id = %id, "Skipping file {id} in archive: {e}");
+                let reason = ProblemType::from(&e).slug();
+                manifest.skipped.push(SkippedFile { id, reason });
+                continue;
+            }
+        };
+
+        // The size is part of the entry header and thus needs to be known in advance.
+        let FileSize::Exactly(size) = file.file_size() else {
+            debug!(file_id = %id, "Skipping incomplete file {id} in archive");
+            let reason = ProblemType::FileIncomplete.slug();
+            manifest.skipped.push(SkippedFile { id, reason });
+            continue;
+        };
+
+        let entry = entry_name(id, &file);
+        out.write_all(&entry_header(
+            &entry,
+            size as u64,
+            unix_time(file.creation_date()),
+        ))
+        .await?;
+
+        let copied = tokio::io::copy(&mut file.take(size as u64), &mut out).await?;
+        if copied != size as u64 {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("File {id} ended after {copied} of {size} bytes"),
+            ));
+        }
+        out.write_all(&padding(size)).await?;
+
+        manifest.files.push(ArchivedFile {
+            id,
+            entry,
+            file_size_bytes: size,
+        });
+    }
+
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+    let header = entry_header(
+        MANIFEST_ENTRY,
+        manifest.len() as u64,
+        unix_time(SystemTime::now()),
+    );
+    out.write_all(&header).await?;
+    out.write_all(&manifest).await?;
+    out.write_all(&padding(manifest.len())).await?;
+
+    // An archive ends with two empty blocks.
+    out.write_all(&[0; 2 * BLOCK_SIZE]).await?;
+    out.shutdown().await
+}
+
+/// Names the entry of a file by its ID and, if it fits the header, its file name.
+fn entry_name(id: ShortGuid, file: &BoxedFileReader) -> String {
+    let file_name = file
+        .summary()
+        .as_ref()
+        .and_then(|summary| summary.file_name.as_deref());
+    match file_name {
+        Some(name) if id.to_string().len() + 1 + name.len() <= MAX_NAME_LEN => {
+            format!("{id}-{name}")
+        }
+        _ => id.to_string(),
+    }
+}
+
+/// Gets the number of seconds since the Unix epoch.
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Encodes the `ustar` header of a regular file entry.
+fn entry_header(name: &str, size: u64, modified: u64) -> [u8; BLOCK_SIZE] {
+    let mut header = [0; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], 0o644);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_size(&mut header[124..136], size);
+    write_octal(&mut header[136..148], modified);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // The checksum is calculated with the checksum field set to spaces.
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|&byte| u64::from(byte)).sum();
+    write_octal(&mut header[148..155], checksum);
+    header
+}
+
+/// Writes a zero-padded, NUL-terminated octal number into a header field.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let octal = format!("{value:0digits$o}");
+    field[..digits].copy_from_slice(&octal.as_bytes()[octal.len() - digits..]);
+    field[digits] = 0;
+}
+
+/// Writes the size of an entry, using the base-256 encoding for sizes
+/// exceeding the octal field (8 GiB).
+fn write_size(field: &mut [u8], size: u64) {
+    if size < 1 << 33 {
+        return write_octal(field, size);
+    }
+
+    field.fill(0);
+    field[0] = 0x80;
+    let start = field.len() - 8;
+    field[start..].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Gets the zeros padding an entry of the specified size to a full block.
+fn padding(size: usize) -> Vec<u8> {
+    vec![0; (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE]
+}
+
+fn map_invalid_file_selection_to_response(detail: String, config: &AppConfig) -> Response {
+    ProblemType::InvalidFileSelection
+        .problem(&config.errors)
+        .with_detail(detail)
+        .with_instance(public_path(config.base_path(), "/yoink"))
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::await_rendezvous;
+    use crate::handlers::YeetRoutes;
+    use app_config::AppConfig;
+    use axum::body::Body;
+    use backend_traits::BackendCommand;
+    use hyper::{Request, StatusCode};
+    use std::io::Read;
+    use tower::ServiceExt;
+
+    #[test]
+    fn ids_are_parsed_and_deduplicated() {
+        let (first, second) = (ShortGuid::new_random(), ShortGuid::new_random());
+        assert_eq!(
+            parse_ids(&format!("{first}, {second},{first},")),
+            Ok(vec![first, second])
+        );
+        assert!(parse_ids("").is_err());
+        assert!(parse_ids("not-an-id").is_err());
+
+        let repeated = vec![ShortGuid::new_random().to_string(); MAX_ARCHIVE_FILES + 1];
+        assert!(
+            parse_ids(&repeated.join(",")).is_ok(),
+            "duplicates are dropped"
+        );
+        let too_many: Vec<_> = (0..=MAX_ARCHIVE_FILES)
+            .map(|_| ShortGuid::new_random().to_string())
+            .collect();
+        assert!(parse_ids(&too_many.join(",")).is_err());
+    }
+
+    #[test]
+    fn large_sizes_use_base_256() {
+        let mut field = [0; 12];
+        write_size(&mut field, 1024);
+        assert_eq!(&field, b"00000002000\0");
+
+        write_size(&mut field, 1 << 34);
+        assert_eq!(field, [0x80, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn archives_contain_the_files_and_a_manifest() {
+        // A stub backend that does not store any files.
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        tokio::spawn(async move {
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::ReceiveFile(_, reply) = command {
+                    reply.send(Ok(None)).ok();
+                }
+            }
+        });
+
+        let app = Router::new()
+            .map_yeet_endpoint()
+            .map_archive_endpoint()
+            .with_state(state.clone());
+
+        let (named, unnamed, missing) = (
+            ShortGuid::new_random(),
+            ShortGuid::new_random(),
+            ShortGuid::new_random(),
+        );
+        let upload = Request::put(format!("/yeet/{named}?file_name=cat.txt"))
+            .body(Body::from("meow"))
+            .unwrap();
+        let response = app.clone().oneshot(upload).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let upload = Request::put(format!("/yeet/{unnamed}"))
+            .body(Body::from(vec![b'x'; 1000]))
+            .unwrap();
+        let response = app.clone().oneshot(upload).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let request = Request::get(format!("/yoink?ids={named},{missing},{unnamed}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-tar"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        let mut entries = Vec::new();
+        let mut archive = tar::Archive::new(body.as_ref());
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().to_string();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            entries.push((name, contents));
+        }
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], (format!("{named}-cat.txt"), b"meow".to_vec()));
+        assert_eq!(entries[1], (unnamed.to_string(), vec![b'x'; 1000]));
+        assert_eq!(entries[2].0, MANIFEST_ENTRY);
+
+        let manifest: serde_json::Value = serde_json::from_slice(&entries[2].1).unwrap();
+        assert_eq!(manifest["files"][0]["id"], named.to_string());
+        assert_eq!(manifest["files"][1]["file_size_bytes"], 1000);
+        assert_eq!(manifest["skipped"][0]["id"], missing.to_string());
+        assert_eq!(manifest["skipped"][0]["reason"], "file-not-found");
+
+        let request = Request::get("/yoink?ids=not-an-id")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        for id in [named, unnamed] {
+            state.backbone.remove_file(id).await.unwrap();
+        }
+        drop(state);
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn abandoned_archives_release_the_backbone() {
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        // A stub backend that never answers whether it stores a file.
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        tokio::spawn(async move {
+            let mut pending = Vec::new();
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::ReceiveFile(_, reply) = command {
+                    pending.push(reply);
+                }
+            }
+        });
+
+        let references = Arc::strong_count(&state.backbone);
+        let app = Router::new()
+            .map_archive_endpoint()
+            .with_state(state.clone());
+
+        let request = Request::get(format!("/yoink?ids={}", ShortGuid::new_random()))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // The client disconnects while the file is requested from the backends.
+        let mut body = response.into_body();
+        let chunk = tokio::time::timeout(Duration::from_millis(100), body.data()).await;
+        assert!(chunk.is_err(), "the archive was written");
+        drop(body);
+
+        assert_eq!(Arc::strong_count(&state.backbone), references);
+
+        drop(state);
+        await_rendezvous(rendezvous).await;
+    }
+}
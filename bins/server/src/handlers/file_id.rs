@@ -0,0 +1,81 @@
+//! Contains the file ID path extractor.
+
+use crate::error::ProblemType;
+use crate::handlers::public_path;
+use app_config::AppConfig;
+use axum::async_trait;
+use axum::extract::rejection::PathRejection;
+use axum::extract::{FromRef, FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use shortguid::ShortGuid;
+use std::sync::Arc;
+
+/// Extracts the `:id` path parameter of a file.
+///
+/// Unlike [`Path<ShortGuid>`], malformed IDs are rejected with an `invalid-file-id`
+/// problem rather than a plain text response.
+#[derive(Debug, Clone, Copy)]
+pub struct FileId(pub ShortGuid);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for FileId
+where
+    S: Send + Sync,
+    Arc<AppConfig>: FromRef<S>,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Arc::<AppConfig>::from_ref(state);
+        match Path::<ShortGuid>::from_request_parts(parts, state).await {
+            Ok(Path(id)) => Ok(Self(id)),
+            Err(PathRejection::FailedToDeserializePathParams(e)) => Err(ProblemType::InvalidFileId
+                .problem(&config.errors)
+                .with_detail(format!(
+                    "File IDs must be 22 character URL-safe Base64 strings or UUIDs: {e}",
+                    e = e.body_text()
+                ))
+                .with_instance(public_path(config.base_path(), parts.uri.path()))
+                .into_response()),
+            Err(e) => Err(e.into_response()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn malformed_ids_are_rejected_with_problem_details() {
+        let app = Router::new()
+            .route(
+                "/yoink/:id",
+                get(|FileId(id): FileId| async move { id.to_string() }),
+            )
+            .with_state(Arc::new(AppConfig::default()));
+
+        let id = ShortGuid::new_random();
+        let request = Request::get(format!("/yoink/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let request = Request::get("/yoink/not-an-id")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "urn:yeet-yoink:problem:invalid-file-id");
+        assert_eq!(body["instance"], "/yoink/not-an-id");
+    }
+}
@@ -0,0 +1,167 @@
+//! Contains the `/files` endpoint filter.
+
+use crate::handlers::instant_as_datetime;
+use crate::AppState;
+use axum::body::HttpBody;
+use axum::extract::{Query, State};
+use axum::http::HeaderName;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use backbone::LiveFile;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shortguid::ShortGuid;
+
+/// Response header carrying the number of live files before pagination.
+static TOTAL_COUNT_HEADER: HeaderName = HeaderName::from_static("x-total-count");
+
+/// The number of files listed if no `limit` is specified.
+const DEFAULT_LIMIT: usize = 100;
+
+/// The maximum number of files listed per request.
+const MAX_LIMIT: usize = 1000;
+
+pub trait FilesRoutes {
+    /// Provides an API for listing the locally buffered files, oldest first.
+    ///
+    /// ```http
+    /// GET /files?limit=100&offset=0 HTTP/1.1
+    /// ```
+    fn map_files_endpoint(self) -> Self;
+}
+
+impl<B> FilesRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_files_endpoint(self) -> Self {
+        self.route("/files", get(do_files))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct QueryParams {
+    /// The maximum number of files to list; bounded by [`MAX_LIMIT`].
+    limit: Option<usize>,
+    /// The number of files to skip.
+    offset: Option<usize>,
+}
+
+/// Lists the locally buffered files, including files that are still being written.
+///
+/// The total number of files is returned in the `X-Total-Count` header.
+///
+/// ```http
+/// GET /files
+/// ```
+async fn do_files(Query(query): Query<QueryParams>, State(state): State<AppState>) -> Response {
+    let files = state.backbone.list_files().await;
+    let total = files.len();
+    let page = paginate(files, &query)
+        .iter()
+        .map(FileResponse::from)
+        .collect::<Vec<_>>();
+
+    let headers = [(TOTAL_COUNT_HEADER.clone(), total.to_string())];
+    (headers, axum::Json(page)).into_response()
+}
+
+/// Selects the page of files described by the `limit` and `offset` query parameters.
+fn paginate(files: Vec<LiveFile>, query: &QueryParams) -> Vec<LiveFile> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    files
+        .into_iter()
+        .skip(query.offset.unwrap_or_default())
+        .take(limit)
+        .collect()
+}
+
+#[derive(Serialize)]
+struct FileResponse {
+    /// The ID of the file.
+    id: ShortGuid,
+    /// The name of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    /// The content type of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// The size of the file in bytes; omitted while the file is still being written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_size_bytes: Option<u64>,
+    /// The time the file was created.
+    created: DateTime<Utc>,
+    /// The time the file expires.
+    expires: DateTime<Utc>,
+}
+
+impl From<&LiveFile> for FileResponse {
+    fn from(file: &LiveFile) -> Self {
+        Self {
+            id: file.id,
+            file_name: file.file_name.clone(),
+            content_type: file.content_type.as_ref().map(ToString::to_string),
+            file_size_bytes: file.file_size_bytes,
+            created: instant_as_datetime(&file.created),
+            expires: instant_as_datetime(&file.expires),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    fn files(count: usize) -> Vec<LiveFile> {
+        (0..count)
+            .map(|_| LiveFile {
+                id: ShortGuid::new_random(),
+                file_name: None,
+                content_type: None,
+                file_size_bytes: Some(4),
+                created: Instant::now() - Duration::from_secs(60),
+                expires: Instant::now() + Duration::from_secs(60),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pages_are_selected_by_limit_and_offset() {
+        let all = files(5);
+        let query = QueryParams {
+            limit: Some(2),
+            offset: Some(3),
+        };
+        let page: Vec<_> = paginate(all.clone(), &query).iter().map(|f| f.id).collect();
+        assert_eq!(page, [all[3].id, all[4].id]);
+
+        let query = QueryParams {
+            limit: None,
+            offset: Some(10),
+        };
+        assert!(paginate(all, &query).is_empty());
+    }
+
+    #[test]
+    fn limit_is_bounded() {
+        let page = paginate(
+            files(MAX_LIMIT + 1),
+            &QueryParams {
+                limit: Some(usize::MAX),
+                offset: None,
+            },
+        );
+        assert_eq!(page.len(), MAX_LIMIT);
+    }
+
+    #[test]
+    fn creation_time_lies_in_the_past() {
+        let response = FileResponse::from(&files(1)[0]);
+        assert!(response.created < Utc::now() - chrono::Duration::seconds(30));
+        assert!(response.expires > Utc::now());
+    }
+}
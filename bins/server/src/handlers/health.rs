@@ -1,10 +1,14 @@
 //! Contains the `/health` endpoint filter.
 
 use crate::health::HealthState;
+use crate::AppState;
 use axum::body::HttpBody;
+use axum::extract::State;
+use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, MethodRouter};
 use axum::Router;
+use backend_traits::BackendHealth;
 use std::convert::Infallible;
 
 /// Defines a type of health check.
@@ -30,7 +34,8 @@ pub enum HealthCheckFormat {
 pub trait HealthRoutes {
     /// Provides an API for initiating health checks.
     ///
-    /// For readiness probes (compact output):
+    /// For readiness probes (compact output); the service reports itself as not ready
+    /// while it is draining for a shutdown or if any backend fails its health check:
     ///
     /// ```http
     /// GET /readyz HTTP/1.1
@@ -42,7 +47,7 @@ pub trait HealthRoutes {
     /// GET /livez HTTP/1.1
     /// ```
     ///
-    /// For combined health probes (compact output):
+    /// For combined health probes (compact output); unhealthy backends degrade the service:
     ///
     /// ```http
     /// GET /health HTTP/1.1
@@ -56,9 +61,8 @@ pub trait HealthRoutes {
     fn map_health_endpoints(self) -> Self;
 }
 
-impl<S, B> HealthRoutes for Router<S, B>
+impl<B> HealthRoutes for Router<AppState, B>
 where
-    S: Clone + Send + Sync + 'static,
     B: HttpBody + Send + 'static,
 {
     fn map_health_endpoints(self) -> Self {
@@ -82,12 +86,11 @@ where
 /// ## Arguments
 /// * `path` - The path on which to host the handler, e.g. `health`, `readyz`, etc.
 /// * `checks` - The type of health check to run on that path.
-fn health_endpoint<S, B>(checks: HealthCheck) -> MethodRouter<S, B, Infallible>
+fn health_endpoint<B>(checks: HealthCheck) -> MethodRouter<AppState, B, Infallible>
 where
-    S: Clone + Send + Sync + 'static,
     B: HttpBody + Send + 'static,
 {
-    get(move || handle_health(checks))
+    get(move |State(state): State<AppState>| handle_health(checks, state))
 }
 
 /// Performs a health check.
@@ -95,19 +98,88 @@ where
 /// ```http
 /// GET /health
 /// ```
-async fn handle_health(checks: HealthCheck) -> Result<HealthState, Infallible> {
-    // TODO: Actually implement health checks!
+async fn handle_health(checks: HealthCheck, state: AppState) -> Result<HealthState, Infallible> {
+    let draining = state.shutdown.is_draining();
     match checks {
         HealthCheck::Startup => Ok(HealthState::Healthy),
+        HealthCheck::Readiness if draining => Ok(HealthState::Failed),
+        HealthCheck::Readiness if !backends_healthy(&state).await => Ok(HealthState::Failed),
         HealthCheck::Readiness => Ok(HealthState::Healthy),
         HealthCheck::Liveness => Ok(HealthState::Healthy),
+        HealthCheck::Full(_) if draining => Ok(HealthState::Degraded),
+        HealthCheck::Full(_) if !backends_healthy(&state).await => Ok(HealthState::Degraded),
         HealthCheck::Full(HealthCheckFormat::Compact) => Ok(HealthState::Healthy),
         HealthCheck::Full(HealthCheckFormat::Complex) => Ok(HealthState::Healthy),
     }
 }
 
+/// Determines whether all backends pass their health checks.
+async fn backends_healthy(state: &AppState) -> bool {
+    let timeout = state.config.timeouts.backend_health_check_timeout();
+    match state.backbone.check_backends(timeout).await {
+        Some(backends) => backends.iter().all(BackendHealth::is_healthy),
+        None => false,
+    }
+}
+
 impl IntoResponse for HealthState {
     fn into_response(self) -> Response {
-        format!("{}", self).into_response()
+        let status = match self {
+            HealthState::Healthy | HealthState::Degraded => StatusCode::OK,
+            HealthState::Failed => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        (status, format!("{}", self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::await_rendezvous;
+    use app_config::AppConfig;
+    use axum::body::Body;
+    use backend_traits::{BackendCommand, HealthCheckError};
+    use hyper::Request;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn unhealthy_backends_fail_readiness_but_not_liveness() {
+        // A stub backend whose health is controlled by the test.
+        let healthy = Arc::new(AtomicBool::new(true));
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        tokio::spawn({
+            let healthy = healthy.clone();
+            async move {
+                while let Some(command) = backend_receiver.recv().await {
+                    if let BackendCommand::CheckHealth(timeout, reply) = command {
+                        let error = (!healthy.load(Ordering::Relaxed))
+                            .then_some(HealthCheckError::TimedOut(timeout));
+                        let tag = "stub".to_string();
+                        reply.send(vec![BackendHealth { tag, error }]).ok();
+                    }
+                }
+            }
+        });
+
+        let app = Router::new().map_health_endpoints().with_state(state);
+        let status = |path: &'static str| {
+            let app = app.clone();
+            async move {
+                let request = Request::get(path).body(Body::empty()).unwrap();
+                app.oneshot(request).await.unwrap().status()
+            }
+        };
+
+        assert_eq!(status("/readyz").await, StatusCode::OK);
+
+        healthy.store(false, Ordering::Relaxed);
+        assert_eq!(status("/readyz").await, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(status("/livez").await, StatusCode::OK);
+        assert_eq!(status("/health").await, StatusCode::OK);
+
+        drop(app);
+        await_rendezvous(rendezvous).await;
     }
 }
@@ -0,0 +1,95 @@
+//! Contains the `/keepalive` endpoint filter.
+
+use crate::error::ProblemType;
+use crate::handlers::{expiration_as_rfc1123, instant_as_datetime, public_path, FileId};
+use crate::AppState;
+use app_config::AppConfig;
+use axum::body::HttpBody;
+use axum::extract::State;
+use axum::http::header::EXPIRES;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use file_distribution::GetFileReaderError;
+use serde::Serialize;
+use shortguid::ShortGuid;
+
+pub trait KeepAliveRoutes {
+    /// Provides an API for extending the lease of a file.
+    ///
+    /// ```http
+    /// POST /keepalive/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// ```
+    fn map_keepalive_endpoint(self) -> Self;
+}
+
+impl<B> KeepAliveRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_keepalive_endpoint(self) -> Self {
+        self.route("/keepalive/:id", post(do_keepalive))
+    }
+}
+
+/// Resets the temporal lease of a file.
+///
+/// ```http
+/// POST /keepalive/:id
+/// ```
+async fn do_keepalive(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    let expires = match state.backbone.extend_lease(id).await {
+        Ok(expires) => expires,
+        Err(e) => return map_keepalive_error_to_response(e, &state.config),
+    };
+
+    let response = KeepAliveResponse {
+        id,
+        expires: instant_as_datetime(&expires),
+    };
+
+    let headers = [(EXPIRES, expiration_as_rfc1123(&expires))];
+    (headers, axum::Json(response)).into_response()
+}
+
+#[derive(Serialize)]
+struct KeepAliveResponse {
+    /// The ID of the file.
+    id: ShortGuid,
+    /// The new expiration date of the file.
+    expires: DateTime<Utc>,
+}
+
+fn map_keepalive_error_to_response(value: GetFileReaderError, config: &AppConfig) -> Response {
+    match value {
+        GetFileReaderError::UnknownFile(id) => ProblemType::FileNotFound
+            .problem(&config.errors)
+            .with_detail(format!("The file with ID {id} could not be found"))
+            .with_instance(public_path(config.base_path(), format!("/keepalive/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileExpired(id) => ProblemType::FileExpired
+            .problem(&config.errors)
+            .with_detail(format!("The file with ID {id} has already expired"))
+            .with_instance(public_path(config.base_path(), format!("/keepalive/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileError(id, e) => ProblemType::FileAccessFailed
+            .problem(&config.errors)
+            .with_detail(format!("Unable to process file: {e}"))
+            .with_instance(public_path(config.base_path(), format!("/keepalive/{id}")))
+            .with_value("id", id.to_string())
+            .with_value("error", e.to_string())
+            .into_response(),
+        GetFileReaderError::BackendTimeout(id) => ProblemType::BackendTimeout
+            .problem(&config.errors)
+            .with_detail(format!(
+                "The backends did not provide the file with ID {id} in time"
+            ))
+            .with_instance(public_path(config.base_path(), format!("/keepalive/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+    }
+}
@@ -0,0 +1,201 @@
+//! Contains the `/meta` endpoint filter.
+
+use crate::error::ProblemType;
+use crate::handlers::{public_path, FileId, ResponseFormat};
+use crate::AppState;
+use app_config::AppConfig;
+use axum::body::HttpBody;
+use axum::extract::State;
+use axum::headers::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use file_distribution::metadata::ItemMetadata;
+use file_distribution::GetFileReaderError;
+use serde::Serialize;
+use shortguid::ShortGuid;
+use std::collections::BTreeMap;
+
+pub trait MetaRoutes {
+    /// Provides an API for fetching the metadata record of a file.
+    ///
+    /// ```http
+    /// GET /meta/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// Accept: application/x-protobuf
+    /// ```
+    fn map_meta_endpoint(self) -> Self;
+}
+
+impl<B> MetaRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_meta_endpoint(self) -> Self {
+        self.route("/meta/:id", get(do_meta))
+    }
+}
+
+/// Gets the metadata record of a locally buffered file.
+///
+/// The record is encoded as protobuf if the client accepts it, and as JSON otherwise.
+///
+/// ```http
+/// GET /meta/:id
+/// ```
+async fn do_meta(
+    FileId(id): FileId,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let metadata = match state.backbone.get_metadata(id).await {
+        Ok(Some(metadata)) => metadata,
+        Ok(None) => {
+            return ProblemType::FileIncomplete
+                .problem(&state.config.errors)
+                .with_detail(format!("The file with ID {id} is still being written"))
+                .with_instance(public_path(state.config.base_path(), format!("/meta/{id}")))
+                .with_value("id", id.to_string())
+                .into_response()
+        }
+        Err(e) => return map_meta_error_to_response(e, &state.config),
+    };
+
+    ResponseFormat::from_headers(&headers).respond(&MetaResponse::new(id, &metadata), &metadata)
+}
+
+#[derive(Serialize)]
+struct MetaResponse {
+    /// The ID of the file.
+    id: ShortGuid,
+    /// The name of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    /// The content type of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// The size of the file in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_size_bytes: Option<u64>,
+    /// The hex encoded hashes of the file; hashes that were not computed are omitted.
+    hashes: MetaHashes,
+    /// The time the file was created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<DateTime<Utc>>,
+    /// The time the file expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<DateTime<Utc>>,
+    /// The key/value metadata attached to the file by the client.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+struct MetaHashes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    md5: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blake3: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crc32c: Option<String>,
+}
+
+impl MetaResponse {
+    fn new(id: ShortGuid, metadata: &ItemMetadata) -> Self {
+        let hashes = metadata.hashes.as_ref();
+        let hash = |bytes: Option<&Vec<u8>>| bytes.filter(|b| !b.is_empty()).map(hex::encode);
+        Self {
+            id,
+            file_name: metadata.file_name.clone(),
+            content_type: metadata.content_type.clone(),
+            file_size_bytes: metadata.file_size_bytes,
+            hashes: MetaHashes {
+                md5: hash(hashes.map(|h| &h.md5)),
+                sha256: hash(hashes.map(|h| &h.sha256)),
+                blake3: hash(hashes.map(|h| &h.blake3)),
+                crc32c: hashes
+                    .and_then(|h| h.crc32c)
+                    .map(|crc32c| format!("{crc32c:08x}")),
+            },
+            created: metadata.created_unix_ms.and_then(unix_millis_as_datetime),
+            expires: metadata.expires_unix_ms.and_then(unix_millis_as_datetime),
+            metadata: metadata
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+fn unix_millis_as_datetime(millis: u64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(i64::try_from(millis).ok()?)
+}
+
+fn map_meta_error_to_response(value: GetFileReaderError, config: &AppConfig) -> Response {
+    match value {
+        GetFileReaderError::UnknownFile(id) => ProblemType::FileNotFound
+            .problem(&config.errors)
+            .with_detail(format!("The file with ID {id} could not be found"))
+            .with_instance(public_path(config.base_path(), format!("/meta/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileExpired(id) => ProblemType::FileExpired
+            .problem(&config.errors)
+            .with_detail(format!("The file with ID {id} has already expired"))
+            .with_instance(public_path(config.base_path(), format!("/meta/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileError(id, e) => ProblemType::FileAccessFailed
+            .problem(&config.errors)
+            .with_detail(format!("Unable to process file: {e}"))
+            .with_instance(public_path(config.base_path(), format!("/meta/{id}")))
+            .with_value("id", id.to_string())
+            .with_value("error", e.to_string())
+            .into_response(),
+        GetFileReaderError::BackendTimeout(id) => ProblemType::BackendTimeout
+            .problem(&config.errors)
+            .with_detail(format!(
+                "The backends did not provide the file with ID {id} in time"
+            ))
+            .with_instance(public_path(config.base_path(), format!("/meta/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_response_encodes_hashes_as_hex() {
+        let metadata = ItemMetadata {
+            id: vec![0; 16],
+            file_name: None,
+            hashes: Some(file_distribution::metadata::Hashes {
+                md5: vec![0xab, 0xcd],
+                sha256: Vec::new(),
+                blake3: Vec::new(),
+                crc32c: None,
+                registered: Default::default(),
+            }),
+            content_type: Some("text/plain".to_string()),
+            file_size_bytes: Some(4),
+            created_unix_ms: Some(1_700_000_000_000),
+            expires_unix_ms: None,
+            metadata: [("tenant".to_string(), "acme".to_string())].into(),
+        };
+
+        let response = MetaResponse::new(ShortGuid::new_random(), &metadata);
+        let json = serde_json::to_value(response).expect("failed to serialize");
+        assert_eq!(json["hashes"], serde_json::json!({ "md5": "abcd" }));
+        assert_eq!(json["file_size_bytes"], 4);
+        assert_eq!(json["created"], "2023-11-14T22:13:20Z");
+        assert!(json.get("expires").is_none());
+        assert_eq!(json["metadata"], serde_json::json!({ "tenant": "acme" }));
+    }
+}
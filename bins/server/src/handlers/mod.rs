@@ -1,23 +1,80 @@
 //! Contains warp filters.
 
+mod admin;
+mod archive;
+mod file_id;
+mod files;
 mod health;
+mod keepalive;
+mod meta;
 mod metrics;
+mod negotiation;
+mod openapi;
+mod receipts;
+mod redistribute;
 mod shutdown;
+mod version;
 mod yeet;
 mod yoink;
 
+pub use admin::AdminRoutes;
+pub use archive::ArchiveRoutes;
 use chrono::{DateTime, Utc};
+pub use file_id::FileId;
+pub use files::FilesRoutes;
 pub use health::HealthRoutes;
+pub use keepalive::KeepAliveRoutes;
+pub use meta::MetaRoutes;
 pub use metrics::MetricsRoutes;
+pub use negotiation::{ContentCoding, ResponseFormat};
+pub use openapi::OpenApiRoutes;
+pub use receipts::ReceiptRoutes;
+pub use redistribute::RedistributeRoutes;
 pub use shutdown::ShutdownRoutes;
+pub use version::VersionRoutes;
 pub use yeet::YeetRoutes;
 pub use yoink::YoinkRoutes;
 
+/// Gets the public path of a route path, i.e. including the configured `base_path`
+/// (see [`AppConfig::base_path`](app_config::AppConfig::base_path)), such as in the
+/// `instance` of problem details or `Location` headers.
+pub fn public_path(base_path: Option<&str>, path: impl AsRef<str>) -> String {
+    match base_path {
+        Some(base_path) => format!("{base_path}{path}", path = path.as_ref()),
+        None => path.as_ref().to_string(),
+    }
+}
+
 pub fn expiration_as_rfc1123(expires: &tokio::time::Instant) -> String {
-    let expire_in = expires.duration_since(tokio::time::Instant::now());
-    let expiration_date = std::time::SystemTime::now() + expire_in;
-    let expiration_date = DateTime::<Utc>::from(expiration_date);
-    expiration_date
-        .format("%a, %d %b %Y %H:%M:%S GMT")
-        .to_string()
+    datetime_as_rfc1123(&instant_as_datetime(expires))
+}
+
+/// Formats a wall-clock time as an HTTP date.
+pub fn datetime_as_rfc1123(date: &DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Maps an instant to wall-clock time.
+pub fn instant_as_datetime(instant: &tokio::time::Instant) -> DateTime<Utc> {
+    let now = tokio::time::Instant::now();
+    let date = if *instant >= now {
+        std::time::SystemTime::now() + instant.duration_since(now)
+    } else {
+        std::time::SystemTime::now() - now.duration_since(*instant)
+    };
+    DateTime::<Utc>::from(date)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_prefixed_with_the_base_path() {
+        assert_eq!(public_path(None, "/yoink/abc"), "/yoink/abc");
+        assert_eq!(
+            public_path(Some("/api/v1"), "/yoink/abc"),
+            "/api/v1/yoink/abc"
+        );
+    }
 }
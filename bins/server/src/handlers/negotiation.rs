@@ -0,0 +1,232 @@
+//! Selects the format and content coding of response bodies from the `Accept`
+//! and `Accept-Encoding` request headers.
+
+use axum::http::header::{ACCEPT, ACCEPT_ENCODING, CONTENT_TYPE};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+
+/// The media type of protobuf encoded responses.
+pub const PROTOBUF_MEDIA_TYPE: &str = "application/x-protobuf";
+
+/// An alternative media type of protobuf encoded responses.
+const PROTOBUF_MEDIA_TYPE_ALT: &str = "application/protobuf";
+
+/// The media type of JSON encoded responses.
+const JSON_MEDIA_TYPE: &str = "application/json";
+
+/// The encoding of a response body.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResponseFormat {
+    /// The body is encoded as JSON.
+    #[default]
+    Json,
+    /// The body is encoded as protobuf.
+    Protobuf,
+}
+
+impl ResponseFormat {
+    /// Selects the response format from the `Accept` header of a request.
+    ///
+    /// Protobuf is only selected if the client prefers it over JSON; wildcards,
+    /// ties and missing headers result in JSON.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut json = Preference::default();
+        let mut protobuf = Preference::default();
+
+        let ranges = headers
+            .get_all(ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+
+        for range in ranges {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            let specificity = match media_type.as_str() {
+                "*/*" => 1,
+                "application/*" => 2,
+                _ => 3,
+            };
+
+            if specificity < 3 || media_type == JSON_MEDIA_TYPE {
+                json.update(specificity, quality);
+            }
+            if specificity < 3
+                || media_type == PROTOBUF_MEDIA_TYPE
+                || media_type == PROTOBUF_MEDIA_TYPE_ALT
+            {
+                protobuf.update(specificity, quality);
+            }
+        }
+
+        if protobuf.quality > 0.0 && protobuf.quality > json.quality {
+            Self::Protobuf
+        } else {
+            Self::Json
+        }
+    }
+
+    /// Encodes the response body in this format, using `json` for JSON
+    /// and `message` for protobuf responses.
+    pub fn respond<T, M>(self, json: &T, message: &M) -> Response
+    where
+        T: Serialize,
+        M: prost::Message,
+    {
+        match self {
+            Self::Json => axum::Json(json).into_response(),
+            Self::Protobuf => (
+                [(CONTENT_TYPE, PROTOBUF_MEDIA_TYPE)],
+                message.encode_to_vec(),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// A compression applied to a response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentCoding {
+    Gzip,
+    Deflate,
+}
+
+impl ContentCoding {
+    /// Selects the preferred content coding from the `Accept-Encoding` header of a request,
+    /// or `None` if the client does not accept a compressed response. Gzip wins ties.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut gzip = Preference::default();
+        let mut deflate = Preference::default();
+
+        let codings = headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+
+        for coding in codings {
+            let mut parts = coding.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            match name.as_str() {
+                "*" => {
+                    gzip.update(1, quality);
+                    deflate.update(1, quality);
+                }
+                "gzip" | "x-gzip" => gzip.update(2, quality),
+                "deflate" => deflate.update(2, quality),
+                _ => {}
+            }
+        }
+
+        if gzip.quality > 0.0 && gzip.quality >= deflate.quality {
+            Some(Self::Gzip)
+        } else if deflate.quality > 0.0 {
+            Some(Self::Deflate)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the value of the `Content-Encoding` header.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+}
+
+/// The quality assigned to a media type or coding by its most specific matching range.
+#[derive(Debug, Default)]
+struct Preference {
+    specificity: u8,
+    quality: f32,
+}
+
+impl Preference {
+    fn update(&mut self, specificity: u8, quality: f32) {
+        if specificity > self.specificity {
+            self.specificity = specificity;
+            self.quality = quality;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn format(accept: &'static str) -> ResponseFormat {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(accept));
+        ResponseFormat::from_headers(&headers)
+    }
+
+    #[test]
+    fn json_is_the_default() {
+        assert_eq!(
+            ResponseFormat::from_headers(&HeaderMap::new()),
+            ResponseFormat::Json
+        );
+        assert_eq!(format("*/*"), ResponseFormat::Json);
+        assert_eq!(format("text/html"), ResponseFormat::Json);
+        assert_eq!(format("application/json"), ResponseFormat::Json);
+    }
+
+    #[test]
+    fn protobuf_is_selected_when_preferred() {
+        assert_eq!(format("application/x-protobuf"), ResponseFormat::Protobuf);
+        assert_eq!(format("application/protobuf"), ResponseFormat::Protobuf);
+        assert_eq!(
+            format("application/json;q=0.5, application/x-protobuf"),
+            ResponseFormat::Protobuf
+        );
+        assert_eq!(
+            format("application/x-protobuf, */*;q=0.1"),
+            ResponseFormat::Protobuf
+        );
+    }
+
+    #[test]
+    fn quality_values_are_respected() {
+        assert_eq!(
+            format("application/x-protobuf;q=0.5, application/json"),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            format("application/x-protobuf, application/json"),
+            ResponseFormat::Json
+        );
+        assert_eq!(format("application/x-protobuf;q=0"), ResponseFormat::Json);
+    }
+
+    fn coding(accept_encoding: &'static str) -> Option<ContentCoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(accept_encoding));
+        ContentCoding::from_headers(&headers)
+    }
+
+    #[test]
+    fn content_coding_is_negotiated() {
+        assert_eq!(ContentCoding::from_headers(&HeaderMap::new()), None);
+        assert_eq!(coding("identity"), None);
+        assert_eq!(coding("br"), None);
+        assert_eq!(coding("gzip, deflate, br"), Some(ContentCoding::Gzip));
+        assert_eq!(coding("deflate"), Some(ContentCoding::Deflate));
+        assert_eq!(coding("gzip;q=0.5, deflate"), Some(ContentCoding::Deflate));
+        assert_eq!(coding("*"), Some(ContentCoding::Gzip));
+        assert_eq!(coding("*, gzip;q=0"), Some(ContentCoding::Deflate));
+    }
+}
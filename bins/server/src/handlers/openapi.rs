@@ -0,0 +1,140 @@
+//! Contains the `/openapi.json` endpoint filter.
+
+use crate::error::ProblemDetails;
+use crate::handlers::yeet::{Hashes, SuccessfulUploadResponse, ValidatedUploadResponse};
+use axum::body::HttpBody;
+use axum::routing::get;
+use axum::{Json, Router};
+use utoipa::openapi::path::{OperationBuilder, PathItem, PathItemType};
+use utoipa::openapi::response::ResponseBuilder;
+use utoipa::openapi::{ContentBuilder, ObjectBuilder, SchemaType};
+use utoipa::{Modify, OpenApi};
+
+pub trait OpenApiRoutes {
+    /// Provides the OpenAPI document describing the HTTP API.
+    ///
+    /// ```http
+    /// GET /openapi.json HTTP/1.1
+    /// ```
+    fn map_openapi_endpoint(self) -> Self;
+}
+
+impl<S, B> OpenApiRoutes for Router<S, B>
+where
+    S: Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_openapi_endpoint(self) -> Self {
+        self.route("/openapi.json", get(render_openapi))
+    }
+}
+
+async fn render_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        super::yeet::do_yeet,
+        super::yoink::do_yoink,
+        super::archive::do_yoink_archive
+    ),
+    components(schemas(
+        SuccessfulUploadResponse,
+        ValidatedUploadResponse,
+        Hashes,
+        ProblemDetails
+    )),
+    modifiers(&HealthPaths),
+    tags(
+        (name = "files", description = "Storing and retrieving files"),
+        (name = "health", description = "Health probes")
+    )
+)]
+struct ApiDoc;
+
+/// Adds the health routes, which are built per check type rather than annotated.
+struct HealthPaths;
+
+impl Modify for HealthPaths {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let probes = [
+            ("/health", "Runs a full health check"),
+            (
+                "/healthz",
+                "Runs a full health check with human-readable output",
+            ),
+            ("/startupz", "Kubernetes startup probe"),
+            (
+                "/readyz",
+                "Kubernetes readiness probe; fails while draining or if a backend is unhealthy",
+            ),
+            ("/livez", "Kubernetes liveness probe"),
+        ];
+
+        for (path, summary) in probes {
+            let state = ContentBuilder::new()
+                .schema(ObjectBuilder::new().schema_type(SchemaType::String))
+                .build();
+            let operation = OperationBuilder::new()
+                .tag("health")
+                .summary(Some(summary))
+                .response(
+                    "200",
+                    ResponseBuilder::new()
+                        .description("The service is healthy or degraded")
+                        .content("text/plain", state.clone()),
+                )
+                .response(
+                    "503",
+                    ResponseBuilder::new()
+                        .description("The service is unhealthy")
+                        .content("text/plain", state),
+                );
+            openapi
+                .paths
+                .paths
+                .insert(path.into(), PathItem::new(PathItemType::Get, operation));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use hyper::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn document_describes_files_and_health_routes() {
+        let app: Router<(), Body> = Router::new().map_openapi_endpoint();
+        let request = Request::get("/openapi.json").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        for path in [
+            "/yeet",
+            "/yoink/{id}",
+            "/yoink",
+            "/health",
+            "/readyz",
+            "/livez",
+        ] {
+            assert!(document["paths"][path].is_object(), "missing {path}");
+        }
+
+        let upload = &document["paths"]["/yeet"]["post"]["responses"]["201"];
+        assert_eq!(
+            upload["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/SuccessfulUploadResponse"
+        );
+        let schemas = &document["components"]["schemas"];
+        assert!(schemas["Hashes"]["properties"]["crc32c"].is_object());
+        assert!(schemas["ProblemDetails"]["properties"]["type"].is_object());
+    }
+}
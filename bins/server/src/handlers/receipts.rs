@@ -0,0 +1,97 @@
+//! Contains the `/receipts` endpoint filter.
+
+use crate::error::ProblemType;
+use crate::handlers::public_path;
+use crate::receipts::{ReceiptValidationError, SignedReceipt};
+use crate::AppState;
+use app_config::AppConfig;
+use axum::body::HttpBody;
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{BoxError, Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub trait ReceiptRoutes {
+    /// Provides an API for validating signed distribution receipts.
+    ///
+    /// ```http
+    /// POST /receipts/verify HTTP/1.1
+    /// Content-Type: application/json
+    ///
+    /// { "receipt": { ... }, "signature": { ... } }
+    /// ```
+    fn map_receipts_endpoint(self) -> Self;
+}
+
+impl<B> ReceiptRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_receipts_endpoint(self) -> Self {
+        self.route("/receipts/verify", post(verify_receipt))
+    }
+}
+
+/// Validates the signature and timestamps of a receipt.
+async fn verify_receipt(
+    State(state): State<AppState>,
+    Json(receipt): Json<SignedReceipt>,
+) -> Response {
+    let server_time = Utc::now();
+    let Some(key) = state.config.receipts.signing_key.as_deref() else {
+        return ProblemType::ReceiptSigningDisabled
+            .problem(&state.config.errors)
+            .with_detail("No signing key is configured, so receipts cannot be verified")
+            .with_instance(public_path(state.config.base_path(), "/receipts/verify"))
+            .into_response();
+    };
+
+    // Out-of-range values fall back to no tolerance.
+    let max_clock_skew =
+        chrono::Duration::from_std(state.config.receipts.max_clock_skew()).unwrap_or_default();
+
+    match receipt.validate(key.as_bytes(), server_time, max_clock_skew) {
+        Ok(()) => Json(ReceiptValidResponse {
+            id: receipt.receipt.id.to_string(),
+            server_time,
+        })
+        .into_response(),
+        Err(e) => map_receipt_validation_error_to_response(e, server_time, &state.config),
+    }
+}
+
+#[derive(Serialize)]
+struct ReceiptValidResponse {
+    /// The ID of the file the receipt is for.
+    id: String,
+    /// The current time of the server.
+    server_time: DateTime<Utc>,
+}
+
+fn map_receipt_validation_error_to_response(
+    value: ReceiptValidationError,
+    server_time: DateTime<Utc>,
+    config: &AppConfig,
+) -> Response {
+    let problem_type = match value {
+        ReceiptValidationError::Unsigned | ReceiptValidationError::InvalidSignature => {
+            ProblemType::InvalidReceiptSignature
+        }
+        ReceiptValidationError::IssuedInFuture(_) | ReceiptValidationError::Expired(_) => {
+            ProblemType::ReceiptNotValid
+        }
+    };
+
+    // The server time is included so that clients can detect skewed clocks.
+    problem_type
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .with_instance(public_path(config.base_path(), "/receipts/verify"))
+        .with_value("server_time", server_time.to_rfc3339())
+        .into_response()
+}
@@ -0,0 +1,123 @@
+//! Contains the `/redistribute` endpoint filter.
+
+use crate::error::ProblemType;
+use crate::handlers::{expiration_as_rfc1123, instant_as_datetime, public_path, FileId};
+use crate::services::record_file_id;
+use crate::AppState;
+use app_config::AppConfig;
+use axum::body::HttpBody;
+use axum::extract::State;
+use axum::http::header::EXPIRES;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use file_distribution::GetFileReaderError;
+use serde::Serialize;
+use shortguid::ShortGuid;
+
+pub trait RedistributeRoutes {
+    /// Provides an API for distributing a file to the backends again.
+    ///
+    /// ```http
+    /// POST /redistribute/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// ```
+    fn map_redistribute_endpoint(self) -> Self;
+}
+
+impl<B> RedistributeRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_redistribute_endpoint(self) -> Self {
+        self.route("/redistribute/:id", post(do_redistribute))
+    }
+}
+
+/// Distributes a locally buffered file to the backends again, e.g. after a backend outage.
+///
+/// Responds with `202 Accepted` once the distribution is queued; its outcome is
+/// reported in the receipt of the file.
+///
+/// ```http
+/// POST /redistribute/:id
+/// ```
+async fn do_redistribute(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    record_file_id(id);
+    let expires = match state.backbone.redistribute(id).await {
+        Ok(Some(expires)) => expires,
+        Ok(None) => {
+            return ProblemType::FileIncomplete
+                .problem(&state.config.errors)
+                .with_detail(format!(
+                    "The file with ID {id} is still being written and will be distributed once complete"
+                ))
+                .with_instance(public_path(state.config.base_path(), format!("/redistribute/{id}")))
+                .with_value("id", id.to_string())
+                .into_response()
+        }
+        Err(e) => return map_redistribute_error_to_response(e, &state.config),
+    };
+
+    let response = RedistributeResponse {
+        id,
+        expires: instant_as_datetime(&expires),
+    };
+
+    let headers = [(EXPIRES, expiration_as_rfc1123(&expires))];
+    (StatusCode::ACCEPTED, headers, axum::Json(response)).into_response()
+}
+
+#[derive(Serialize)]
+struct RedistributeResponse {
+    /// The ID of the file.
+    id: ShortGuid,
+    /// The expiration date of the file, up to which the backends store it.
+    expires: DateTime<Utc>,
+}
+
+fn map_redistribute_error_to_response(value: GetFileReaderError, config: &AppConfig) -> Response {
+    match value {
+        GetFileReaderError::UnknownFile(id) => ProblemType::FileNotFound
+            .problem(&config.errors)
+            .with_detail(format!("The file with ID {id} could not be found"))
+            .with_instance(public_path(
+                config.base_path(),
+                format!("/redistribute/{id}"),
+            ))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileExpired(id) => ProblemType::FileExpired
+            .problem(&config.errors)
+            .with_detail(format!("The file with ID {id} has already expired"))
+            .with_instance(public_path(
+                config.base_path(),
+                format!("/redistribute/{id}"),
+            ))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileError(id, e) => ProblemType::FileAccessFailed
+            .problem(&config.errors)
+            .with_detail(format!("Unable to process file: {e}"))
+            .with_instance(public_path(
+                config.base_path(),
+                format!("/redistribute/{id}"),
+            ))
+            .with_value("id", id.to_string())
+            .with_value("error", e.to_string())
+            .into_response(),
+        GetFileReaderError::BackendTimeout(id) => ProblemType::BackendTimeout
+            .problem(&config.errors)
+            .with_detail(format!(
+                "The backends did not provide the file with ID {id} in time"
+            ))
+            .with_instance(public_path(
+                config.base_path(),
+                format!("/redistribute/{id}"),
+            ))
+            .with_value("id", id.to_string())
+            .into_response(),
+    }
+}
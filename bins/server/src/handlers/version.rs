@@ -0,0 +1,82 @@
+//! Contains the `/version` endpoint filter.
+
+use axum::body::HttpBody;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+pub trait VersionRoutes {
+    /// Provides an API for identifying the deployed build.
+    ///
+    /// ```http
+    /// GET /version HTTP/1.1
+    /// ```
+    fn map_version_endpoint(self) -> Self;
+}
+
+impl<S, B> VersionRoutes for Router<S, B>
+where
+    S: Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+{
+    // Ensure HttpCallMetricTracker is updated.
+    fn map_version_endpoint(self) -> Self {
+        self.route("/version", get(render_version))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct VersionResponse {
+    /// The version of the crate.
+    version: &'static str,
+    /// The SHA of the commit the service was built from, if built from a git repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_sha: Option<&'static str>,
+    /// The time the service was built.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build_timestamp: Option<DateTime<Utc>>,
+}
+
+impl VersionResponse {
+    /// Gets the build information captured by the build script.
+    fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: option_env!("YY_GIT_SHA"),
+            build_timestamp: env!("YY_BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        }
+    }
+}
+
+async fn render_version() -> Json<VersionResponse> {
+    Json(VersionResponse::current())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use hyper::{Request, StatusCode};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn version_describes_the_build() {
+        let app: Router<(), Body> = Router::new().map_version_endpoint();
+        let request = Request::get("/version").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let version: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+        assert!(version["build_timestamp"]
+            .as_str()
+            .map_or(false, |timestamp| timestamp
+                .parse::<DateTime<Utc>>()
+                .is_ok()));
+    }
+}
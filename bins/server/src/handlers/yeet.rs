@@ -1,29 +1,114 @@
 //! Contains the `/yeet` endpoint filter.
 
+use crate::error::ProblemType;
 use crate::expiration_as_rfc1123;
+use crate::handlers::yoink::{DEFAULT_CONTENT_TYPE, SNIFF_BYTES};
+use crate::handlers::{public_path, ContentCoding, FileId, ResponseFormat};
+use crate::quotas::QuotaExceeded;
+use crate::services::{record_file_id, AuthenticatedToken};
+use crate::upload_limit::UploadsBusy;
 use crate::AppState;
-use axum::body::HttpBody;
-use axum::extract::{BodyStream, Query, State, TypedHeader};
+use app_config::files::{FilesConfig, SyncPolicy};
+use app_config::AppConfig;
+use async_compression::tokio::bufread::{DeflateDecoder, GzipDecoder};
+use axum::body::{Bytes, HttpBody};
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::{BodyStream, Extension, Multipart, Query, State, TypedHeader};
 use axum::headers::{ContentLength, ContentType};
-use axum::http::{HeaderName, HeaderValue};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
 use axum::response::{IntoResponse, Response};
-use axum::routing::post;
+use axum::routing::{get, post, put};
 use axum::Router;
-use backbone::{CompletionMode, NewFileError};
-use file_distribution::FileHashes;
+use backbone::{
+    ChunkRangeError, CompletionMode, ExistingFile, FinalizationError, Finalized,
+    HighWaterMarkExceeded, InsufficientStorage, NewFileError, UploadSessionOptions,
+    UploadSessionStatus,
+};
+use backend_traits::DistributionOutcome;
+use base64::Engine;
+use file_distribution::hash::{
+    FileHasher, HashAlgorithms, UnknownHashAlgorithm, BUILT_IN_ALGORITHMS,
+};
+use file_distribution::metadata::ItemMetadata;
+use file_distribution::{FileHashes, WriteSummary};
+use futures::Stream;
 use headers_content_md5::ContentMd5;
 use hyper::body::Buf;
 use hyper::header::EXPIRES;
-use hyper::StatusCode;
+use hyper::{Method, StatusCode};
+use metrics::backbone::{BackboneMetrics, SyncOperation};
+use metrics::http::HttpMetrics;
 use metrics::transfer::TransferMethod;
 use metrics::transfer::TransferMetrics;
+use percent_encoding::percent_decode_str;
+use problemdetails::Problem;
 use serde::Serialize;
 use shortguid::ShortGuid;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
 use tokio_stream::StreamExt;
-use tracing::{debug, trace};
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::{debug, trace, warn};
+use utoipa::ToSchema;
 
 static ID_HEADER: HeaderName = HeaderName::from_static("yy-id");
 
+/// Optional request header specifying the temporal lease of the file, in seconds.
+static LEASE_HEADER: HeaderName = HeaderName::from_static("yy-lease");
+
+/// Optional request header selecting the hash algorithms computed for the file.
+static HASHES_HEADER: HeaderName = HeaderName::from_static("x-yeet-hashes");
+
+/// Optional request header specifying the hex encoded SHA-256 hash the upload must match.
+static EXPECTED_SHA256_HEADER: HeaderName = HeaderName::from_static("x-expected-sha256");
+
+/// The number of seconds after which clients should retry uploads rejected because
+/// the backends were busy.
+const BACKENDS_BUSY_RETRY_AFTER_SECS: u64 = 1;
+
+/// The number of seconds after which clients should retry uploads rejected because
+/// the maximum number of concurrent uploads was reached.
+const UPLOADS_BUSY_RETRY_AFTER_SECS: u64 = 1;
+
+/// The number of random IDs tried for an upload before giving up.
+const MAX_ID_ATTEMPTS: usize = 3;
+
+/// Optional request header identifying retries of the same upload.
+static IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("x-idempotency-key");
+
+/// Optional request header attaching key/value metadata to the file, as base64 encoded
+/// JSON object of strings. Downloads return the metadata in the same header.
+pub(super) static META_HEADER: HeaderName = HeaderName::from_static("x-yeet-meta");
+
+/// Optional request header asking to respond only after the backends stored the file.
+static WAIT_HEADER: HeaderName = HeaderName::from_static("x-yeet-wait");
+
+/// Response header listing the result of each backend for uploads waiting for the
+/// backends, e.g. `memory=stored, s3=failed`.
+static BACKENDS_HEADER: HeaderName = HeaderName::from_static("x-yeet-backends");
+
+/// Optional request header asking to only hash the upload rather than storing it.
+static DRY_RUN_HEADER: HeaderName = HeaderName::from_static("x-yeet-dryrun");
+
+/// Response header advertising the maximum temporal lease of a file, in seconds.
+static MAX_LEASE_HEADER: HeaderName = HeaderName::from_static("x-yeet-max-lease");
+
+/// Response header advertising the largest upload currently accepted, in bytes.
+static MAX_BODY_SIZE_HEADER: HeaderName = HeaderName::from_static("x-yeet-max-body-size");
+
+/// The methods accepted by `/yeet`.
+const YEET_METHODS: &str = "POST, HEAD, OPTIONS";
+
+/// The content codings accepted for request bodies.
+const ACCEPTED_ENCODINGS: &str = "gzip, deflate";
+
 pub trait YeetRoutes {
     /// Provides an API for storing files.
     ///
@@ -31,9 +116,119 @@ pub trait YeetRoutes {
     /// POST /yeet HTTP/1.1
     /// Content-Length: 1024
     /// Content-Type: application/my-type
+    /// yy-lease: 300
+    ///
+    /// your-data
+    /// ```
+    ///
+    /// Bodies of unknown length, e.g. sent with `Transfer-Encoding: chunked`, are accepted
+    /// as well; the response reports the size of the file once the body ended.
+    ///
+    /// The optional `yy-lease` header overrides the number of seconds the file is kept
+    /// available, bounded by the configured maximum.
+    ///
+    /// The original file name can be provided in a `Content-Disposition: attachment;
+    /// filename="cat.jpg"` header or the `file_name` query parameter; it is reduced to its
+    /// last path component and restored when the file is downloaded.
+    ///
+    /// With `X-Yeet-Wait: durable`, the response is only sent once at least one backend
+    /// stored the file; `durable-all` waits for all backends to store it. If the backends
+    /// do not confirm storing the file, `502 Bad Gateway` is returned. The result of each
+    /// backend is listed in the `backends` field and the `X-Yeet-Backends` header; if some
+    /// backends failed to store the file, `207 Multi-Status` is returned instead of `201`.
+    ///
+    /// Clients sending `Expect: 100-continue` receive `100 Continue` only once the headers,
+    /// quota, storage headroom and file ID of the upload were checked; otherwise the error
+    /// is returned before the body is sent. Other expectations are rejected with
+    /// `417 Expectation Failed`.
+    ///
+    /// Bodies sent with `Content-Encoding: gzip` or `deflate` are decompressed before they
+    /// are stored; hashes and sizes then describe the decompressed file.
+    ///
+    /// With `X-Yeet-DryRun: true`, the body is hashed and measured, but neither stored nor
+    /// distributed; `200 OK` reports its size and hashes without assigning an ID. Posting
+    /// to `/yeet/validate` does the same:
+    ///
+    /// ```http
+    /// POST /yeet/validate HTTP/1.1
+    /// Content-Length: 1024
+    /// x-yeet-hashes: sha256
+    ///
+    /// your-data
+    /// ```
+    ///
+    /// Files can also be uploaded as `multipart/form-data`, e.g. from HTML forms. The first
+    /// field carrying a file name is stored along with its name and content type:
+    ///
+    /// ```http
+    /// POST /yeet/form HTTP/1.1
+    /// Content-Type: multipart/form-data; boundary=X
+    ///
+    /// --X
+    /// Content-Disposition: form-data; name="file"; filename="cat.jpg"
+    /// Content-Type: image/jpeg
+    ///
+    /// your-data
+    /// --X--
+    /// ```
+    ///
+    /// Clients holding a key of their own can store the file under a chosen ID instead;
+    /// the upload is rejected with `409 Conflict` if a buffered file already uses it:
+    ///
+    /// ```http
+    /// PUT /yeet/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// Content-Length: 1024
+    ///
+    /// your-data
+    /// ```
+    ///
+    /// Once the file was distributed, a receipt describing where and when it was stored
+    /// can be obtained:
+    ///
+    /// ```http
+    /// GET /yeet/KmC6e8laTnK3dioUSMpM0Q/receipt HTTP/1.1
+    /// ```
+    ///
+    /// The progress of an upload can be queried while it is still in flight:
+    ///
+    /// ```http
+    /// GET /yeet/KmC6e8laTnK3dioUSMpM0Q/status HTTP/1.1
+    /// ```
+    ///
+    /// Large uploads over unreliable links can be sent in chunks. A session is opened
+    /// first, taking the same headers as `/yeet` except for the body related ones:
+    ///
+    /// ```http
+    /// POST /yeet/resumable HTTP/1.1
+    /// Content-Type: application/my-type
+    /// ```
+    ///
+    /// The chunks are then sent in any order, each announcing its byte range and the total
+    /// size. Overlapping chunks are accepted, and the bytes of interrupted chunks that were
+    /// received are kept. The chunk completing the file stores it like a regular upload:
+    ///
+    /// ```http
+    /// PATCH /yeet/resumable/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// Content-Range: bytes 0-1023/4096
+    /// Content-Length: 1024
     ///
     /// your-data
     /// ```
+    ///
+    /// The byte ranges received so far are reported by `GET /yeet/resumable/:id`, such
+    /// that clients can resume by sending the missing ones; `DELETE` aborts the upload.
+    ///
+    /// Clients can discover the capabilities of the endpoint with `OPTIONS` or `HEAD`,
+    /// answered with `204 No Content` and the accepted methods (`Allow`), request content
+    /// codings (`Accept-Encoding`), selectable hashes (`x-yeet-hashes`), the maximum lease
+    /// (`x-yeet-max-lease`) and, if storage is limited, the largest upload currently
+    /// accepted (`x-yeet-max-body-size`):
+    ///
+    /// ```http
+    /// OPTIONS /yeet HTTP/1.1
+    /// ```
+    ///
+    /// CORS preflight requests are answered by the CORS layer, if configured.
     fn map_yeet_endpoint(self) -> Self;
 }
 
@@ -45,30 +240,210 @@ where
 {
     // Ensure HttpCallMetricTracker is updated.
     fn map_yeet_endpoint(self) -> Self {
-        self.route("/yeet", post(do_yeet))
+        self.route(
+            "/yeet",
+            post(do_yeet).head(describe_yeet).options(describe_yeet),
+        )
+        .route("/yeet/form", post(do_yeet_form))
+        .route("/yeet/validate", post(do_yeet_validate))
+        .route("/yeet/resumable", post(create_resumable_upload))
+        .route(
+            "/yeet/resumable/:id",
+            get(get_resumable_upload)
+                .patch(append_resumable_upload)
+                .delete(abort_resumable_upload),
+        )
+        .route("/yeet/:id", put(do_yeet_with_id))
+        .route("/yeet/:id/receipt", get(get_receipt))
+        .route("/yeet/:id/status", get(get_status))
+    }
+}
+
+/// Advertises the capabilities of the upload endpoint in response headers.
+#[axum::debug_handler]
+async fn describe_yeet(State(state): State<AppState>) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ALLOW, HeaderValue::from_static(YEET_METHODS));
+    headers.insert(
+        header::ACCEPT_ENCODING,
+        HeaderValue::from_static(ACCEPTED_ENCODINGS),
+    );
+    headers.insert(
+        &HASHES_HEADER,
+        HeaderValue::from_str(&BUILT_IN_ALGORITHMS.join(", "))
+            .expect("algorithm names are valid header values"),
+    );
+    headers.insert(
+        &MAX_LEASE_HEADER,
+        HeaderValue::from(state.config.files.max_lease().as_secs()),
+    );
+    if let Some(available) = state.backbone.available_storage() {
+        headers.insert(&MAX_BODY_SIZE_HEADER, HeaderValue::from(available));
     }
+    (StatusCode::NO_CONTENT, headers).into_response()
 }
 
 #[derive(Debug, serde::Deserialize)]
 struct QueryParams {
     file_name: Option<String>,
+    /// The metadata of the file, unless sent in the `x-yeet-meta` header.
+    meta: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/yeet",
+    tag = "files",
+    params(
+        ("file_name" = Option<String>, Query, description = "The original name of the file"),
+        ("yy-lease" = Option<u64>, Header, description = "The number of seconds the file is kept available"),
+        ("x-yeet-hashes" = Option<String>, Header, description = "The comma-separated hash algorithms to compute: md5, sha256, blake3, crc32c"),
+        ("x-expected-sha256" = Option<String>, Header, description = "The hex encoded SHA-256 hash the file must match; the upload is rejected otherwise"),
+        ("x-idempotency-key" = Option<String>, Header, description = "Identifies retries of the same upload"),
+        ("x-yeet-meta" = Option<String>, Header, description = "Base64 encoded JSON object of strings attached to the file; alternatively sent in the meta query parameter"),
+        ("x-yeet-wait" = Option<String>, Header, description = "Responds only after at least one (durable) or all (durable-all) backends stored the file"),
+        ("x-yeet-dryrun" = Option<bool>, Header, description = "Only computes the size and hashes of the file instead of storing it"),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream", description = "The file contents"),
+    responses(
+        (status = 200, description = "The file was hashed without storing it (dry run)", body = ValidatedUploadResponse),
+        (status = 201, description = "The file was stored", body = SuccessfulUploadResponse),
+        (status = 207, description = "The file was stored, but some backends failed to store it (with x-yeet-wait)", body = SuccessfulUploadResponse),
+        (status = 400, description = "The request was malformed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 408, description = "The body was not received in time", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "The content encoding or content type is not supported", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 417, description = "The expectation is not supported", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The file does not match the expected SHA-256 hash", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "The upload could not be read or stored", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 502, description = "The backends did not confirm storing the file", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "The upload quota was exceeded", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "The service is shutting down, the backends are busy or too many uploads are in progress", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 507, description = "The service ran out of storage", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[axum::debug_handler]
+#[allow(clippy::too_many_arguments)]
 async fn do_yeet(
     content_length: Option<TypedHeader<ContentLength>>,
     content_type: Option<TypedHeader<ContentType>>,
     content_md5: Option<TypedHeader<ContentMd5>>,
     State(state): State<AppState>,
+    token: Option<Extension<AuthenticatedToken>>,
+    query: Query<QueryParams>,
+    headers: HeaderMap,
+    stream: BodyStream,
+) -> Result<Response, StatusCode> {
+    yeet(
+        None,
+        false,
+        content_length,
+        content_type,
+        content_md5,
+        state,
+        token,
+        query,
+        headers,
+        stream,
+    )
+    .await
+}
+
+#[axum::debug_handler]
+#[allow(clippy::too_many_arguments)]
+async fn do_yeet_with_id(
+    FileId(id): FileId,
+    content_length: Option<TypedHeader<ContentLength>>,
+    content_type: Option<TypedHeader<ContentType>>,
+    content_md5: Option<TypedHeader<ContentMd5>>,
+    State(state): State<AppState>,
+    token: Option<Extension<AuthenticatedToken>>,
+    query: Query<QueryParams>,
+    headers: HeaderMap,
+    stream: BodyStream,
+) -> Result<Response, StatusCode> {
+    yeet(
+        Some(id),
+        false,
+        content_length,
+        content_type,
+        content_md5,
+        state,
+        token,
+        query,
+        headers,
+        stream,
+    )
+    .await
+}
+
+#[axum::debug_handler]
+#[allow(clippy::too_many_arguments)]
+async fn do_yeet_validate(
+    content_length: Option<TypedHeader<ContentLength>>,
+    content_type: Option<TypedHeader<ContentType>>,
+    content_md5: Option<TypedHeader<ContentMd5>>,
+    State(state): State<AppState>,
+    token: Option<Extension<AuthenticatedToken>>,
+    query: Query<QueryParams>,
+    headers: HeaderMap,
+    stream: BodyStream,
+) -> Result<Response, StatusCode> {
+    yeet(
+        None,
+        true,
+        content_length,
+        content_type,
+        content_md5,
+        state,
+        token,
+        query,
+        headers,
+        stream,
+    )
+    .await
+}
+
+/// Stores the body of a request, either under the client-provided `id` or a random one.
+///
+/// If `validate` is set, i.e. for requests to `/yeet/validate`, the body is only hashed.
+#[allow(clippy::too_many_arguments)]
+async fn yeet(
+    id: Option<ShortGuid>,
+    validate: bool,
+    content_length: Option<TypedHeader<ContentLength>>,
+    content_type: Option<TypedHeader<ContentType>>,
+    content_md5: Option<TypedHeader<ContentMd5>>,
+    state: AppState,
+    token: Option<Extension<AuthenticatedToken>>,
     query: Query<QueryParams>,
+    headers: HeaderMap,
     stream: BodyStream,
 ) -> Result<Response, StatusCode> {
+    let (method, route) = match id {
+        Some(_) => (Method::PUT, "/yeet/:id"),
+        None if validate => (Method::POST, "/yeet/validate"),
+        None => (Method::POST, "/yeet"),
+    };
+    let instance = upload_instance(route, id, &state.config);
+
+    if state.shutdown.is_draining() {
+        return Ok(map_shutting_down_to_response(&instance, &state.config));
+    }
+
+    // The body is only read once all checks passed, which is when hyper answers
+    // `Expect: 100-continue`; rejections are therefore sent before the body is transferred.
+    if let Err(e) = check_expectation(&headers) {
+        return Ok(map_expectation_error_to_response(e, &state.config));
+    }
+
     TransferMetrics::track_transfer(TransferMethod::Store);
 
     let content_length = if let Some(TypedHeader(ContentLength(n))) = content_length {
         trace!("Expecting {value} bytes", value = n);
         Some(n)
     } else {
+        // E.g. bodies sent with `Transfer-Encoding: chunked`; the size is known at the end.
+        trace!("Expecting a body of unknown length");
         None
     };
 
@@ -79,6 +454,14 @@ async fn do_yeet(
         None
     };
 
+    if let Err(e) = check_content_type(&state.config.files, content_type.as_ref()) {
+        return Ok(map_content_type_not_allowed_to_response(
+            None,
+            e,
+            &state.config,
+        ));
+    }
+
     let content_md5 = if let Some(TypedHeader(ContentMd5(md5))) = content_md5 {
         trace!("Expecting content MD5 {value}", value = hex::encode(md5));
         Some(md5)
@@ -86,40 +469,387 @@ async fn do_yeet(
         None
     };
 
-    let id = ShortGuid::new_random();
+    let temporal_lease = match parse_temporal_lease(&headers, state.config.files.max_lease()) {
+        Ok(lease) => lease,
+        Err(e) => return Ok(map_lease_header_error_to_response(e, &state.config)),
+    };
 
-    // TODO: Allow capacity? Test whether we have enough resources?
+    let hash_algorithms = match parse_hash_algorithms(&headers) {
+        Ok(algorithms) => algorithms,
+        Err(e) => return Ok(map_hashes_header_error_to_response(e, &state.config)),
+    };
 
-    let mut writer = match state
-        .backbone
-        .new_file(
-            id,
-            content_length,
-            content_type,
-            content_md5,
-            query.file_name.clone(),
-        )
-        .await
-    {
-        Ok(writer) => writer,
-        Err(e) => return Ok(map_new_file_error_to_response(e)),
+    let expected_sha256 = match parse_expected_sha256(&headers) {
+        Ok(sha256) => sha256,
+        Err(e) => return Ok(map_expected_hash_error_to_response(e, &state.config)),
+    };
+
+    let wait = match parse_wait_mode(&headers) {
+        Ok(wait) => wait,
+        Err(e) => return Ok(map_wait_header_error_to_response(e, &state.config)),
+    };
+
+    let dry_run = match parse_dry_run(&headers) {
+        Ok(dry_run) => validate || dry_run,
+        Err(e) => return Ok(map_dry_run_header_error_to_response(e, &state.config)),
+    };
+
+    let content_encoding = match parse_content_encoding(&headers) {
+        Ok(encoding) => encoding,
+        Err(e) => return Ok(map_content_encoding_error_to_response(e, &state.config)),
+    };
+
+    let max_metadata_bytes = state.config.files.max_metadata_bytes();
+    let metadata = match parse_metadata(&headers, &query, max_metadata_bytes) {
+        Ok(metadata) => metadata,
+        Err(e) => return Ok(map_metadata_error_to_response(e, &state.config)),
+    };
+
+    // Dry runs only hash the body and hence do not take one of the upload slots.
+    let _slot = if dry_run {
+        None
+    } else {
+        match state.uploads.acquire().await {
+            Ok(slot) => Some(slot),
+            Err(e) => return Ok(map_uploads_busy_to_response(e, &instance, &state.config)),
+        }
+    };
+
+    let upload = Upload {
+        id,
+        content_length,
+        // The Content-Length of compressed uploads describes the compressed body,
+        // which is checked below; the size of the decompressed file is unknown.
+        expected_file_size: content_length.filter(|_| content_encoding.is_none()),
+        content_type,
+        content_md5,
+        expected_sha256,
+        file_name: parse_file_name(&headers, &query),
+        temporal_lease,
+        hash_algorithms,
+        idempotency_key: parse_idempotency_key(&headers),
+        response_format: ResponseFormat::from_headers(&headers),
+        token: token.map(|Extension(token)| token),
+        compressed: content_encoding.is_some(),
+        wait,
+        dry_run,
+        metadata,
+    };
+
+    // Count the bytes as received, i.e. before decompression.
+    let bytes_received = Arc::new(AtomicU64::new(0));
+    let stream = decode_body(stream, content_encoding, bytes_received.clone());
+    Ok(store_upload(&state, upload, stream, bytes_received, method, route).await)
+}
+
+#[axum::debug_handler]
+async fn do_yeet_form(
+    State(state): State<AppState>,
+    token: Option<Extension<AuthenticatedToken>>,
+    query: Query<QueryParams>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Response, StatusCode> {
+    let instance = public_path(state.config.base_path(), "/yeet/form");
+    if state.shutdown.is_draining() {
+        return Ok(map_shutting_down_to_response(&instance, &state.config));
+    }
+
+    if let Err(e) = check_expectation(&headers) {
+        return Ok(map_expectation_error_to_response(e, &state.config));
+    }
+
+    TransferMetrics::track_transfer(TransferMethod::Store);
+
+    let temporal_lease = match parse_temporal_lease(&headers, state.config.files.max_lease()) {
+        Ok(lease) => lease,
+        Err(e) => return Ok(map_lease_header_error_to_response(e, &state.config)),
+    };
+
+    let hash_algorithms = match parse_hash_algorithms(&headers) {
+        Ok(algorithms) => algorithms,
+        Err(e) => return Ok(map_hashes_header_error_to_response(e, &state.config)),
+    };
+
+    let expected_sha256 = match parse_expected_sha256(&headers) {
+        Ok(sha256) => sha256,
+        Err(e) => return Ok(map_expected_hash_error_to_response(e, &state.config)),
+    };
+
+    let wait = match parse_wait_mode(&headers) {
+        Ok(wait) => wait,
+        Err(e) => return Ok(map_wait_header_error_to_response(e, &state.config)),
+    };
+
+    let dry_run = match parse_dry_run(&headers) {
+        Ok(dry_run) => dry_run,
+        Err(e) => return Ok(map_dry_run_header_error_to_response(e, &state.config)),
+    };
+
+    let max_metadata_bytes = state.config.files.max_metadata_bytes();
+    let metadata = match parse_metadata(&headers, &query, max_metadata_bytes) {
+        Ok(metadata) => metadata,
+        Err(e) => return Ok(map_metadata_error_to_response(e, &state.config)),
+    };
+
+    let _slot = if dry_run {
+        None
+    } else {
+        match state.uploads.acquire().await {
+            Ok(slot) => Some(slot),
+            Err(e) => return Ok(map_uploads_busy_to_response(e, &instance, &state.config)),
+        }
+    };
+
+    // Store the first file field; fields without a file name are skipped.
+    let field = loop {
+        match multipart.next_field().await {
+            Ok(Some(field)) if field.file_name().is_some() => break field,
+            Ok(Some(_)) => continue,
+            Ok(None) => return Ok(map_missing_file_field_to_response(&state.config)),
+            Err(e) => return Ok(map_multipart_error_to_response(e, &state.config)),
+        }
+    };
+
+    let content_type = field
+        .content_type()
+        .and_then(|value| value.parse::<ContentType>().ok());
+    if let Some(content_type) = &content_type {
+        trace!("Expecting MIME type {value}", value = content_type);
+    }
+
+    if let Err(e) = check_content_type(&state.config.files, content_type.as_ref()) {
+        return Ok(map_content_type_not_allowed_to_response(
+            None,
+            e,
+            &state.config,
+        ));
+    }
+
+    let upload = Upload {
+        id: None,
+        content_length: None,
+        expected_file_size: None,
+        content_type,
+        content_md5: None,
+        expected_sha256,
+        file_name: field
+            .file_name()
+            .and_then(sanitize_file_name)
+            .or_else(|| query.file_name.as_deref().and_then(sanitize_file_name)),
+        temporal_lease,
+        hash_algorithms,
+        idempotency_key: parse_idempotency_key(&headers),
+        response_format: ResponseFormat::from_headers(&headers),
+        token: token.map(|Extension(token)| token),
+        compressed: false,
+        wait,
+        dry_run,
+        metadata,
+    };
+
+    let bytes_received = Arc::new(AtomicU64::new(0));
+    let stream = field_body(field, bytes_received.clone());
+    Ok(store_upload(
+        &state,
+        upload,
+        stream,
+        bytes_received,
+        Method::POST,
+        "/yeet/form",
+    )
+    .await)
+}
+
+/// The parameters of an upload, independent of how its body is sent.
+struct Upload {
+    /// The ID requested by the client; a random ID is generated if absent.
+    id: Option<ShortGuid>,
+    /// The announced size of the body, which must match the bytes received.
+    content_length: Option<u64>,
+    /// The expected size of the stored file, if known.
+    expected_file_size: Option<u64>,
+    content_type: Option<ContentType>,
+    content_md5: Option<[u8; 16]>,
+    /// The SHA-256 hash the stored file must match, as per `X-Expected-SHA256` header.
+    expected_sha256: Option<[u8; 32]>,
+    file_name: Option<String>,
+    temporal_lease: Option<Duration>,
+    hash_algorithms: HashAlgorithms,
+    idempotency_key: Option<String>,
+    response_format: ResponseFormat,
+    /// The token the upload is authenticated with, if any.
+    token: Option<AuthenticatedToken>,
+    /// Whether the body is decompressed while it is received.
+    compressed: bool,
+    /// Whether to respond only after the backends stored the file.
+    wait: Option<WaitMode>,
+    /// Whether to only hash the body instead of storing it.
+    dry_run: bool,
+    /// The key/value metadata attached to the file.
+    metadata: BTreeMap<String, String>,
+}
+
+/// Selects the backends that need to store a file before the upload is confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WaitMode {
+    /// At least one backend needs to store the file.
+    Durable,
+    /// All backends need to store the file.
+    DurableAll,
+}
+
+impl WaitMode {
+    /// Determines whether the outcome of a distribution satisfies the wait mode.
+    fn is_satisfied_by(self, outcome: &DistributionOutcome) -> bool {
+        match self {
+            WaitMode::Durable => !outcome.succeeded().is_empty(),
+            WaitMode::DurableAll => !outcome.succeeded().is_empty() && outcome.failed().is_empty(),
+        }
+    }
+}
+
+/// Stores the body of an upload and builds the response describing the file.
+///
+/// The `method` and `route` are used to track the size of the upload.
+async fn store_upload(
+    state: &AppState,
+    upload: Upload,
+    mut stream: BodyChunks<'_>,
+    bytes_received: Arc<AtomicU64>,
+    method: Method,
+    route: &str,
+) -> Response {
+    if upload.dry_run {
+        return validate_upload(state, upload, stream, bytes_received, method, route).await;
+    }
+
+    let content_type_name = upload.content_type.as_ref().map(ContentType::to_string);
+
+    // Retries of an upload that already completed refer to the existing file.
+    if let Some(key) = &upload.idempotency_key {
+        if let Some(existing) = state.backbone.find_by_idempotency_key(key).await {
+            debug!(file_id = %existing.id, "Upload with idempotency key {key:?} refers to existing file {id}", id = existing.id);
+            return existing_file_response(upload.response_format, existing);
+        }
+    }
+
+    // Uploads count against the quota of the token they are authenticated with,
+    // already while they are in progress.
+    let mut quota = match upload.token {
+        Some(token) => match state.quotas.reserve(token, upload.expected_file_size) {
+            Ok(reservation) => Some(reservation),
+            Err(e) => {
+                debug!("Rejecting upload: {e}");
+                return map_quota_exceeded_to_response(e, &state.config);
+            }
+        },
+        None => None,
+    };
+
+    let instance = upload_instance(route, upload.id, &state.config);
+
+    // Random IDs may collide with a live file, albeit rarely; a fresh ID is drawn then.
+    // Client-provided IDs are tried only once.
+    let mut attempts = 0;
+    let (id, mut writer) = loop {
+        attempts += 1;
+        let id = upload.id.unwrap_or_else(ShortGuid::new_random);
+        match state
+            .backbone
+            .new_file(
+                id,
+                upload.expected_file_size,
+                upload.content_type.clone(),
+                upload.content_md5,
+                upload.file_name.clone(),
+                upload.temporal_lease,
+            )
+            .await
+        {
+            Ok(writer) => break (id, writer),
+            Err(NewFileError::IdAlreadyExists(id))
+                if upload.id.is_none() && attempts < MAX_ID_ATTEMPTS =>
+            {
+                warn!(file_id = %id, "Generated file ID {id} is already in use, retrying");
+            }
+            Err(NewFileError::IdAlreadyExists(id)) if upload.id.is_none() => {
+                return map_id_attempts_exhausted_to_response(
+                    id,
+                    attempts,
+                    &instance,
+                    &state.config,
+                )
+            }
+            Err(e) => return map_new_file_error_to_response(e, &instance, &state.config),
+        }
     };
+    record_file_id(id);
+
+    writer.set_expected_sha256(upload.expected_sha256);
+    writer.select_hashes(upload.hash_algorithms);
+    writer.set_idempotency_key(upload.idempotency_key);
+    writer.set_metadata(upload.metadata);
 
-    let mut stream = Box::pin(stream);
+    // The detected content type is only of interest if it may be rejected.
+    let sniff =
+        state.config.downloads.sniff_content_type && state.config.files.restricts_content_types();
+    let mut head = Vec::new();
 
+    let sync_policy = state.config.files.sync_policy;
+    let slow_sync = state.config.files.slow_sync_threshold();
+    let idle_timeout = state.config.timeouts.upload_idle_timeout();
+    let deadline = state
+        .config
+        .timeouts
+        .upload_timeout()
+        .map(|timeout| Instant::now() + timeout);
     let mut bytes_written = 0;
-    while let Some(result) = stream.next().await {
+    loop {
+        let result = match next_chunk(&mut stream, idle_timeout, deadline).await {
+            Ok(Some(result)) => result,
+            Ok(None) => break,
+            Err(timeout) => {
+                let received = bytes_received.load(Ordering::Relaxed);
+                return map_upload_timeout_to_response(Some(id), timeout, received, &state.config);
+            }
+        };
+
         let mut data = match result {
             Ok(data) => data,
+            Err(e) if upload.compressed && is_decoding_error(&e) => {
+                return map_decoding_error_to_response(Some(id), e, &state.config);
+            }
             Err(e) => {
-                return Ok((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to obtain data from the read stream: {e}"),
+                return map_storage_error_to_response(
+                    ProblemType::UploadReadFailed,
+                    Some(id),
+                    &instance,
+                    "Failed to obtain data from the read stream",
+                    e,
+                    &state.config,
                 )
-                    .into_response())
             }
         };
 
+        // Reject bodies exceeding the announced length before writing the excess.
+        if let Some(expected) = upload.content_length {
+            let received = bytes_received.load(Ordering::Relaxed);
+            if received > expected {
+                return map_content_length_mismatch_to_response(
+                    Some(id),
+                    expected,
+                    received,
+                    &state.config,
+                );
+            }
+        }
+
+        if sniff && head.len() < SNIFF_BYTES as usize {
+            let n = data.len().min(SNIFF_BYTES as usize - head.len());
+            head.extend_from_slice(&data[..n]);
+        }
+
         while data.has_remaining() {
             let chunk = data.chunk();
             match writer.write(chunk).await {
@@ -129,127 +859,3142 @@ async fn do_yeet(
                     data.advance(n);
                 }
                 Err(e) => {
-                    return Ok((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to write to temporary file: {e}"),
-                    )
-                        .into_response())
+                    if let Some(e) = as_insufficient_storage(&e) {
+                        return map_insufficient_storage_to_response(id, e, &state.config);
+                    }
+
+                    return map_storage_error_to_response(
+                        ProblemType::FileWriteFailed,
+                        Some(id),
+                        &instance,
+                        "Failed to write to temporary file",
+                        e,
+                        &state.config,
+                    );
                 }
             }
         }
 
-        match writer.sync_data().await {
+        if let Some(quota) = &mut quota {
+            quota.grow_to(bytes_written as u64);
+        }
+
+        let sync = sync_policy == SyncPolicy::SyncPerChunk;
+        let started = Instant::now();
+        let committed = writer.commit(sync).await;
+        if sync {
+            track_sync(id, SyncOperation::Sync, started, slow_sync);
+        }
+        match committed {
             Ok(_) => {}
             Err(e) => {
-                return Ok((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to flush data to temporary file: {e}"),
+                return map_storage_error_to_response(
+                    ProblemType::FileWriteFailed,
+                    Some(id),
+                    &instance,
+                    "Failed to flush data to temporary file",
+                    e,
+                    &state.config,
                 )
-                    .into_response())
             }
         }
     }
 
-    // The file was already synced to disk in the last iteration, so
-    // we can skip the sync here.
-    // TODO: Add server-side validation of MD5 value if header is present.
-    let write_result = match writer.finalize(CompletionMode::NoSync).await {
-        Ok(write_result) => write_result,
+    // Reject truncated uploads; dropping the writer removes the file.
+    if let Some(expected) = upload.content_length {
+        let received = bytes_received.load(Ordering::Relaxed);
+        if received != expected {
+            return map_content_length_mismatch_to_response(
+                Some(id),
+                expected,
+                received,
+                &state.config,
+            );
+        }
+    }
+
+    // Reject uploads whose content is detected as a type that is not allowed;
+    // dropping the writer removes the file.
+    if let Some(kind) = infer::get(&head) {
+        let detected = kind.mime_type();
+        if !state.config.files.is_content_type_allowed(detected) {
+            debug!(file_id = %id, "Rejecting upload {id} detected as {detected}");
+            let e = ContentTypeNotAllowed(detected.to_string());
+            return map_content_type_not_allowed_to_response(Some(id), e, &state.config);
+        }
+    }
+
+    // The outcome is requested before writing completes, which starts the distribution.
+    let distribution = match upload.wait {
+        Some(mode) => Some((mode, state.backbone.await_distribution(id).await)),
+        None => None,
+    };
+
+    let started = Instant::now();
+    let finalized = writer
+        .finalize_deduplicated(completion_mode(sync_policy))
+        .await;
+    track_sync(id, SyncOperation::Finalize, started, slow_sync);
+    let finalized = match finalized {
+        Ok(finalized) => finalized,
+        // The writer removed the file.
+        Err(FinalizationError::IntegrityCheckFailed(expected, actual)) => {
+            debug!(file_id = %id, "Rejecting upload {id} not matching its Content-MD5 header");
+            return map_md5_mismatch_to_response(Some(id), &expected, &actual, &state.config);
+        }
+        Err(FinalizationError::Sha256Mismatch(expected, actual)) => {
+            debug!(file_id = %id, "Rejecting upload {id} not matching the expected SHA-256 hash");
+            return map_hash_mismatch_to_response(Some(id), &expected, &actual, &state.config);
+        }
         Err(e) => {
-            return Ok((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to complete writing to temporary file: {e}"),
+            return map_storage_error_to_response(
+                ProblemType::FileWriteFailed,
+                Some(id),
+                &instance,
+                "Failed to complete writing to temporary file",
+                e,
+                &state.config,
             )
-                .into_response())
         }
     };
 
     debug!(
         file_id = %id,
-        "Stream ended, buffered {bytes} bytes to disk; {hashes}",
-        bytes = bytes_written,
-        hashes = write_result.hashes
+        "Stream ended, buffered {bytes} bytes to disk",
+        bytes = bytes_written
+    );
+    HttpMetrics::track_request_size(
+        route,
+        method,
+        bytes_received.load(Ordering::Relaxed) as usize,
     );
 
-    let mut response = axum::Json(SuccessfulUploadResponse {
-        id,
-        file_size_bytes: write_result.file_size_bytes,
-        hashes: (&write_result.hashes).into(),
-    })
-    .into_response();
+    let write_result = match finalized {
+        Finalized::Stored(write_result) => write_result,
+        Finalized::Duplicate(existing, write_result) => {
+            debug!(file_id = %id, "Upload {id} duplicates existing file {existing}; discarded it");
 
-    let expiration_date = expiration_as_rfc1123(&write_result.expires);
+            // The existing file may not have been registered completely yet; since
+            // the content is identical, the summary of the upload describes it as well.
+            let existing = match state.backbone.find_existing(existing).await {
+                Some(existing) => existing,
+                None => ExistingFile {
+                    id: existing,
+                    expires: write_result.expires,
+                    summary: write_result,
+                    content_type: upload.content_type,
+                },
+            };
+            return existing_file_response(upload.response_format, existing);
+        }
+    };
 
-    *response.status_mut() = StatusCode::CREATED;
-    let headers = response.headers_mut();
+    debug!(file_id = %id, "Stored file {id}; {hashes}", hashes = write_result.hashes);
+    if let Some(quota) = quota {
+        quota.settle(write_result.file_size_bytes as u64);
+    }
 
-    // Set the file expiration.
-    headers
-        .entry(EXPIRES)
-        .or_insert(HeaderValue::from_str(&expiration_date).expect("invalid time input provided"));
+    let mut status = StatusCode::CREATED;
+    let mut backends = None;
+    if let Some((mode, outcome)) = distribution {
+        debug!(file_id = %id, "Waiting for the backends to store file {id}");
+        let outcome = match outcome {
+            Ok(outcome) => outcome.await.ok(),
+            Err(_) => None,
+        };
+        match outcome {
+            Some(outcome) if mode.is_satisfied_by(&outcome) => {
+                debug!(file_id = %id, "File {id} was stored by backends {backends:?}", backends = outcome.succeeded());
+                // Clients may keep their copy unless all backends stored the file.
+                if !outcome.failed().is_empty() {
+                    status = StatusCode::MULTI_STATUS;
+                }
+                backends = Some(outcome);
+            }
+            outcome => {
+                return map_distribution_failed_to_response(id, outcome, &instance, &state.config)
+            }
+        }
+    }
 
-    // Add the ID as a separate header to simplify testing.
-    let id = format!("{id}");
-    headers
-        .entry(&ID_HEADER)
-        .or_insert(HeaderValue::from_str(&id).expect("invalid ID input provided"));
+    let metrics = &state.config.metrics;
+    if metrics.tracks_content_types() {
+        TransferMetrics::track_content_type(
+            metrics.content_type_label(content_type_name.as_deref()),
+        );
+    }
 
-    Ok(response)
+    let mut response = upload_response(
+        upload.response_format,
+        status,
+        id,
+        &write_result,
+        &write_result.expires,
+        content_type_name,
+        backends.as_ref(),
+    );
+    if let Some(outcome) = &backends {
+        if let Ok(value) = HeaderValue::from_str(&backend_results(outcome)) {
+            response
+                .headers_mut()
+                .insert(BACKENDS_HEADER.clone(), value);
+        }
+    }
+    response
 }
 
-#[derive(Serialize)]
-struct SuccessfulUploadResponse {
-    /// The ID of the file.
+/// Gets the public path of an upload to `route`; client-provided IDs are part of the request path.
+fn upload_instance(route: &str, id: Option<ShortGuid>, config: &AppConfig) -> String {
+    match id {
+        Some(id) => public_path(config.base_path(), format!("/yeet/{id}")),
+        None => public_path(config.base_path(), route),
+    }
+}
+
+/// Lists the result of each backend, e.g. `memory=stored, s3=failed`.
+fn backend_results(outcome: &DistributionOutcome) -> String {
+    outcome
+        .backends
+        .iter()
+        .map(|(tag, result)| format!("{tag}={result}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Hashes the body of a dry-run upload without storing it and builds the response
+/// describing its size and hashes.
+///
+/// No file is created, hence neither quotas nor storage headroom apply.
+async fn validate_upload(
+    state: &AppState,
+    upload: Upload,
+    mut stream: BodyChunks<'_>,
+    bytes_received: Arc<AtomicU64>,
+    method: Method,
+    route: &str,
+) -> Response {
+    let idle_timeout = state.config.timeouts.upload_idle_timeout();
+    let deadline = state
+        .config
+        .timeouts
+        .upload_timeout()
+        .map(|timeout| Instant::now() + timeout);
+    let mut algorithms = upload.hash_algorithms;
+    if upload.expected_sha256.is_some() {
+        algorithms = algorithms.with_sha256();
+    }
+    // Like for stored uploads, MD5 is computed whenever a Content-MD5 header is provided.
+    if upload.content_md5.is_some() {
+        algorithms = algorithms.with_md5();
+    }
+    let mut hasher = FileHasher::new(algorithms);
+    let mut file_size_bytes = 0;
+    loop {
+        let result = match next_chunk(&mut stream, idle_timeout, deadline).await {
+            Ok(Some(result)) => result,
+            Ok(None) => break,
+            Err(timeout) => {
+                let received = bytes_received.load(Ordering::Relaxed);
+                return map_upload_timeout_to_response(None, timeout, received, &state.config);
+            }
+        };
+
+        let data = match result {
+            Ok(data) => data,
+            Err(e) if upload.compressed && is_decoding_error(&e) => {
+                return map_decoding_error_to_response(None, e, &state.config);
+            }
+            Err(e) => {
+                return map_storage_error_to_response(
+                    ProblemType::UploadReadFailed,
+                    None,
+                    &public_path(state.config.base_path(), route),
+                    "Failed to obtain data from the read stream",
+                    e,
+                    &state.config,
+                )
+            }
+        };
+
+        if let Some(expected) = upload.content_length {
+            let received = bytes_received.load(Ordering::Relaxed);
+            if received > expected {
+                return map_content_length_mismatch_to_response(
+                    None,
+                    expected,
+                    received,
+                    &state.config,
+                );
+            }
+        }
+
+        hasher.update(&data);
+        file_size_bytes += data.len();
+    }
+
+    if let Some(expected) = upload.content_length {
+        let received = bytes_received.load(Ordering::Relaxed);
+        if received != expected {
+            return map_content_length_mismatch_to_response(
+                None,
+                expected,
+                received,
+                &state.config,
+            );
+        }
+    }
+
+    HttpMetrics::track_request_size(
+        route,
+        method,
+        bytes_received.load(Ordering::Relaxed) as usize,
+    );
+
+    let hashes = hasher.finalize();
+    if let (Some(expected), Some(actual)) = (upload.content_md5, hashes.md5.as_ref()) {
+        if expected.ne(&actual[..]) {
+            return map_md5_mismatch_to_response(
+                None,
+                &hex::encode(expected),
+                &hex::encode(&actual[..]),
+                &state.config,
+            );
+        }
+    }
+    if let (Some(expected), Some(actual)) = (upload.expected_sha256, hashes.sha256.as_ref()) {
+        // Compare in constant time, just like stored uploads are.
+        if !bool::from(expected[..].ct_eq(&actual[..])) {
+            return map_hash_mismatch_to_response(
+                None,
+                &hex::encode(expected),
+                &hex::encode(&actual[..]),
+                &state.config,
+            );
+        }
+    }
+
+    debug!("Validated upload of {file_size_bytes} bytes without storing it; {hashes}");
+    upload.response_format.respond(
+        &ValidatedUploadResponse {
+            file_size_bytes,
+            hashes: (&hashes).into(),
+        },
+        &ItemMetadata {
+            file_name: upload.file_name,
+            hashes: Some((&hashes).into()),
+            content_type: upload.content_type.as_ref().map(ContentType::to_string),
+            file_size_bytes: Some(file_size_bytes as u64),
+            ..ItemMetadata::default()
+        },
+    )
+}
+
+/// The reason an upload was aborted before its body was received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UploadTimeout {
+    /// No data arrived for the specified time.
+    Idle(Duration),
+    /// The body was not received completely before the deadline.
+    Deadline,
+}
+
+/// Waits for the next chunk of the body, giving up if none arrives within the
+/// `idle_timeout` or before the `deadline` of the upload.
+async fn next_chunk(
+    stream: &mut BodyChunks<'_>,
+    idle_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> Result<Option<std::io::Result<Bytes>>, UploadTimeout> {
+    let idle = idle_timeout.map(|timeout| (Instant::now() + timeout, UploadTimeout::Idle(timeout)));
+    let overall = deadline.map(|deadline| (deadline, UploadTimeout::Deadline));
+    let Some((wait_until, timeout)) = idle.into_iter().chain(overall).min_by_key(|(at, _)| *at)
+    else {
+        return Ok(stream.next().await);
+    };
+
+    tokio::time::timeout_at(wait_until, stream.next())
+        .await
+        .map_err(|_| timeout)
+}
+
+/// Records the duration of syncing or finalizing an upload since `started`,
+/// warning if it took at least `threshold`.
+fn track_sync(id: ShortGuid, operation: SyncOperation, started: Instant, threshold: Duration) {
+    let elapsed = started.elapsed();
+    BackboneMetrics::track_sync(operation, elapsed);
+    if elapsed >= threshold {
+        warn!(
+            file_id = %id,
+            "Slow {operation} of file {id}: took {elapsed:?}, exceeding {threshold:?}"
+        );
+    }
+}
+
+/// Selects how a file is completed under the configured sync policy.
+///
+/// Files synced per chunk were already synced with the last chunk.
+fn completion_mode(policy: SyncPolicy) -> CompletionMode {
+    match policy {
+        SyncPolicy::SyncOnFinalize => CompletionMode::Sync,
+        SyncPolicy::NoSync | SyncPolicy::SyncPerChunk => CompletionMode::NoSync,
+    }
+}
+
+/// A stream of body chunks.
+type BodyChunks<'a> = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send + 'a>>;
+
+/// Decompresses the request body according to its content coding, counting the
+/// bytes received before decompression.
+fn decode_body(
+    stream: BodyStream,
+    content_encoding: Option<ContentCoding>,
+    bytes_received: Arc<AtomicU64>,
+) -> BodyChunks<'static> {
+    let stream = stream.map(move |result| {
+        let data = result.map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        bytes_received.fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(data)
+    });
+
+    match content_encoding {
+        None => Box::pin(stream),
+        Some(ContentCoding::Gzip) => Box::pin(ReaderStream::new(GzipDecoder::new(
+            StreamReader::new(stream),
+        ))),
+        Some(ContentCoding::Deflate) => Box::pin(ReaderStream::new(DeflateDecoder::new(
+            StreamReader::new(stream),
+        ))),
+    }
+}
+
+/// Streams the contents of a form field, counting the bytes received.
+fn field_body(field: Field<'_>, bytes_received: Arc<AtomicU64>) -> BodyChunks<'_> {
+    Box::pin(field.map(move |result| {
+        let data = result.map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+        bytes_received.fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(data)
+    }))
+}
+
+/// Streams the contents of a completely received part file, counting the bytes read.
+fn file_body(file: tokio::fs::File, bytes_received: Arc<AtomicU64>) -> BodyChunks<'static> {
+    Box::pin(ReaderStream::new(file).map(move |result| {
+        let data = result?;
+        bytes_received.fetch_add(data.len() as u64, Ordering::Relaxed);
+        Ok(data)
+    }))
+}
+
+/// Determines whether reading the body failed because it could not be decompressed,
+/// as opposed to failing to receive it.
+fn is_decoding_error(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof
+    )
+}
+
+/// Builds the `200 OK` response referring to an existing file.
+fn existing_file_response(format: ResponseFormat, existing: ExistingFile) -> Response {
+    upload_response(
+        format,
+        StatusCode::OK,
+        existing.id,
+        &existing.summary,
+        &existing.expires,
+        existing.content_type.as_ref().map(ContentType::to_string),
+        None,
+    )
+}
+
+/// Builds the response describing a stored file.
+fn upload_response(
+    format: ResponseFormat,
+    status: StatusCode,
+    id: ShortGuid,
+    summary: &Arc<WriteSummary>,
+    expires: &Instant,
+    content_type: Option<String>,
+    backends: Option<&DistributionOutcome>,
+) -> Response {
+    let mut response = format.respond(
+        &SuccessfulUploadResponse {
+            id,
+            file_size_bytes: summary.file_size_bytes,
+            hashes: (&summary.hashes).into(),
+            backends: backends.map(backend_result_map),
+        },
+        &ItemMetadata::new(id, summary).with_content_type(content_type),
+    );
+
+    let expiration_date = expiration_as_rfc1123(expires);
+
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+
+    // Set the file expiration.
+    headers
+        .entry(EXPIRES)
+        .or_insert(HeaderValue::from_str(&expiration_date).expect("invalid time input provided"));
+
+    // Add the ID as a separate header to simplify testing.
+    let id = format!("{id}");
+    headers
+        .entry(&ID_HEADER)
+        .or_insert(HeaderValue::from_str(&id).expect("invalid ID input provided"));
+
+    response
+}
+
+/// Returns the distribution receipt of a file, signed if a signing key is configured.
+#[axum::debug_handler]
+async fn get_receipt(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    let Some(receipt) = state.receipts.receipt(id) else {
+        return ProblemType::ReceiptNotFound
+            .problem(&state.config.errors)
+            .with_detail(format!(
+                "No distribution receipt exists for the file with ID {id}"
+            ))
+            .with_instance(public_path(
+                state.config.base_path(),
+                format!("/yeet/{id}/receipt"),
+            ))
+            .with_value("id", id.to_string())
+            .into_response();
+    };
+
+    let key = state.config.receipts.signing_key.as_deref();
+    axum::Json(receipt.sign(key.map(str::as_bytes))).into_response()
+}
+
+/// Returns the upload progress of a file.
+#[axum::debug_handler]
+async fn get_status(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    match state.backbone.upload_progress(id).await {
+        Ok(progress) => axum::Json(UploadStatusResponse {
+            id,
+            bytes_received: progress.bytes_received,
+            bytes_expected: progress.bytes_expected,
+            completed: progress.completed,
+        })
+        .into_response(),
+        Err(e) => ProblemType::from(&e)
+            .problem(&state.config.errors)
+            .with_detail(e.to_string())
+            .with_instance(public_path(
+                state.config.base_path(),
+                format!("/yeet/{id}/status"),
+            ))
+            .with_value("id", id.to_string())
+            .into_response(),
+    }
+}
+
+/// Opens a session for a resumable upload.
+#[axum::debug_handler]
+async fn create_resumable_upload(
+    content_type: Option<TypedHeader<ContentType>>,
+    State(state): State<AppState>,
+    query: Query<QueryParams>,
+    headers: HeaderMap,
+) -> Response {
+    let instance = public_path(state.config.base_path(), "/yeet/resumable");
+    if state.shutdown.is_draining() {
+        return map_shutting_down_to_response(&instance, &state.config);
+    }
+
+    let temporal_lease = match parse_temporal_lease(&headers, state.config.files.max_lease()) {
+        Ok(lease) => lease,
+        Err(e) => return map_lease_header_error_to_response(e, &state.config),
+    };
+
+    let hash_algorithms = match parse_hash_algorithms(&headers) {
+        Ok(algorithms) => algorithms,
+        Err(e) => return map_hashes_header_error_to_response(e, &state.config),
+    };
+
+    let content_type = content_type.map(|TypedHeader(content_type)| content_type);
+    if let Err(e) = check_content_type(&state.config.files, content_type.as_ref()) {
+        return map_content_type_not_allowed_to_response(None, e, &state.config);
+    }
+
+    let max_metadata_bytes = state.config.files.max_metadata_bytes();
+    let metadata = match parse_metadata(&headers, &query, max_metadata_bytes) {
+        Ok(metadata) => metadata,
+        Err(e) => return map_metadata_error_to_response(e, &state.config),
+    };
+
+    let options = UploadSessionOptions {
+        content_type,
+        file_name: parse_file_name(&headers, &query),
+        temporal_lease,
+        hash_algorithms,
+        metadata,
+    };
+
+    let mut attempts = 0;
+    let session = loop {
+        attempts += 1;
+        let id = ShortGuid::new_random();
+        match state
+            .backbone
+            .create_upload_session(id, options.clone())
+            .await
+        {
+            Ok(session) => break session,
+            Err(NewFileError::IdAlreadyExists(id)) if attempts < MAX_ID_ATTEMPTS => {
+                warn!(file_id = %id, "Generated file ID {id} is already in use, retrying");
+            }
+            Err(NewFileError::IdAlreadyExists(id)) => {
+                return map_id_attempts_exhausted_to_response(
+                    id,
+                    attempts,
+                    &instance,
+                    &state.config,
+                )
+            }
+            Err(e) => return map_new_file_error_to_response(e, &instance, &state.config),
+        }
+    };
+
+    let id = session.id();
+    record_file_id(id);
+    debug!(file_id = %id, "Opened resumable upload {id}");
+
+    let mut response = (
+        StatusCode::CREATED,
+        axum::Json(ResumableUploadResponse::new(id, &session.status())),
+    )
+        .into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::LOCATION,
+        HeaderValue::from_str(&public_path(
+            state.config.base_path(),
+            format!("/yeet/resumable/{id}"),
+        ))
+        .expect("invalid ID provided"),
+    );
+    headers.insert(
+        &ID_HEADER,
+        HeaderValue::from_str(&id.to_string()).expect("invalid ID provided"),
+    );
+    response
+}
+
+/// Reports the byte ranges received for a resumable upload.
+#[axum::debug_handler]
+async fn get_resumable_upload(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    match state.backbone.upload_session(id) {
+        Some(session) => {
+            axum::Json(ResumableUploadResponse::new(id, &session.status())).into_response()
+        }
+        None => map_unknown_upload_session_to_response(id, &state.config),
+    }
+}
+
+/// Aborts a resumable upload, discarding the chunks received.
+#[axum::debug_handler]
+async fn abort_resumable_upload(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    match state.backbone.remove_upload_session(id) {
+        Some(_) => {
+            debug!(file_id = %id, "Aborted resumable upload {id}");
+            StatusCode::NO_CONTENT.into_response()
+        }
+        None => map_unknown_upload_session_to_response(id, &state.config),
+    }
+}
+
+/// Writes a chunk of a resumable upload, storing the file once all bytes were received.
+#[axum::debug_handler]
+async fn append_resumable_upload(
+    FileId(id): FileId,
+    content_length: Option<TypedHeader<ContentLength>>,
+    State(state): State<AppState>,
+    token: Option<Extension<AuthenticatedToken>>,
+    headers: HeaderMap,
+    stream: BodyStream,
+) -> Response {
+    let instance = public_path(state.config.base_path(), format!("/yeet/resumable/{id}"));
+    if state.shutdown.is_draining() {
+        return map_shutting_down_to_response(&instance, &state.config);
+    }
+
+    if let Err(e) = check_expectation(&headers) {
+        return map_expectation_error_to_response(e, &state.config);
+    }
+
+    let range = match parse_content_range(&headers) {
+        Ok(range) => range,
+        Err(e) => return map_content_range_error_to_response(id, e, &state.config),
+    };
+    let expected = range.end - range.start;
+    if let Some(TypedHeader(ContentLength(length))) = content_length {
+        if length != expected {
+            let error = ContentRangeError::LengthMismatch(length, expected);
+            return map_content_range_error_to_response(id, error, &state.config);
+        }
+    }
+
+    let wait = match parse_wait_mode(&headers) {
+        Ok(wait) => wait,
+        Err(e) => return map_wait_header_error_to_response(e, &state.config),
+    };
+
+    let Some(session) = state.backbone.upload_session(id) else {
+        return map_unknown_upload_session_to_response(id, &state.config);
+    };
+    if let Err(e) = session.announce(range.end, range.total) {
+        return map_content_range_error_to_response(id, e.into(), &state.config);
+    }
+    if let Err(e) = state.backbone.reserve_upload_session(&session) {
+        return map_new_file_error_to_response(e, &instance, &state.config);
+    }
+
+    // The slot is held until the last chunk was stored along with the file.
+    let _slot = match state.uploads.acquire().await {
+        Ok(slot) => slot,
+        Err(e) => return map_uploads_busy_to_response(e, &instance, &state.config),
+    };
+
+    let mut file = match session.open_at(range.start).await {
+        Ok(file) => file,
+        Err(e) => {
+            return map_storage_error_to_response(
+                ProblemType::FileWriteFailed,
+                Some(id),
+                &instance,
+                "Failed to open the part file",
+                e,
+                &state.config,
+            )
+        }
+    };
+
+    let idle_timeout = state.config.timeouts.upload_idle_timeout();
+    let deadline = state
+        .config
+        .timeouts
+        .upload_timeout()
+        .map(|timeout| Instant::now() + timeout);
+    let bytes_received = Arc::new(AtomicU64::new(0));
+    let mut stream = decode_body(stream, None, bytes_received.clone());
+    let mut written = 0;
+    let failure = loop {
+        let result = match next_chunk(&mut stream, idle_timeout, deadline).await {
+            Ok(Some(result)) => result,
+            Ok(None) => break None,
+            Err(timeout) => {
+                let received = bytes_received.load(Ordering::Relaxed);
+                break Some(map_upload_timeout_to_response(
+                    Some(id),
+                    timeout,
+                    received,
+                    &state.config,
+                ));
+            }
+        };
+
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => {
+                break Some(map_storage_error_to_response(
+                    ProblemType::UploadReadFailed,
+                    Some(id),
+                    &instance,
+                    "Failed to obtain data from the read stream",
+                    e,
+                    &state.config,
+                ))
+            }
+        };
+
+        if written + data.len() as u64 > expected {
+            let error = ContentRangeError::TooLong(expected);
+            break Some(map_content_range_error_to_response(
+                id,
+                error,
+                &state.config,
+            ));
+        }
+
+        if let Err(e) = file.write_all(&data).await {
+            break Some(map_storage_error_to_response(
+                ProblemType::FileWriteFailed,
+                Some(id),
+                &instance,
+                "Failed to write to the part file",
+                e,
+                &state.config,
+            ));
+        }
+        written += data.len() as u64;
+    };
+
+    // Keep what was written, such that interrupted chunks need not be sent again completely.
+    if let Err(e) = file.flush().await {
+        return map_storage_error_to_response(
+            ProblemType::FileWriteFailed,
+            Some(id),
+            &instance,
+            "Failed to flush the part file",
+            e,
+            &state.config,
+        );
+    }
+    drop(file);
+    let status = session.record(range.start..range.start + written);
+    if let Some(response) = failure {
+        return response;
+    }
+    if written != expected {
+        let error = ContentRangeError::TooShort(written, expected);
+        return map_content_range_error_to_response(id, error, &state.config);
+    }
+
+    debug!(file_id = %id, "Received bytes {first}-{last} of resumable upload {id}", first = range.start, last = range.end - 1);
+    let Some(total) = status.total.filter(|_| status.complete) else {
+        return axum::Json(ResumableUploadResponse::new(id, &status)).into_response();
+    };
+
+    // All bytes were received; the session is kept until the file is stored such that
+    // clients can complete it again by resending a chunk.
+    let file = match tokio::fs::File::open(session.path()).await {
+        Ok(file) => file,
+        Err(e) => {
+            session.reopen();
+            return map_storage_error_to_response(
+                ProblemType::UploadReadFailed,
+                Some(id),
+                &instance,
+                "Failed to open the part file",
+                e,
+                &state.config,
+            );
+        }
+    };
+
+    debug!(file_id = %id, "Received all {total} bytes of resumable upload {id}");
+    let options = session.options();
+    let upload = Upload {
+        id: Some(id),
+        content_length: Some(total),
+        expected_file_size: Some(total),
+        content_type: options.content_type.clone(),
+        content_md5: None,
+        expected_sha256: None,
+        file_name: options.file_name.clone(),
+        temporal_lease: options.temporal_lease,
+        hash_algorithms: options.hash_algorithms,
+        idempotency_key: None,
+        response_format: ResponseFormat::from_headers(&headers),
+        token: token.map(|Extension(token)| token),
+        compressed: false,
+        wait,
+        dry_run: false,
+        metadata: options.metadata.clone(),
+    };
+
+    // The stored file reserves the storage itself.
+    session.release_reservation();
+    let bytes_received = Arc::new(AtomicU64::new(0));
+    let stream = file_body(file, bytes_received.clone());
+    let response = store_upload(
+        &state,
+        upload,
+        stream,
+        bytes_received,
+        Method::PATCH,
+        "/yeet/resumable/:id",
+    )
+    .await;
+
+    // Files failing to be distributed are stored nonetheless.
+    if response.status().is_success() || state.backbone.find_existing(id).await.is_some() {
+        // The part file is deleted once the session is dropped.
+        state.backbone.remove_upload_session(id);
+    } else {
+        debug!(file_id = %id, "Failed to store resumable upload {id}; keeping the session");
+        session.reopen();
+    }
+    response
+}
+
+/// Describes the state of a resumable upload.
+#[derive(Serialize)]
+struct ResumableUploadResponse {
+    /// The ID the file is stored under once all bytes were received.
+    id: ShortGuid,
+    /// The number of distinct bytes received so far.
+    bytes_received: u64,
+    /// The total size of the file, once announced by a chunk.
+    bytes_expected: Option<u64>,
+    /// The byte ranges received so far in `Content-Range` notation, e.g. `0-1023`.
+    received_ranges: Vec<String>,
+}
+
+impl ResumableUploadResponse {
+    fn new(id: ShortGuid, status: &UploadSessionStatus) -> Self {
+        Self {
+            id,
+            bytes_received: status.bytes_received(),
+            bytes_expected: status.total,
+            received_ranges: status
+                .received
+                .iter()
+                .map(|range| format!("{first}-{last}", first = range.start, last = range.end - 1))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct UploadStatusResponse {
+    /// The ID of the file.
     id: ShortGuid,
+    /// The number of bytes received so far.
+    bytes_received: u64,
+    /// The total number of bytes, if announced by the client.
+    bytes_expected: Option<u64>,
+    /// Whether the upload has completed.
+    completed: bool,
+}
+
+/// Describes an upload that was hashed without storing it.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ValidatedUploadResponse {
     /// The file size in bytes.
     file_size_bytes: usize,
     /// The hashes of the file.
     hashes: Hashes,
 }
 
-#[derive(Serialize)]
-struct Hashes {
+#[derive(Serialize, ToSchema)]
+pub(crate) struct SuccessfulUploadResponse {
+    /// The ID of the file.
+    #[schema(value_type = String, example = "KmC6e8laTnK3dioUSMpM0Q")]
+    id: ShortGuid,
+    /// The file size in bytes.
+    file_size_bytes: usize,
+    /// The hashes of the file.
+    hashes: Hashes,
+    /// The result of each backend, keyed by the backend tag; only present if the upload
+    /// waited for the backends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!({ "memory": "stored", "s3": "failed" }))]
+    backends: Option<BTreeMap<String, String>>,
+}
+
+/// The hashes of the file; hashes that were not requested are omitted.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct Hashes {
     /// The MD5 hash in hex encoding.
-    md5: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    md5: Option<String>,
     /// The SHA-256 hash in hex encoding
-    sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// The BLAKE3 hash in hex encoding.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blake3: Option<String>,
+    /// The CRC32C checksum in big-endian hex encoding.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crc32c: Option<String>,
+    /// The hex encoded digests of all computed algorithms, including registered ones,
+    /// keyed by algorithm name.
+    digests: BTreeMap<String, String>,
 }
 
 impl From<&FileHashes> for Hashes {
     fn from(value: &FileHashes) -> Self {
         Self {
-            md5: hex::encode(value.md5.as_slice()),
-            sha256: hex::encode(value.sha256),
+            md5: value.md5.map(|md5| hex::encode(md5.as_slice())),
+            sha256: value.sha256.map(hex::encode),
+            blake3: value.blake3.map(|blake3| blake3.to_hex().to_string()),
+            crc32c: value.crc32c.map(|crc32c| format!("{crc32c:08x}")),
+            digests: value.digests(),
         }
     }
 }
 
-fn map_new_file_error_to_response(value: NewFileError) -> Response {
-    match value {
-        NewFileError::FailedCreatingFile(id, e) => {
-            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
-                .with_title("File not found")
-                .with_detail(format!("Failed to create temporary file: {e}"))
-                .with_value("id", id.to_string())
-                .with_value("error", e.to_string())
-                .into_response()
-        }
-        NewFileError::FailedCreatingWriter(id, e) => {
-            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
-                .with_title("File not found")
-                .with_detail(format!(
-                    "Failed to create a writer for the temporary file: {e}"
-                ))
-                .with_value("id", id.to_string())
-                .with_value("error", e.to_string())
-                .into_response()
-        }
-        NewFileError::InternalErrorMayRetry(id) => {
-            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
-                .with_title("File not found")
-                .with_detail("Failed to create temporary file - ID already in use".to_string())
-                .with_value("id", id.to_string())
-                .into_response()
+/// Gets the optional `X-Idempotency-Key` header.
+fn parse_idempotency_key(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(&IDEMPOTENCY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Parses the optional `X-Expected-SHA256` header carrying the hex encoded SHA-256 hash
+/// the upload must match.
+fn parse_expected_sha256(headers: &HeaderMap) -> Result<Option<[u8; 32]>, ExpectedHashError> {
+    let Some(value) = headers.get(&EXPECTED_SHA256_HEADER) else {
+        return Ok(None);
+    };
+
+    let value = value.to_str().map_err(|_| ExpectedHashError)?;
+    let mut sha256 = [0; 32];
+    hex::decode_to_slice(value.trim(), &mut sha256).map_err(|_| ExpectedHashError)?;
+    trace!("Expecting SHA-256 {value}");
+    Ok(Some(sha256))
+}
+
+/// Gets the file name from the optional `Content-Disposition` header, falling back
+/// to the `file_name` query parameter.
+fn parse_file_name(headers: &HeaderMap, query: &QueryParams) -> Option<String> {
+    headers
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(file_name_from_content_disposition)
+        .or_else(|| query.file_name.clone())
+        .as_deref()
+        .and_then(sanitize_file_name)
+}
+
+/// Extracts the file name from a `Content-Disposition` header value such as
+/// `attachment; filename="cat.jpg"`. The RFC 5987 `filename*` parameter takes precedence.
+fn file_name_from_content_disposition(value: &str) -> Option<String> {
+    let mut file_name = None;
+    for parameter in value.split(';').skip(1) {
+        let Some((key, value)) = parameter.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                // Only UTF-8 is supported, e.g. `UTF-8''na%C3%AFve.txt`.
+                let (charset, encoded) = value.split_once("''")?;
+                if charset.eq_ignore_ascii_case("utf-8") {
+                    return percent_decode_str(encoded)
+                        .decode_utf8()
+                        .ok()
+                        .map(|name| name.into_owned());
+                }
+            }
+            "filename" => {
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|v| v.strip_suffix('"'))
+                    .unwrap_or(value);
+                file_name = Some(value.replace("\\\"", "\""));
+            }
+            _ => {}
         }
     }
+
+    file_name
+}
+
+/// Reduces a client-provided file name to its last path component so that it
+/// cannot be used for path traversal, removing control characters.
+///
+/// Returns `None` if no usable name remains.
+fn sanitize_file_name(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name = name.trim();
+    match name {
+        "" | "." | ".." => None,
+        name => Some(name.to_string()),
+    }
+}
+
+/// Parses the optional `yy-lease` header into a lease duration.
+fn parse_temporal_lease(
+    headers: &HeaderMap,
+    max_lease: Duration,
+) -> Result<Option<Duration>, LeaseHeaderError> {
+    let Some(value) = headers.get(&LEASE_HEADER) else {
+        return Ok(None);
+    };
+
+    let lease = match value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+    {
+        Some(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => return Err(LeaseHeaderError::Invalid),
+    };
+
+    if lease > max_lease {
+        return Err(LeaseHeaderError::TooLong(
+            lease.as_secs(),
+            max_lease.as_secs(),
+        ));
+    }
+
+    trace!("Requested a temporal lease of {lease:?}");
+    Ok(Some(lease))
+}
+
+/// Parses the optional `x-yeet-hashes` header into the hash algorithms to compute.
+/// The default algorithms are computed if the header is absent.
+fn parse_hash_algorithms(headers: &HeaderMap) -> Result<HashAlgorithms, HashesHeaderError> {
+    let Some(value) = headers.get(&HASHES_HEADER) else {
+        return Ok(HashAlgorithms::default());
+    };
+
+    let value = value.to_str().map_err(|_| HashesHeaderError::Empty)?;
+    let algorithms: HashAlgorithms = value
+        .parse()
+        .map_err(|e: UnknownHashAlgorithm| HashesHeaderError::Unknown(e.0))?;
+    if algorithms == HashAlgorithms::none() {
+        return Err(HashesHeaderError::Empty);
+    }
+
+    trace!("Requested hash algorithms {algorithms:?}");
+    Ok(algorithms)
+}
+
+/// Parses the key/value metadata of the file from the optional `x-yeet-meta` header,
+/// falling back to the `meta` query parameter. Both carry a base64 encoded JSON object
+/// of strings, which must not exceed `max_bytes` once decoded.
+fn parse_metadata(
+    headers: &HeaderMap,
+    query: &QueryParams,
+    max_bytes: usize,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    let value = match headers.get(&META_HEADER) {
+        Some(value) => value.to_str().map_err(|_| MetadataError::InvalidEncoding)?,
+        None => match &query.meta {
+            Some(value) => value.as_str(),
+            None => return Ok(BTreeMap::new()),
+        },
+    };
+
+    // Query parameters are more conveniently sent using the URL-safe alphabet.
+    let value = value.trim();
+    let json = base64::engine::general_purpose::STANDARD
+        .decode(value)
+        .or_else(|_| {
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.decode(value.trim_end_matches('='))
+        })
+        .map_err(|_| MetadataError::InvalidEncoding)?;
+    if json.len() > max_bytes {
+        return Err(MetadataError::TooLarge(json.len(), max_bytes));
+    }
+
+    let metadata = serde_json::from_slice(&json).map_err(|_| MetadataError::InvalidJson)?;
+    trace!("Attaching metadata {metadata:?}");
+    Ok(metadata)
+}
+
+/// Parses the optional `x-yeet-wait` header into the backends to wait for.
+fn parse_wait_mode(headers: &HeaderMap) -> Result<Option<WaitMode>, WaitHeaderError> {
+    let Some(value) = headers.get(&WAIT_HEADER) else {
+        return Ok(None);
+    };
+
+    let value = value.to_str().unwrap_or_default().trim();
+    match value.to_ascii_lowercase().as_str() {
+        "durable" => Ok(Some(WaitMode::Durable)),
+        "durable-all" => Ok(Some(WaitMode::DurableAll)),
+        _ => Err(WaitHeaderError::Unsupported(value.to_string())),
+    }
+}
+
+/// The byte range of a chunk of a resumable upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChunkRange {
+    /// The offset of the first byte.
+    start: u64,
+    /// The offset after the last byte.
+    end: u64,
+    /// The total size of the file, unless sent as `*`.
+    total: Option<u64>,
+}
+
+/// Parses the `Content-Range` header of a chunk, e.g. `bytes 0-1023/4096` or `bytes 0-1023/*`.
+fn parse_content_range(headers: &HeaderMap) -> Result<ChunkRange, ContentRangeError> {
+    let value = headers
+        .get(header::CONTENT_RANGE)
+        .ok_or(ContentRangeError::Missing)?;
+    let value = value.to_str().unwrap_or_default().trim();
+    let invalid = || ContentRangeError::Invalid(value.to_string());
+
+    let (first, rest) = value
+        .strip_prefix("bytes ")
+        .and_then(|range| range.split_once('-'))
+        .ok_or_else(invalid)?;
+    let (last, total) = rest.split_once('/').ok_or_else(invalid)?;
+    let start: u64 = first.trim().parse().map_err(|_| invalid())?;
+    let last: u64 = last.trim().parse().map_err(|_| invalid())?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse::<u64>().map_err(|_| invalid())?),
+    };
+    if last < start {
+        return Err(invalid());
+    }
+
+    Ok(ChunkRange {
+        start,
+        end: last + 1,
+        total,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ContentRangeError {
+    #[error("The Content-Range header is required, e.g. bytes 0-1023/4096")]
+    Missing,
+    #[error("The Content-Range {0:?} is invalid; use bytes <first>-<last>/<total>")]
+    Invalid(String),
+    #[error("The Content-Length of {0} bytes does not match the Content-Range of {1} bytes")]
+    LengthMismatch(u64, u64),
+    #[error("Received more than the {0} bytes announced by the Content-Range header")]
+    TooLong(u64),
+    #[error("Received {0} bytes, but the Content-Range header announced {1} bytes")]
+    TooShort(u64, u64),
+    #[error(transparent)]
+    Session(#[from] ChunkRangeError),
+}
+
+fn map_content_range_error_to_response(
+    id: ShortGuid,
+    value: ContentRangeError,
+    config: &AppConfig,
+) -> Response {
+    ProblemType::InvalidContentRange
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .with_instance(public_path(
+            config.base_path(),
+            format!("/yeet/resumable/{id}"),
+        ))
+        .with_value("id", id.to_string())
+        .into_response()
+}
+
+fn map_unknown_upload_session_to_response(id: ShortGuid, config: &AppConfig) -> Response {
+    ProblemType::UploadSessionNotFound
+        .problem(&config.errors)
+        .with_detail(format!("No resumable upload with ID {id} is in progress"))
+        .with_instance(public_path(
+            config.base_path(),
+            format!("/yeet/resumable/{id}"),
+        ))
+        .with_value("id", id.to_string())
+        .into_response()
+}
+
+/// Parses the optional `x-yeet-dryrun` header; uploads are stored if it is absent.
+fn parse_dry_run(headers: &HeaderMap) -> Result<bool, DryRunHeaderError> {
+    let Some(value) = headers.get(&DRY_RUN_HEADER) else {
+        return Ok(false);
+    };
+
+    let value = value.to_str().unwrap_or_default().trim();
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(DryRunHeaderError::Invalid(value.to_string())),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum DryRunHeaderError {
+    #[error("The dry run mode {0:?} is not supported; use true or false")]
+    Invalid(String),
+}
+
+fn map_dry_run_header_error_to_response(value: DryRunHeaderError, config: &AppConfig) -> Response {
+    ProblemType::InvalidDryRun
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+#[derive(Debug, thiserror::Error)]
+enum WaitHeaderError {
+    #[error("The wait mode {0:?} is not supported; use durable or durable-all")]
+    Unsupported(String),
+}
+
+fn map_wait_header_error_to_response(value: WaitHeaderError, config: &AppConfig) -> Response {
+    ProblemType::InvalidWaitMode
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+/// Checks that the optional `Expect` header only asks for `100-continue`, which hyper
+/// answers once the body is first read.
+fn check_expectation(headers: &HeaderMap) -> Result<(), ExpectationError> {
+    let Some(value) = headers.get(header::EXPECT) else {
+        return Ok(());
+    };
+
+    let value = value.to_str().unwrap_or_default().trim();
+    if value.eq_ignore_ascii_case("100-continue") {
+        Ok(())
+    } else {
+        Err(ExpectationError::Unsupported(value.to_string()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ExpectationError {
+    #[error("The expectation {0:?} is not supported; use 100-continue")]
+    Unsupported(String),
+}
+
+fn map_expectation_error_to_response(value: ExpectationError, config: &AppConfig) -> Response {
+    ProblemType::UnsupportedExpectation
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+/// Parses the optional `Content-Encoding` header into the coding to decompress the body with.
+fn parse_content_encoding(
+    headers: &HeaderMap,
+) -> Result<Option<ContentCoding>, ContentEncodingError> {
+    let Some(value) = headers.get(header::CONTENT_ENCODING) else {
+        return Ok(None);
+    };
+
+    let value = value.to_str().unwrap_or_default().trim();
+    match value.to_ascii_lowercase().as_str() {
+        "identity" => Ok(None),
+        "gzip" | "x-gzip" => Ok(Some(ContentCoding::Gzip)),
+        "deflate" => Ok(Some(ContentCoding::Deflate)),
+        _ => Err(ContentEncodingError::Unsupported(value.to_string())),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ContentEncodingError {
+    #[error("The content encoding {0:?} is not supported; use gzip or deflate")]
+    Unsupported(String),
+}
+
+fn map_content_encoding_error_to_response(
+    value: ContentEncodingError,
+    config: &AppConfig,
+) -> Response {
+    ProblemType::UnsupportedContentEncoding
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+/// Checks the content type of an upload against the configured allowed and denied types.
+///
+/// Uploads without a content type are checked as [`DEFAULT_CONTENT_TYPE`].
+fn check_content_type(
+    files: &FilesConfig,
+    content_type: Option<&ContentType>,
+) -> Result<(), ContentTypeNotAllowed> {
+    let content_type = content_type
+        .map(ContentType::to_string)
+        .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string());
+    if files.is_content_type_allowed(&content_type) {
+        Ok(())
+    } else {
+        Err(ContentTypeNotAllowed(content_type))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Uploads of content type {0:?} are not allowed")]
+struct ContentTypeNotAllowed(String);
+
+fn map_content_type_not_allowed_to_response(
+    id: Option<ShortGuid>,
+    value: ContentTypeNotAllowed,
+    config: &AppConfig,
+) -> Response {
+    let problem = ProblemType::ContentTypeNotAllowed
+        .problem(&config.errors)
+        .with_detail(value.to_string());
+    with_file_id(problem, id).into_response()
+}
+
+fn map_decoding_error_to_response(
+    id: Option<ShortGuid>,
+    error: std::io::Error,
+    config: &AppConfig,
+) -> Response {
+    let problem = ProblemType::InvalidContentEncoding
+        .problem(&config.errors)
+        .with_detail(format!("Failed to decompress the upload: {error}"))
+        .with_value("error", error.to_string());
+    with_file_id(problem, id).into_response()
+}
+
+#[derive(Debug, thiserror::Error)]
+enum HashesHeaderError {
+    #[error("The x-yeet-hashes header must name at least one of md5, sha256, blake3 or crc32c")]
+    Empty,
+    #[error("The hash algorithm {0} is not supported; use md5, sha256, blake3 or crc32c")]
+    Unknown(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("The x-expected-sha256 header must be a hex encoded SHA-256 hash of 64 characters")]
+struct ExpectedHashError;
+
+#[derive(Debug, thiserror::Error)]
+enum MetadataError {
+    #[error("The x-yeet-meta header and meta query parameter must be base64 encoded")]
+    InvalidEncoding,
+    #[error("The metadata must be a JSON object with string values")]
+    InvalidJson,
+    #[error("The metadata is {0} bytes long, exceeding the limit of {1} bytes")]
+    TooLarge(usize, usize),
+}
+
+fn map_metadata_error_to_response(value: MetadataError, config: &AppConfig) -> Response {
+    ProblemType::InvalidMetadata
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+fn map_shutting_down_to_response(instance: &str, config: &AppConfig) -> Response {
+    ProblemType::ShuttingDown
+        .problem(&config.errors)
+        .with_detail("The service is shutting down and no longer accepts uploads")
+        .with_instance(instance)
+        .into_response()
+}
+
+fn map_missing_file_field_to_response(config: &AppConfig) -> Response {
+    ProblemType::InvalidFormData
+        .problem(&config.errors)
+        .with_detail("The form does not contain a file field")
+        .into_response()
+}
+
+fn map_multipart_error_to_response(value: MultipartError, config: &AppConfig) -> Response {
+    ProblemType::InvalidFormData
+        .problem(&config.errors)
+        .with_detail(format!("Failed to read the form: {value}"))
+        .into_response()
+}
+
+fn map_hashes_header_error_to_response(value: HashesHeaderError, config: &AppConfig) -> Response {
+    ProblemType::InvalidHashSelection
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+#[derive(Debug, thiserror::Error)]
+enum LeaseHeaderError {
+    #[error("The yy-lease header must specify a positive number of seconds")]
+    Invalid,
+    #[error("The requested lease of {0} seconds exceeds the maximum of {1} seconds")]
+    TooLong(u64, u64),
+}
+
+fn map_expected_hash_error_to_response(value: ExpectedHashError, config: &AppConfig) -> Response {
+    ProblemType::InvalidExpectedHash
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+fn map_lease_header_error_to_response(value: LeaseHeaderError, config: &AppConfig) -> Response {
+    ProblemType::InvalidLease
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .into_response()
+}
+
+fn map_id_attempts_exhausted_to_response(
+    id: ShortGuid,
+    attempts: usize,
+    instance: &str,
+    config: &AppConfig,
+) -> Response {
+    ProblemType::FileCreationFailed
+        .problem(&config.errors)
+        .with_detail(format!(
+            "Failed to create the file - {attempts} generated IDs were already in use"
+        ))
+        .with_instance(instance)
+        .with_value("id", id.to_string())
+        .into_response()
+}
+
+fn map_new_file_error_to_response(
+    value: NewFileError,
+    instance: &str,
+    config: &AppConfig,
+) -> Response {
+    match value {
+        NewFileError::FailedCreatingFile(id, e) => ProblemType::FileCreationFailed
+            .problem(&config.errors)
+            .with_detail(format!("Failed to create temporary file: {e}"))
+            .with_instance(instance)
+            .with_value("id", id.to_string())
+            .with_value("error", e.to_string())
+            .into_response(),
+        NewFileError::FailedCreatingWriter(id, e) => ProblemType::FileCreationFailed
+            .problem(&config.errors)
+            .with_detail(format!(
+                "Failed to create a writer for the temporary file: {e}"
+            ))
+            .with_instance(instance)
+            .with_value("id", id.to_string())
+            .with_value("error", e.to_string())
+            .into_response(),
+        NewFileError::IdAlreadyExists(id) => ProblemType::FileIdConflict
+            .problem(&config.errors)
+            .with_detail(format!("The file ID {id} is already in use"))
+            .with_instance(instance)
+            .with_value("id", id.to_string())
+            .into_response(),
+        NewFileError::InsufficientStorage(id, e) => {
+            map_insufficient_storage_to_response(id, e, config)
+        }
+        NewFileError::HighWaterMarkExceeded(id, e) => {
+            map_high_water_mark_to_response(id, e, config)
+        }
+        NewFileError::BackendsBusy(id, e) => {
+            let mut response = ProblemType::BackendsBusy
+                .problem(&config.errors)
+                .with_detail(format!(
+                    "The file cannot be accepted right now; retry later: {e}"
+                ))
+                .with_instance(instance)
+                .with_value("id", id.to_string())
+                .into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(BACKENDS_BUSY_RETRY_AFTER_SECS),
+            );
+            response
+        }
+    }
+}
+
+fn map_uploads_busy_to_response(
+    value: UploadsBusy,
+    instance: &str,
+    config: &AppConfig,
+) -> Response {
+    let mut response = ProblemType::TooManyUploads
+        .problem(&config.errors)
+        .with_detail(format!("{value}; retry later"))
+        .with_instance(instance)
+        .with_value("max_uploads", value.max_uploads)
+        .into_response();
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(UPLOADS_BUSY_RETRY_AFTER_SECS),
+    );
+    response
+}
+
+fn map_high_water_mark_to_response(
+    id: ShortGuid,
+    value: HighWaterMarkExceeded,
+    config: &AppConfig,
+) -> Response {
+    ProblemType::InsufficientStorage
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .with_value("id", id.to_string())
+        .with_value("used_bytes", value.used)
+        .with_value("high_water_bytes", value.high_water_mark)
+        .into_response()
+}
+
+/// Extracts the storage headroom violation from a failed write, if that is what caused it.
+fn as_insufficient_storage(error: &std::io::Error) -> Option<InsufficientStorage> {
+    error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<InsufficientStorage>())
+        .copied()
+}
+
+fn map_insufficient_storage_to_response(
+    id: ShortGuid,
+    value: InsufficientStorage,
+    config: &AppConfig,
+) -> Response {
+    ProblemType::InsufficientStorage
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .with_value("id", id.to_string())
+        .with_value("requested_bytes", value.requested)
+        .with_value("available_bytes", value.available)
+        .into_response()
+}
+
+fn map_quota_exceeded_to_response(value: QuotaExceeded, config: &AppConfig) -> Response {
+    let mut response = ProblemType::QuotaExceeded
+        .problem(&config.errors)
+        .with_detail(value.to_string())
+        .with_value("quota_bytes", value.quota_bytes)
+        .with_value("used_bytes", value.used_bytes)
+        .into_response();
+    if let Some(retry_after) = value.retry_after {
+        // Round up so that the upload fits once the client retries.
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    }
+    response
+}
+
+fn map_content_length_mismatch_to_response(
+    id: Option<ShortGuid>,
+    expected: u64,
+    received: u64,
+    config: &AppConfig,
+) -> Response {
+    let detail = if received > expected {
+        format!("Received at least {received} bytes, but the Content-Length header announced {expected} bytes")
+    } else {
+        format!(
+            "Received {received} bytes, but the Content-Length header announced {expected} bytes"
+        )
+    };
+
+    let problem = ProblemType::ContentLengthMismatch
+        .problem(&config.errors)
+        .with_detail(detail)
+        .with_value("expected_bytes", expected)
+        .with_value("received_bytes", received);
+    with_file_id(problem, id).into_response()
+}
+
+fn map_hash_mismatch_to_response(
+    id: Option<ShortGuid>,
+    expected: &str,
+    actual: &str,
+    config: &AppConfig,
+) -> Response {
+    let problem = ProblemType::HashMismatch
+        .problem(&config.errors)
+        .with_detail(format!(
+            "The upload has the SHA-256 hash {actual}, but {expected} was expected"
+        ))
+        .with_value("expected_sha256", expected)
+        .with_value("actual_sha256", actual);
+    with_file_id(problem, id).into_response()
+}
+
+fn map_md5_mismatch_to_response(
+    id: Option<ShortGuid>,
+    expected: &str,
+    actual: &str,
+    config: &AppConfig,
+) -> Response {
+    let problem = ProblemType::HashMismatch
+        .problem(&config.errors)
+        .with_detail(format!(
+            "The upload has the MD5 hash {actual}, but the Content-MD5 header announced {expected}"
+        ))
+        .with_value("expected_md5", expected)
+        .with_value("actual_md5", actual);
+    with_file_id(problem, id).into_response()
+}
+
+/// Describes a failure to receive or store the data of an upload at the public path `instance`.
+fn map_storage_error_to_response(
+    problem_type: ProblemType,
+    id: Option<ShortGuid>,
+    instance: &str,
+    detail: &str,
+    error: impl std::fmt::Display,
+    config: &AppConfig,
+) -> Response {
+    let problem = problem_type
+        .problem(&config.errors)
+        .with_detail(format!("{detail}: {error}"))
+        .with_instance(instance)
+        .with_value("error", error.to_string());
+    with_file_id(problem, id).into_response()
+}
+
+fn map_upload_timeout_to_response(
+    id: Option<ShortGuid>,
+    timeout: UploadTimeout,
+    received: u64,
+    config: &AppConfig,
+) -> Response {
+    let detail = match timeout {
+        UploadTimeout::Idle(idle) => format!(
+            "No data was received for {secs} seconds after {received} bytes",
+            secs = idle.as_secs()
+        ),
+        UploadTimeout::Deadline => {
+            format!("The upload did not complete in time; received {received} bytes")
+        }
+    };
+
+    let problem = ProblemType::UploadTimeout
+        .problem(&config.errors)
+        .with_detail(detail)
+        .with_value("received_bytes", received);
+    with_file_id(problem, id).into_response()
+}
+
+/// Adds the ID of the file to a problem; dry runs do not assign an ID.
+fn with_file_id(problem: Problem, id: Option<ShortGuid>) -> Problem {
+    match id {
+        Some(id) => problem.with_value("id", id.to_string()),
+        None => problem,
+    }
+}
+
+/// Describes a distribution that did not satisfy the requested wait mode.
+///
+/// The outcome is `None` if the distribution was aborted.
+fn map_distribution_failed_to_response(
+    id: ShortGuid,
+    outcome: Option<DistributionOutcome>,
+    instance: &str,
+    config: &AppConfig,
+) -> Response {
+    let Some(outcome) = outcome else {
+        return ProblemType::DistributionFailed
+            .problem(&config.errors)
+            .with_detail(format!("The distribution of file {id} was aborted"))
+            .with_instance(instance)
+            .with_value("id", id.to_string())
+            .into_response();
+    };
+
+    let detail = if outcome.backends.is_empty() {
+        format!("No backend is available to store file {id}")
+    } else {
+        format!(
+            "{failed} of {total} backends failed to store file {id}",
+            failed = outcome.failed().len(),
+            total = outcome.backends.len()
+        )
+    };
+
+    ProblemType::DistributionFailed
+        .problem(&config.errors)
+        .with_detail(detail)
+        .with_instance(instance)
+        .with_value("id", id.to_string())
+        .with_value("stored_by", outcome.succeeded())
+        .with_value("failed", outcome.failed())
+        .with_value("backends", serde_json::json!(backend_result_map(&outcome)))
+        .into_response()
+}
+
+/// Maps the tag of each backend to the name of its result.
+fn backend_result_map(outcome: &DistributionOutcome) -> BTreeMap<String, String> {
+    outcome
+        .backends
+        .iter()
+        .map(|(tag, result)| (tag.clone(), result.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::await_rendezvous;
+    use crate::handlers::YoinkRoutes;
+    use app_config::auth::TokenConfig;
+    use app_config::AppConfig;
+    use axum::body::Body;
+    use axum::extract::FromRequest;
+    use backend_traits::{BackendCommand, BackendCommandReserveError, BackendResult};
+    use file_distribution::hash::HashCrc32c;
+    use hyper::Request;
+    use tower::ServiceExt;
+
+    const MAX_LEASE: Duration = Duration::from_secs(3600);
+
+    fn headers_with_lease(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(&LEASE_HEADER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn missing_lease_uses_default() {
+        let lease = parse_temporal_lease(&HeaderMap::new(), MAX_LEASE).unwrap();
+        assert_eq!(lease, None);
+    }
+
+    #[test]
+    fn valid_lease_is_parsed() {
+        let lease = parse_temporal_lease(&headers_with_lease("30"), MAX_LEASE).unwrap();
+        assert_eq!(lease, Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn invalid_lease_is_rejected() {
+        for value in ["0", "-1", "soon", "3601"] {
+            let error = parse_temporal_lease(&headers_with_lease(value), MAX_LEASE)
+                .expect_err("lease should be rejected");
+            let response = map_lease_header_error_to_response(error, &AppConfig::default());
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    fn headers_with_hashes(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(&HASHES_HEADER, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn missing_hashes_header_selects_defaults() {
+        let algorithms = parse_hash_algorithms(&HeaderMap::new()).unwrap();
+        assert_eq!(algorithms, HashAlgorithms::default());
+        assert!(!algorithms.crc32c);
+    }
+
+    #[test]
+    fn hashes_header_selects_algorithms() {
+        let algorithms = parse_hash_algorithms(&headers_with_hashes("sha256, BLAKE3")).unwrap();
+        assert_eq!(
+            algorithms,
+            HashAlgorithms {
+                md5: false,
+                sha256: true,
+                blake3: true,
+                crc32c: false,
+            }
+        );
+    }
+
+    #[test]
+    fn hashes_header_selects_crc32c() {
+        let algorithms = parse_hash_algorithms(&headers_with_hashes("crc32c")).unwrap();
+        assert_eq!(
+            algorithms,
+            HashAlgorithms {
+                crc32c: true,
+                ..HashAlgorithms::none()
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_hashes_header_is_rejected() {
+        for value in ["", " , ", "sha1", "sha256,crc32"] {
+            let error = parse_hash_algorithms(&headers_with_hashes(value))
+                .expect_err("hash selection should be rejected");
+            let response = map_hashes_header_error_to_response(error, &AppConfig::default());
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn insufficient_storage_is_reported() {
+        let error = std::io::Error::new(
+            std::io::ErrorKind::Other,
+            InsufficientStorage {
+                requested: 10,
+                available: 5,
+            },
+        );
+        let error = as_insufficient_storage(&error).expect("error should be detected");
+        let response = map_insufficient_storage_to_response(
+            ShortGuid::new_random(),
+            error,
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+        let error = std::io::Error::new(std::io::ErrorKind::Other, "disk on fire");
+        assert_eq!(as_insufficient_storage(&error), None);
+
+        let response = map_new_file_error_to_response(
+            NewFileError::HighWaterMarkExceeded(
+                ShortGuid::new_random(),
+                HighWaterMarkExceeded {
+                    used: 10,
+                    high_water_mark: 8,
+                },
+            ),
+            "/yeet",
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    }
+
+    #[test]
+    fn id_collisions_are_conflicts_until_retries_are_exhausted() {
+        let id = ShortGuid::new_random();
+        let response = map_new_file_error_to_response(
+            NewFileError::IdAlreadyExists(id),
+            &format!("/yeet/{id}"),
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = map_id_attempts_exhausted_to_response(
+            id,
+            MAX_ID_ATTEMPTS,
+            "/yeet",
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn busy_backends_ask_clients_to_retry() {
+        let response = map_new_file_error_to_response(
+            NewFileError::BackendsBusy(
+                ShortGuid::new_random(),
+                BackendCommandReserveError::Full(Duration::from_millis(500)),
+            ),
+            "/yeet",
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[tokio::test]
+    async fn server_errors_name_the_instance() {
+        let id = ShortGuid::new_random();
+        let config = AppConfig::default();
+        let responses = [
+            map_shutting_down_to_response("/yeet", &config),
+            map_id_attempts_exhausted_to_response(id, MAX_ID_ATTEMPTS, "/yeet", &config),
+            map_new_file_error_to_response(
+                NewFileError::BackendsBusy(
+                    id,
+                    BackendCommandReserveError::Full(Duration::from_millis(500)),
+                ),
+                "/yeet",
+                &config,
+            ),
+            map_uploads_busy_to_response(UploadsBusy { max_uploads: 1 }, "/yeet", &config),
+            map_distribution_failed_to_response(id, None, "/yeet", &config),
+        ];
+
+        for response in responses {
+            assert!(response.status().is_server_error());
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            for member in ["type", "title", "detail"] {
+                assert!(body[member].is_string(), "{member} missing in {body}");
+            }
+            assert_eq!(body["instance"], "/yeet");
+        }
+    }
+
+    #[test]
+    fn exceeded_quotas_ask_clients_to_retry() {
+        let response = map_quota_exceeded_to_response(
+            QuotaExceeded {
+                quota_bytes: 100,
+                used_bytes: 100,
+                retry_after: Some(Duration::from_millis(1500)),
+            },
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "2");
+
+        let response = map_quota_exceeded_to_response(
+            QuotaExceeded {
+                quota_bytes: 100,
+                used_bytes: 0,
+                retry_after: None,
+            },
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(!response.headers().contains_key(header::RETRY_AFTER));
+    }
+
+    #[test]
+    fn content_length_mismatch_is_a_bad_request() {
+        let id = ShortGuid::new_random();
+        for received in [5, 15] {
+            let response = map_content_length_mismatch_to_response(
+                Some(id),
+                10,
+                received,
+                &AppConfig::default(),
+            );
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn storage_errors_are_problem_details() {
+        let id = ShortGuid::new_random();
+        let error = std::io::Error::new(ErrorKind::Other, "disk on fire");
+        let response = map_storage_error_to_response(
+            ProblemType::FileWriteFailed,
+            Some(id),
+            &format!("/yeet/{id}"),
+            "Failed to write to temporary file",
+            error,
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "urn:yeet-yoink:problem:file-write-failed");
+        assert_eq!(body["title"], "Unable to write file");
+        assert_eq!(
+            body["detail"],
+            "Failed to write to temporary file: disk on fire"
+        );
+        assert_eq!(body["instance"], format!("/yeet/{id}"));
+        assert_eq!(body["id"], id.to_string());
+    }
+
+    #[test]
+    fn unrequested_hashes_are_omitted() {
+        let hashes = FileHashes {
+            md5: None,
+            sha256: Some(Default::default()),
+            blake3: None,
+            crc32c: None,
+            registered: BTreeMap::new(),
+        };
+        let json = serde_json::to_value(Hashes::from(&hashes)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "sha256": hex::encode([0u8; 32]),
+                "digests": { "sha256": hex::encode([0u8; 32]) }
+            })
+        );
+    }
+
+    #[test]
+    fn crc32c_is_encoded_as_big_endian_hex() {
+        let mut crc32c = HashCrc32c::new();
+        crc32c.update(b"1234");
+        crc32c.update(b"56789");
+        let hashes = FileHashes {
+            md5: None,
+            sha256: None,
+            blake3: None,
+            crc32c: Some(crc32c.finalize()),
+            registered: BTreeMap::from([("xor".to_string(), vec![0x0d])]),
+        };
+        let json = serde_json::to_value(Hashes::from(&hashes)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "crc32c": "e3069283",
+                "digests": { "crc32c": "e3069283", "xor": "0d" }
+            })
+        );
+    }
+
+    #[test]
+    fn metadata_is_parsed_from_base64_json() {
+        let no_query = QueryParams {
+            file_name: None,
+            meta: None,
+        };
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            parse_metadata(&headers, &no_query, 64).unwrap(),
+            BTreeMap::new()
+        );
+
+        // {"tenant":"acme"}
+        headers.insert(
+            &META_HEADER,
+            HeaderValue::from_static("eyJ0ZW5hbnQiOiJhY21lIn0="),
+        );
+        let metadata = parse_metadata(&headers, &no_query, 64).unwrap();
+        assert_eq!(metadata["tenant"], "acme");
+        assert!(matches!(
+            parse_metadata(&headers, &no_query, 8),
+            Err(MetadataError::TooLarge(17, 8))
+        ));
+
+        // The query parameter may use the URL-safe alphabet without padding.
+        let query = QueryParams {
+            file_name: None,
+            meta: Some("eyJ0ZW5hbnQiOiJhY21lIn0".to_string()),
+        };
+        let metadata = parse_metadata(&HeaderMap::new(), &query, 64).unwrap();
+        assert_eq!(metadata["tenant"], "acme");
+
+        // ["acme"]
+        headers.insert(&META_HEADER, HeaderValue::from_static("WyJhY21lIl0="));
+        assert!(matches!(
+            parse_metadata(&headers, &no_query, 64),
+            Err(MetadataError::InvalidJson)
+        ));
+        headers.insert(&META_HEADER, HeaderValue::from_static("not base64!"));
+        assert!(matches!(
+            parse_metadata(&headers, &no_query, 64),
+            Err(MetadataError::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn file_name_is_taken_from_content_disposition() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_DISPOSITION,
+            HeaderValue::from_static("attachment; filename=\"cat.jpg\""),
+        );
+        let query = QueryParams {
+            file_name: Some("dog.jpg".to_string()),
+            meta: None,
+        };
+        assert_eq!(
+            parse_file_name(&headers, &query).as_deref(),
+            Some("cat.jpg")
+        );
+
+        assert_eq!(
+            parse_file_name(&HeaderMap::new(), &query).as_deref(),
+            Some("dog.jpg")
+        );
+    }
+
+    #[test]
+    fn extended_file_name_takes_precedence() {
+        assert_eq!(
+            file_name_from_content_disposition(
+                "attachment; filename=\"naive.txt\"; filename*=UTF-8''na%C3%AFve.txt"
+            )
+            .as_deref(),
+            Some("na\u{ef}ve.txt")
+        );
+        assert_eq!(
+            file_name_from_content_disposition("attachment; filename=plain.txt").as_deref(),
+            Some("plain.txt")
+        );
+        assert_eq!(file_name_from_content_disposition("attachment"), None);
+    }
+
+    #[test]
+    fn file_names_are_sanitized() {
+        assert_eq!(
+            sanitize_file_name("../../etc/passwd").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(
+            sanitize_file_name("C:\\Users\\me\\cat.jpg").as_deref(),
+            Some("cat.jpg")
+        );
+        assert_eq!(
+            sanitize_file_name("evil\r\nname.txt").as_deref(),
+            Some("evilname.txt")
+        );
+        assert_eq!(sanitize_file_name(".."), None);
+        assert_eq!(sanitize_file_name("dir/"), None);
+    }
+
+    #[test]
+    fn only_files_synced_on_finalize_are_completed_with_sync() {
+        assert!(matches!(
+            completion_mode(SyncPolicy::SyncOnFinalize),
+            CompletionMode::Sync
+        ));
+        assert!(matches!(
+            completion_mode(SyncPolicy::SyncPerChunk),
+            CompletionMode::NoSync
+        ));
+        assert!(matches!(
+            completion_mode(SyncPolicy::NoSync),
+            CompletionMode::NoSync
+        ));
+    }
+
+    fn headers_with_encoding(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn content_encoding_is_parsed() {
+        assert_eq!(parse_content_encoding(&HeaderMap::new()).unwrap(), None);
+        assert_eq!(
+            parse_content_encoding(&headers_with_encoding("identity")).unwrap(),
+            None
+        );
+        assert_eq!(
+            parse_content_encoding(&headers_with_encoding("GZIP")).unwrap(),
+            Some(ContentCoding::Gzip)
+        );
+
+        let error = parse_content_encoding(&headers_with_encoding("br"))
+            .expect_err("encoding should be rejected");
+        let response = map_content_encoding_error_to_response(error, &AppConfig::default());
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn gzip_bodies_are_decompressed() {
+        use async_compression::tokio::bufread::GzipEncoder;
+        use tokio::io::AsyncReadExt;
+
+        let mut compressed = Vec::new();
+        GzipEncoder::new(&b"yeet yoink"[..])
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        let compressed_len = compressed.len() as u64;
+
+        let bytes_received = Arc::new(AtomicU64::new(0));
+        let body = axum::body::Body::from(compressed);
+        let stream = BodyStream::from_request(axum::http::Request::new(body), &())
+            .await
+            .unwrap();
+        let mut stream = decode_body(stream, Some(ContentCoding::Gzip), bytes_received.clone());
+
+        let mut content = Vec::new();
+        while let Some(data) = stream.next().await {
+            content.extend_from_slice(&data.unwrap());
+        }
+        assert_eq!(content, b"yeet yoink");
+        assert_eq!(bytes_received.load(Ordering::Relaxed), compressed_len);
+    }
+
+    #[tokio::test]
+    async fn corrupt_bodies_are_a_decoding_error() {
+        let body = axum::body::Body::from("not gzip at all");
+        let stream = BodyStream::from_request(axum::http::Request::new(body), &())
+            .await
+            .unwrap();
+        let mut stream = decode_body(stream, Some(ContentCoding::Gzip), Default::default());
+
+        let error = loop {
+            match stream.next().await {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => break e,
+                None => panic!("corrupt body should fail to decode"),
+            }
+        };
+        assert!(is_decoding_error(&error));
+        let response = map_decoding_error_to_response(
+            Some(ShortGuid::new_random()),
+            error,
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn form_uploads_store_the_first_file_field() {
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let shutdown = state.shutdown.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        let file_form = concat!(
+            "--X\r\nContent-Disposition: form-data; name=\"comment\"\r\n\r\nhi\r\n",
+            "--X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"hello.txt\"\r\n",
+            "Content-Type: text/plain\r\n\r\nhello\r\n--X--\r\n"
+        );
+        let form = |body: &'static str| {
+            Request::post("/yeet/form")
+                .header(header::CONTENT_TYPE, "multipart/form-data; boundary=X")
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(form(
+                "--X\r\nContent-Disposition: form-data; name=\"comment\"\r\n\r\nhi\r\n--X--\r\n",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app.clone().oneshot(form(file_form)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["file_size_bytes"], 5);
+
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+        // The metadata is recorded once the backbone took note of the completed write.
+        let metadata = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match backbone.get_metadata(id).await.expect("file is unknown") {
+                    Some(metadata) => break metadata,
+                    None => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .expect("file is incomplete");
+        assert_eq!(metadata.file_name.as_deref(), Some("hello.txt"));
+        assert_eq!(metadata.content_type.as_deref(), Some("text/plain"));
+
+        // Uploads are rejected once the service is draining.
+        shutdown.begin_drain();
+        let response = app.oneshot(form(file_form)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        drop((backbone, shutdown, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn uploads_are_stored_under_client_provided_ids() {
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        let id = ShortGuid::new_random();
+        let upload = || {
+            Request::put(format!("/yeet/{id}"))
+                .body(Body::from("hello"))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(upload()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], id.to_string());
+        assert_eq!(body["file_size_bytes"], 5);
+
+        // The ID is taken as long as the file is buffered.
+        let response = app.clone().oneshot(upload()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = app
+            .oneshot(Request::put("/yeet/not-an-id").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        drop((backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn idle_uploads_time_out_and_are_removed() {
+        let mut config = AppConfig::default();
+        config.timeouts.upload_idle_sec = Some(1);
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        // The client sends the first bytes, then stalls.
+        let (mut sender, body) = Body::channel();
+        sender.send_data(Bytes::from("hello")).await.unwrap();
+        let request = Request::post("/yeet").body(body).unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["received_bytes"], 5);
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        // The partial file is removed from the bookkeeping asynchronously.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while backbone.get_local_file(id).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the partial file was not removed");
+
+        drop((sender, backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn uploads_beyond_the_concurrency_limit_are_rejected() {
+        let mut config = AppConfig::default();
+        config.files.max_concurrent_uploads = Some(1);
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        // The first upload takes the only slot while its body is still being sent.
+        let (mut sender, body) = Body::channel();
+        sender.send_data(Bytes::from("hello")).await.unwrap();
+        let request = Request::post("/yeet").body(body).unwrap();
+        let first = tokio::spawn(app.clone().oneshot(request));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let request = Request::post("/yeet").body(Body::from("yoink")).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "urn:yeet-yoink:problem:too-many-uploads");
+        assert_eq!(body["max_uploads"], 1);
+
+        // The first upload completes once its body ends.
+        drop(sender);
+        let response = first
+            .await
+            .expect("failed to join the upload")
+            .expect("the upload failed");
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        drop((backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn durable_uploads_wait_for_the_backends() {
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        // One of two backends stores each file.
+        let backends = tokio::spawn(async move {
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::DistributeFile(_, _, Some(completion), _) = command {
+                    let mut outcome = DistributionOutcome::default();
+                    outcome.insert("memory", BackendResult::Stored);
+                    outcome.insert("s3", BackendResult::Failed);
+                    completion.send(outcome).ok();
+                }
+            }
+        });
+
+        let upload = |wait: &str| {
+            Request::post("/yeet")
+                .header(&WAIT_HEADER, wait)
+                .body(Body::from("hello"))
+                .unwrap()
+        };
+
+        // Only some backends stored the file.
+        let response = app.clone().oneshot(upload("durable")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::MULTI_STATUS);
+        assert_eq!(
+            response.headers()[&BACKENDS_HEADER],
+            "memory=stored, s3=failed"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["backends"],
+            serde_json::json!({ "memory": "stored", "s3": "failed" })
+        );
+        let stored: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let response = app.clone().oneshot(upload("durable-all")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["stored_by"], serde_json::json!(["memory"]));
+        assert_eq!(body["failed"], serde_json::json!(["s3"]));
+        assert_eq!(body["backends"]["s3"], "failed");
+        let failed: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let response = app.oneshot(upload("eventually")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        for id in [stored, failed] {
+            backbone
+                .remove_file(id)
+                .await
+                .expect("failed to remove file");
+        }
+        drop(backbone);
+        backends.await.expect("failed to await the backends");
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn empty_uploads_store_a_zero_byte_file() {
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new()
+            .map_yeet_endpoint()
+            .map_yoink_endpoint()
+            .with_state(state);
+
+        let request = Request::post("/yeet")
+            .header(header::CONTENT_LENGTH, 0)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["file_size_bytes"], 0);
+        assert_eq!(body["hashes"]["md5"], "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(
+            body["hashes"]["sha256"],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let request = Request::get(format!("/yoink/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "0");
+        assert_eq!(
+            response.headers()["content-md5"],
+            "1B2M2Y8AsgTpgAmY7PhCfg=="
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(body.is_empty());
+
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        drop((backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn chunked_uploads_report_the_real_file_size() {
+        use tokio::io::AsyncReadExt;
+
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        // Streamed bodies are sent with chunked transfer coding and without a length.
+        let upload = |chunks: Vec<&'static str>| {
+            let chunks = chunks.into_iter().map(Ok::<_, std::io::Error>);
+            Request::post("/yeet")
+                .header(header::TRANSFER_ENCODING, "chunked")
+                .body(Body::wrap_stream(futures::stream::iter(chunks)))
+                .unwrap()
+        };
+
+        let mut ids = Vec::new();
+        for (chunks, content, sha256) in [
+            (
+                vec!["yeet", " ", "yoink"],
+                "yeet yoink",
+                "061977e10556433ace113af1b8b84f14046ab35880353ff1713e7e3ae1d45eaf",
+            ),
+            (
+                vec![],
+                "",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+        ] {
+            let response = app.clone().oneshot(upload(chunks)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["file_size_bytes"], content.len());
+            assert_eq!(body["hashes"]["sha256"], sha256);
+
+            let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+            let mut reader = backbone
+                .get_local_file(id)
+                .await
+                .expect("the file was not stored");
+            let mut stored = String::new();
+            reader
+                .read_to_string(&mut stored)
+                .await
+                .expect("failed to read file");
+            assert_eq!(stored, content);
+            ids.push(id);
+        }
+
+        for id in ids {
+            backbone
+                .remove_file(id)
+                .await
+                .expect("failed to remove file");
+        }
+        drop((app, backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn capabilities_are_advertised() {
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        for method in [Method::OPTIONS, Method::HEAD] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri("/yeet")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NO_CONTENT);
+            let headers = response.headers();
+            assert_eq!(headers[header::ALLOW], YEET_METHODS);
+            assert_eq!(headers[header::ACCEPT_ENCODING], ACCEPTED_ENCODINGS);
+            assert_eq!(headers[&HASHES_HEADER], "md5, sha256, blake3, crc32c");
+            assert_eq!(headers[&MAX_LEASE_HEADER], "3600");
+            assert!(headers.get(&MAX_BODY_SIZE_HEADER).is_none());
+        }
+
+        drop((app, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn uploads_not_matching_the_expected_sha256_are_rejected() {
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        // The SHA-256 hash of "hello".
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let upload = |path: &str, expected: &str| {
+            Request::post(path)
+                .header("x-expected-sha256", expected)
+                .body(Body::from("hello"))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(upload("/yeet", "abc")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let mismatch = "0".repeat(64);
+        let response = app
+            .clone()
+            .oneshot(upload("/yeet/validate", &mismatch))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = app
+            .clone()
+            .oneshot(upload("/yeet", &mismatch))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["type"], "urn:yeet-yoink:problem:hash-mismatch");
+        assert_eq!(body["actual_sha256"], sha256);
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while backbone.get_local_file(id).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the rejected file was not removed");
+
+        let response = app
+            .clone()
+            .oneshot(upload("/yeet", &sha256.to_uppercase()))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        drop((app, backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn uploads_are_counted_by_content_type() {
+        let mut config = AppConfig::default();
+        config.metrics.content_types = vec!["application/x-yeet".to_string()];
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        for content_type in ["application/x-yeet; version=2", "application/zip"] {
+            let request = Request::post("/yeet")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from("hello"))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let metrics = metrics::Metrics::get().encode();
+        assert!(metrics
+            .contains("uploads_by_content_type_total{content_type=\"application/x-yeet\"} 1"));
+        assert!(metrics.contains("uploads_by_content_type_total{content_type=\"other\"} 1"));
+
+        drop((app, backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn uploads_of_denied_content_types_are_rejected() {
+        let mut config = AppConfig::default();
+        config.files.denied_content_types = vec![
+            "application/x-msdownload".to_string(),
+            "application/vnd.microsoft.portable-executable".to_string(),
+        ];
+        config.downloads.sniff_content_type = true;
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        let upload = |content_type: &str, body: &'static [u8]| {
+            Request::post("/yeet")
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        // Declared types are rejected before the body is stored.
+        let response = app
+            .clone()
+            .oneshot(upload("application/x-msdownload", b"MZ"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["type"],
+            "urn:yeet-yoink:problem:content-type-not-allowed"
+        );
+        assert!(body.get("id").is_none());
+
+        // Detected types are rejected once the body was received, removing the file.
+        let response = app
+            .clone()
+            .oneshot(upload("text/plain", b"MZ\x90\x00\x03\x00\x00\x00"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while backbone.get_local_file(id).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the rejected file was not removed");
+
+        let response = app.oneshot(upload("text/plain", b"hello")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        drop((backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn dry_runs_hash_uploads_without_storing_them() {
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        let sha256 = "061977e10556433ace113af1b8b84f14046ab35880353ff1713e7e3ae1d45eaf";
+        for request in [
+            Request::post("/yeet").header(&DRY_RUN_HEADER, "true"),
+            Request::post("/yeet/validate").header(&HASHES_HEADER, "sha256"),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::from("yeet yoink")).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get(&ID_HEADER).is_none());
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert!(body.get("id").is_none());
+            assert_eq!(body["file_size_bytes"], 10);
+            assert_eq!(body["hashes"]["sha256"], sha256);
+        }
+
+        // Dry runs check the Content-MD5 header just like stored uploads.
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/yeet")
+                    .header(&DRY_RUN_HEADER, "true")
+                    .header("content-md5", "lqQp8e6i2XzapEXt2vhmqA==")
+                    .body(Body::from("yeet yoink"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["hashes"]["md5"], "96a429f1eea2d97cdaa445eddaf866a8");
+
+        let mismatch = |dry_run: &'static str| {
+            Request::post("/yeet")
+                .header(&DRY_RUN_HEADER, dry_run)
+                .header("content-md5", "lqQp8e6i2XzapEXt2vhmqA==")
+                .body(Body::from("yeet yoink?"))
+                .unwrap()
+        };
+        let response = app.clone().oneshot(mismatch("true")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let dry_run = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let dry_run: serde_json::Value = serde_json::from_slice(&dry_run).unwrap();
+        assert_eq!(dry_run["type"], "urn:yeet-yoink:problem:hash-mismatch");
+        assert_eq!(dry_run["expected_md5"], "96a429f1eea2d97cdaa445eddaf866a8");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/yeet")
+                    .header(&DRY_RUN_HEADER, "maybe")
+                    .body(Body::from("yeet yoink"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Nothing was handed to the backends.
+        assert!(backend_receiver.try_recv().is_err());
+
+        // Stored uploads report the same problem.
+        let response = app.clone().oneshot(mismatch("false")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let stored = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let mut stored: serde_json::Value = serde_json::from_slice(&stored).unwrap();
+        stored.as_object_mut().unwrap().remove("id");
+        assert_eq!(stored, dry_run);
+
+        drop((app, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[test]
+    fn content_range_is_parsed() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_RANGE, HeaderValue::from_str(value).unwrap());
+            headers
+        };
+
+        assert_eq!(
+            parse_content_range(&headers("bytes 0-1023/4096")).unwrap(),
+            ChunkRange {
+                start: 0,
+                end: 1024,
+                total: Some(4096)
+            }
+        );
+        assert_eq!(
+            parse_content_range(&headers("bytes 10-10/*")).unwrap(),
+            ChunkRange {
+                start: 10,
+                end: 11,
+                total: None
+            }
+        );
+        for invalid in ["bytes 5-4/10", "bytes */10", "items 0-1/2", "bytes 0-1"] {
+            assert!(
+                matches!(
+                    parse_content_range(&headers(invalid)),
+                    Err(ContentRangeError::Invalid(_))
+                ),
+                "{invalid} was accepted"
+            );
+        }
+        assert!(matches!(
+            parse_content_range(&HeaderMap::new()),
+            Err(ContentRangeError::Missing)
+        ));
+    }
+
+    #[tokio::test]
+    async fn resumable_uploads_accept_chunks_in_any_order() {
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/yeet/resumable")
+                    .header(header::CONTENT_TYPE, "text/plain")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let chunk = |range: &str, data: &'static str| {
+            Request::patch(format!("/yeet/resumable/{id}"))
+                .header(header::CONTENT_RANGE, range)
+                .body(Body::from(data))
+                .unwrap()
+        };
+
+        // "yeet yoink" sent backwards, with an overlapping chunk of unknown total size.
+        for (range, data, received) in [
+            ("bytes 5-9/10", "yoink", "5-9"),
+            ("bytes 3-6/*", "t yo", "3-9"),
+        ] {
+            let response = app.clone().oneshot(chunk(range, data)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["bytes_expected"], 10);
+            assert_eq!(body["received_ranges"], serde_json::json!([received]));
+        }
+
+        // Chunks not matching the announced size are rejected.
+        let response = app
+            .clone()
+            .oneshot(chunk("bytes 0-2/12", "yee"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(format!("/yeet/resumable/{id}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["bytes_received"], 7);
+
+        // The last chunk stores the file.
+        let response = app
+            .clone()
+            .oneshot(chunk("bytes 0-2/10", "yee"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["id"], id.to_string());
+        assert_eq!(body["file_size_bytes"], 10);
+        assert_eq!(
+            body["hashes"]["sha256"],
+            "061977e10556433ace113af1b8b84f14046ab35880353ff1713e7e3ae1d45eaf"
+        );
+
+        // The session is closed once the file was stored.
+        let response = app
+            .clone()
+            .oneshot(chunk("bytes 0-2/10", "yee"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        drop((app, backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn resumable_uploads_failing_to_be_stored_are_kept() {
+        let mut config = AppConfig::default();
+        config.auth.tokens = vec![TokenConfig {
+            token: "s3cr3t".to_string(),
+            quota_bytes: Some(5),
+        }];
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let app = Router::new()
+            .map_yeet_endpoint()
+            .layer(Extension(AuthenticatedToken(0)))
+            .with_state(state);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/yeet/resumable")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        let chunk = |range: &str, data: &'static str| {
+            Request::patch(format!("/yeet/resumable/{id}"))
+                .header(header::CONTENT_RANGE, range)
+                .body(Body::from(data))
+                .unwrap()
+        };
+
+        // The upload exceeds the quota of the token once it is stored.
+        let response = app
+            .clone()
+            .oneshot(chunk("bytes 5-9/10", "yoink"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .clone()
+            .oneshot(chunk("bytes 0-4/10", "yeet "))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // The received bytes are kept, and resending a chunk completes the upload again.
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get(format!("/yeet/resumable/{id}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["bytes_received"], 10);
+
+        let response = app
+            .clone()
+            .oneshot(chunk("bytes 0-4/10", "yeet "))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::delete(format!("/yeet/resumable/{id}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        drop((app, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[test]
+    fn only_continue_expectations_are_supported() {
+        let headers = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::EXPECT, HeaderValue::from_static(value));
+            headers
+        };
+
+        assert!(check_expectation(&HeaderMap::new()).is_ok());
+        assert!(check_expectation(&headers("100-continue")).is_ok());
+        assert!(check_expectation(&headers("100-Continue")).is_ok());
+        let response = map_expectation_error_to_response(
+            check_expectation(&headers("200-ok")).expect_err("the expectation is unsupported"),
+            &AppConfig::default(),
+        );
+        assert_eq!(response.status(), StatusCode::EXPECTATION_FAILED);
+    }
+
+    #[tokio::test]
+    async fn uploads_expecting_continue_are_checked_before_the_body_is_sent() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        /// Reads a response head from the connection.
+        async fn read_head(connection: &mut TcpStream) -> String {
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(connection.read_u8().await.expect("failed to read response"));
+            }
+            String::from_utf8(head).expect("the response head is not UTF-8")
+        }
+
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        let backbone = state.backbone.clone();
+        let app = Router::new().map_yeet_endpoint().with_state(state);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(async {
+                    stopped.await.ok();
+                }),
+        );
+
+        // Rejected uploads are answered without waiting for the body.
+        let mut connection = TcpStream::connect(addr).await.unwrap();
+        connection
+            .write_all(
+                b"POST /yeet HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\
+                  Expect: 100-continue\r\nyy-lease: forever\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let head = read_head(&mut connection).await;
+        assert!(head.starts_with("HTTP/1.1 400 "), "{head}");
+        drop(connection);
+
+        // Accepted uploads are asked to send the body.
+        let mut connection = TcpStream::connect(addr).await.unwrap();
+        connection
+            .write_all(
+                b"POST /yeet HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\
+                  Expect: 100-continue\r\nConnection: close\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let head = read_head(&mut connection).await;
+        assert!(head.starts_with("HTTP/1.1 100 Continue"), "{head}");
+
+        connection.write_all(b"yeet yoink").await.unwrap();
+        let mut response = String::new();
+        connection.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 201 "), "{response}");
+
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+
+        stop.send(()).ok();
+        server
+            .await
+            .expect("failed to join the server")
+            .expect("the server failed");
+        drop((backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
 }
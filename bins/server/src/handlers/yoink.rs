@@ -1,23 +1,43 @@
 //! Contains the `/yoink` endpoint filter.
 
+use crate::error::ProblemType;
 use crate::expiration_as_rfc1123;
+use crate::handlers::yeet::META_HEADER;
+use crate::handlers::{datetime_as_rfc1123, public_path, ContentCoding, FileId};
+use crate::services::record_file_id;
+use crate::shutdown::ReadGuard;
+use crate::throttle::ThrottledReader;
+use crate::verify::VerifyingReader;
 use crate::AppState;
+use app_config::downloads::CacheControlConfig;
+use app_config::AppConfig;
+use async_compression::tokio::bufread::{DeflateEncoder, GzipEncoder};
 use axum::body::{HttpBody, StreamBody};
-use axum::extract::{Path, State};
-use axum::http::{header, HeaderName};
+use axum::extract::State;
+use axum::headers::{HeaderMapExt, IfModifiedSince};
+use axum::http::{header, HeaderMap, HeaderName};
 use axum::response::{AppendHeaders, IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
 use base64::Engine;
-use file_distribution::{FileReaderTrait, GetFileReaderError};
-use hyper::StatusCode;
+use chrono::{DateTime, Utc};
+use file_distribution::{BoxedFileReader, FileReaderTrait, GetFileReaderError};
+use hyper::{Method, StatusCode};
+use metrics::http::HttpMetrics;
 use metrics::transfer::{TransferMethod, TransferMetrics};
 use mime_db::extension;
 use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
 use shared_files::FileSize;
 use shortguid::ShortGuid;
 use std::borrow::Borrow;
+use std::io::Cursor;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader, ReadBuf};
+use tokio::time::Instant;
 use tokio_util::io::ReaderStream;
+use tracing::debug;
 
 /// Escape control set for URL/hex-encoding file names in the Content-Disposition header.
 static ASCII_CONTROLS: AsciiSet = CONTROLS
@@ -32,6 +52,29 @@ static ASCII_CONTROLS: AsciiSet = CONTROLS
     .add(b'|')
     .add(b'}');
 
+/// The content type of files stored without one whose content type is not detected.
+pub(super) const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The number of leading bytes of a file inspected to detect its content type.
+pub(super) const SNIFF_BYTES: u64 = 8192;
+
+/// The header with which clients request a bandwidth limit in bytes per second.
+const RATE_HEADER: &str = "x-yoink-rate";
+
+/// Content types whose payload is compressed already and is therefore never compressed again.
+const COMPRESSED_CONTENT_TYPES: &[&str] = &[
+    "application/gzip",
+    "application/x-gzip",
+    "application/zip",
+    "application/x-7z-compressed",
+    "application/x-bzip2",
+    "application/x-rar-compressed",
+    "application/x-xz",
+    "application/zstd",
+    "font/woff",
+    "font/woff2",
+];
+
 pub trait YoinkRoutes {
     /// Provides an API for storing files.
     ///
@@ -43,6 +86,32 @@ pub trait YoinkRoutes {
     ///
     /// your-data
     /// ```
+    ///
+    /// The metadata of a file can be probed without downloading it:
+    ///
+    /// ```http
+    /// HEAD /yoink/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// ```
+    ///
+    /// Compressible files are sent gzip or deflate compressed if the client accepts it:
+    ///
+    /// ```http
+    /// GET /yoink/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// Accept-Encoding: gzip
+    /// ```
+    ///
+    /// Downloads can be limited to a number of bytes per second, up to the server's maximum:
+    ///
+    /// ```http
+    /// GET /yoink/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// X-Yoink-Rate: 1048576
+    /// ```
+    ///
+    /// Files can be removed before their lease expires:
+    ///
+    /// ```http
+    /// DELETE /yoink/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// ```
     fn map_yoink_endpoint(self) -> Self;
 }
 
@@ -54,55 +123,397 @@ where
 {
     // Ensure HttpCallMetricTracker is updated.
     fn map_yoink_endpoint(self) -> Self {
-        self.route("/yoink/:id", get(do_yoink))
+        self.route("/yoink/:id", get(do_yoink).head(do_head).delete(do_delete))
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/yoink/{id}",
+    tag = "files",
+    params(("id" = String, Path, description = "The ID of the file")),
+    responses(
+        (status = 200, description = "The file contents", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 302, description = "The file is served from a presigned backend URL"),
+        (status = 304, description = "The file matches If-None-Match or was not modified since If-Modified-Since"),
+        (status = 400, description = "The file ID is malformed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "The file does not exist", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 410, description = "The file expired", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 504, description = "The backends did not provide the file in time", body = ProblemDetails, content_type = "application/problem+json"),
+    )
+)]
 #[axum::debug_handler]
 async fn do_yoink(
-    Path(id): Path<ShortGuid>,
+    FileId(id): FileId,
+    headers: HeaderMap,
     State(state): State<AppState>,
 ) -> Result<Response, StatusCode> {
-    let file = match state.backbone.get_file(id).await {
+    record_file_id(id);
+    if let Some(url) = redirect_url(id, &state).await {
+        debug!(file_id = %id, "Redirecting download of file {id} to a presigned URL");
+        return Ok((StatusCode::FOUND, [(header::LOCATION, url)]).into_response());
+    }
+
+    let mut file = match state.backbone.get_file(id).await {
         Ok(file) => file,
-        Err(e) => return Ok(map_file_reader_error_to_response(e)),
+        Err(e) => return Ok(map_file_reader_error_to_response(e, &state.config)),
     };
 
+    let sniff = state.config.downloads.sniff_content_type;
+    let (content_type, head) = match detect_content_type(&mut file, sniff).await {
+        Ok(detected) => detected,
+        Err(e) => return Ok(map_sniff_error_to_response(id, e, &state.config)),
+    };
+
+    let coding = response_coding(&headers, content_type.as_deref());
+    if is_not_modified(&headers, entity_tag(&file, coding).as_deref())
+        || is_unmodified_since(&headers, &file)
+    {
+        return Ok(not_modified_response(file_headers(
+            id,
+            &file,
+            content_type.as_deref(),
+            coding,
+            &state.config.downloads.cache_control,
+        )));
+    }
+
     TransferMetrics::track_transfer(TransferMethod::Fetch);
 
+    let rate_limit = state.config.downloads.rate_limit(requested_rate(&headers));
+    let headers = AppendHeaders(file_headers(
+        id,
+        &file,
+        content_type.as_deref(),
+        coding,
+        &state.config.downloads.cache_control,
+    ));
+
+    // Files still being written have no hash yet and are sent unverified.
+    let expected_sha256 = file
+        .summary()
+        .as_ref()
+        .and_then(|summary| Some((summary.hashes.sha256?, summary.file_size_bytes as u64)))
+        .filter(|_| state.config.downloads.verify_hashes);
+
+    // The bytes read to detect the content type are sent first.
+    let file = Cursor::new(head).chain(file);
+    let file: Pin<Box<dyn AsyncRead + Send>> = match expected_sha256 {
+        None => Box::pin(file),
+        Some((sha256, size)) => Box::pin(VerifyingReader::new(file, id, sha256, size)),
+    };
+    let reader: Pin<Box<dyn AsyncRead + Send>> = match coding {
+        None => Box::pin(file),
+        Some(ContentCoding::Gzip) => Box::pin(GzipEncoder::new(BufReader::new(file))),
+        Some(ContentCoding::Deflate) => Box::pin(DeflateEncoder::new(BufReader::new(file))),
+    };
+    let reader: Pin<Box<dyn AsyncRead + Send>> = match rate_limit {
+        None => reader,
+        Some(bytes_per_sec) => Box::pin(ThrottledReader::new(reader, bytes_per_sec)),
+    };
+    let reader = ResponseSizeTracker::new(reader).with_read_guard(state.shutdown.track_read());
+    let stream = ReaderStream::new(reader);
+    let body = StreamBody::new(stream);
+
+    Ok((headers, body).into_response())
+}
+
+/// Gets the bandwidth limit in bytes per second requested using the `X-Yoink-Rate` header.
+///
+/// Values that are not a positive number are ignored.
+fn requested_rate(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(RATE_HEADER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&rate| rate > 0)
+}
+
+/// Gets the presigned URL to redirect the download of a file to, if redirects are
+/// enabled and a backend provides one.
+///
+/// Files buffered locally are only redirected if they reach the configured minimum size.
+async fn redirect_url(id: ShortGuid, state: &AppState) -> Option<String> {
+    let downloads = &state.config.downloads;
+    let min_bytes = downloads.redirect_min_bytes()?;
+
+    let local_size = match state.backbone.get_metadata(id).await {
+        Ok(metadata) => metadata.and_then(|metadata| metadata.file_size_bytes),
+        Err(_) => None,
+    };
+    if matches!(local_size, Some(size) if size < min_bytes) {
+        return None;
+    }
+
+    state
+        .backbone
+        .presigned_url(id, downloads.redirect_expiry())
+        .await
+}
+
+/// Builds the entity tag of a file from its SHA-256 hash, if it was computed.
+///
+/// Compressed responses carry a weak tag, since their bytes differ from the file
+/// while the content is semantically equivalent.
+fn entity_tag(file: &BoxedFileReader, coding: Option<ContentCoding>) -> Option<String> {
+    let sha256 = file.summary().as_ref()?.hashes.sha256?;
+    let tag = format!("\"{hash}\"", hash = hex::encode(&sha256[..]));
+    Some(match coding {
+        None => tag,
+        Some(_) => format!("W/{tag}"),
+    })
+}
+
+/// Determines whether the `If-None-Match` header of a request matches the entity tag,
+/// using the weak comparison.
+fn is_not_modified(headers: &HeaderMap, etag: Option<&str>) -> bool {
+    let Some(etag) = etag else {
+        return false;
+    };
+
+    let opaque_tag = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque_tag(etag);
+
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque_tag(tag) == etag)
+}
+
+/// Gets the wall-clock time the file was created.
+fn last_modified(file: &BoxedFileReader) -> DateTime<Utc> {
+    DateTime::from(file.creation_date())
+}
+
+/// Determines whether the file was created before the `If-Modified-Since` date of a request.
+///
+/// The header is ignored if the request carries an `If-None-Match` header.
+fn is_unmodified_since(headers: &HeaderMap, file: &BoxedFileReader) -> bool {
+    if headers.contains_key(header::IF_NONE_MATCH) {
+        return false;
+    }
+
+    headers
+        .typed_get::<IfModifiedSince>()
+        .map_or(false, |since| {
+            !since.is_modified(SystemTime::from(last_modified(file)))
+        })
+}
+
+/// Builds a `304 Not Modified` response, retaining the headers relevant for caching.
+fn not_modified_response(headers: Vec<(HeaderName, String)>) -> Response {
+    let headers = headers.into_iter().filter(|(name, _)| {
+        name == header::ETAG
+            || name == header::EXPIRES
+            || name == header::VARY
+            || name == header::LAST_MODIFIED
+    });
+    (StatusCode::NOT_MODIFIED, AppendHeaders(headers)).into_response()
+}
+
+/// Determines the content type of a file, as stored or, if `sniff` is set and none was
+/// stored, detected from its first bytes.
+///
+/// Returns the bytes read from the file for the detection, which precede the remaining file.
+async fn detect_content_type(
+    file: &mut BoxedFileReader,
+    sniff: bool,
+) -> std::io::Result<(Option<String>, Vec<u8>)> {
+    if let Some(content_type) = file.content_type() {
+        return Ok((Some(content_type.into_owned()), Vec::new()));
+    }
+    if !sniff {
+        return Ok((None, Vec::new()));
+    }
+
+    let mut head = Vec::new();
+    file.take(SNIFF_BYTES).read_to_end(&mut head).await?;
+    let content_type = infer::get(&head).map(|kind| kind.mime_type().to_string());
+    Ok((content_type, head))
+}
+
+/// Selects the compression of a file as accepted by the client, or `None` if the
+/// file is sent as is.
+fn response_coding(headers: &HeaderMap, content_type: Option<&str>) -> Option<ContentCoding> {
+    let coding = ContentCoding::from_headers(headers)?;
+    let compressible = content_type.map_or(true, is_compressible);
+    compressible.then_some(coding)
+}
+
+/// Determines whether a file of the specified content type benefits from compression.
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let media_type = essence.split('/').next().unwrap_or_default();
+    if matches!(media_type, "image" | "audio" | "video") {
+        return essence == "image/svg+xml" || essence == "image/bmp";
+    }
+
+    !COMPRESSED_CONTENT_TYPES.contains(&essence.as_str())
+}
+
+/// Counts the bytes sent in a `/yoink` response and reports them
+/// to [`HttpMetrics::track_response_size`] when the response ends.
+struct ResponseSizeTracker<R> {
+    reader: R,
+    bytes_sent: usize,
+    /// Keeps the download registered for the graceful shutdown until the response ends.
+    _read_guard: Option<ReadGuard>,
+}
+
+impl<R> ResponseSizeTracker<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            bytes_sent: 0,
+            _read_guard: None,
+        }
+    }
+
+    fn with_read_guard(mut self, guard: ReadGuard) -> Self {
+        self._read_guard = Some(guard);
+        self
+    }
+}
+
+impl<R> AsyncRead for ResponseSizeTracker<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.reader).poll_read(cx, buf);
+        self.bytes_sent += buf.filled().len() - filled;
+        poll
+    }
+}
+
+impl<R> Drop for ResponseSizeTracker<R> {
+    fn drop(&mut self) {
+        HttpMetrics::track_response_size("/yoink/:id", Method::GET, self.bytes_sent);
+    }
+}
+
+/// Provides the same headers as [`do_yoink`] without transferring the file.
+#[axum::debug_handler]
+async fn do_head(
+    FileId(id): FileId,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    record_file_id(id);
+    if let Some(url) = redirect_url(id, &state).await {
+        return (StatusCode::FOUND, [(header::LOCATION, url)]).into_response();
+    }
+
+    match state.backbone.get_file(id).await {
+        Ok(mut file) => {
+            let sniff = state.config.downloads.sniff_content_type;
+            let content_type = match detect_content_type(&mut file, sniff).await {
+                Ok((content_type, _)) => content_type,
+                Err(e) => return map_sniff_error_to_response(id, e, &state.config),
+            };
+
+            let coding = response_coding(&headers, content_type.as_deref());
+            let file_headers = file_headers(
+                id,
+                &file,
+                content_type.as_deref(),
+                coding,
+                &state.config.downloads.cache_control,
+            );
+            if is_not_modified(&headers, entity_tag(&file, coding).as_deref())
+                || is_unmodified_since(&headers, &file)
+            {
+                return not_modified_response(file_headers);
+            }
+            AppendHeaders(file_headers).into_response()
+        }
+        Err(e) => map_file_reader_error_to_response(e, &state.config),
+    }
+}
+
+/// Builds the response headers describing a file of the specified content type, if known,
+/// sent using the specified compression.
+fn file_headers(
+    id: ShortGuid,
+    file: &BoxedFileReader,
+    content_type: Option<&str>,
+    coding: Option<ContentCoding>,
+    cache_control: &CacheControlConfig,
+) -> Vec<(HeaderName, String)> {
     let summary = file.summary();
 
-    let mut headers = Vec::new();
-    if let FileSize::Exactly(size) = file.file_size() {
-        headers.push((header::CONTENT_LENGTH, size.to_string()));
+    let mut headers = vec![(header::VARY, header::ACCEPT_ENCODING.to_string())];
+    match coding {
+        // The length of the compressed body is not known in advance.
+        Some(coding) => headers.push((header::CONTENT_ENCODING, coding.as_str().to_string())),
+        None => {
+            if let FileSize::Exactly(size) = file.file_size() {
+                headers.push((header::CONTENT_LENGTH, size.to_string()));
+            }
+        }
     }
 
-    // The content type specified on file creation, or an empty string.
-    let content_type = file
-        .content_type()
-        .map_or(String::default(), |c| c.to_string());
+    // The content type of the file, or an empty string.
+    let content_type = content_type.map_or(String::default(), str::to_string);
 
-    // Add ETag from SHA-256 hash, etc.
+    if let Some(etag) = entity_tag(file, coding) {
+        headers.push((header::ETAG, etag));
+    }
+
+    // Add hash headers, etc.
     if let Some(summary) = summary {
-        headers.push((
-            header::ETAG,
-            base64::engine::general_purpose::STANDARD.encode(&summary.hashes.sha256[..]),
-        ));
+        // Hashes are only present if they were selected during the upload.
+        if let Some(sha256) = &summary.hashes.sha256 {
+            headers.push((
+                HeaderName::from_static("yy-file-sha256"),
+                hex::encode(&sha256[..]),
+            ));
 
-        headers.push((
-            HeaderName::from_static("content-md5"),
-            base64::engine::general_purpose::STANDARD.encode(&summary.hashes.md5[..]),
-        ));
+            headers.push((
+                HeaderName::from_static("x-checksum-sha256"),
+                hex::encode(&sha256[..]),
+            ));
+        }
 
-        headers.push((
-            HeaderName::from_static("yy-file-md5"),
-            hex::encode(&summary.hashes.md5[..]),
-        ));
+        if let Some(md5) = &summary.hashes.md5 {
+            // Content-MD5 describes the body as sent, so it is omitted for compressed bodies.
+            if coding.is_none() {
+                headers.push((
+                    HeaderName::from_static("content-md5"),
+                    base64::engine::general_purpose::STANDARD.encode(&md5[..]),
+                ));
+            }
 
-        headers.push((
-            HeaderName::from_static("yy-file-sha256"),
-            hex::encode(&summary.hashes.sha256[..]),
-        ));
+            headers.push((
+                HeaderName::from_static("yy-file-md5"),
+                hex::encode(&md5[..]),
+            ));
+        }
+
+        // The metadata is returned the way it was attached.
+        if !summary.metadata.is_empty() {
+            let json = serde_json::to_vec(&summary.metadata).expect("failed to serialize metadata");
+            headers.push((
+                META_HEADER.clone(),
+                base64::engine::general_purpose::STANDARD.encode(json),
+            ));
+        }
 
         let file_name = &summary.file_name;
 
@@ -114,21 +525,41 @@ async fn do_yoink(
         headers.push(header);
     }
 
-    if !content_type.is_empty() {
+    // Browsers mishandle responses without a content type.
+    if content_type.is_empty() {
+        headers.push((header::CONTENT_TYPE, DEFAULT_CONTENT_TYPE.to_string()));
+    } else {
         headers.push((header::CONTENT_TYPE, content_type));
     }
 
     headers.push((header::AGE, file.file_age().as_secs().to_string()));
+    headers.push((
+        header::LAST_MODIFIED,
+        datetime_as_rfc1123(&last_modified(file)),
+    ));
 
     // Provide expiration header.
     let expiration_date = expiration_as_rfc1123(&file.expiration_date());
     headers.push((header::EXPIRES, expiration_date));
 
-    let stream = ReaderStream::new(file);
-    let body = StreamBody::new(stream);
+    // Caches must not serve the file past its expiration.
+    let remaining_lease = file
+        .expiration_date()
+        .saturating_duration_since(Instant::now());
+    if let Some(value) = cache_control.header_value(remaining_lease) {
+        headers.push((header::CACHE_CONTROL, value));
+    }
+    headers
+}
 
-    let headers = AppendHeaders(headers);
-    Ok((headers, body).into_response())
+/// Removes a file from local storage, releasing its disk space immediately.
+#[axum::debug_handler]
+async fn do_delete(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    record_file_id(id);
+    match state.backbone.remove_file(id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => map_file_reader_error_to_response(e, &state.config),
+    }
 }
 
 /// Attempts to generate a `Content-Disposition` header from the optionally specified
@@ -182,28 +613,613 @@ where
     }
 }
 
-fn map_file_reader_error_to_response(value: GetFileReaderError) -> Response {
+fn map_file_reader_error_to_response(value: GetFileReaderError, config: &AppConfig) -> Response {
     match value {
-        GetFileReaderError::UnknownFile(id) => problemdetails::new(StatusCode::NOT_FOUND)
-            .with_title("File not found")
+        GetFileReaderError::UnknownFile(id) => ProblemType::FileNotFound
+            .problem(&config.errors)
             .with_detail(format!("The file with ID {id} could not be found"))
-            .with_instance(format!("/yoink/{id}"))
+            .with_instance(public_path(config.base_path(), format!("/yoink/{id}")))
             .with_value("id", id.to_string())
             .into_response(),
-        GetFileReaderError::FileExpired(id) => problemdetails::new(StatusCode::GONE)
-            .with_title("File not found")
+        GetFileReaderError::FileExpired(id) => ProblemType::FileExpired
+            .problem(&config.errors)
             .with_detail(format!("The file with ID {id} has expired"))
-            .with_instance(format!("/yoink/{id}"))
+            .with_instance(public_path(config.base_path(), format!("/yoink/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+        GetFileReaderError::FileError(id, e) => ProblemType::FileAccessFailed
+            .problem(&config.errors)
+            .with_detail(format!("Unable to process file: {e}"))
+            .with_instance(public_path(config.base_path(), format!("/yoink/{id}")))
             .with_value("id", id.to_string())
+            .with_value("error", e.to_string())
             .into_response(),
-        GetFileReaderError::FileError(id, e) => {
-            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
-                .with_title("File not found")
-                .with_detail(format!("Unable to process file: {e}"))
-                .with_instance(format!("/yoink/{id}"))
-                .with_value("id", id.to_string())
-                .with_value("error", e.to_string())
-                .into_response()
+        GetFileReaderError::BackendTimeout(id) => ProblemType::BackendTimeout
+            .problem(&config.errors)
+            .with_detail(format!(
+                "The backends did not provide the file with ID {id} in time"
+            ))
+            .with_instance(public_path(config.base_path(), format!("/yoink/{id}")))
+            .with_value("id", id.to_string())
+            .into_response(),
+    }
+}
+
+fn map_sniff_error_to_response(id: ShortGuid, e: std::io::Error, config: &AppConfig) -> Response {
+    ProblemType::FileAccessFailed
+        .problem(&config.errors)
+        .with_detail(format!("Unable to process file: {e}"))
+        .with_instance(public_path(config.base_path(), format!("/yoink/{id}")))
+        .with_value("id", id.to_string())
+        .with_value("error", e.to_string())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use file_distribution::hash::{HashBlake3, HashMd5, HashSha256};
+    use file_distribution::{BufferedFileReader, FileHashes, WriteSummary};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn header<'a>(headers: &'a [(HeaderName, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[tokio::test]
+    async fn response_size_is_counted() {
+        use tokio::io::AsyncReadExt;
+
+        let file = BoxedFileReader::new(BufferedFileReader::new("yeet yoink"));
+        let mut tracker = ResponseSizeTracker::new(file);
+        let mut content = Vec::new();
+        tracker
+            .read_to_end(&mut content)
+            .await
+            .expect("failed to read");
+        assert_eq!(content, b"yeet yoink");
+        assert_eq!(tracker.bytes_sent, 10);
+    }
+
+    #[test]
+    fn requested_rates_are_parsed() {
+        let rate = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(RATE_HEADER, HeaderValue::from_static(value));
+            requested_rate(&headers)
+        };
+
+        assert_eq!(requested_rate(&HeaderMap::new()), None);
+        assert_eq!(rate("1048576"), Some(1024 * 1024));
+        assert_eq!(rate("0"), None);
+        assert_eq!(rate("fast"), None);
+    }
+
+    #[test]
+    fn file_headers_contain_hashes() {
+        let summary = WriteSummary {
+            expires: Instant::now(),
+            hashes: FileHashes::new(
+                HashMd5::new().finalize(),
+                HashSha256::new().finalize(),
+                HashBlake3::new().finalize(),
+            ),
+            file_name: None,
+            file_size_bytes: 0,
+            metadata: [("tenant".to_string(), "acme".to_string())].into(),
+        };
+        let file =
+            BoxedFileReader::new(BufferedFileReader::new("").with_summary(Some(Arc::new(summary))));
+
+        let headers = file_headers(
+            ShortGuid::new_random(),
+            &file,
+            None,
+            None,
+            &CacheControlConfig::default(),
+        );
+        assert_eq!(
+            header(&headers, "content-md5"),
+            Some("1B2M2Y8AsgTpgAmY7PhCfg==")
+        );
+        assert_eq!(
+            header(&headers, "x-checksum-sha256"),
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            header(&headers, "etag"),
+            Some("\"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855\"")
+        );
+        assert_eq!(
+            header(&headers, "x-yeet-meta"),
+            Some("eyJ0ZW5hbnQiOiJhY21lIn0=")
+        );
+    }
+
+    #[tokio::test]
+    async fn downloads_are_redirected_to_presigned_urls() {
+        use crate::{await_rendezvous, AppState};
+        use app_config::AppConfig;
+        use axum::body::Body;
+        use backend_traits::BackendCommand;
+        use hyper::Request;
+        use tower::ServiceExt;
+
+        let mut config = AppConfig::default();
+        config.downloads.redirect = true;
+
+        // A stub backend presigning every file.
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(config);
+        tokio::spawn(async move {
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::PresignFile(id, _, reply) = command {
+                    reply.send(Some(format!("https://bucket/{id}"))).ok();
+                }
+            }
+        });
+
+        let app = Router::new().map_yoink_endpoint().with_state(state);
+
+        let id = ShortGuid::new_random();
+        for method in [Method::GET, Method::HEAD] {
+            let request = Request::builder()
+                .method(method.clone())
+                .uri(format!("/yoink/{id}"))
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FOUND, "{method}");
+            assert_eq!(
+                response.headers()[header::LOCATION],
+                format!("https://bucket/{id}")
+            );
+        }
+        drop(app);
+
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn head_requests_answer_like_get_requests() {
+        use crate::handlers::YeetRoutes;
+        use crate::{await_rendezvous, AppState};
+        use app_config::AppConfig;
+        use axum::body::Body;
+        use hyper::Request;
+        use tower::ServiceExt;
+
+        fn expires(response: &Response) -> DateTime<Utc> {
+            let expires = response.headers()[header::EXPIRES].to_str().unwrap();
+            DateTime::parse_from_rfc2822(expires)
+                .expect("invalid Expires header")
+                .with_timezone(&Utc)
+        }
+
+        let mut config = AppConfig::default();
+        config.files.lease_sec = Some(1);
+
+        // A stub backend that provides no files.
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(config);
+        tokio::spawn(async move { while backend_receiver.recv().await.is_some() {} });
+
+        let app = Router::new()
+            .map_yeet_endpoint()
+            .map_yoink_endpoint()
+            .with_state(state);
+
+        let request = Request::post("/yeet")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(Body::from("yeet yoink"))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = body["id"].as_str().unwrap().to_string();
+
+        let send = |method: Method, id: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(format!("/yoink/{id}"))
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+
+        let get = send(Method::GET, &id).await.unwrap();
+        let head = send(Method::HEAD, &id).await.unwrap();
+        assert_eq!(head.status(), StatusCode::OK);
+        assert_eq!(head.status(), get.status());
+        for name in [header::CONTENT_LENGTH, header::CONTENT_TYPE] {
+            assert_eq!(
+                head.headers().get(&name),
+                get.headers().get(&name),
+                "{name}"
+            );
+        }
+        assert_eq!(head.headers()[header::CONTENT_LENGTH], "10");
+        assert_eq!(head.headers()[header::CONTENT_TYPE], "text/plain");
+
+        // Both dates are derived from the lease at the time of the request.
+        let drift = expires(&head) - expires(&get);
+        assert!(drift.num_seconds().abs() <= 1, "{drift}");
+
+        let get = hyper::body::to_bytes(get.into_body()).await.unwrap();
+        let head = hyper::body::to_bytes(head.into_body()).await.unwrap();
+        assert_eq!(get, "yeet yoink");
+        assert!(head.is_empty());
+
+        let unknown = ShortGuid::new_random().to_string();
+        for method in [Method::GET, Method::HEAD] {
+            let response = send(method.clone(), &unknown).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{method}");
+        }
+
+        tokio::time::pause();
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        for method in [Method::GET, Method::HEAD] {
+            let response = send(method.clone(), &id).await.unwrap();
+            assert_eq!(response.status(), StatusCode::GONE, "{method}");
+        }
+
+        drop(app);
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn backend_timeouts_are_reported_as_gateway_timeouts() {
+        use crate::{await_rendezvous, AppState};
+        use app_config::AppConfig;
+        use axum::body::Body;
+        use backend_traits::{BackendCommand, ReceiveTimeout};
+        use hyper::Request;
+        use tower::ServiceExt;
+
+        // A stub backend that never answers in time.
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(AppConfig::default());
+        tokio::spawn(async move {
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::ReceiveFile(_, reply) = command {
+                    reply.send(Err(ReceiveTimeout)).ok();
+                }
+            }
+        });
+
+        let app = Router::new().map_yoink_endpoint().with_state(state);
+
+        let request = Request::get(format!("/yoink/{}", ShortGuid::new_random()))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[test]
+    fn entity_tags_are_matched() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_not_modified(&headers, Some("\"abc\"")));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static("\"def\", W/\"abc\""),
+        );
+        assert!(is_not_modified(&headers, Some("\"abc\"")));
+        assert!(is_not_modified(&headers, Some("W/\"abc\"")));
+        assert!(!is_not_modified(&headers, Some("\"xyz\"")));
+        assert!(!is_not_modified(&headers, None));
+
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(is_not_modified(&headers, Some("\"xyz\"")));
+    }
+
+    #[test]
+    fn modification_dates_are_compared_to_the_creation_time() {
+        let file = BoxedFileReader::new(BufferedFileReader::new("yeet"));
+        let created = SystemTime::from(last_modified(&file));
+        let since = |time: SystemTime| {
+            let mut headers = HeaderMap::new();
+            headers.typed_insert(IfModifiedSince::from(time));
+            headers
+        };
+
+        assert!(!is_unmodified_since(&HeaderMap::new(), &file));
+        assert!(is_unmodified_since(&since(created), &file));
+        assert!(is_unmodified_since(
+            &since(created + Duration::from_secs(60)),
+            &file
+        ));
+        assert!(!is_unmodified_since(
+            &since(created - Duration::from_secs(60)),
+            &file
+        ));
+
+        // Entity tags take precedence over modification dates.
+        let mut headers = since(created);
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"xyz\""));
+        assert!(!is_unmodified_since(&headers, &file));
+
+        let headers = file_headers(
+            ShortGuid::new_random(),
+            &file,
+            None,
+            None,
+            &CacheControlConfig::default(),
+        );
+        let date = header(&headers, "last-modified").expect("Last-Modified missing");
+        assert!(date.ends_with(" GMT"));
+
+        // Dates sent back by clients match the creation time.
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_str(date).unwrap(),
+        );
+        assert!(is_unmodified_since(&headers, &file));
+    }
+
+    #[test]
+    fn not_modified_response_has_no_content_headers() {
+        let response = not_modified_response(vec![
+            (header::ETAG, "\"abc\"".to_string()),
+            (header::CONTENT_LENGTH, "10".to_string()),
+        ]);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], "\"abc\"");
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+    }
+
+    #[test]
+    fn compressed_content_types_are_not_compressed_again() {
+        assert!(is_compressible("text/plain; charset=utf-8"));
+        assert!(is_compressible("application/json"));
+        assert!(is_compressible("image/svg+xml"));
+        assert!(!is_compressible("image/png"));
+        assert!(!is_compressible("video/mp4"));
+        assert!(!is_compressible("Application/GZIP"));
+        assert!(!is_compressible("application/zip"));
+    }
+
+    #[tokio::test]
+    async fn gzip_responses_decode_to_the_file() {
+        use async_compression::tokio::bufread::GzipDecoder;
+        use tokio::io::AsyncReadExt;
+
+        let file = BoxedFileReader::new(BufferedFileReader::new("yeet yoink yeet yoink"));
+        let mut encoder = GzipEncoder::new(BufReader::new(file));
+        let mut compressed = Vec::new();
+        encoder
+            .read_to_end(&mut compressed)
+            .await
+            .expect("failed to compress");
+
+        let mut content = Vec::new();
+        GzipDecoder::new(&compressed[..])
+            .read_to_end(&mut content)
+            .await
+            .expect("failed to decompress");
+        assert_eq!(content, b"yeet yoink yeet yoink");
+    }
+
+    #[test]
+    fn compressed_responses_have_no_content_length() {
+        let file = BoxedFileReader::new(BufferedFileReader::new("yeet"));
+        let headers = file_headers(
+            ShortGuid::new_random(),
+            &file,
+            None,
+            Some(ContentCoding::Gzip),
+            &CacheControlConfig::default(),
+        );
+        assert_eq!(header(&headers, "content-encoding"), Some("gzip"));
+        assert_eq!(header(&headers, "vary"), Some("accept-encoding"));
+        assert_eq!(header(&headers, "content-length"), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cache_control_follows_the_remaining_lease() {
+        let expires = Instant::now() + Duration::from_secs(120);
+        let file =
+            BoxedFileReader::new(BufferedFileReader::new("yeet").with_expiration_date(expires));
+        let cache_control = CacheControlConfig {
+            enabled: true,
+            ..Default::default()
+        };
+
+        let headers = file_headers(ShortGuid::new_random(), &file, None, None, &cache_control);
+        assert_eq!(
+            header(&headers, "cache-control"),
+            Some("private, max-age=120")
+        );
+
+        tokio::time::advance(Duration::from_secs(100)).await;
+        let headers = file_headers(ShortGuid::new_random(), &file, None, None, &cache_control);
+        assert_eq!(
+            header(&headers, "cache-control"),
+            Some("private, max-age=20")
+        );
+
+        tokio::time::advance(Duration::from_secs(60)).await;
+        let headers = file_headers(ShortGuid::new_random(), &file, None, None, &cache_control);
+        assert_eq!(
+            header(&headers, "cache-control"),
+            Some("private, max-age=0")
+        );
+
+        let headers = file_headers(
+            ShortGuid::new_random(),
+            &file,
+            None,
+            None,
+            &CacheControlConfig::default(),
+        );
+        assert_eq!(header(&headers, "cache-control"), None);
+    }
+
+    #[test]
+    fn missing_content_types_default_to_octet_stream() {
+        let file = BoxedFileReader::new(BufferedFileReader::new("yeet"));
+        let headers = file_headers(
+            ShortGuid::new_random(),
+            &file,
+            None,
+            None,
+            &CacheControlConfig::default(),
+        );
+        assert_eq!(
+            header(&headers, "content-type"),
+            Some("application/octet-stream")
+        );
+
+        let headers = file_headers(
+            ShortGuid::new_random(),
+            &file,
+            Some("text/plain"),
+            None,
+            &CacheControlConfig::default(),
+        );
+        assert_eq!(header(&headers, "content-type"), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn content_types_are_sniffed_if_enabled() {
+        const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR";
+
+        let mut file = BoxedFileReader::new(BufferedFileReader::new(PNG));
+        let (content_type, head) = detect_content_type(&mut file, false).await.unwrap();
+        assert_eq!(content_type, None);
+        assert!(head.is_empty());
+
+        let (content_type, head) = detect_content_type(&mut file, true).await.unwrap();
+        assert_eq!(content_type.as_deref(), Some("image/png"));
+
+        // The sniffed bytes are sent before the remaining file.
+        let mut content = Vec::new();
+        Cursor::new(head)
+            .chain(file)
+            .read_to_end(&mut content)
+            .await
+            .unwrap();
+        assert_eq!(content, PNG);
+
+        // Stored content types take precedence.
+        let mut file = BoxedFileReader::new(
+            BufferedFileReader::new(PNG).with_content_type(Some("text/plain".to_string())),
+        );
+        let (content_type, head) = detect_content_type(&mut file, true).await.unwrap();
+        assert_eq!(content_type.as_deref(), Some("text/plain"));
+        assert!(head.is_empty());
+    }
+
+    #[tokio::test]
+    async fn corrupted_downloads_are_cut_off() {
+        use crate::handlers::YeetRoutes;
+        use crate::{await_rendezvous, AppState};
+        use app_config::AppConfig;
+        use axum::body::Body;
+        use axum::http::Request;
+        use std::io::{Seek, SeekFrom, Write};
+        use tokio::io::AsyncWriteExt;
+        use tokio::net::TcpStream;
+        use tower::ServiceExt;
+
+        fn corrupted_downloads() -> u64 {
+            let encoded = metrics::Metrics::get().encode();
+            encoded
+                .lines()
+                .find_map(|line| line.strip_prefix("buffered_file_corrupted_downloads_total "))
+                .map_or(0, |count| count.parse().expect("invalid metric value"))
         }
+
+        let mut config = AppConfig::default();
+        config.downloads.verify_hashes = true;
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let backbone = state.backbone.clone();
+        let app = Router::new()
+            .map_yeet_endpoint()
+            .map_yoink_endpoint()
+            .with_state(state);
+
+        let content = vec![b'y'; 100_000];
+        let response = app
+            .clone()
+            .oneshot(Request::post("/yeet").body(Body::from(content)).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id: ShortGuid = body["id"].as_str().unwrap().parse().unwrap();
+
+        // Flip bytes of the buffered file behind the service's back.
+        let path = std::env::temp_dir().join(format!("atmp_{}", uuid::Uuid::from(id)));
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .expect("failed to open the buffered file");
+        file.seek(SeekFrom::Start(50_000)).unwrap();
+        file.write_all(b"yoink").unwrap();
+        drop(file);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(async {
+                    stopped.await.ok();
+                }),
+        );
+
+        let corrupted_before = corrupted_downloads();
+        let mut connection = TcpStream::connect(addr).await.unwrap();
+        connection
+            .write_all(format!("GET /yoink/{id} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+            .await
+            .unwrap();
+
+        // The server closes the connection instead of completing the response.
+        let mut response = Vec::new();
+        let mut buffer = [0; 8192];
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match connection.read(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => response.extend_from_slice(&buffer[..read]),
+                }
+            }
+        })
+        .await
+        .expect("the connection was not closed");
+
+        let separator = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .expect("no response head was received");
+        let head = String::from_utf8_lossy(&response[..separator]);
+        assert!(head.starts_with("HTTP/1.1 200 "), "{head}");
+        assert!(head.contains("content-length: 100000"), "{head}");
+        assert!(response.len() - separator - 4 < 100_000);
+        assert!(corrupted_downloads() > corrupted_before);
+
+        backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        stop.send(()).ok();
+        server
+            .await
+            .expect("failed to join the server")
+            .expect("the server failed");
+        drop((backbone, backend_receiver));
+        await_rendezvous(rendezvous).await;
     }
 }
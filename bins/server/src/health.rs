@@ -1,7 +1,6 @@
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-#[allow(dead_code)]
 pub enum HealthState {
     Healthy,
     Degraded,
@@ -5,6 +5,7 @@
 
 use crate::handlers::*;
 use app_config::AppConfig;
+use axum::extract::FromRef;
 use axum::Router;
 use backbone::{Backbone, FileAccessorBridge};
 use clap::ArgMatches;
@@ -12,30 +13,113 @@ use directories::ProjectDirs;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 use hyper::Server;
+use metrics::http::{HttpMetrics, LabelNormalization};
 use rendezvous::Rendezvous;
 use std::net::SocketAddr;
 use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::broadcast;
 use tower::ServiceBuilder;
 use tracing::{debug, error, info, warn};
 
+use crate::backend_directory::BackendDirectory;
 use crate::backend_registry::BackendRegistry;
+use crate::quotas::UploadQuotas;
+use crate::receipts::DistributionRecords;
+use crate::shutdown::ShutdownCoordinator;
+use crate::upload_limit::UploadLimiter;
+#[cfg(feature = "filesystem")]
+use backend_filesystem::FilesystemBackend;
+#[cfg(feature = "http")]
+use backend_http::HttpBackend;
 #[cfg(feature = "memcache")]
 use backend_memcache::MemcacheBackend;
+#[cfg(feature = "redis")]
+use backend_redis::RedisBackend;
+#[cfg(feature = "s3")]
+use backend_s3::S3Backend;
 use file_distribution::FileProvider;
 
+mod backend_directory;
 mod backend_registry;
+mod circuit_breaker;
 mod commands;
+mod error;
 mod handlers;
 mod health;
 mod logging;
+mod quotas;
+mod receipts;
 mod services;
+mod shutdown;
+mod throttle;
+mod upload_limit;
+mod verify;
 
 #[derive(Clone)]
 pub struct AppState {
     shutdown_tx: broadcast::Sender<()>,
     backbone: Arc<Backbone>,
+    receipts: Arc<DistributionRecords>,
+    backends: Arc<BackendDirectory>,
+    quotas: Arc<UploadQuotas>,
+    uploads: Arc<UploadLimiter>,
+    shutdown: Arc<ShutdownCoordinator>,
+    config: Arc<AppConfig>,
+}
+
+impl FromRef<AppState> for Arc<AppConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// Creates the state of a service without backends using the specified configuration.
+    ///
+    /// Commands sent to the backends arrive at the returned receiver. Once the state and the
+    /// receiver were dropped, the returned rendezvous is completed using [`await_rendezvous`].
+    pub(crate) fn for_tests(
+        config: AppConfig,
+    ) -> (
+        Self,
+        tokio::sync::mpsc::Receiver<backend_traits::BackendCommand>,
+        Rendezvous,
+    ) {
+        let (backend_sender, backend_receiver) = tokio::sync::mpsc::channel(16);
+        let rendezvous = Rendezvous::new();
+        let state = Self {
+            shutdown_tx: broadcast::channel(1).0,
+            backbone: Arc::new(
+                Backbone::new(
+                    backend_sender.into(),
+                    rendezvous.fork_guard(),
+                    config.files.lease(),
+                )
+                .with_expired_tombstones(
+                    config.files.tombstone_grace(),
+                    config.files.tombstone_capacity(),
+                ),
+            ),
+            receipts: Arc::new(DistributionRecords::default()),
+            backends: Arc::new(BackendDirectory::default()),
+            quotas: Arc::new(UploadQuotas::new(&config.auth)),
+            uploads: Arc::new(UploadLimiter::new(&config.files)),
+            shutdown: Arc::new(ShutdownCoordinator::new(rendezvous.fork_guard())),
+            config: Arc::new(config),
+        };
+        (state, backend_receiver, rendezvous)
+    }
+}
+
+/// Waits until all tasks of a service created using [`AppState::for_tests`] have finished.
+#[cfg(test)]
+pub(crate) async fn await_rendezvous(rendezvous: Rendezvous) {
+    tokio::task::spawn_blocking(move || rendezvous.rendezvous())
+        .await
+        .expect("failed to await the rendezvous");
 }
 
 #[tokio::main]
@@ -61,13 +145,30 @@ async fn main() -> ExitCode {
         }
     };
 
+    if let Err(e) = cfg.validate() {
+        error!("{e}");
+        return ExitCode::FAILURE;
+    }
+
+    HttpMetrics::set_label_normalization(LabelNormalization {
+        status_classes: cfg.metrics.status_classes,
+        route_templates: cfg.metrics.route_templates,
+    });
+
     // Provide a signal that can be used to shut down the server.
     let (shutdown_tx, _) = broadcast::channel::<()>(1);
-    register_shutdown_handler(shutdown_tx.clone());
 
     // Create a rendezvous channel to ensure all relevant tasks have been shut down.
     let rendezvous = Rendezvous::new();
 
+    // Termination signals drain in-flight downloads before the servers are stopped.
+    let coordinator = Arc::new(ShutdownCoordinator::new(rendezvous.fork_guard()));
+    register_shutdown_handler(
+        coordinator.clone(),
+        shutdown_tx.clone(),
+        cfg.shutdown.drain_timeout(),
+    );
+
     let file_accessor = Arc::new(FileAccessorBridge::default());
 
     // TODO: Create and register backends.
@@ -83,22 +184,92 @@ async fn main() -> ExitCode {
         Err(_) => return ExitCode::FAILURE,
     };
 
-    let registry = registry.build();
+    #[cfg(feature = "filesystem")]
+    let registry = match registry.add_backends::<FilesystemBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    #[cfg(feature = "s3")]
+    let registry = match registry.add_backends::<S3Backend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    #[cfg(feature = "http")]
+    let registry = match registry.add_backends::<HttpBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    #[cfg(feature = "redis")]
+    let registry = match registry.add_backends::<RedisBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    let registry = registry.build(&cfg);
     let backend_sender = registry.get_sender().expect("failed to get backend sender");
 
-    let backbone = Arc::new(Backbone::new(backend_sender, rendezvous.fork_guard()));
+    let mut backbone = Backbone::new(backend_sender, rendezvous.fork_guard(), cfg.files.lease())
+        .with_enqueue_timeout(cfg.distribution.enqueue_timeout())
+        .with_upload_session_timeout(cfg.timeouts.resumable_session_timeout())
+        .with_expired_tombstones(cfg.files.tombstone_grace(), cfg.files.tombstone_capacity());
+    if let Some(max_bytes) = cfg.files.broadcast_max_bytes() {
+        backbone = backbone.with_broadcast_reads(max_bytes);
+    }
+    if let Some(capacity_bytes) = cfg.files.max_storage_bytes {
+        backbone = backbone.with_storage_quota(capacity_bytes);
+    }
+    if let Some(high_water_bytes) = cfg.files.storage_high_water_bytes {
+        backbone = backbone.with_storage_high_water_mark(high_water_bytes);
+    }
+    if cfg.files.deduplicate {
+        backbone = backbone.with_deduplication();
+    }
+    if let Some(dir) = cfg.files.temp_dir.clone() {
+        if !dir.is_dir() {
+            error!("The temporary directory {dir:?} does not exist");
+            return ExitCode::FAILURE;
+        }
+        backbone = backbone.with_temp_dir(dir);
+    }
+    if let Some((dir, max_bytes)) = cfg.files.in_memory_buffer() {
+        if !dir.is_dir() {
+            error!("The in-memory directory {dir:?} does not exist");
+            return ExitCode::FAILURE;
+        }
+        backbone = backbone.with_in_memory_buffer(dir, max_bytes);
+    }
+    if let Some(path) = cfg.files.index_path.clone() {
+        backbone = match backbone.with_file_index(&path).await {
+            Ok(backbone) => backbone,
+            Err(e) => {
+                error!("Failed to open the file index {path:?}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    let backbone = Arc::new(backbone);
     file_accessor.set_backbone(&backbone);
 
     // The application state is shared with the Axum servers.
     let app_state = AppState {
         shutdown_tx: shutdown_tx.clone(),
         backbone: backbone.clone(),
+        receipts: registry.distribution_records(),
+        backends: registry.backend_directory(),
+        quotas: Arc::new(UploadQuotas::new(&cfg.auth)),
+        uploads: Arc::new(UploadLimiter::new(&cfg.files)),
+        shutdown: coordinator.clone(),
+        config: Arc::new(cfg),
     };
 
     let exit_code = serve_requests(matches, app_state).await.err();
 
     // If all servers are shut down, ensure the news is broadcast as well.
     stop_all_servers(shutdown_tx);
+    coordinator.complete();
 
     // TODO: Ensure registry is dropped, backbone is halted, ...
     shut_down_backbone(backbone);
@@ -119,17 +290,16 @@ fn stop_all_servers(shutdown_tx: broadcast::Sender<()>) {
 
 async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(), ExitCode> {
     let shutdown_tx = app_state.shutdown_tx.clone();
+    let app = match build_app(app_state) {
+        Ok(app) => app,
+        Err(e) => {
+            error!("{e}");
+            return Err(ExitCode::FAILURE);
+        }
+    };
 
-    let app = Router::new()
-        .map_metrics_endpoint()
-        .map_shutdown_endpoint()
-        .map_yeet_endpoint()
-        .map_yoink_endpoint()
-        .map_health_endpoints()
-        .with_state(app_state)
-        .layer(services::HttpCallMetricsLayer);
-
-    let make_svc = app.into_make_service();
+    // The peer address identifies the client, or the proxy forwarding its requests.
+    let make_svc = app.into_make_service_with_connect_info::<SocketAddr>();
 
     let service_builder = ServiceBuilder::new().service(make_svc);
 
@@ -199,10 +369,177 @@ async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(),
     }
 }
 
-fn register_shutdown_handler(shutdown_tx: broadcast::Sender<()>) {
+/// Builds the router serving all endpoints.
+fn build_app(app_state: AppState) -> Result<Router, services::InvalidCorsConfig> {
+    let config = app_state.config.clone();
+
+    // Storing and retrieving files requires authentication; health checks do not.
+    let files = Router::new()
+        .map_yeet_endpoint()
+        .map_yoink_endpoint()
+        .map_archive_endpoint()
+        .map_files_endpoint()
+        .map_redistribute_endpoint()
+        .map_keepalive_endpoint()
+        .map_meta_endpoint()
+        .map_receipts_endpoint()
+        .route_layer(services::BearerAuthLayer::new(config.clone()));
+
+    // Administrative routes are only accessible with an admin token.
+    let admin = Router::new()
+        .map_admin_endpoints()
+        .map_shutdown_endpoint()
+        .route_layer(services::BearerAuthLayer::admin(config.clone()));
+
+    // Preflight requests carry no credentials and are answered before authenticating.
+    let files = match services::cors_layer(&config.cors)? {
+        Some(cors) => files.layer(cors),
+        None => files,
+    };
+
+    let app = Router::new()
+        .map_metrics_endpoint()
+        .merge(files)
+        .merge(admin)
+        .map_health_endpoints()
+        .map_openapi_endpoint()
+        .map_version_endpoint()
+        .with_state(app_state)
+        .layer(services::HandlerTimeoutLayer::new(config.clone()))
+        .layer(services::HttpCallMetricsLayer::new(config.base_path()))
+        .layer(services::ClientIpLayer::new(&config.proxies))
+        .layer(services::RequestIdLayer);
+
+    // Nested routes see the request path without the base path, hence metrics and
+    // per-route timeouts refer to the routes as they are.
+    Ok(match config.base_path() {
+        Some(base_path) => {
+            info!("Serving all routes under {base_path}");
+            Router::new().nest(base_path, app)
+        }
+        None => app,
+    })
+}
+
+fn register_shutdown_handler(
+    coordinator: Arc<ShutdownCoordinator>,
+    shutdown_tx: broadcast::Sender<()>,
+    drain_timeout: Duration,
+) {
+    let runtime = tokio::runtime::Handle::current();
     ctrlc::set_handler(move || {
-        warn!("Initiating shutdown from OS");
-        shutdown_tx.send(()).ok();
+        // A second signal skips waiting for downloads.
+        if !coordinator.begin_drain() {
+            warn!("Forcing shutdown from OS");
+            shutdown_tx.send(()).ok();
+            return;
+        }
+
+        warn!("Initiating shutdown from OS; draining in-flight downloads");
+        runtime.spawn(drain_and_stop(
+            coordinator.clone(),
+            shutdown_tx.clone(),
+            drain_timeout,
+        ));
     })
     .expect("Error setting process termination handler");
 }
+
+/// Waits up to `drain_timeout` for in-flight downloads to finish, then stops the servers.
+async fn drain_and_stop(
+    coordinator: Arc<ShutdownCoordinator>,
+    shutdown_tx: broadcast::Sender<()>,
+    drain_timeout: Duration,
+) {
+    if tokio::time::timeout(drain_timeout, coordinator.drained())
+        .await
+        .is_err()
+    {
+        warn!(
+            "Stopping with {reads} downloads still in flight after {drain_timeout:?}",
+            reads = coordinator.active_reads()
+        );
+    }
+
+    shutdown_tx.send(()).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use app_config::auth::TokenConfig;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use shortguid::ShortGuid;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn all_routes_but_health_checks_require_a_token() {
+        let mut config = AppConfig::default();
+        config.auth.tokens = vec![TokenConfig {
+            token: "s3cr3t".to_string(),
+            quota_bytes: None,
+        }];
+        config.auth.admin_tokens = vec!["4dm1n".to_string()];
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let app = build_app(state).expect("failed to build the router");
+
+        let id = ShortGuid::new_random();
+        let requests = [
+            ("POST", format!("/keepalive/{id}")),
+            ("GET", format!("/meta/{id}")),
+            ("POST", "/receipts/verify".to_string()),
+            ("POST", "/stop".to_string()),
+            ("GET", format!("/yoink/{id}")),
+            ("GET", "/admin/backends".to_string()),
+        ];
+        for (method, path) in &requests {
+            let request = Request::builder()
+                .method(*method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{method} {path}"
+            );
+        }
+
+        // File tokens do not grant access to the administrative routes.
+        let request = Request::post("/stop")
+            .header(header::AUTHORIZATION, "Bearer s3cr3t")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = Request::get("/livez").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        drop((app, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn problem_instances_include_the_base_path() {
+        let mut config = AppConfig::default();
+        config.base_path = Some("/api/v1/".to_string());
+        let (state, backend_receiver, rendezvous) = AppState::for_tests(config);
+        let app = build_app(state).expect("failed to build the router");
+
+        let request = Request::get("/api/v1/yoink/not-an-id")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["instance"], "/api/v1/yoink/not-an-id");
+
+        drop((app, backend_receiver));
+        await_rendezvous(rendezvous).await;
+    }
+}
@@ -0,0 +1,287 @@
+//! Contains the upload quotas of bearer tokens.
+
+use crate::services::AuthenticatedToken;
+use app_config::auth::AuthConfig;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Keeps track of the bytes uploaded per token over a rolling window.
+#[derive(Default)]
+pub struct UploadQuotas {
+    /// The quota of each token, indexed like `auth.tokens`.
+    quotas: Vec<Option<u64>>,
+    /// The period over which uploads are counted.
+    window: Duration,
+    /// The bytes counted against the quota of each token.
+    usage: Mutex<HashMap<AuthenticatedToken, Usage>>,
+}
+
+/// The bytes counted against the quota of a token.
+#[derive(Default)]
+struct Usage {
+    /// The uploads within the window, oldest first.
+    uploads: VecDeque<Upload>,
+    /// The bytes reserved by uploads in progress.
+    reserved: u64,
+}
+
+/// A completed upload counted against a quota.
+struct Upload {
+    completed: Instant,
+    bytes: u64,
+}
+
+/// The bytes of an upload in progress, counted against the quota of its token.
+///
+/// The bytes are released when the reservation is dropped, unless it was
+/// [settled](QuotaReservation::settle) once the upload completed.
+pub struct QuotaReservation {
+    quotas: Arc<UploadQuotas>,
+    token: AuthenticatedToken,
+    /// The number of bytes reserved for the upload.
+    bytes: u64,
+}
+
+/// The quota of a token is used up.
+#[derive(Debug, thiserror::Error)]
+#[error("The upload quota of {quota_bytes} bytes is used up ({used_bytes} bytes used)")]
+pub struct QuotaExceeded {
+    /// The quota of the token.
+    pub quota_bytes: u64,
+    /// The bytes uploaded within the window, including those of uploads in progress.
+    pub used_bytes: u64,
+    /// The time after which the upload would fit into the quota,
+    /// or `None` if it never does.
+    pub retry_after: Option<Duration>,
+}
+
+impl UploadQuotas {
+    /// Creates the quotas of the tokens of the specified configuration.
+    pub fn new(config: &AuthConfig) -> Self {
+        Self {
+            quotas: config
+                .tokens
+                .iter()
+                .map(|token| token.quota_bytes)
+                .collect(),
+            window: config.quota_window(),
+            usage: Mutex::default(),
+        }
+    }
+
+    /// Ensures that the quota of the token is not used up, and that an upload of
+    /// the expected size (if known) fits into it, then reserves the expected size
+    /// for as long as the upload is in progress.
+    ///
+    /// Uploads in progress count against the quota, such that concurrent uploads
+    /// cannot exceed it together.
+    pub fn reserve(
+        self: &Arc<Self>,
+        token: AuthenticatedToken,
+        expected_bytes: Option<u64>,
+    ) -> Result<QuotaReservation, QuotaExceeded> {
+        let mut reservation = QuotaReservation {
+            quotas: self.clone(),
+            token,
+            bytes: 0,
+        };
+        let Some(quota_bytes) = self.quota(token) else {
+            return Ok(reservation);
+        };
+
+        let now = Instant::now();
+        let mut usage = self.usage.lock().expect("failed to lock upload quotas");
+        let usage = usage.entry(token).or_default();
+        self.prune(&mut usage.uploads, now);
+        let completed_bytes: u64 = usage.uploads.iter().map(|upload| upload.bytes).sum();
+        let used_bytes = completed_bytes.saturating_add(usage.reserved);
+        let exceeds = |used: u64| {
+            used >= quota_bytes || used.saturating_add(expected_bytes.unwrap_or(0)) > quota_bytes
+        };
+        if !exceeds(used_bytes) {
+            reservation.bytes = expected_bytes.unwrap_or(0);
+            usage.reserved += reservation.bytes;
+            return Ok(reservation);
+        }
+
+        // Find the time at which enough uploads left the window; uploads in progress
+        // complete no earlier than now.
+        let in_progress = (usage.reserved > 0).then_some((now, usage.reserved));
+        let mut remaining = used_bytes;
+        let retry_after = usage
+            .uploads
+            .iter()
+            .map(|upload| (upload.completed, upload.bytes))
+            .chain(in_progress)
+            .find_map(|(completed, bytes)| {
+                remaining -= bytes;
+                (!exceeds(remaining)).then(|| (completed + self.window).duration_since(now))
+            });
+
+        Err(QuotaExceeded {
+            quota_bytes,
+            used_bytes,
+            retry_after,
+        })
+    }
+
+    /// Gets the quota of the token, or `None` if it is unlimited.
+    fn quota(&self, token: AuthenticatedToken) -> Option<u64> {
+        self.quotas.get(token.0).copied().flatten()
+    }
+
+    /// Changes the bytes reserved for uploads of the token in progress and, if the
+    /// upload completed, counts it against the quota.
+    fn update(
+        &self,
+        token: AuthenticatedToken,
+        release: u64,
+        reserve: u64,
+        completed: Option<u64>,
+    ) {
+        let now = Instant::now();
+        let mut usage = self.usage.lock().expect("failed to lock upload quotas");
+        let usage = usage.entry(token).or_default();
+        usage.reserved = usage.reserved.saturating_sub(release) + reserve;
+        self.prune(&mut usage.uploads, now);
+        if let Some(bytes) = completed {
+            usage.uploads.push_back(Upload {
+                completed: now,
+                bytes,
+            });
+        }
+    }
+
+    /// Removes the uploads that left the window.
+    fn prune(&self, uploads: &mut VecDeque<Upload>, now: Instant) {
+        while let Some(upload) = uploads.front() {
+            if now.duration_since(upload.completed) < self.window {
+                break;
+            }
+            uploads.pop_front();
+        }
+    }
+}
+
+impl QuotaReservation {
+    /// Grows the reservation such that it covers at least the `total` bytes received,
+    /// e.g. for uploads of unknown size.
+    pub fn grow_to(&mut self, total: u64) {
+        if total <= self.bytes || self.quotas.quota(self.token).is_none() {
+            return;
+        }
+
+        self.quotas.update(self.token, 0, total - self.bytes, None);
+        self.bytes = total;
+    }
+
+    /// Counts the completed upload of the specified size against the quota, in place
+    /// of the reserved bytes.
+    pub fn settle(mut self, bytes: u64) {
+        if self.quotas.quota(self.token).is_none() {
+            return;
+        }
+
+        let reserved = std::mem::take(&mut self.bytes);
+        self.quotas.update(self.token, reserved, 0, Some(bytes));
+    }
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        if self.bytes > 0 {
+            self.quotas.update(self.token, self.bytes, 0, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use app_config::auth::TokenConfig;
+
+    const LIMITED: AuthenticatedToken = AuthenticatedToken(0);
+    const UNLIMITED: AuthenticatedToken = AuthenticatedToken(1);
+
+    fn quotas() -> Arc<UploadQuotas> {
+        Arc::new(UploadQuotas::new(&AuthConfig {
+            tokens: vec![
+                TokenConfig {
+                    token: "limited".to_string(),
+                    quota_bytes: Some(100),
+                },
+                TokenConfig {
+                    token: "unlimited".to_string(),
+                    quota_bytes: None,
+                },
+            ],
+            quota_window_sec: Some(60),
+            ..AuthConfig::default()
+        }))
+    }
+
+    /// Counts a completed upload against the quota of the token.
+    fn record(quotas: &Arc<UploadQuotas>, token: AuthenticatedToken, bytes: u64) {
+        quotas
+            .reserve(token, None)
+            .expect("quota is used up")
+            .settle(bytes);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn uploads_are_rejected_once_the_quota_is_used_up() {
+        let quotas = quotas();
+        assert!(quotas.reserve(LIMITED, Some(100)).is_ok());
+        assert!(quotas.reserve(LIMITED, Some(101)).is_err());
+
+        record(&quotas, LIMITED, 60);
+        tokio::time::advance(Duration::from_secs(30)).await;
+        record(&quotas, LIMITED, 40);
+
+        let error = quotas.reserve(LIMITED, None).err().unwrap();
+        assert_eq!(error.used_bytes, 100);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(30)));
+
+        // Announced sizes must fit into the remaining quota.
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(quotas.reserve(LIMITED, None).is_ok());
+        let error = quotas.reserve(LIMITED, Some(61)).err().unwrap();
+        assert_eq!(error.retry_after, Some(Duration::from_secs(30)));
+
+        // Uploads larger than the quota never fit.
+        let error = quotas.reserve(LIMITED, Some(101)).err().unwrap();
+        assert_eq!(error.retry_after, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn uploads_in_progress_count_against_the_quota() {
+        let quotas = quotas();
+        let first = quotas.reserve(LIMITED, Some(60)).expect("quota is used up");
+        let error = quotas.reserve(LIMITED, Some(60)).err().unwrap();
+        assert_eq!(error.used_bytes, 60);
+        assert_eq!(error.retry_after, Some(Duration::from_secs(60)));
+
+        // Uploads of unknown size reserve the bytes received so far.
+        let mut second = quotas.reserve(LIMITED, None).expect("quota is used up");
+        second.grow_to(30);
+        assert!(quotas.reserve(LIMITED, Some(11)).is_err());
+        assert!(quotas.reserve(LIMITED, Some(10)).is_ok());
+
+        // Failed uploads release their bytes; completed ones count as received.
+        drop(second);
+        assert!(quotas.reserve(LIMITED, Some(40)).is_ok());
+        first.settle(50);
+        assert!(quotas.reserve(LIMITED, Some(50)).is_ok());
+        assert!(quotas.reserve(LIMITED, Some(51)).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn tokens_without_quota_are_unlimited() {
+        let quotas = quotas();
+        record(&quotas, UNLIMITED, 1000);
+        assert!(quotas.reserve(UNLIMITED, Some(1000)).is_ok());
+        assert!(quotas.reserve(AuthenticatedToken(2), Some(1000)).is_ok());
+    }
+}
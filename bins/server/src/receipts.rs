@@ -0,0 +1,378 @@
+//! Contains distribution receipts, i.e. records of where and when a file was stored.
+
+use crate::handlers::instant_as_datetime;
+use chrono::{DateTime, Duration, Utc};
+use file_distribution::WriteSummary;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The algorithm used for signing receipts.
+const SIGNATURE_ALGORITHM: &str = "HMAC-SHA256";
+
+/// Keeps track of the outcomes of file distributions.
+#[derive(Default)]
+pub struct DistributionRecords {
+    records: RwLock<HashMap<ShortGuid, DistributionRecord>>,
+}
+
+struct DistributionRecord {
+    file_size_bytes: usize,
+    hashes: ReceiptHashes,
+    started: DateTime<Utc>,
+    expires: DateTime<Utc>,
+    outcomes: Vec<BackendOutcome>,
+}
+
+/// The outcome of distributing a file to a single backend.
+struct BackendOutcome {
+    tag: String,
+    location: Option<String>,
+    succeeded: bool,
+    completed: DateTime<Utc>,
+}
+
+impl DistributionRecords {
+    /// Registers the start of the distribution of a file.
+    ///
+    /// Records of files whose lease has expired are pruned.
+    pub fn begin(&self, id: ShortGuid, summary: &WriteSummary) {
+        let now = Utc::now();
+        let record = DistributionRecord {
+            file_size_bytes: summary.file_size_bytes,
+            hashes: ReceiptHashes {
+                md5: summary.hashes.md5.map(|md5| hex::encode(md5.as_slice())),
+                sha256: summary.hashes.sha256.map(hex::encode),
+            },
+            started: now,
+            expires: instant_as_datetime(&summary.expires),
+            outcomes: Vec::default(),
+        };
+
+        let mut records = self
+            .records
+            .write()
+            .expect("failed to lock distribution records");
+        records.retain(|_, record| record.expires > now);
+        records.insert(id, record);
+    }
+
+    /// Records the outcome of the distribution of a file to a backend.
+    pub fn record(&self, id: ShortGuid, tag: &str, location: Option<String>, succeeded: bool) {
+        let mut records = self
+            .records
+            .write()
+            .expect("failed to lock distribution records");
+        if let Some(record) = records.get_mut(&id) {
+            record.outcomes.push(BackendOutcome {
+                tag: tag.to_string(),
+                location,
+                succeeded,
+                completed: Utc::now(),
+            });
+        }
+    }
+
+    /// Builds a receipt listing the backends that successfully stored the file,
+    /// or `None` if the file is unknown.
+    pub fn receipt(&self, id: ShortGuid) -> Option<Receipt> {
+        let records = self
+            .records
+            .read()
+            .expect("failed to lock distribution records");
+        let record = records.get(&id)?;
+        Some(Receipt {
+            id,
+            file_size_bytes: record.file_size_bytes,
+            hashes: record.hashes.clone(),
+            distribution_started: record.started,
+            expires: record.expires,
+            issued: Utc::now(),
+            backends: record
+                .outcomes
+                .iter()
+                .filter(|outcome| outcome.succeeded)
+                .map(|outcome| StoredLocation {
+                    tag: outcome.tag.clone(),
+                    location: outcome.location.clone(),
+                    stored_at: outcome.completed,
+                })
+                .collect(),
+        })
+    }
+}
+
+/// A receipt describing where and when a file was stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    /// The ID of the file.
+    pub id: ShortGuid,
+    /// The file size in bytes.
+    pub file_size_bytes: usize,
+    /// The hashes of the file.
+    pub hashes: ReceiptHashes,
+    /// The time at which the distribution of the file started.
+    pub distribution_started: DateTime<Utc>,
+    /// The time at which the file's lease expires.
+    pub expires: DateTime<Utc>,
+    /// The time at which this receipt was issued.
+    pub issued: DateTime<Utc>,
+    /// The backends that successfully stored the file.
+    pub backends: Vec<StoredLocation>,
+}
+
+/// The hashes of a file, in hex encoding. Hashes that were not computed are omitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptHashes {
+    /// The MD5 hash in hex encoding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub md5: Option<String>,
+    /// The SHA-256 hash in hex encoding.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// A backend that stored a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredLocation {
+    /// The tag of the backend.
+    pub tag: String,
+    /// The backend-specific location of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    /// The time at which the backend confirmed storing the file.
+    pub stored_at: DateTime<Utc>,
+}
+
+/// A receipt along with its optional signature.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedReceipt {
+    /// The receipt.
+    pub receipt: Receipt,
+    /// The signature over the compact JSON representation of the receipt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<ReceiptSignature>,
+}
+
+/// The signature of a receipt.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReceiptSignature {
+    /// The signature algorithm, e.g. `HMAC-SHA256`.
+    pub algorithm: String,
+    /// The signature in hex encoding.
+    pub value: String,
+}
+
+impl Receipt {
+    /// Signs the receipt with the specified key, if any.
+    pub fn sign(self, key: Option<&[u8]>) -> SignedReceipt {
+        let signature = key.map(|key| ReceiptSignature {
+            algorithm: SIGNATURE_ALGORITHM.to_string(),
+            value: hex::encode(self.mac(key).finalize().into_bytes()),
+        });
+
+        SignedReceipt {
+            receipt: self,
+            signature,
+        }
+    }
+
+    fn mac(&self, key: &[u8]) -> HmacSha256 {
+        let payload = serde_json::to_vec(self).expect("failed to serialize receipt");
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(&payload);
+        mac
+    }
+}
+
+impl SignedReceipt {
+    /// Verifies the signature of the receipt in constant time.
+    ///
+    /// Returns `false` if the receipt is unsigned or the signature does not match.
+    pub fn verify(&self, key: &[u8]) -> bool {
+        let Some(signature) = &self.signature else {
+            return false;
+        };
+
+        if signature.algorithm != SIGNATURE_ALGORITHM {
+            return false;
+        }
+
+        match hex::decode(&signature.value) {
+            Ok(value) => self.receipt.mac(key).verify_slice(&value).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Verifies the signature of the receipt and validates its timestamps against
+    /// the specified time, allowing for clocks that differ by up to `max_clock_skew`.
+    pub fn validate(
+        &self,
+        key: &[u8],
+        now: DateTime<Utc>,
+        max_clock_skew: Duration,
+    ) -> Result<(), ReceiptValidationError> {
+        if self.signature.is_none() {
+            return Err(ReceiptValidationError::Unsigned);
+        }
+
+        if !self.verify(key) {
+            return Err(ReceiptValidationError::InvalidSignature);
+        }
+
+        if self.receipt.issued > now + max_clock_skew {
+            return Err(ReceiptValidationError::IssuedInFuture(self.receipt.issued));
+        }
+
+        if self.receipt.expires < now - max_clock_skew {
+            return Err(ReceiptValidationError::Expired(self.receipt.expires));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiptValidationError {
+    #[error("The receipt is not signed")]
+    Unsigned,
+    #[error("The receipt signature is invalid")]
+    InvalidSignature,
+    #[error("The receipt was issued in the future ({0})")]
+    IssuedInFuture(DateTime<Utc>),
+    #[error("The receipt expired at {0}")]
+    Expired(DateTime<Utc>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_distribution::hash::{HashBlake3, HashMd5, HashSha256};
+    use file_distribution::FileHashes;
+    use tokio::time::Instant;
+
+    fn summary() -> WriteSummary {
+        WriteSummary {
+            expires: Instant::now() + std::time::Duration::from_secs(60),
+            hashes: FileHashes::new(
+                HashMd5::new().finalize(),
+                HashSha256::new().finalize(),
+                HashBlake3::new().finalize(),
+            ),
+            file_name: None,
+            file_size_bytes: 0,
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn receipt_lists_successful_backends() {
+        let records = DistributionRecords::default();
+        let id = ShortGuid::new_random();
+
+        records.begin(id, &summary());
+        records.record(id, "memcache-1", Some(format!("data-{id}")), true);
+        records.record(id, "s3-1", None, false);
+        records.record(id, "fs-1", None, true);
+
+        let receipt = records.receipt(id).expect("receipt should exist");
+        let tags: Vec<_> = receipt.backends.iter().map(|b| b.tag.as_str()).collect();
+        assert_eq!(tags, ["memcache-1", "fs-1"]);
+        assert_eq!(receipt.backends[0].location, Some(format!("data-{id}")));
+        assert_eq!(
+            receipt.hashes.sha256.as_deref(),
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+    }
+
+    #[test]
+    fn unknown_file_has_no_receipt() {
+        let records = DistributionRecords::default();
+        assert!(records.receipt(ShortGuid::new_random()).is_none());
+    }
+
+    #[test]
+    fn signed_receipt_verifies() {
+        let records = DistributionRecords::default();
+        let id = ShortGuid::new_random();
+        records.begin(id, &summary());
+        records.record(id, "memcache-1", None, true);
+
+        let signed = records.receipt(id).unwrap().sign(Some(b"s3cr3t"));
+        assert!(signed.verify(b"s3cr3t"));
+        assert!(!signed.verify(b"wrong"));
+
+        // Roundtrip through JSON, as a client would.
+        let json = serde_json::to_string(&signed).unwrap();
+        let mut parsed: SignedReceipt = serde_json::from_str(&json).unwrap();
+        assert!(parsed.verify(b"s3cr3t"));
+
+        // Tampering invalidates the signature.
+        parsed.receipt.backends.clear();
+        assert!(!parsed.verify(b"s3cr3t"));
+    }
+
+    #[test]
+    fn unsigned_receipt_does_not_verify() {
+        let records = DistributionRecords::default();
+        let id = ShortGuid::new_random();
+        records.begin(id, &summary());
+
+        let unsigned = records.receipt(id).unwrap().sign(None);
+        assert!(unsigned.signature.is_none());
+        assert!(!unsigned.verify(b"s3cr3t"));
+    }
+
+    fn signed_receipt(issued: DateTime<Utc>, expires: DateTime<Utc>) -> SignedReceipt {
+        let records = DistributionRecords::default();
+        let id = ShortGuid::new_random();
+        records.begin(id, &summary());
+
+        let mut receipt = records.receipt(id).unwrap();
+        receipt.issued = issued;
+        receipt.expires = expires;
+        receipt.sign(Some(b"s3cr3t"))
+    }
+
+    #[test]
+    fn receipt_timestamps_within_tolerance_validate() {
+        let now = Utc::now();
+        let skew = Duration::seconds(30);
+
+        // Issued by a server whose clock is slightly ahead.
+        let future = signed_receipt(now + Duration::seconds(20), now + Duration::minutes(5));
+        assert!(future.validate(b"s3cr3t", now, skew).is_ok());
+
+        // Expired only slightly ago.
+        let past = signed_receipt(now - Duration::minutes(5), now - Duration::seconds(20));
+        assert!(past.validate(b"s3cr3t", now, skew).is_ok());
+    }
+
+    #[test]
+    fn receipt_timestamps_outside_tolerance_are_rejected() {
+        let now = Utc::now();
+        let skew = Duration::seconds(30);
+
+        let future = signed_receipt(now + Duration::seconds(40), now + Duration::minutes(5));
+        assert!(matches!(
+            future.validate(b"s3cr3t", now, skew),
+            Err(ReceiptValidationError::IssuedInFuture(_))
+        ));
+
+        let past = signed_receipt(now - Duration::minutes(5), now - Duration::seconds(40));
+        assert!(matches!(
+            past.validate(b"s3cr3t", now, skew),
+            Err(ReceiptValidationError::Expired(_))
+        ));
+
+        assert!(matches!(
+            past.validate(b"wrong", now, skew),
+            Err(ReceiptValidationError::InvalidSignature)
+        ));
+    }
+}
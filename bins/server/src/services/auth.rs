@@ -0,0 +1,329 @@
+use crate::error::ProblemType;
+use app_config::AppConfig;
+use axum::body::BoxBody;
+use axum::http::{header, HeaderValue, Response};
+use axum::response::IntoResponse;
+use hyper::service::Service;
+use hyper::Request;
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
+use tower::Layer;
+use tracing::debug;
+
+/// The token a request was authenticated with, available as a request extension.
+///
+/// Refers to the token by its index in `auth.tokens`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AuthenticatedToken(pub usize);
+
+/// A middleware rejecting requests without a valid bearer token with `401 Unauthorized`.
+///
+/// Requests must carry one of the configured `auth.tokens` in an
+/// `Authorization: Bearer <token>` header; the token is made available to handlers
+/// as an [`AuthenticatedToken`] extension. If no tokens are configured,
+/// all requests are passed through.
+///
+/// Administrative routes instead require one of the `auth.admin_tokens` and reject
+/// all requests if none are configured.
+#[derive(Clone)]
+pub struct BearerAuth<S> {
+    inner: S,
+    config: Arc<AppConfig>,
+    scope: TokenScope,
+}
+
+/// A layer for bearer token authentication. Uses [`BearerAuth`].
+#[derive(Clone)]
+pub struct BearerAuthLayer {
+    config: Arc<AppConfig>,
+    scope: TokenScope,
+}
+
+/// The tokens a [`BearerAuth`] middleware accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenScope {
+    /// The `auth.tokens` for storing and retrieving files.
+    Files,
+    /// The `auth.admin_tokens` for administrative routes.
+    Admin,
+}
+
+impl BearerAuthLayer {
+    /// Creates a new [`BearerAuthLayer`] accepting the tokens of the specified configuration.
+    pub fn new(config: Arc<AppConfig>) -> Self {
+        Self {
+            config,
+            scope: TokenScope::Files,
+        }
+    }
+
+    /// Creates a new [`BearerAuthLayer`] accepting the admin tokens of the specified
+    /// configuration.
+    pub fn admin(config: Arc<AppConfig>) -> Self {
+        Self {
+            config,
+            scope: TokenScope::Admin,
+        }
+    }
+}
+
+impl<S> Layer<S> for BearerAuthLayer {
+    type Service = BearerAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BearerAuth {
+            inner,
+            config: self.config.clone(),
+            scope: self.scope,
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for BearerAuth<S>
+where
+    S: Service<Request<B>>,
+    S::Response: IntoResponse,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BearerAuthFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let auth = &self.config.auth;
+        if self.scope == TokenScope::Admin {
+            let tokens = auth.admin_tokens.iter().map(String::as_str);
+            return match bearer_token(&request).and_then(|token| find_token(tokens, token)) {
+                Some(_) => BearerAuthFuture::Authorized {
+                    future: self.inner.call(request),
+                },
+                None => {
+                    debug!("Rejecting administrative request without a valid admin token");
+                    BearerAuthFuture::unauthorized(
+                        &self.config,
+                        "The request requires a valid administrative bearer token",
+                    )
+                }
+            };
+        }
+
+        if !auth.enabled() {
+            return BearerAuthFuture::Authorized {
+                future: self.inner.call(request),
+            };
+        }
+
+        let tokens = auth.tokens.iter().map(|token| token.token.as_str());
+        let token = bearer_token(&request).map(|token| find_token(tokens, token));
+        match token {
+            Some(Some(index)) => {
+                request.extensions_mut().insert(AuthenticatedToken(index));
+                BearerAuthFuture::Authorized {
+                    future: self.inner.call(request),
+                }
+            }
+            Some(None) => {
+                debug!("Rejecting request with an unknown bearer token");
+                BearerAuthFuture::unauthorized(&self.config, "The bearer token is invalid")
+            }
+            None => {
+                debug!("Rejecting request without a bearer token");
+                BearerAuthFuture::unauthorized(&self.config, "The request requires a bearer token")
+            }
+        }
+    }
+}
+
+/// Gets the token of the `Authorization: Bearer <token>` header, if present.
+fn bearer_token<B>(request: &Request<B>) -> Option<&[u8]> {
+    let value = request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token.as_bytes())
+}
+
+/// Gets the index of the token among the accepted tokens, if it is one of them. Every
+/// accepted token is compared in constant time, so that timings do not reveal prefixes.
+fn find_token<'a>(tokens: impl Iterator<Item = &'a str>, token: &[u8]) -> Option<usize> {
+    let mut known = Choice::from(0);
+    let mut index = 0u64;
+    for (i, candidate) in tokens.enumerate() {
+        let matches = candidate.as_bytes().ct_eq(token);
+        index.conditional_assign(&(i as u64), matches);
+        known |= matches;
+    }
+    bool::from(known).then_some(index as usize)
+}
+
+/// A future returned from the [`BearerAuth`] middleware.
+#[pin_project(project = BearerAuthFutureProj)]
+pub enum BearerAuthFuture<F> {
+    /// The request is authorized and handled by the inner service.
+    Authorized {
+        #[pin]
+        future: F,
+    },
+    /// The request is rejected.
+    Unauthorized { response: Option<Response<BoxBody>> },
+}
+
+impl<F> BearerAuthFuture<F> {
+    fn unauthorized(config: &AppConfig, detail: &str) -> Self {
+        let mut response = ProblemType::Unauthorized
+            .problem(&config.errors)
+            .with_detail(detail)
+            .into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        Self::Unauthorized {
+            response: Some(response),
+        }
+    }
+}
+
+impl<F, R, E> Future for BearerAuthFuture<F>
+where
+    F: Future<Output = Result<R, E>>,
+    R: IntoResponse,
+{
+    type Output = Result<Response<BoxBody>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            BearerAuthFutureProj::Authorized { future } => future
+                .poll(cx)
+                .map(|result| result.map(IntoResponse::into_response)),
+            BearerAuthFutureProj::Unauthorized { response } => {
+                Poll::Ready(Ok(response.take().expect("future polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use app_config::auth::TokenConfig;
+    use axum::body::Body;
+    use axum::extract::Extension;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn app(tokens: &[&str]) -> Router {
+        let mut config = AppConfig::default();
+        config.auth.tokens = tokens
+            .iter()
+            .map(|token| TokenConfig {
+                token: token.to_string(),
+                quota_bytes: None,
+            })
+            .collect();
+        Router::new()
+            .route(
+                "/",
+                get(|token: Option<Extension<AuthenticatedToken>>| async move {
+                    token.map_or(String::from("anonymous"), |Extension(token)| {
+                        token.0.to_string()
+                    })
+                }),
+            )
+            .layer(BearerAuthLayer::new(Arc::new(config)))
+    }
+
+    async fn status(app: Router, authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::get("/");
+        if let Some(value) = authorization {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        response.status()
+    }
+
+    #[tokio::test]
+    async fn configured_tokens_are_accepted() {
+        let app = app(&["s3cr3t", "t0k3n"]);
+        assert_eq!(
+            status(app.clone(), Some("Bearer t0k3n")).await,
+            StatusCode::OK
+        );
+        assert_eq!(status(app, Some("bearer s3cr3t")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn authenticated_tokens_are_identified() {
+        let request = Request::get("/")
+            .header(header::AUTHORIZATION, "Bearer t0k3n")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(&["s3cr3t", "t0k3n"]).oneshot(request).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"1");
+    }
+
+    #[tokio::test]
+    async fn missing_or_unknown_tokens_are_rejected() {
+        let app = app(&["s3cr3t"]);
+        assert_eq!(status(app.clone(), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(app.clone(), Some("Bearer s3cr3")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(app.clone(), Some("Basic s3cr3t")).await,
+            StatusCode::UNAUTHORIZED
+        );
+
+        let response = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn requests_pass_without_configured_tokens() {
+        assert_eq!(status(app(&[]), None).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn admin_routes_require_admin_tokens() {
+        let admin = |admin_tokens: &[&str]| {
+            let mut config = AppConfig::default();
+            config.auth.admin_tokens = admin_tokens.iter().map(ToString::to_string).collect();
+            Router::new()
+                .route("/", get(|| async { "ok" }))
+                .layer(BearerAuthLayer::admin(Arc::new(config)))
+        };
+
+        let app = admin(&["4dm1n"]);
+        assert_eq!(
+            status(app.clone(), Some("Bearer 4dm1n")).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(app.clone(), Some("Bearer s3cr3t")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(app, None).await, StatusCode::UNAUTHORIZED);
+
+        // Without admin tokens, the routes are not accessible at all.
+        assert_eq!(status(admin(&[]), None).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,222 @@
+use app_config::proxies::ProxiesConfig;
+use axum::extract::ConnectInfo;
+use axum::http::header::FORWARDED;
+use axum::http::{HeaderMap, HeaderName};
+use hyper::service::Service;
+use hyper::Request;
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tracing::Span;
+
+/// The de-facto standard header listing the addresses a request was forwarded for.
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// A middleware recording the address of the client in the `client_ip` field of the
+/// request span, and with it in the access log.
+///
+/// The address is that of the peer, unless the peer is a trusted proxy; then the
+/// `Forwarded` or `X-Forwarded-For` header is followed from the nearest hop until
+/// the first address that is not a trusted proxy. Must be applied within the
+/// [`RequestIdLayer`](super::RequestIdLayer).
+#[derive(Clone)]
+pub struct ClientIps<S> {
+    inner: S,
+    trusted: Arc<[IpNet]>,
+}
+
+/// A layer for client addresses. Uses [`ClientIps`].
+#[derive(Clone)]
+pub struct ClientIpLayer {
+    trusted: Arc<[IpNet]>,
+}
+
+impl ClientIpLayer {
+    pub fn new(config: &ProxiesConfig) -> Self {
+        Self {
+            trusted: config.trusted_networks().into(),
+        }
+    }
+}
+
+impl<S> tower::Layer<S> for ClientIpLayer {
+    type Service = ClientIps<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientIps {
+            inner,
+            trusted: self.trusted.clone(),
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for ClientIps<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        if let Some(ip) = client_ip(&request, &self.trusted) {
+            Span::current().record("client_ip", tracing::field::display(ip));
+        }
+        self.inner.call(request)
+    }
+}
+
+/// Resolves the address of the client, or `None` if the peer address is unknown.
+fn client_ip<B>(request: &Request<B>, trusted: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|network| network.contains(ip));
+
+    let ConnectInfo(peer) = request.extensions().get::<ConnectInfo<SocketAddr>>()?;
+    let mut client = canonical(peer.ip());
+    if !is_trusted(&client) {
+        return Some(client);
+    }
+
+    // Each proxy appends the address it received the request from; only the hops
+    // appended by trusted proxies are reliable.
+    for hop in forwarded_hops(request.headers()).into_iter().rev() {
+        let Some(hop) = hop else {
+            break;
+        };
+        client = hop;
+        if !is_trusted(&client) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+/// Gets the addresses a request was forwarded for, in the order of the hops; `None`
+/// for hops that are obfuscated or malformed.
+///
+/// The standardized `Forwarded` header takes precedence over `X-Forwarded-For`.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name: &HeaderName| {
+        headers
+            .get_all(name)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+            .collect::<Vec<_>>()
+    };
+
+    if headers.contains_key(FORWARDED) {
+        return values(&FORWARDED)
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node))
+            })
+            .collect();
+    }
+
+    values(&X_FORWARDED_FOR)
+        .into_iter()
+        .map(parse_node)
+        .collect()
+}
+
+/// Parses a node such as `192.0.2.60`, `192.0.2.60:4711` or `"[2001:db8::17]:4711"`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    let ip = match node.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next()?.parse().ok()?,
+        None => node
+            .parse()
+            .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+            .ok()?,
+    };
+    Some(canonical(ip))
+}
+
+/// Unwraps IPv4-mapped IPv6 addresses, as reported by dual-stack sockets.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted() -> Vec<IpNet> {
+        vec!["10.0.0.0/8".parse().unwrap()]
+    }
+
+    fn forwarded(peer: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::get("/");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut request = request.body(()).unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        request
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_forward_addresses() {
+        let request = forwarded("203.0.113.7:5000", &[("x-forwarded-for", "198.51.100.1")]);
+        assert_eq!(client_ip(&request, &trusted()), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn trusted_proxies_forward_the_client_address() {
+        // The client prepended a spoofed address, which is skipped.
+        let request = forwarded(
+            "10.0.0.2:5000",
+            &[("x-forwarded-for", "192.0.2.99, 198.51.100.1, 10.0.0.1")],
+        );
+        assert_eq!(client_ip(&request, &trusted()), ip("198.51.100.1"));
+
+        let request = forwarded("[::ffff:10.0.0.2]:5000", &[]);
+        assert_eq!(client_ip(&request, &trusted()), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn forwarded_headers_take_precedence() {
+        let request = forwarded(
+            "10.0.0.2:5000",
+            &[
+                ("x-forwarded-for", "198.51.100.1"),
+                (
+                    "forwarded",
+                    r#"for="[2001:db8::17]:4711";proto=https, for=10.0.0.1"#,
+                ),
+            ],
+        );
+        assert_eq!(client_ip(&request, &trusted()), ip("2001:db8::17"));
+
+        let request = forwarded(
+            "10.0.0.2:5000",
+            &[("forwarded", "for=_hidden, for=10.0.0.1:80")],
+        );
+        assert_eq!(client_ip(&request, &trusted()), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn unknown_peers_are_not_resolved() {
+        let request = Request::get("/").body(()).unwrap();
+        assert_eq!(client_ip(&request, &trusted()), None);
+    }
+}
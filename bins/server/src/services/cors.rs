@@ -0,0 +1,175 @@
+use app_config::cors::CorsConfig;
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Builds the layer answering preflight requests and adding the CORS headers to
+/// responses, or `None` if cross-origin requests are not allowed.
+pub fn cors_layer(config: &CorsConfig) -> Result<Option<CorsLayer>, InvalidCorsConfig> {
+    if !config.enabled() {
+        return Ok(None);
+    }
+
+    if config.permissive {
+        return Ok(Some(CorsLayer::permissive().max_age(config.max_age())));
+    }
+
+    let origins = config
+        .allowed_origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin).map_err(|_| InvalidCorsConfig::Origin(origin.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let methods = config
+        .allowed_methods()
+        .into_iter()
+        .map(|method| {
+            Method::from_bytes(method.as_bytes())
+                .map_err(|_| InvalidCorsConfig::Method(method.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(methods)
+            .allow_headers(header_names(config.allowed_headers())?)
+            .expose_headers(header_names(config.exposed_headers())?)
+            .max_age(config.max_age()),
+    ))
+}
+
+fn header_names(names: Vec<&str>) -> Result<Vec<HeaderName>, InvalidCorsConfig> {
+    names
+        .into_iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| InvalidCorsConfig::Header(name.to_string()))
+        })
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidCorsConfig {
+    #[error("The allowed CORS origin {0:?} is invalid")]
+    Origin(String),
+    #[error("The allowed CORS method {0:?} is invalid")]
+    Method(String),
+    #[error("The CORS header name {0:?} is invalid")]
+    Header(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn app(config: &CorsConfig) -> Router {
+        let layer = cors_layer(config)
+            .expect("invalid CORS config")
+            .expect("CORS is disabled");
+        Router::new()
+            .route("/yoink/:id", get(|| async { "yoink" }))
+            .layer(layer)
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/yoink/KmC6e8laTnK3dioUSMpM0Q")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn cors_is_disabled_by_default() {
+        assert!(cors_layer(&CorsConfig::default()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn only_allowed_origins_pass_preflight_requests() {
+        let config = CorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            ..CorsConfig::default()
+        };
+
+        let response = app(&config)
+            .oneshot(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert!(headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("authorization"));
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let response = app(&config)
+            .oneshot(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+        assert!(response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn responses_expose_caching_headers() {
+        let config = CorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            ..CorsConfig::default()
+        };
+
+        let request = Request::get("/yoink/KmC6e8laTnK3dioUSMpM0Q")
+            .header(header::ORIGIN, "https://app.example.com")
+            .body(Body::empty())
+            .unwrap();
+        let response = app(&config).oneshot(request).await.unwrap();
+        let exposed = response.headers()[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(exposed.contains("expires"));
+        assert!(exposed.contains("etag"));
+    }
+
+    #[tokio::test]
+    async fn permissive_mode_allows_any_origin() {
+        let config = CorsConfig {
+            permissive: true,
+            ..CorsConfig::default()
+        };
+
+        let response = app(&config)
+            .oneshot(preflight("http://localhost:3000"))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[test]
+    fn invalid_methods_are_rejected() {
+        let config = CorsConfig {
+            allowed_origins: vec!["https://app.example.com".to_string()],
+            allowed_methods: Some(vec!["GET POST".to_string()]),
+            ..CorsConfig::default()
+        };
+        assert!(matches!(
+            cors_layer(&config),
+            Err(InvalidCorsConfig::Method(method)) if method == "GET POST"
+        ));
+    }
+}
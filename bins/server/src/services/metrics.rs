@@ -3,6 +3,7 @@ use hyper::{Request, StatusCode, Version};
 use pin_project::pin_project;
 
 use axum::body::BoxBody;
+use axum::extract::MatchedPath;
 use axum::http::Response;
 use axum::response::IntoResponse;
 use hyper::body::HttpBody;
@@ -10,6 +11,7 @@ use metrics::http::HttpMetrics;
 use std::cell::Cell;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 use tokio::time::Instant;
@@ -17,19 +19,36 @@ use tower::Layer;
 use tracing::debug;
 
 /// A middleware for call metrics. Uses [`HttpMetrics`].
+///
+/// Requests are labeled with the template of the route they matched, as provided by
+/// the router's [`MatchedPath`] extension; the middleware must therefore be applied
+/// to the routes, e.g. using [`Router::layer`](axum::Router::layer).
 #[derive(Clone)]
 pub struct HttpCallMetrics<S> {
     inner: S,
+    base_path: Option<Arc<str>>,
 }
 
 /// A layer for call metrics. Uses [`HttpCallMetrics`].
 #[derive(Clone, Default)]
-pub struct HttpCallMetricsLayer;
+pub struct HttpCallMetricsLayer {
+    base_path: Option<Arc<str>>,
+}
+
+impl HttpCallMetricsLayer {
+    /// Creates a layer for routes nested under `base_path`, which is removed from the
+    /// route templates such that they refer to the routes as they are.
+    pub fn new(base_path: Option<&str>) -> Self {
+        Self {
+            base_path: base_path.map(Arc::from),
+        }
+    }
+}
 
 impl<S> HttpCallMetrics<S> {
     /// Creates a new [`HttpCallMetrics`]
-    pub fn new(inner: S) -> Self {
-        Self { inner }
+    pub fn new(inner: S, base_path: Option<Arc<str>>) -> Self {
+        Self { inner, base_path }
     }
 }
 
@@ -37,7 +56,7 @@ impl<S> Layer<S> for HttpCallMetricsLayer {
     type Service = HttpCallMetrics<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        HttpCallMetrics::new(inner)
+        HttpCallMetrics::new(inner, self.base_path.clone())
     }
 }
 
@@ -56,7 +75,7 @@ where
     }
 
     fn call(&mut self, request: Request<B>) -> Self::Future {
-        let tracker = HttpCallMetricTracker::start(&request);
+        let tracker = HttpCallMetricTracker::start(&request, self.base_path.as_deref());
 
         // We start tracking request time before the first call to the future.
         HttpCallMetricsFuture::new(self.inner.call(request), tracker)
@@ -127,10 +146,11 @@ where
 struct HttpCallMetricTracker {
     version: Version,
     method: hyper::Method,
-    path_base: String,
     start: Instant,
     state: Cell<ResultState>,
     path_full: String,
+    /// The template of the route serving the request, if any matched.
+    template: Option<String>,
 }
 
 pub enum ResultState {
@@ -145,30 +165,31 @@ pub enum ResultState {
 }
 
 impl HttpCallMetricTracker {
-    fn start<B>(request: &Request<B>) -> Self {
+    fn start<B>(request: &Request<B>, base_path: Option<&str>) -> Self {
         let method = request.method().clone();
         let path = request.uri().path();
         let version = request.version();
 
-        // Ensure we don't create a new metric for every file name, i.e.
-        // /yoink/4d6DOAMKQ5uhlE6eXKM_dQ should be tracked as /yoink.
+        // HttpMetrics normalizes the path such that we don't create a new metric
+        // for every file name, i.e. /yoink/4d6DOAMKQ5uhlE6eXKM_dQ is tracked as /yoink,
+        // or as its route template /yoink/:id.
         let path_str = path.to_string();
-        let path_base = match path[1..].find('/') {
-            None => path_str.clone(),
-            Some(pos) => String::from(&path[0..(pos + 1)]),
-        };
+        let template = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|matched| route_template(matched.as_str(), base_path).to_string());
 
         debug!(
-            "Start processing {version:?} {method} {path} (tracking as {path_base})",
+            "Start processing {version:?} {method} {path}",
             path = path_str
         );
-        HttpMetrics::inc_in_flight(path_base.as_str());
+        HttpMetrics::inc_in_flight(path_str.as_str(), template.as_deref(), &method);
         let start = Instant::now();
         Self {
             version,
             method,
             path_full: path_str,
-            path_base,
+            template,
             start,
             state: Cell::new(ResultState::Started),
         }
@@ -203,7 +224,13 @@ impl Drop for HttpCallMetricTracker {
                     path = self.path_full,
                     duration = duration
                 );
-                HttpMetrics::track(&self.path_base, self.method.clone(), 0, duration);
+                HttpMetrics::track(
+                    &self.path_full,
+                    self.template.as_deref(),
+                    self.method.clone(),
+                    0,
+                    duration,
+                );
             }
             ResultState::Result(status, version) => {
                 let duration = self.duration();
@@ -217,7 +244,8 @@ impl Drop for HttpCallMetricTracker {
                         response_status = status
                     );
                 HttpMetrics::track(
-                    &self.path_base,
+                    &self.path_full,
+                    self.template.as_deref(),
                     self.method.clone(),
                     status.as_u16(),
                     duration,
@@ -225,6 +253,52 @@ impl Drop for HttpCallMetricTracker {
             }
         }
 
-        HttpMetrics::dec_in_flight(self.path_base.as_str());
+        HttpMetrics::dec_in_flight(
+            self.path_full.as_str(),
+            self.template.as_deref(),
+            &self.method,
+        );
+    }
+}
+
+/// Removes the base path the routes are nested under from a matched route template.
+fn route_template<'a>(matched: &'a str, base_path: Option<&str>) -> &'a str {
+    base_path
+        .and_then(|base_path| matched.strip_prefix(base_path))
+        .filter(|template| template.starts_with('/'))
+        .unwrap_or(matched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::Router;
+    use metrics::http::LabelNormalization;
+    use metrics::Metrics;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn nested_routes_are_labeled_without_the_base_path() {
+        HttpMetrics::set_label_normalization(LabelNormalization {
+            status_classes: false,
+            route_templates: true,
+        });
+
+        let routes = Router::new()
+            .route("/route-template/:id", get(|| async { "ok" }))
+            .layer(HttpCallMetricsLayer::new(Some("/base")));
+        let app = Router::new().nest("/base", routes);
+
+        let request = Request::get("/base/route-template/4d6DOAMKQ5uhlE6eXKM_dQ")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let metrics = Metrics::get().encode();
+        assert!(metrics.contains("path=\"/route-template/:id\""));
+        assert!(!metrics.contains("4d6DOAMKQ5uhlE6eXKM_dQ"));
     }
 }
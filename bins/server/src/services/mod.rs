@@ -1,5 +1,16 @@
 //! Contains Tower services.
 
+mod auth;
+mod client_ip;
+mod cors;
 mod metrics;
+mod request_id;
+mod timeout;
 
+pub(crate) use ::metrics::http::route_base;
+pub use auth::{AuthenticatedToken, BearerAuthLayer};
+pub use client_ip::ClientIpLayer;
+pub use cors::{cors_layer, InvalidCorsConfig};
 pub use metrics::HttpCallMetricsLayer;
+pub use request_id::{record_file_id, RequestIdLayer};
+pub use timeout::HandlerTimeoutLayer;
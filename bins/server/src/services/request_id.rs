@@ -0,0 +1,196 @@
+use axum::body::BoxBody;
+use axum::http::{HeaderName, HeaderValue, Response};
+use axum::response::IntoResponse;
+use hyper::service::Service;
+use hyper::Request;
+use pin_project::pin_project;
+use shortguid::ShortGuid;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::time::Instant;
+use tracing::instrument::Instrumented;
+use tracing::{info, info_span, Instrument, Span};
+
+/// The header carrying the ID of a request.
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The maximum length of client-supplied request IDs.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The ID of a request, available as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// A middleware assigning each request an ID and running its handler in a tracing span
+/// carrying it, such that all logs of the request can be correlated. Logs an access
+/// log entry when the response is ready.
+///
+/// The ID is taken from the `X-Request-Id` request header if present, or generated
+/// otherwise, and returned in the `X-Request-Id` response header. The span has a
+/// `file_id` field that handlers record once the file is known; tasks spawned for the
+/// file log the same field. The `client_ip` field is recorded by the [`ClientIps`](super::client_ip::ClientIps)
+/// middleware.
+#[derive(Clone)]
+pub struct RequestIds<S> {
+    inner: S,
+}
+
+/// A layer for request IDs. Uses [`RequestIds`].
+#[derive(Clone, Default)]
+pub struct RequestIdLayer;
+
+impl<S> tower::Layer<S> for RequestIdLayer {
+    type Service = RequestIds<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestIds { inner }
+    }
+}
+
+impl<S, B> Service<Request<B>> for RequestIds<S>
+where
+    S: Service<Request<B>>,
+    S::Response: IntoResponse,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = RequestIdFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let request_id = request_id_from_headers(&request)
+            .unwrap_or_else(|| ShortGuid::new_random().to_string());
+
+        let span = info_span!(
+            "request",
+            request_id = %request_id,
+            method = %request.method(),
+            path = %request.uri().path(),
+            file_id = tracing::field::Empty,
+            client_ip = tracing::field::Empty,
+        );
+
+        request
+            .extensions_mut()
+            .insert(RequestId(request_id.clone()));
+
+        let future = {
+            let _entered = span.enter();
+            self.inner.call(request)
+        };
+
+        RequestIdFuture {
+            future: future.instrument(span.clone()),
+            span,
+            request_id,
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Records the ID of the file a request refers to in the span of the request.
+pub fn record_file_id(id: ShortGuid) {
+    Span::current().record("file_id", tracing::field::display(id));
+}
+
+/// Gets the client-supplied request ID, if it is present and valid.
+fn request_id_from_headers<B>(request: &Request<B>) -> Option<String> {
+    let value = request.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LENGTH
+        && value.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| value.to_string())
+}
+
+/// A future returned from the [`RequestIds`] middleware.
+#[pin_project]
+pub struct RequestIdFuture<F>
+where
+    F: Future,
+{
+    #[pin]
+    future: Instrumented<F>,
+    span: Span,
+    request_id: String,
+    start: Instant,
+}
+
+impl<F, R, E> Future for RequestIdFuture<F>
+where
+    F: Future<Output = Result<R, E>>,
+    R: IntoResponse,
+{
+    type Output = Result<Response<BoxBody>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = match this.future.poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(result) => result,
+        };
+
+        Poll::Ready(result.map(|reply| {
+            let mut response = reply.into_response();
+            if let Ok(value) = HeaderValue::from_str(this.request_id) {
+                response
+                    .headers_mut()
+                    .insert(REQUEST_ID_HEADER.clone(), value);
+            }
+
+            let _entered = this.span.enter();
+            info!(
+                status = response.status().as_u16(),
+                elapsed_ms = this.start.elapsed().as_millis() as u64,
+                "Handled request"
+            );
+            response
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Extension;
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(|Extension(RequestId(id)): Extension<RequestId>| async move { id }),
+            )
+            .layer(RequestIdLayer)
+    }
+
+    #[tokio::test]
+    async fn client_request_ids_are_retained() {
+        let request = Request::get("/")
+            .header(&REQUEST_ID_HEADER, "upload-42")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[&REQUEST_ID_HEADER], "upload-42");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"upload-42");
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_generated() {
+        let request = Request::get("/")
+            .header(&REQUEST_ID_HEADER, "not valid")
+            .body(Body::empty())
+            .unwrap();
+        let response = app().oneshot(request).await.unwrap();
+        let id = response.headers()[&REQUEST_ID_HEADER].to_str().unwrap();
+        assert!(id.parse::<ShortGuid>().is_ok());
+    }
+}
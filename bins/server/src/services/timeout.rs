@@ -0,0 +1,200 @@
+use crate::error::ProblemType;
+use crate::handlers::public_path;
+use crate::services::route_base;
+use app_config::AppConfig;
+use axum::body::BoxBody;
+use axum::http::Response;
+use axum::response::IntoResponse;
+use hyper::service::Service;
+use hyper::Request;
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Sleep;
+use tower::Layer;
+use tracing::warn;
+
+/// A middleware cutting off request handlers that exceed their configured
+/// timeout with `504 Gateway Timeout`.
+///
+/// The timeout covers the whole handler, including the time spent waiting on
+/// backends, but not the streaming of the response body. When it elapses, the
+/// handler future is dropped, releasing any resources held by it.
+#[derive(Clone)]
+pub struct HandlerTimeout<S> {
+    inner: S,
+    config: Arc<AppConfig>,
+}
+
+/// A layer for handler timeouts. Uses [`HandlerTimeout`].
+#[derive(Clone)]
+pub struct HandlerTimeoutLayer {
+    config: Arc<AppConfig>,
+}
+
+impl HandlerTimeoutLayer {
+    /// Creates a new [`HandlerTimeoutLayer`] using the timeouts of the specified configuration.
+    pub fn new(config: Arc<AppConfig>) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for HandlerTimeoutLayer {
+    type Service = HandlerTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HandlerTimeout {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for HandlerTimeout<S>
+where
+    S: Service<Request<B>>,
+    S::Response: IntoResponse,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = HandlerTimeoutFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let path = request.uri().path();
+        let timeout = self.config.timeouts.handler_timeout(route_base(path));
+        let path = public_path(self.config.base_path(), path);
+        HandlerTimeoutFuture::new(self.inner.call(request), timeout, path, self.config.clone())
+    }
+}
+
+/// A future returned from the [`HandlerTimeout`].
+#[pin_project]
+pub struct HandlerTimeoutFuture<F>
+where
+    F: Future,
+{
+    #[pin]
+    future: F,
+    #[pin]
+    sleep: Option<Sleep>,
+    timeout: Option<Duration>,
+    path: String,
+    config: Arc<AppConfig>,
+}
+
+impl<F> HandlerTimeoutFuture<F>
+where
+    F: Future,
+{
+    fn new(future: F, timeout: Option<Duration>, path: String, config: Arc<AppConfig>) -> Self {
+        Self {
+            future,
+            sleep: timeout.map(tokio::time::sleep),
+            timeout,
+            path,
+            config,
+        }
+    }
+}
+
+impl<F, R, E> Future for HandlerTimeoutFuture<F>
+where
+    F: Future<Output = Result<R, E>>,
+    R: IntoResponse,
+{
+    type Output = Result<Response<BoxBody>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(result) = this.future.poll(cx) {
+            return Poll::Ready(result.map(IntoResponse::into_response));
+        }
+
+        let Some(sleep) = this.sleep.as_pin_mut() else {
+            return Poll::Pending;
+        };
+
+        match sleep.poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(()) => {
+                let timeout = this.timeout.unwrap_or_default();
+                warn!(
+                    "Handler for {path} timed out after {timeout:?}",
+                    path = this.path
+                );
+                Poll::Ready(Ok(map_timeout_to_response(this.path, timeout, this.config)))
+            }
+        }
+    }
+}
+
+fn map_timeout_to_response(path: &str, timeout: Duration, config: &AppConfig) -> Response<BoxBody> {
+    ProblemType::RequestTimeout
+        .problem(&config.errors)
+        .with_detail(format!(
+            "The request could not be completed within {seconds} seconds",
+            seconds = timeout.as_secs()
+        ))
+        .with_instance(path)
+        .with_value("timeout_sec", timeout.as_secs())
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::YoinkRoutes;
+    use crate::{await_rendezvous, AppState};
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::Router;
+    use backend_traits::BackendCommand;
+    use shortguid::ShortGuid;
+    use tower::ServiceExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn hanging_backend_read_back_times_out() {
+        let mut config = AppConfig::default();
+        config.timeouts.routes.insert("/yoink".to_string(), 5);
+        let (state, mut backend_receiver, rendezvous) = AppState::for_tests(config);
+        let config = state.config.clone();
+
+        // A stub backend that never answers requests to read files back.
+        let backend = tokio::spawn(async move {
+            while let Some(command) = backend_receiver.recv().await {
+                if let BackendCommand::ReceiveFile(_, mut reply) = command {
+                    // Hang until the request is abandoned.
+                    reply.closed().await;
+                    return true;
+                }
+            }
+            false
+        });
+
+        let app = Router::new()
+            .map_yoink_endpoint()
+            .with_state(state)
+            .layer(HandlerTimeoutLayer::new(config));
+
+        let request = Request::get(format!("/yoink/{id}", id = ShortGuid::new_random()))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // The abandoned handler must have released the pending read-back.
+        assert!(
+            backend.await.unwrap(),
+            "the backend was not asked for the file"
+        );
+
+        await_rendezvous(rendezvous).await;
+    }
+}
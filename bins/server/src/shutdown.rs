@@ -0,0 +1,138 @@
+//! Coordinates the graceful shutdown of the service.
+
+use rendezvous::RendezvousGuard;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// Coordinates the graceful shutdown of the service.
+///
+/// Once draining, the service reports itself as not ready and rejects new uploads,
+/// while downloads in flight continue to be served. Each download holds a fork of the
+/// cleanup rendezvous guard so that shutting down waits for it to end.
+pub struct ShutdownCoordinator {
+    /// Whether the service is draining.
+    draining: AtomicBool,
+    /// The number of downloads in flight.
+    active_reads: watch::Sender<usize>,
+    /// The guard forked for each download; `None` once the coordinator completed.
+    cleanup_rendezvous: Mutex<Option<RendezvousGuard>>,
+}
+
+/// Registers a download with the [`ShutdownCoordinator`] until it is dropped.
+pub struct ReadGuard {
+    coordinator: Arc<ShutdownCoordinator>,
+    _rendezvous: Option<RendezvousGuard>,
+}
+
+impl ShutdownCoordinator {
+    pub fn new(cleanup_rendezvous: RendezvousGuard) -> Self {
+        Self {
+            draining: AtomicBool::new(false),
+            active_reads: watch::Sender::new(0),
+            cleanup_rendezvous: Mutex::new(Some(cleanup_rendezvous)),
+        }
+    }
+
+    /// Determines whether the service is draining, i.e. is about to shut down.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Acquire)
+    }
+
+    /// Starts draining the service.
+    ///
+    /// Returns `false` if the service was draining already.
+    pub fn begin_drain(&self) -> bool {
+        !self.draining.swap(true, Ordering::AcqRel)
+    }
+
+    /// Gets the number of downloads in flight.
+    pub fn active_reads(&self) -> usize {
+        *self.active_reads.borrow()
+    }
+
+    /// Registers a download in flight until the returned guard is dropped.
+    pub fn track_read(self: &Arc<Self>) -> ReadGuard {
+        let rendezvous = self
+            .cleanup_rendezvous
+            .lock()
+            .expect("failed to lock the rendezvous guard")
+            .as_ref()
+            .map(RendezvousGuard::fork);
+        self.active_reads.send_modify(|reads| *reads += 1);
+        ReadGuard {
+            coordinator: self.clone(),
+            _rendezvous: rendezvous,
+        }
+    }
+
+    /// Waits until no downloads are in flight.
+    pub async fn drained(&self) {
+        let mut active_reads = self.active_reads.subscribe();
+        active_reads.wait_for(|reads| *reads == 0).await.ok();
+    }
+
+    /// Releases the cleanup rendezvous guard of the coordinator.
+    ///
+    /// Downloads still in flight keep their forks of the guard until they end.
+    pub fn complete(&self) {
+        if let Some(guard) = self
+            .cleanup_rendezvous
+            .lock()
+            .expect("failed to lock the rendezvous guard")
+            .take()
+        {
+            guard.completed();
+        }
+    }
+}
+
+impl Drop for ReadGuard {
+    fn drop(&mut self) {
+        self.coordinator
+            .active_reads
+            .send_modify(|reads| *reads -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rendezvous::Rendezvous;
+    use std::time::Duration;
+
+    #[test]
+    fn draining_starts_once() {
+        let rendezvous = Rendezvous::new();
+        let coordinator = ShutdownCoordinator::new(rendezvous.fork_guard());
+        assert!(!coordinator.is_draining());
+        assert!(coordinator.begin_drain());
+        assert!(coordinator.is_draining());
+        assert!(!coordinator.begin_drain());
+
+        coordinator.complete();
+        rendezvous.rendezvous();
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_reads_in_flight() {
+        let rendezvous = Rendezvous::new();
+        let coordinator = Arc::new(ShutdownCoordinator::new(rendezvous.fork_guard()));
+        let read = coordinator.track_read();
+        assert_eq!(coordinator.active_reads(), 1);
+
+        let drained = tokio::time::timeout(Duration::from_millis(10), coordinator.drained());
+        assert!(drained.await.is_err(), "drained while a read is in flight");
+
+        // The rendezvous waits for the read even after the coordinator completed.
+        coordinator.complete();
+        let rendezvous = tokio::task::spawn_blocking(move || rendezvous.rendezvous());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!rendezvous.is_finished());
+
+        drop(read);
+        coordinator.drained().await;
+        assert_eq!(coordinator.active_reads(), 0);
+        rendezvous.await.expect("failed to await the rendezvous");
+    }
+}
@@ -0,0 +1,109 @@
+//! Contains the reader pacing downloads to a bandwidth limit.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// The number of reads per second at the limit, keeping the pacing smooth.
+const READS_PER_SECOND: u64 = 10;
+
+/// Paces reads from the inner reader to a number of bytes per second.
+///
+/// Reads are delayed until the bytes read so far are within the limit, and each read
+/// yields at most a tenth of a second's worth of bytes.
+pub struct ThrottledReader<R> {
+    reader: R,
+    bytes_per_sec: u64,
+    started: Instant,
+    bytes_read: u64,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> ThrottledReader<R> {
+    /// Limits reads from `reader` to `bytes_per_sec`, which must not be zero.
+    pub fn new(reader: R, bytes_per_sec: u64) -> Self {
+        debug_assert_ne!(bytes_per_sec, 0, "the rate limit must not be zero");
+        Self {
+            reader,
+            bytes_per_sec: bytes_per_sec.max(1),
+            started: Instant::now(),
+            bytes_read: 0,
+            delay: None,
+        }
+    }
+
+    /// Gets the instant at which the bytes read so far are within the limit.
+    fn due(&self) -> Instant {
+        let nanos = u128::from(self.bytes_read) * 1_000_000_000 / u128::from(self.bytes_per_sec);
+        self.started + Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+}
+
+impl<R> AsyncRead for ThrottledReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                self.delay = None;
+            }
+
+            let due = self.due();
+            if due <= Instant::now() {
+                break;
+            }
+            self.delay = Some(Box::pin(tokio::time::sleep_until(due)));
+        }
+
+        let max_bytes = (self.bytes_per_sec / READS_PER_SECOND).max(1);
+        let max_bytes = usize::try_from(max_bytes)
+            .unwrap_or(usize::MAX)
+            .min(buf.remaining());
+
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(max_bytes));
+        ready!(Pin::new(&mut self.reader).poll_read(cx, &mut limited))?;
+        let read = limited.filled().len();
+        buf.advance(read);
+        self.bytes_read += read as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test(start_paused = true)]
+    async fn reads_are_paced_to_the_limit() {
+        let content = vec![7u8; 1000];
+        let mut reader = ThrottledReader::new(&content[..], 100);
+
+        let started = Instant::now();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).await.expect("failed to read");
+        assert_eq!(read, content);
+
+        // The end of the file is reached once all bytes read are within the limit.
+        assert_eq!(started.elapsed(), Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reads_are_limited_to_a_tenth_of_the_rate() {
+        let content = vec![7u8; 1000];
+        let mut reader = ThrottledReader::new(&content[..], 100);
+
+        let mut buf = [0u8; 64];
+        let read = reader.read(&mut buf).await.expect("failed to read");
+        assert_eq!(read, 10);
+    }
+}
@@ -0,0 +1,161 @@
+//! Contains the limit of concurrent uploads across all clients.
+
+use app_config::files::FilesConfig;
+use metrics::transfer::{TransferMetrics, UploadState};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits the number of uploads written at the same time.
+///
+/// Unlike the per-token [`UploadQuotas`](crate::quotas::UploadQuotas), the limit applies
+/// to the node as a whole, bounding the memory and disk I/O spent on uploads.
+#[derive(Default)]
+pub struct UploadLimiter {
+    /// The slots of concurrent uploads; `None` if uploads are unlimited.
+    slots: Option<Arc<Semaphore>>,
+    /// The number of slots.
+    max_uploads: usize,
+    /// The time an upload waits for a free slot.
+    queue_timeout: Duration,
+}
+
+/// No upload slot became free in time.
+#[derive(Debug, thiserror::Error)]
+#[error("All {max_uploads} upload slots are in use")]
+pub struct UploadsBusy {
+    /// The maximum number of concurrent uploads.
+    pub max_uploads: usize,
+}
+
+/// A slot held for the duration of an upload.
+pub struct UploadPermit {
+    _slot: Option<OwnedSemaphorePermit>,
+}
+
+/// Counts an upload as queued until it is dropped.
+struct QueuedUpload;
+
+impl UploadLimiter {
+    /// Creates the limit of the specified configuration.
+    pub fn new(config: &FilesConfig) -> Self {
+        let max_uploads = config.max_concurrent_uploads.unwrap_or_default();
+        Self {
+            slots: config
+                .max_concurrent_uploads
+                .map(|max_uploads| Arc::new(Semaphore::new(max_uploads))),
+            max_uploads,
+            queue_timeout: config.upload_queue_timeout(),
+        }
+    }
+
+    /// Obtains a slot for an upload, waiting up to the queue timeout for one to become free.
+    pub async fn acquire(&self) -> Result<UploadPermit, UploadsBusy> {
+        let slot = match &self.slots {
+            Some(slots) => Some(self.wait_for_slot(slots.clone()).await?),
+            None => None,
+        };
+
+        TransferMetrics::inc_uploads(UploadState::Active);
+        Ok(UploadPermit { _slot: slot })
+    }
+
+    async fn wait_for_slot(
+        &self,
+        slots: Arc<Semaphore>,
+    ) -> Result<OwnedSemaphorePermit, UploadsBusy> {
+        let busy = UploadsBusy {
+            max_uploads: self.max_uploads,
+        };
+        if let Ok(slot) = slots.clone().try_acquire_owned() {
+            return Ok(slot);
+        }
+        if self.queue_timeout.is_zero() {
+            return Err(busy);
+        }
+
+        let _queued = QueuedUpload::new();
+        match tokio::time::timeout(self.queue_timeout, slots.acquire_owned()).await {
+            Ok(Ok(slot)) => Ok(slot),
+            _ => Err(busy),
+        }
+    }
+}
+
+impl Drop for UploadPermit {
+    fn drop(&mut self) {
+        TransferMetrics::dec_uploads(UploadState::Active);
+    }
+}
+
+impl QueuedUpload {
+    fn new() -> Self {
+        TransferMetrics::inc_uploads(UploadState::Queued);
+        Self
+    }
+}
+
+/// Ensures that uploads cancelled while waiting are no longer counted.
+impl Drop for QueuedUpload {
+    fn drop(&mut self) {
+        TransferMetrics::dec_uploads(UploadState::Queued);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(yaml: &str) -> UploadLimiter {
+        let config: FilesConfig = serde_yaml::from_str(yaml).expect("invalid configuration");
+        UploadLimiter::new(&config)
+    }
+
+    #[tokio::test]
+    async fn uploads_beyond_the_limit_are_rejected() {
+        let limiter = limiter("max_concurrent_uploads: 1");
+        let permit = limiter.acquire().await.expect("no slot was free");
+        let error = limiter
+            .acquire()
+            .await
+            .err()
+            .expect("the limit was ignored");
+        assert_eq!(error.max_uploads, 1);
+
+        drop(permit);
+        limiter.acquire().await.expect("the slot was not released");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queued_uploads_wait_for_a_free_slot() {
+        let limiter = Arc::new(limiter(
+            "{ max_concurrent_uploads: 1, upload_queue_timeout_ms: 1000 }",
+        ));
+        let permit = limiter.acquire().await.expect("no slot was free");
+
+        let queued = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.map(drop) }
+        });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drop(permit);
+        queued
+            .await
+            .expect("failed to join the upload")
+            .expect("the queued upload was rejected");
+
+        let _permit = limiter.acquire().await.expect("no slot was free");
+        limiter
+            .acquire()
+            .await
+            .err()
+            .expect("the queue did not time out");
+    }
+
+    #[tokio::test]
+    async fn uploads_are_unlimited_by_default() {
+        let limiter = UploadLimiter::default();
+        let _first = limiter.acquire().await.expect("the upload was limited");
+        let _second = limiter.acquire().await.expect("the upload was limited");
+    }
+}
@@ -0,0 +1,180 @@
+//! Contains the reader verifying downloads against the hash of the stored file.
+
+use file_distribution::hash::{HashSha256, Sha256Digest};
+use metrics::backbone::BackboneMetrics;
+use shortguid::ShortGuid;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use tracing::error;
+
+/// Hashes the bytes read from the inner reader and compares the SHA-256 hash against
+/// the expected one once the size of the file was read.
+///
+/// On a mismatch, the corruption is logged and counted, and the final read fails without
+/// yielding its bytes such that the response is aborted rather than completed.
+///
+/// ## Remarks
+///
+/// The hash is checked as soon as the last byte of the file arrives rather than on the
+/// subsequent empty read, since hyper stops polling a body once `Content-Length` bytes
+/// were sent.
+pub struct VerifyingReader<R> {
+    reader: R,
+    id: ShortGuid,
+    expected: Sha256Digest,
+    /// The size of the file in bytes.
+    size: u64,
+    /// The number of bytes read so far.
+    read: u64,
+    /// The hash of the bytes read so far; `None` once the hash was checked.
+    hasher: Option<HashSha256>,
+}
+
+impl<R> VerifyingReader<R> {
+    /// Verifies the content of the file `id` of `size` bytes read from `reader` against
+    /// the `expected` hash.
+    pub fn new(reader: R, id: ShortGuid, expected: Sha256Digest, size: u64) -> Self {
+        Self {
+            reader,
+            id,
+            expected,
+            size,
+            read: 0,
+            hasher: Some(HashSha256::new()),
+        }
+    }
+}
+
+impl<R> AsyncRead for VerifyingReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.reader).poll_read(cx, buf))?;
+        let read = &buf.filled()[filled..];
+        let at_end = if read.is_empty() {
+            // An empty read into a non-empty buffer marks the end of the file.
+            buf.remaining() > 0
+        } else {
+            self.read += read.len() as u64;
+            if let Some(hasher) = self.hasher.as_mut() {
+                hasher.update(read);
+            }
+            self.read >= self.size
+        };
+
+        if !at_end {
+            return Poll::Ready(Ok(()));
+        }
+        let Some(hasher) = self.hasher.take() else {
+            return Poll::Ready(Ok(()));
+        };
+
+        let actual = hasher.finalize();
+        if actual == self.expected && self.read == self.size {
+            return Poll::Ready(Ok(()));
+        }
+
+        let id = self.id;
+        error!(
+            file_id = %id,
+            "Content of file {id} does not match its SHA-256 hash {expected}, got {actual} after {read} of {size} bytes; aborting the download",
+            expected = hex::encode(self.expected),
+            actual = hex::encode(actual),
+            read = self.read,
+            size = self.size
+        );
+        BackboneMetrics::track_corrupted_download();
+
+        // Withhold the final bytes so that the response is never complete.
+        buf.set_filled(filled);
+        Poll::Ready(Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("the content of file {id} does not match its hash"),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    fn sha256(data: &[u8]) -> Sha256Digest {
+        let mut hasher = HashSha256::new();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    #[tokio::test]
+    async fn intact_files_are_read_completely() {
+        let content = vec![7u8; 10_000];
+        let mut reader = VerifyingReader::new(
+            &content[..],
+            ShortGuid::new_random(),
+            sha256(&content),
+            10_000,
+        );
+
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).await.expect("failed to read");
+        assert_eq!(read, content);
+    }
+
+    #[tokio::test]
+    async fn corrupted_files_fail_on_the_last_bytes() {
+        let content = vec![7u8; 10_000];
+        let expected = sha256(b"something else");
+        let mut reader =
+            VerifyingReader::new(&content[..], ShortGuid::new_random(), expected, 10_000);
+
+        let mut read = Vec::new();
+        let error = reader
+            .read_to_end(&mut read)
+            .await
+            .expect_err("the corruption was not detected");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(read.len() < content.len());
+    }
+
+    #[tokio::test]
+    async fn hashes_are_checked_without_reading_past_the_file_size() {
+        let content = vec![7u8; 10_000];
+        let expected = sha256(b"something else");
+        let mut reader =
+            VerifyingReader::new(&content[..], ShortGuid::new_random(), expected, 10_000);
+
+        // Like hyper, stop reading once the size of the file was read.
+        let mut read = vec![0; 10_000];
+        let error = reader
+            .read_exact(&mut read)
+            .await
+            .expect_err("the corruption was not detected");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn truncated_files_fail_at_the_end() {
+        let content = vec![7u8; 10_000];
+        let mut reader = VerifyingReader::new(
+            &content[..],
+            ShortGuid::new_random(),
+            sha256(&content),
+            20_000,
+        );
+
+        let mut read = Vec::new();
+        let error = reader
+            .read_to_end(&mut read)
+            .await
+            .expect_err("the truncation was not detected");
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default period over which the uploads of a token are counted against its quota.
+pub const DEFAULT_QUOTA_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Configuration of request authentication.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// The bearer tokens accepted by the `/yeet` and `/yoink` endpoints.
+    /// If empty, requests are not authenticated.
+    pub tokens: Vec<TokenConfig>,
+    /// The bearer tokens accepted by the administrative `/admin` endpoints.
+    /// If empty, the administrative endpoints reject all requests.
+    pub admin_tokens: Vec<String>,
+    /// The number of seconds over which the uploads of a token are counted against
+    /// its quota. Defaults to [`DEFAULT_QUOTA_WINDOW`].
+    pub quota_window_sec: Option<u64>,
+}
+
+/// Configuration of a bearer token.
+///
+/// Tokens without a quota can be given as plain strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "TokenConfigRepr")]
+pub struct TokenConfig {
+    /// The secret token.
+    pub token: String,
+    /// The number of bytes that may be uploaded with the token within the
+    /// [quota window](AuthConfig::quota_window). If unset, uploads are not limited.
+    pub quota_bytes: Option<u64>,
+}
+
+/// The accepted representations of a [`TokenConfig`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenConfigRepr {
+    Token(String),
+    Config {
+        token: String,
+        #[serde(default)]
+        quota_bytes: Option<u64>,
+    },
+}
+
+impl From<TokenConfigRepr> for TokenConfig {
+    fn from(value: TokenConfigRepr) -> Self {
+        match value {
+            TokenConfigRepr::Token(token) => Self {
+                token,
+                quota_bytes: None,
+            },
+            TokenConfigRepr::Config { token, quota_bytes } => Self { token, quota_bytes },
+        }
+    }
+}
+
+impl AuthConfig {
+    /// Determines whether requests need to be authenticated.
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Gets the period over which the uploads of a token are counted against its quota.
+    pub fn quota_window(&self) -> Duration {
+        self.quota_window_sec
+            .map_or(DEFAULT_QUOTA_WINDOW, Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_auth_config_works() {
+        let yaml = r#"
+            tokens:
+              - "s3cr3t"
+              - token: "t0k3n"
+                quota_bytes: 1024
+            admin_tokens: ["4dm1n"]
+            quota_window_sec: 3600
+        "#;
+
+        let config: AuthConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize auth config");
+        assert!(config.enabled());
+        assert_eq!(
+            config.tokens,
+            [
+                TokenConfig {
+                    token: "s3cr3t".to_string(),
+                    quota_bytes: None
+                },
+                TokenConfig {
+                    token: "t0k3n".to_string(),
+                    quota_bytes: Some(1024)
+                }
+            ]
+        );
+        assert_eq!(config.admin_tokens, ["4dm1n"]);
+        assert_eq!(config.quota_window(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn auth_config_defaults_work() {
+        let config: AuthConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize auth config");
+        assert!(!config.enabled());
+        assert!(config.admin_tokens.is_empty());
+        assert_eq!(config.quota_window(), DEFAULT_QUOTA_WINDOW);
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The methods allowed for cross-origin requests by default.
+pub const DEFAULT_ALLOWED_METHODS: &[&str] = &["GET", "HEAD", "POST", "PUT", "DELETE"];
+
+/// The request headers allowed for cross-origin requests by default.
+pub const DEFAULT_ALLOWED_HEADERS: &[&str] = &[
+    "authorization",
+    "content-type",
+    "content-disposition",
+    "content-encoding",
+    "content-md5",
+    "if-none-match",
+    "if-modified-since",
+    "yy-lease",
+    "x-yeet-hashes",
+    "x-expected-sha256",
+    "x-yeet-wait",
+    "x-idempotency-key",
+    "x-yoink-rate",
+];
+
+/// The response headers exposed to cross-origin requests by default.
+pub const DEFAULT_EXPOSED_HEADERS: &[&str] = &[
+    "expires",
+    "etag",
+    "last-modified",
+    "content-disposition",
+    "content-md5",
+    "x-checksum-sha256",
+    "yy-file-md5",
+    "yy-file-sha256",
+    "retry-after",
+    "x-yeet-backends",
+];
+
+/// The default time for which browsers may cache the result of a preflight request.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Configuration of cross-origin requests (CORS) to the file endpoints.
+///
+/// Cross-origin requests are rejected by browsers unless `permissive` is set or
+/// `allowed_origins` lists the origin.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Whether requests from any origin, using any method and headers, are allowed,
+    /// exposing all response headers. Intended for development only.
+    pub permissive: bool,
+    /// The origins allowed to send requests, e.g. `https://app.example.com`.
+    pub allowed_origins: Vec<String>,
+    /// The allowed methods. Defaults to [`DEFAULT_ALLOWED_METHODS`].
+    pub allowed_methods: Option<Vec<String>>,
+    /// The allowed request headers. Defaults to [`DEFAULT_ALLOWED_HEADERS`].
+    pub allowed_headers: Option<Vec<String>>,
+    /// The response headers exposed to the browser. Defaults to [`DEFAULT_EXPOSED_HEADERS`].
+    pub exposed_headers: Option<Vec<String>>,
+    /// The number of seconds for which browsers may cache the result of a preflight request.
+    /// Defaults to [`DEFAULT_MAX_AGE`].
+    pub max_age_sec: Option<u64>,
+}
+
+impl CorsConfig {
+    /// Determines whether cross-origin requests are allowed at all.
+    pub fn enabled(&self) -> bool {
+        self.permissive || !self.allowed_origins.is_empty()
+    }
+
+    /// Gets the allowed methods.
+    pub fn allowed_methods(&self) -> Vec<&str> {
+        Self::or_default(&self.allowed_methods, DEFAULT_ALLOWED_METHODS)
+    }
+
+    /// Gets the allowed request headers.
+    pub fn allowed_headers(&self) -> Vec<&str> {
+        Self::or_default(&self.allowed_headers, DEFAULT_ALLOWED_HEADERS)
+    }
+
+    /// Gets the response headers exposed to the browser.
+    pub fn exposed_headers(&self) -> Vec<&str> {
+        Self::or_default(&self.exposed_headers, DEFAULT_EXPOSED_HEADERS)
+    }
+
+    /// Gets the time for which browsers may cache the result of a preflight request.
+    pub fn max_age(&self) -> Duration {
+        self.max_age_sec
+            .map_or(DEFAULT_MAX_AGE, Duration::from_secs)
+    }
+
+    fn or_default<'a>(values: &'a Option<Vec<String>>, default: &[&'static str]) -> Vec<&'a str> {
+        match values {
+            Some(values) => values.iter().map(String::as_str).collect(),
+            None => default.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_cors_config_works() {
+        let yaml = r#"
+            allowed_origins:
+              - "https://app.example.com"
+            allowed_methods: ["GET", "POST"]
+            allowed_headers: ["authorization"]
+            exposed_headers: ["etag"]
+            max_age_sec: 60
+        "#;
+
+        let config: CorsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize CORS config");
+        assert!(config.enabled());
+        assert!(!config.permissive);
+        assert_eq!(config.allowed_origins, ["https://app.example.com"]);
+        assert_eq!(config.allowed_methods(), ["GET", "POST"]);
+        assert_eq!(config.allowed_headers(), ["authorization"]);
+        assert_eq!(config.exposed_headers(), ["etag"]);
+        assert_eq!(config.max_age(), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn cors_config_defaults_work() {
+        let config: CorsConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize CORS config");
+        assert!(!config.enabled());
+        assert_eq!(config.allowed_methods(), DEFAULT_ALLOWED_METHODS);
+        assert_eq!(config.allowed_headers(), DEFAULT_ALLOWED_HEADERS);
+        assert_eq!(config.exposed_headers(), DEFAULT_EXPOSED_HEADERS);
+        assert_eq!(config.max_age(), DEFAULT_MAX_AGE);
+
+        let config: CorsConfig =
+            serde_yaml::from_str("permissive: true").expect("Failed to deserialize CORS config");
+        assert!(config.enabled());
+    }
+}
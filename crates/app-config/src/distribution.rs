@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default capacity of the queue of events sent to the backends.
+pub const DEFAULT_EVENT_BUFFER_SIZE: usize = 64;
+
+/// The default time to wait for the backends to accept a new file.
+pub const DEFAULT_ENQUEUE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The default number of attempts to distribute a file to a backend.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// The default delay before the first retry of a failed distribution.
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// The default upper bound of the delay between retries.
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The default number of consecutive failed attempts after which a backend's circuit breaker opens.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// The default time a backend's open circuit breaker skips distributions.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Configuration of the distribution of files to the backends.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DistributionConfig {
+    /// Whether backends of a lower priority should only be started after
+    /// all backends of a higher priority have finished.
+    ///
+    /// When disabled (the default), all backends are started at the same
+    /// time, in order of their priority.
+    pub gate_by_priority: bool,
+    /// Whether backends that do not depend on the file hashes should start
+    /// receiving a file while it is still being uploaded.
+    ///
+    /// The hashes are attached to these files once the upload has completed.
+    /// When disabled (the default), distribution starts after the upload completed.
+    pub early_distribution: bool,
+    /// How to proceed when a file is deleted while it is being distributed.
+    pub on_delete: DeleteBehavior,
+    /// How failed distributions to a backend are retried.
+    pub retry: RetryConfig,
+    /// When distributions to a failing backend are skipped.
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// The number of events (e.g. files ready for distribution) that can be queued for
+    /// the backends before uploads wait for the queue to drain.
+    /// Defaults to [`DEFAULT_EVENT_BUFFER_SIZE`].
+    pub event_buffer_size: Option<usize>,
+    /// The number of milliseconds an upload waits for a slot in the event queue before it
+    /// is rejected with `503 Service Unavailable`. Defaults to [`DEFAULT_ENQUEUE_TIMEOUT`].
+    pub enqueue_timeout_ms: Option<u64>,
+}
+
+impl DistributionConfig {
+    /// Gets the capacity of the queue of events sent to the backends; at least one.
+    pub fn event_buffer_size(&self) -> usize {
+        self.event_buffer_size
+            .unwrap_or(DEFAULT_EVENT_BUFFER_SIZE)
+            .max(1)
+    }
+
+    /// Gets the time an upload waits for a slot in the event queue.
+    pub fn enqueue_timeout(&self) -> Duration {
+        self.enqueue_timeout_ms
+            .map_or(DEFAULT_ENQUEUE_TIMEOUT, Duration::from_millis)
+    }
+}
+
+/// Configuration of the retries of failed distributions.
+///
+/// The delay between attempts starts at the initial backoff and doubles with
+/// every attempt, up to the maximum backoff.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// The number of attempts to distribute a file to a backend before giving up,
+    /// including the first one. Defaults to [`DEFAULT_MAX_ATTEMPTS`]; `1` disables retries.
+    pub max_attempts: Option<u32>,
+    /// The delay before the first retry, in milliseconds.
+    /// Defaults to [`DEFAULT_INITIAL_BACKOFF`].
+    pub initial_backoff_ms: Option<u64>,
+    /// The maximum delay between retries, in milliseconds.
+    /// Defaults to [`DEFAULT_MAX_BACKOFF`].
+    pub max_backoff_ms: Option<u64>,
+}
+
+impl RetryConfig {
+    /// Gets the number of attempts to distribute a file to a backend; at least one.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1)
+    }
+
+    /// Gets the delay before the first retry.
+    pub fn initial_backoff(&self) -> Duration {
+        self.initial_backoff_ms
+            .map_or(DEFAULT_INITIAL_BACKOFF, Duration::from_millis)
+    }
+
+    /// Gets the maximum delay between retries.
+    pub fn max_backoff(&self) -> Duration {
+        self.max_backoff_ms
+            .map_or(DEFAULT_MAX_BACKOFF, Duration::from_millis)
+    }
+}
+
+/// Configuration of the per-backend circuit breakers.
+///
+/// A backend's circuit breaker opens after a number of consecutive failed distribution
+/// attempts, skipping distributions to the backend for a cooldown. Afterwards, a single
+/// distribution is attempted; its success closes the circuit breaker again.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failed attempts after which the circuit breaker opens.
+    /// Defaults to [`DEFAULT_FAILURE_THRESHOLD`]; `0` disables the circuit breakers.
+    pub failure_threshold: Option<u32>,
+    /// The time distributions are skipped once the circuit breaker opened, in milliseconds.
+    /// Defaults to [`DEFAULT_COOLDOWN`].
+    pub cooldown_ms: Option<u64>,
+}
+
+impl CircuitBreakerConfig {
+    /// Gets the number of consecutive failed attempts after which the circuit breaker opens;
+    /// `0` if disabled.
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold.unwrap_or(DEFAULT_FAILURE_THRESHOLD)
+    }
+
+    /// Gets the time distributions are skipped once the circuit breaker opened.
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown_ms
+            .map_or(DEFAULT_COOLDOWN, Duration::from_millis)
+    }
+}
+
+/// Controls the distribution of files that are deleted while being distributed.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeleteBehavior {
+    /// Lets in-flight backend writes complete, then deletes the file from the backends.
+    #[default]
+    Complete,
+    /// Aborts in-flight backend writes immediately, then deletes the file from the backends.
+    Abort,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_distribution_config_works() {
+        let yaml = r#"
+            gate_by_priority: true
+            early_distribution: true
+            on_delete: abort
+            retry:
+              max_attempts: 5
+              initial_backoff_ms: 100
+              max_backoff_ms: 2000
+            circuit_breaker:
+              failure_threshold: 10
+              cooldown_ms: 5000
+            event_buffer_size: 1024
+            enqueue_timeout_ms: 250
+        "#;
+
+        let config: DistributionConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize distribution config");
+        assert!(config.gate_by_priority);
+        assert!(config.early_distribution);
+        assert_eq!(config.on_delete, DeleteBehavior::Abort);
+        assert_eq!(config.retry.max_attempts(), 5);
+        assert_eq!(config.retry.initial_backoff(), Duration::from_millis(100));
+        assert_eq!(config.retry.max_backoff(), Duration::from_secs(2));
+        assert_eq!(config.circuit_breaker.failure_threshold(), 10);
+        assert_eq!(config.circuit_breaker.cooldown(), Duration::from_secs(5));
+        assert_eq!(config.event_buffer_size(), 1024);
+        assert_eq!(config.enqueue_timeout(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn distribution_config_defaults_work() {
+        let config: DistributionConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize distribution config");
+        assert!(!config.gate_by_priority);
+        assert!(!config.early_distribution);
+        assert_eq!(config.on_delete, DeleteBehavior::Complete);
+        assert_eq!(config.retry.max_attempts(), DEFAULT_MAX_ATTEMPTS);
+        assert_eq!(config.retry.initial_backoff(), DEFAULT_INITIAL_BACKOFF);
+        assert_eq!(config.retry.max_backoff(), DEFAULT_MAX_BACKOFF);
+        assert_eq!(
+            config.circuit_breaker.failure_threshold(),
+            DEFAULT_FAILURE_THRESHOLD
+        );
+        assert_eq!(config.circuit_breaker.cooldown(), DEFAULT_COOLDOWN);
+        assert_eq!(config.event_buffer_size(), DEFAULT_EVENT_BUFFER_SIZE);
+        assert_eq!(config.enqueue_timeout(), DEFAULT_ENQUEUE_TIMEOUT);
+    }
+}
@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default validity of presigned URLs that downloads are redirected to.
+pub const DEFAULT_REDIRECT_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/// The default number of attempts to read a file back from each backend.
+pub const DEFAULT_FALLBACK_MAX_ATTEMPTS: u32 = 1;
+
+/// The default time to wait for each backend to provide a file.
+pub const DEFAULT_FALLBACK_BACKEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The default time to wait for any backend to provide a file.
+pub const DEFAULT_FALLBACK_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Configuration of file downloads via `/yoink`.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadsConfig {
+    /// Whether downloads are redirected with `302 Found` to a presigned URL of a backend
+    /// storing the file, rather than streamed through the server. Downloads are streamed
+    /// if no backend provides a URL. Disabled by default.
+    pub redirect: bool,
+    /// The minimum size of locally buffered files whose downloads are redirected, in bytes.
+    /// Files not buffered locally are always redirected if possible. Defaults to `0`.
+    pub redirect_min_bytes: Option<u64>,
+    /// The number of seconds for which presigned URLs are valid.
+    /// Defaults to [`DEFAULT_REDIRECT_EXPIRY`].
+    pub redirect_expiry_sec: Option<u64>,
+    /// Whether the content type of files stored without one is detected from their first
+    /// bytes. Otherwise, or if detection fails, they are served as `application/octet-stream`.
+    /// Disabled by default.
+    pub sniff_content_type: bool,
+    /// Whether the SHA-256 hash of each download is recomputed while it is sent and compared
+    /// against the hash of the stored file, detecting corruption of the buffered files.
+    /// Since the response is sent already, mismatches abort the connection. Disabled by default,
+    /// since hashing costs CPU time.
+    pub verify_hashes: bool,
+    /// The bandwidth limit of each download, in bytes per second. Unlimited by default.
+    pub rate_limit_bytes_per_sec: Option<u64>,
+    /// The highest bandwidth limit a client can request using the `X-Yoink-Rate` header,
+    /// in bytes per second. Defaults to `rate_limit_bytes_per_sec`; if neither is set,
+    /// requested limits are applied as is.
+    pub max_rate_limit_bytes_per_sec: Option<u64>,
+    /// How files that are no longer buffered locally are read back from the backends.
+    pub fallback: FallbackConfig,
+    /// The `Cache-Control` header of downloads.
+    pub cache_control: CacheControlConfig,
+}
+
+/// Configuration of the `Cache-Control` header of downloads.
+///
+/// The `max-age` directive is the remaining lease of the file, such that caches never
+/// serve a file past its expiration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheControlConfig {
+    /// Whether downloads carry a `Cache-Control` header. Disabled by default.
+    pub enabled: bool,
+    /// Whether shared caches, such as CDNs, may store downloads. Otherwise, downloads
+    /// are marked `private`. Disabled by default.
+    pub public: bool,
+    /// The highest `max-age`, in seconds. Unlimited by default.
+    pub max_age_sec: Option<u64>,
+}
+
+/// Configuration of reading files back from the backends.
+///
+/// All backends are asked at once; the first one providing the file serves the download.
+/// Downloads are answered with `504 Gateway Timeout` if no backend provided the file and
+/// at least one of them did not answer in time.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FallbackConfig {
+    /// The number of attempts to read a file from each backend, including the first one.
+    /// Failed and timed out attempts are retried. Defaults to [`DEFAULT_FALLBACK_MAX_ATTEMPTS`].
+    pub max_attempts: Option<u32>,
+    /// The time to wait for each attempt, in milliseconds.
+    /// Defaults to [`DEFAULT_FALLBACK_BACKEND_TIMEOUT`].
+    pub backend_timeout_ms: Option<u64>,
+    /// The time to wait for any backend to provide the file, including all attempts,
+    /// in milliseconds. Defaults to [`DEFAULT_FALLBACK_DEADLINE`].
+    pub deadline_ms: Option<u64>,
+}
+
+impl FallbackConfig {
+    /// Gets the number of attempts to read a file from each backend; at least one.
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+            .unwrap_or(DEFAULT_FALLBACK_MAX_ATTEMPTS)
+            .max(1)
+    }
+
+    /// Gets the time to wait for each attempt.
+    pub fn backend_timeout(&self) -> Duration {
+        self.backend_timeout_ms
+            .map_or(DEFAULT_FALLBACK_BACKEND_TIMEOUT, Duration::from_millis)
+    }
+
+    /// Gets the time to wait for any backend to provide a file.
+    pub fn deadline(&self) -> Duration {
+        self.deadline_ms
+            .map_or(DEFAULT_FALLBACK_DEADLINE, Duration::from_millis)
+    }
+}
+
+impl CacheControlConfig {
+    /// Gets the `Cache-Control` header value of a file with the specified remaining lease,
+    /// or `None` if the header is disabled.
+    pub fn header_value(&self, remaining_lease: Duration) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+
+        let visibility = if self.public { "public" } else { "private" };
+        let max_age = self.max_age_sec.map_or(remaining_lease.as_secs(), |max| {
+            remaining_lease.as_secs().min(max)
+        });
+        Some(format!("{visibility}, max-age={max_age}"))
+    }
+}
+
+impl DownloadsConfig {
+    /// Gets the minimum size of locally buffered files whose downloads are redirected,
+    /// or `None` if redirects are disabled.
+    pub fn redirect_min_bytes(&self) -> Option<u64> {
+        self.redirect
+            .then(|| self.redirect_min_bytes.unwrap_or_default())
+    }
+
+    /// Gets the validity of presigned URLs.
+    pub fn redirect_expiry(&self) -> Duration {
+        self.redirect_expiry_sec
+            .map_or(DEFAULT_REDIRECT_EXPIRY, Duration::from_secs)
+    }
+
+    /// Gets the bandwidth limit of a download in bytes per second, given the limit
+    /// requested by the client, or `None` if the download is not limited.
+    pub fn rate_limit(&self, requested: Option<u64>) -> Option<u64> {
+        let Some(requested) = requested else {
+            return self.rate_limit_bytes_per_sec;
+        };
+
+        let max = self
+            .max_rate_limit_bytes_per_sec
+            .or(self.rate_limit_bytes_per_sec);
+        Some(max.map_or(requested, |max| requested.min(max)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_downloads_config_works() {
+        let yaml = r#"
+            redirect: true
+            redirect_min_bytes: 1048576
+            redirect_expiry_sec: 60
+            sniff_content_type: true
+            verify_hashes: true
+            rate_limit_bytes_per_sec: 1048576
+            max_rate_limit_bytes_per_sec: 4194304
+            fallback:
+              max_attempts: 2
+              backend_timeout_ms: 1500
+              deadline_ms: 5000
+            cache_control:
+              enabled: true
+              public: true
+              max_age_sec: 3600
+        "#;
+
+        let config: DownloadsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize downloads config");
+        assert_eq!(config.redirect_min_bytes(), Some(1024 * 1024));
+        assert_eq!(config.redirect_expiry(), Duration::from_secs(60));
+        assert!(config.sniff_content_type);
+        assert!(config.verify_hashes);
+        assert_eq!(config.rate_limit(None), Some(1024 * 1024));
+        assert_eq!(config.rate_limit(Some(2048)), Some(2048));
+        assert_eq!(config.rate_limit(Some(u64::MAX)), Some(4 * 1024 * 1024));
+        assert_eq!(config.fallback.max_attempts(), 2);
+        assert_eq!(
+            config.fallback.backend_timeout(),
+            Duration::from_millis(1500)
+        );
+        assert_eq!(config.fallback.deadline(), Duration::from_secs(5));
+        assert_eq!(
+            config
+                .cache_control
+                .header_value(Duration::from_millis(60_500))
+                .as_deref(),
+            Some("public, max-age=60")
+        );
+        assert_eq!(
+            config
+                .cache_control
+                .header_value(Duration::from_secs(86400))
+                .as_deref(),
+            Some("public, max-age=3600")
+        );
+    }
+
+    #[test]
+    fn downloads_config_defaults_work() {
+        let config: DownloadsConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize downloads config");
+        assert_eq!(config.redirect_min_bytes(), None);
+        assert_eq!(config.redirect_expiry(), DEFAULT_REDIRECT_EXPIRY);
+        assert!(!config.sniff_content_type);
+        assert!(!config.verify_hashes);
+        assert_eq!(config.rate_limit(None), None);
+        assert_eq!(config.rate_limit(Some(2048)), Some(2048));
+        assert_eq!(
+            config.fallback.max_attempts(),
+            DEFAULT_FALLBACK_MAX_ATTEMPTS
+        );
+        assert_eq!(
+            config.fallback.backend_timeout(),
+            DEFAULT_FALLBACK_BACKEND_TIMEOUT
+        );
+        assert_eq!(config.fallback.deadline(), DEFAULT_FALLBACK_DEADLINE);
+        assert_eq!(
+            config.cache_control.header_value(Duration::from_secs(60)),
+            None
+        );
+    }
+
+    #[test]
+    fn private_cache_control_is_the_default() {
+        let config = CacheControlConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.header_value(Duration::from_secs(60)).as_deref(),
+            Some("private, max-age=60")
+        );
+    }
+}
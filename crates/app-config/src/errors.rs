@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+/// Configuration of error responses.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ErrorsConfig {
+    /// The prefix of the `type` URIs of problem details responses, e.g.
+    /// `https://example.com/problems/`. If unset, `urn:yeet-yoink:problem:` is used.
+    pub type_uri_prefix: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_errors_config_works() {
+        let yaml = r#"
+            type_uri_prefix: "https://example.com/problems/"
+        "#;
+
+        let config: ErrorsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize errors config");
+        assert_eq!(
+            config.type_uri_prefix.as_deref(),
+            Some("https://example.com/problems/")
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The default duration for which files are kept alive.
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(5 * 60);
+
+/// The default upper bound for per-upload lease overrides.
+pub const DEFAULT_MAX_LEASE: Duration = Duration::from_secs(60 * 60);
+
+/// The default upper bound of the size of files served from a shared read pass.
+pub const DEFAULT_BROADCAST_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The default memory-backed directory small files are buffered in.
+pub const DEFAULT_IN_MEMORY_DIR: &str = "/dev/shm";
+
+/// The default duration for which the IDs of expired files are remembered.
+pub const DEFAULT_TOMBSTONE_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// The default maximum number of expired file IDs remembered at a time.
+pub const DEFAULT_TOMBSTONE_CAPACITY: usize = 10_000;
+
+/// The default maximum size of the metadata attached to an upload, in bytes.
+pub const DEFAULT_MAX_METADATA_BYTES: usize = 4096;
+
+/// The default duration from which on syncing an upload to disk is logged as slow.
+pub const DEFAULT_SLOW_SYNC_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Configuration of the locally buffered files.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FilesConfig {
+    /// The number of seconds for which a file is kept available for readers after it
+    /// was written. Defaults to [`DEFAULT_LEASE`].
+    ///
+    /// ### Example
+    ///
+    /// To keep files for 30 seconds:
+    ///
+    /// ```text
+    /// 30
+    /// ```
+    pub lease_sec: Option<u64>,
+    /// The maximum number of seconds a client may request as the lease of an
+    /// individual upload. Defaults to [`DEFAULT_MAX_LEASE`].
+    pub max_lease_sec: Option<u64>,
+    /// Whether concurrent downloads of a completely written file are served from a
+    /// single shared read pass rather than reading the file once per client.
+    /// Disabled by default.
+    ///
+    /// The content of a file is held in memory while it is being broadcast.
+    pub broadcast_reads: bool,
+    /// The maximum size of files served from a shared read pass, in bytes.
+    /// Defaults to [`DEFAULT_BROADCAST_MAX_BYTES`].
+    pub broadcast_max_bytes: Option<u64>,
+    /// The maximum number of bytes buffered locally across all files. Uploads that
+    /// do not fit into the remaining headroom are rejected with `507 Insufficient Storage`.
+    /// Unlimited by default.
+    pub max_storage_bytes: Option<u64>,
+    /// The number of bytes buffered locally from which on new uploads are rejected with
+    /// `507 Insufficient Storage` until expiring leases free enough space. Uploads already
+    /// in progress are not affected. Disabled by default.
+    pub storage_high_water_bytes: Option<u64>,
+    /// Whether uploads whose content (by SHA-256 hash) or `X-Idempotency-Key` header
+    /// matches a live file are answered with the existing file rather than stored again.
+    /// Disabled by default.
+    pub deduplicate: bool,
+    /// Controls when uploaded data is synced to disk. Defaults to [`SyncPolicy::SyncPerChunk`].
+    pub sync_policy: SyncPolicy,
+    /// The number of milliseconds from which on syncing or finalizing an upload is
+    /// logged as a warning, hinting at degraded storage.
+    /// Defaults to [`DEFAULT_SLOW_SYNC_THRESHOLD`].
+    pub slow_sync_warn_ms: Option<u64>,
+    /// The directory uploads are buffered in, e.g. a fast NVMe mount.
+    /// Defaults to the system's temporary directory.
+    pub temp_dir: Option<PathBuf>,
+    /// Uploads announcing at most this many bytes in their `Content-Length` header are
+    /// buffered in [`in_memory_dir`](Self::in_memory_dir) instead of the temporary directory.
+    /// Disabled by default.
+    pub in_memory_max_bytes: Option<u64>,
+    /// The memory-backed directory (e.g. a `tmpfs` mount) small uploads are buffered in.
+    /// Defaults to [`DEFAULT_IN_MEMORY_DIR`].
+    pub in_memory_dir: Option<PathBuf>,
+    /// The file in which buffered files are indexed, such that they survive a restart
+    /// of the server. Disabled by default.
+    ///
+    /// With an index, buffered files are kept on disk when the server shuts down and
+    /// are restored at startup along with their remaining lease.
+    pub index_path: Option<PathBuf>,
+    /// The number of seconds for which the IDs of expired files are remembered, such that
+    /// requests for them are answered with `410 Gone` rather than `404 Not Found`.
+    /// Defaults to [`DEFAULT_TOMBSTONE_GRACE`]; `0` disables it.
+    pub tombstone_grace_sec: Option<u64>,
+    /// The maximum number of expired file IDs remembered at a time; the IDs of the
+    /// files that expired earliest are forgotten first.
+    /// Defaults to [`DEFAULT_TOMBSTONE_CAPACITY`].
+    pub tombstone_capacity: Option<usize>,
+    /// The content types uploads may have, such as `image/png` or `image/*`. Uploads of
+    /// other types are rejected with `415 Unsupported Media Type`; uploads without a
+    /// `Content-Type` header are checked as `application/octet-stream`.
+    /// All types are allowed by default.
+    ///
+    /// With [`DownloadsConfig::sniff_content_type`](crate::downloads::DownloadsConfig::sniff_content_type)
+    /// enabled, the type detected from the first bytes of an upload is checked as well.
+    pub allowed_content_types: Vec<String>,
+    /// The content types uploads are rejected for with `415 Unsupported Media Type`,
+    /// such as `application/x-msdownload`. Takes precedence over
+    /// [`allowed_content_types`](Self::allowed_content_types).
+    pub denied_content_types: Vec<String>,
+    /// The maximum size of the JSON metadata attached to an upload via the `X-Yeet-Meta`
+    /// header or `meta` query parameter, in bytes. Defaults to [`DEFAULT_MAX_METADATA_BYTES`].
+    pub max_metadata_bytes: Option<usize>,
+    /// The maximum number of uploads written at the same time across all clients,
+    /// including chunks of resumable uploads. Further uploads are rejected with
+    /// `503 Service Unavailable` and a `Retry-After` header. Unlimited by default.
+    pub max_concurrent_uploads: Option<usize>,
+    /// The number of milliseconds an upload waits for one of the
+    /// [`max_concurrent_uploads`](Self::max_concurrent_uploads) to finish before it is
+    /// rejected. Defaults to `0`, rejecting uploads right away.
+    pub upload_queue_timeout_ms: Option<u64>,
+}
+
+/// Controls when uploaded data is synced to disk, trading durability for throughput.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncPolicy {
+    /// Never syncs uploads to disk explicitly, leaving it to the operating system.
+    NoSync,
+    /// Syncs every received chunk of an upload to disk.
+    #[default]
+    SyncPerChunk,
+    /// Syncs an upload to disk once it was received completely.
+    SyncOnFinalize,
+}
+
+impl FilesConfig {
+    /// Gets the default lease duration of a file.
+    pub fn lease(&self) -> Duration {
+        self.lease_sec.map_or(DEFAULT_LEASE, Duration::from_secs)
+    }
+
+    /// Gets the maximum lease duration a client may request for a file.
+    pub fn max_lease(&self) -> Duration {
+        self.max_lease_sec
+            .map_or(DEFAULT_MAX_LEASE, Duration::from_secs)
+    }
+
+    /// Gets the maximum size of files served from a shared read pass,
+    /// or `None` if broadcast reads are disabled.
+    pub fn broadcast_max_bytes(&self) -> Option<u64> {
+        self.broadcast_reads.then(|| {
+            self.broadcast_max_bytes
+                .unwrap_or(DEFAULT_BROADCAST_MAX_BYTES)
+        })
+    }
+
+    /// Gets the duration for which the IDs of expired files are remembered.
+    pub fn tombstone_grace(&self) -> Duration {
+        self.tombstone_grace_sec
+            .map_or(DEFAULT_TOMBSTONE_GRACE, Duration::from_secs)
+    }
+
+    /// Gets the maximum number of expired file IDs remembered at a time.
+    pub fn tombstone_capacity(&self) -> usize {
+        self.tombstone_capacity
+            .unwrap_or(DEFAULT_TOMBSTONE_CAPACITY)
+    }
+
+    /// Gets the duration from which on syncing or finalizing an upload is logged as slow.
+    pub fn slow_sync_threshold(&self) -> Duration {
+        self.slow_sync_warn_ms
+            .map_or(DEFAULT_SLOW_SYNC_THRESHOLD, Duration::from_millis)
+    }
+
+    /// Gets the maximum size of the metadata attached to an upload, in bytes.
+    pub fn max_metadata_bytes(&self) -> usize {
+        self.max_metadata_bytes
+            .unwrap_or(DEFAULT_MAX_METADATA_BYTES)
+    }
+
+    /// Gets the time an upload waits for a free slot if the concurrency limit is reached.
+    pub fn upload_queue_timeout(&self) -> Duration {
+        self.upload_queue_timeout_ms
+            .map_or(Duration::ZERO, Duration::from_millis)
+    }
+
+    /// Determines whether any content type is restricted.
+    pub fn restricts_content_types(&self) -> bool {
+        !self.allowed_content_types.is_empty() || !self.denied_content_types.is_empty()
+    }
+
+    /// Determines whether uploads of the specified content type are allowed.
+    ///
+    /// Parameters of the content type, such as the `charset`, are ignored.
+    pub fn is_content_type_allowed(&self, content_type: &str) -> bool {
+        let essence = content_type_essence(content_type);
+        let matches = |pattern: &String| content_type_matches(pattern, &essence);
+        if self.denied_content_types.iter().any(matches) {
+            return false;
+        }
+        self.allowed_content_types.is_empty() || self.allowed_content_types.iter().any(matches)
+    }
+
+    /// Gets the memory-backed directory small uploads are buffered in and the maximum
+    /// size of such uploads, or `None` if disabled.
+    pub fn in_memory_buffer(&self) -> Option<(PathBuf, u64)> {
+        let max_bytes = self.in_memory_max_bytes?;
+        let dir = self
+            .in_memory_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_IN_MEMORY_DIR));
+        Some((dir, max_bytes))
+    }
+}
+
+/// Determines whether a content type pattern is either a `type/subtype` pair,
+/// a `type/*` wildcard or `*/*`.
+pub fn is_content_type_pattern(pattern: &str) -> bool {
+    let is_token = |value: &str| {
+        !value.is_empty()
+            && value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    match pattern.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((kind, "*")) => is_token(kind),
+        Some((kind, subtype)) => is_token(kind) && is_token(subtype),
+        None => false,
+    }
+}
+
+/// Gets the lowercase `type/subtype` essence of a content type, without parameters.
+pub(crate) fn content_type_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Matches the lowercase `type/subtype` essence of a content type against a pattern.
+pub(crate) fn content_type_matches(pattern: &str, essence: &str) -> bool {
+    let pattern = pattern.trim();
+    match pattern.strip_suffix("/*") {
+        Some("*") => true,
+        Some(kind) => essence
+            .split_once('/')
+            .is_some_and(|(other, _)| other.eq_ignore_ascii_case(kind)),
+        None => pattern.eq_ignore_ascii_case(essence),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_files_config_works() {
+        let yaml = r#"
+            lease_sec: 30
+            max_lease_sec: 600
+            broadcast_reads: true
+            broadcast_max_bytes: 1024
+            max_storage_bytes: 1073741824
+            storage_high_water_bytes: 805306368
+            deduplicate: true
+            sync_policy: sync_on_finalize
+            slow_sync_warn_ms: 250
+            temp_dir: /mnt/nvme/yeet-yoink
+            in_memory_max_bytes: 65536
+            index_path: /var/lib/yeet-yoink/files.idx
+            tombstone_grace_sec: 600
+            tombstone_capacity: 100
+            allowed_content_types: ["image/*", "application/pdf"]
+            denied_content_types: ["image/svg+xml"]
+            max_metadata_bytes: 1024
+            max_concurrent_uploads: 16
+            upload_queue_timeout_ms: 500
+        "#;
+
+        let config: FilesConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize files config");
+        assert_eq!(config.lease(), Duration::from_secs(30));
+        assert_eq!(config.max_lease(), Duration::from_secs(600));
+        assert_eq!(config.broadcast_max_bytes(), Some(1024));
+        assert_eq!(config.max_storage_bytes, Some(1024 * 1024 * 1024));
+        assert_eq!(config.storage_high_water_bytes, Some(768 * 1024 * 1024));
+        assert!(config.deduplicate);
+        assert_eq!(config.sync_policy, SyncPolicy::SyncOnFinalize);
+        assert_eq!(config.slow_sync_threshold(), Duration::from_millis(250));
+        assert_eq!(config.temp_dir, Some(PathBuf::from("/mnt/nvme/yeet-yoink")));
+        assert_eq!(
+            config.in_memory_buffer(),
+            Some((PathBuf::from(DEFAULT_IN_MEMORY_DIR), 65536))
+        );
+        assert_eq!(
+            config.index_path,
+            Some(PathBuf::from("/var/lib/yeet-yoink/files.idx"))
+        );
+        assert_eq!(config.tombstone_grace(), Duration::from_secs(600));
+        assert_eq!(config.tombstone_capacity(), 100);
+        assert!(config.restricts_content_types());
+        assert_eq!(config.max_metadata_bytes(), 1024);
+        assert_eq!(config.max_concurrent_uploads, Some(16));
+        assert_eq!(config.upload_queue_timeout(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn files_config_defaults_work() {
+        let config: FilesConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize files config");
+        assert_eq!(config.lease(), DEFAULT_LEASE);
+        assert_eq!(config.max_lease(), DEFAULT_MAX_LEASE);
+        assert_eq!(config.broadcast_max_bytes(), None);
+        assert_eq!(config.sync_policy, SyncPolicy::SyncPerChunk);
+        assert_eq!(config.slow_sync_threshold(), DEFAULT_SLOW_SYNC_THRESHOLD);
+        assert_eq!(config.temp_dir, None);
+        assert_eq!(config.in_memory_buffer(), None);
+        assert_eq!(config.index_path, None);
+        assert_eq!(config.tombstone_grace(), DEFAULT_TOMBSTONE_GRACE);
+        assert_eq!(config.tombstone_capacity(), DEFAULT_TOMBSTONE_CAPACITY);
+        assert!(!config.restricts_content_types());
+        assert_eq!(config.max_metadata_bytes(), DEFAULT_MAX_METADATA_BYTES);
+        assert_eq!(config.max_concurrent_uploads, None);
+        assert_eq!(config.upload_queue_timeout(), Duration::ZERO);
+        assert!(config.is_content_type_allowed("application/x-msdownload"));
+    }
+
+    #[test]
+    fn content_types_are_matched_against_patterns() {
+        let config = FilesConfig {
+            allowed_content_types: vec!["image/*".to_string(), "text/plain".to_string()],
+            denied_content_types: vec!["image/svg+xml".to_string()],
+            ..FilesConfig::default()
+        };
+        assert!(config.is_content_type_allowed("image/png"));
+        assert!(config.is_content_type_allowed("Text/Plain; charset=utf-8"));
+        assert!(!config.is_content_type_allowed("image/svg+xml"));
+        assert!(!config.is_content_type_allowed("text/html"));
+        assert!(!config.is_content_type_allowed("application/octet-stream"));
+
+        assert!(is_content_type_pattern("*/*"));
+        assert!(is_content_type_pattern("application/vnd.ms-excel"));
+        assert!(!is_content_type_pattern("image"));
+        assert!(!is_content_type_pattern("*/png"));
+        assert!(!is_content_type_pattern("image/png; charset=utf-8"));
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The filesystem-specific configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct FilesystemBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The directory to store the files in. It is created if it does not exist.
+    ///
+    /// ## Example
+    /// ```text
+    /// /var/lib/yeet-yoink/files
+    /// ```
+    pub directory: PathBuf,
+    /// The priority of the backend during distribution. Backends with lower
+    /// values are served first. Defaults to `0`.
+    #[serde(default)]
+    pub priority: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_filesystem_config_works() {
+        let yaml = r#"
+            tag: fs-1
+            directory: "/var/lib/yeet-yoink/files"
+            priority: 5
+        "#;
+
+        let config: FilesystemBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize filesystem config");
+        assert_eq!(config.tag, "fs-1");
+        assert_eq!(config.directory, PathBuf::from("/var/lib/yeet-yoink/files"));
+        assert_eq!(config.priority, 5);
+    }
+}
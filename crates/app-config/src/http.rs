@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+/// The configuration of a backend forwarding files to another yeet-yoink instance.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct HttpBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The base URL of the upstream instance, i.e. the URL under which it serves
+    /// `/yeet` and `/yoink/:id`.
+    ///
+    /// ## Example
+    /// ```text
+    /// https://central.example.com
+    /// ```
+    pub base_url: String,
+    /// The bearer token to authenticate with the upstream instance, if it requires one.
+    pub token: Option<String>,
+    /// The priority of the backend during distribution. Backends with lower
+    /// values are served first. Defaults to `0`.
+    #[serde(default)]
+    pub priority: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_http_config_works() {
+        let yaml = r#"
+            tag: central-1
+            base_url: "https://central.example.com"
+            token: "change-me"
+            priority: 4
+        "#;
+
+        let config: HttpBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize HTTP config");
+        assert_eq!(config.tag, "central-1");
+        assert_eq!(config.base_url, "https://central.example.com");
+        assert_eq!(config.token.as_deref(), Some("change-me"));
+        assert_eq!(config.priority, 4);
+    }
+}
@@ -2,9 +2,40 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod auth;
+pub mod cors;
+pub mod distribution;
+pub mod downloads;
+pub mod errors;
+pub mod files;
+#[cfg(feature = "filesystem")]
+pub mod filesystem;
+#[cfg(feature = "http")]
+pub mod http;
 #[cfg(feature = "memcache")]
 pub mod memcache;
+pub mod metrics;
+pub mod proxies;
+pub mod receipts;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod shutdown;
+pub mod timeouts;
+pub mod validation;
 
+use crate::auth::AuthConfig;
+use crate::cors::CorsConfig;
+use crate::distribution::DistributionConfig;
+use crate::downloads::DownloadsConfig;
+use crate::errors::ErrorsConfig;
+use crate::files::FilesConfig;
+use crate::metrics::MetricsConfig;
+use crate::proxies::ProxiesConfig;
+use crate::receipts::ReceiptsConfig;
+use crate::shutdown::ShutdownConfig;
+use crate::timeouts::TimeoutsConfig;
 use clap::ArgMatches;
 use config::builder::DefaultState;
 use config::{ConfigBuilder, File, FileFormat};
@@ -17,8 +48,46 @@ use tracing::{error, info};
 pub struct AppConfig {
     /// The version of the configuration.
     version: u8,
+    /// The path prefix all routes are served under, e.g. `/api/v1` when sharing an
+    /// ingress with other services. Routes are served from the root by default.
+    #[serde(default)]
+    pub base_path: Option<String>,
     /// The backend-specific configuration.
+    #[serde(default)]
     pub backends: BackendsConfig,
+    /// The configuration of locally buffered files.
+    #[serde(default)]
+    pub files: FilesConfig,
+    /// The configuration of file distribution to the backends.
+    #[serde(default)]
+    pub distribution: DistributionConfig,
+    /// The configuration of file downloads.
+    #[serde(default)]
+    pub downloads: DownloadsConfig,
+    /// The configuration of distribution receipts.
+    #[serde(default)]
+    pub receipts: ReceiptsConfig,
+    /// The configuration of request authentication.
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// The configuration of cross-origin requests.
+    #[serde(default)]
+    pub cors: CorsConfig,
+    /// The configuration of trusted reverse proxies.
+    #[serde(default)]
+    pub proxies: ProxiesConfig,
+    /// The configuration of request timeouts.
+    #[serde(default)]
+    pub timeouts: TimeoutsConfig,
+    /// The configuration of the graceful shutdown.
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// The configuration of error responses.
+    #[serde(default)]
+    pub errors: ErrorsConfig,
+    /// The configuration of the Prometheus metrics.
+    #[serde(default)]
+    pub metrics: MetricsConfig,
 }
 
 /// Provides backend-specific configuration.
@@ -27,10 +96,40 @@ pub struct BackendsConfig {
     /// Provides Memcached specific configuration.
     #[cfg_attr(docsrs, doc(cfg(feature = "memcache")))]
     #[cfg(feature = "memcache")]
+    #[serde(default)]
     pub memcache: Vec<memcache::MemcacheBackendConfig>,
+    /// Provides filesystem specific configuration.
+    #[cfg_attr(docsrs, doc(cfg(feature = "filesystem")))]
+    #[cfg(feature = "filesystem")]
+    #[serde(default)]
+    pub filesystem: Vec<filesystem::FilesystemBackendConfig>,
+    /// Provides S3 specific configuration.
+    #[cfg_attr(docsrs, doc(cfg(feature = "s3")))]
+    #[cfg(feature = "s3")]
+    #[serde(default)]
+    pub s3: Vec<s3::S3BackendConfig>,
+    /// Provides configuration of upstream yeet-yoink instances.
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    #[cfg(feature = "http")]
+    #[serde(default)]
+    pub http: Vec<http::HttpBackendConfig>,
+    /// Provides Redis specific configuration.
+    #[cfg_attr(docsrs, doc(cfg(feature = "redis")))]
+    #[cfg(feature = "redis")]
+    #[serde(default)]
+    pub redis: Vec<redis::RedisBackendConfig>,
 }
 
 impl AppConfig {
+    /// Gets the path prefix all routes are served under, without a trailing slash,
+    /// or `None` if they are served from the root.
+    pub fn base_path(&self) -> Option<&str> {
+        self.base_path
+            .as_deref()
+            .map(|path| path.trim_end_matches('/'))
+            .filter(|path| !path.is_empty())
+    }
+
     pub fn load(config_dir: &Path, matches: &ArgMatches) -> Result<Self, anyhow::Error> {
         // TODO: Document configuration file locations
         let mut config_builder = ConfigBuilder::<DefaultState>::default();
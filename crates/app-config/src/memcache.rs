@@ -7,6 +7,10 @@ use url::Url;
 /// The default expiration time for Memcached entries.
 pub const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
 
+/// The default size of the chunks files are split into, leaving headroom below
+/// Memcached's default item size limit of 1 MiB.
+pub const DEFAULT_CHUNK_SIZE_BYTES: usize = 1_000_000;
+
 /// The Memcached-specific configuration.
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct MemcacheBackendConfig {
@@ -30,6 +34,25 @@ pub struct MemcacheBackendConfig {
     /// 300
     /// ```
     pub expiration_sec: Option<u32>,
+    /// The priority of the backend during distribution. Backends with lower
+    /// values are served first. Defaults to `0`.
+    #[serde(default)]
+    pub priority: u16,
+    /// The content type to report for files read back from Memcached, since
+    /// Memcached does not preserve it. If unset, no content type is reported.
+    pub fallback_content_type: Option<String>,
+    /// The maximum number of bytes stored per item. Larger files are split into
+    /// chunks of this size. Defaults to [`DEFAULT_CHUNK_SIZE_BYTES`].
+    ///
+    /// This must not exceed the item size limit of the Memcached server (`-I`).
+    pub chunk_size_bytes: Option<usize>,
+}
+
+impl MemcacheBackendConfig {
+    /// Gets the size of the chunks files are split into.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size_bytes.unwrap_or(DEFAULT_CHUNK_SIZE_BYTES)
+    }
 }
 
 /// A Memcached connection string.
@@ -120,6 +143,9 @@ mod tests {
             tag: memcache-1
             connection_string: "memcache://127.0.0.1:12345?timeout=10&tcp_nodelay=true"
             expiration_sec: 500
+            priority: 10
+            fallback_content_type: "application/octet-stream"
+            chunk_size_bytes: 524288
         "#;
 
         let config: MemcacheBackendConfig =
@@ -130,6 +156,12 @@ mod tests {
             "memcache://127.0.0.1:12345?timeout=10&tcp_nodelay=true"
         );
         assert_eq!(config.expiration_sec, Some(500));
+        assert_eq!(config.priority, 10);
+        assert_eq!(
+            config.fallback_content_type.as_deref(),
+            Some("application/octet-stream")
+        );
+        assert_eq!(config.chunk_size(), 524288);
     }
 
     #[test]
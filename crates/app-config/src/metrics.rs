@@ -0,0 +1,89 @@
+use crate::files::{content_type_essence, content_type_matches};
+use serde::{Deserialize, Serialize};
+
+/// The content type label of uploads not matching any tracked content type.
+pub const OTHER_CONTENT_TYPE: &str = "other";
+
+/// The content type label of uploads without a content type.
+pub const NO_CONTENT_TYPE: &str = "none";
+
+/// Configuration of the Prometheus metrics.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Whether HTTP metrics report status classes such as `2xx` rather than
+    /// exact status codes.
+    pub status_classes: bool,
+    /// Whether HTTP metrics report the route template (e.g. `/yoink/:id`) of a request
+    /// rather than the first path segment. Requests not matching any route are reported
+    /// as `unmatched`.
+    pub route_templates: bool,
+    /// The content types, e.g. `image/png` or `image/*`, by which successful uploads are
+    /// counted. Uploads of other types are counted as `other`; if empty, uploads are not
+    /// counted by content type.
+    pub content_types: Vec<String>,
+}
+
+impl MetricsConfig {
+    /// Determines whether successful uploads are counted by content type.
+    pub fn tracks_content_types(&self) -> bool {
+        !self.content_types.is_empty()
+    }
+
+    /// Gets the label an upload of the specified content type is counted under: the
+    /// first matching entry of [`content_types`](Self::content_types), `other` if none
+    /// matches, or `none` if the upload has no content type.
+    pub fn content_type_label(&self, content_type: Option<&str>) -> &str {
+        let Some(content_type) = content_type else {
+            return NO_CONTENT_TYPE;
+        };
+        let essence = content_type_essence(content_type);
+        self.content_types
+            .iter()
+            .find(|pattern| content_type_matches(pattern, &essence))
+            .map_or(OTHER_CONTENT_TYPE, |pattern| pattern.trim())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_metrics_config_works() {
+        let yaml = r#"
+            status_classes: true
+            route_templates: true
+            content_types: ["image/png", "text/*"]
+        "#;
+
+        let config: MetricsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize metrics config");
+        assert!(config.status_classes);
+        assert!(config.route_templates);
+        assert!(config.tracks_content_types());
+    }
+
+    #[test]
+    fn metrics_config_defaults_work() {
+        let config: MetricsConfig = serde_yaml::from_str("{}").unwrap();
+        assert!(!config.status_classes);
+        assert!(!config.route_templates);
+        assert!(!config.tracks_content_types());
+    }
+
+    #[test]
+    fn unknown_content_types_are_labeled_other() {
+        let config = MetricsConfig {
+            content_types: vec!["image/png".to_string(), "text/*".to_string()],
+            ..MetricsConfig::default()
+        };
+        assert_eq!(config.content_type_label(Some("IMAGE/PNG")), "image/png");
+        assert_eq!(
+            config.content_type_label(Some("text/plain; charset=utf-8")),
+            "text/*"
+        );
+        assert_eq!(config.content_type_label(Some("application/zip")), "other");
+        assert_eq!(config.content_type_label(None), "none");
+    }
+}
@@ -0,0 +1,63 @@
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Configuration of the reverse proxies, e.g. load balancers, in front of the server.
+///
+/// Requests from trusted proxies are attributed to the client named in their
+/// `Forwarded` or `X-Forwarded-For` header; these headers are ignored for all other peers.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxiesConfig {
+    /// The addresses or networks of the trusted proxies, e.g. `10.0.0.0/8` or `192.168.1.10`.
+    pub trusted: Vec<String>,
+}
+
+impl ProxiesConfig {
+    /// Gets the networks of the trusted proxies, skipping invalid entries.
+    pub fn trusted_networks(&self) -> Vec<IpNet> {
+        self.trusted
+            .iter()
+            .filter_map(|network| parse_network(network))
+            .collect()
+    }
+}
+
+/// Parses a network in CIDR notation, or a single address.
+pub(crate) fn parse_network(value: &str) -> Option<IpNet> {
+    value
+        .parse::<IpNet>()
+        .ok()
+        .or_else(|| value.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_proxies_config_works() {
+        let yaml = r#"
+            trusted:
+              - "10.0.0.0/8"
+              - "192.168.1.10"
+              - "fd00::/8"
+              - "proxy.local"
+        "#;
+
+        let config: ProxiesConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize proxies config");
+        let networks = config.trusted_networks();
+        assert_eq!(networks.len(), 3);
+        assert!(networks[0].contains(&"10.1.2.3".parse::<IpAddr>().unwrap()));
+        assert!(networks[1].contains(&"192.168.1.10".parse::<IpAddr>().unwrap()));
+        assert!(!networks[1].contains(&"192.168.1.11".parse::<IpAddr>().unwrap()));
+        assert!(networks[2].contains(&"fd12::1".parse::<IpAddr>().unwrap()));
+    }
+
+    #[test]
+    fn proxies_config_defaults_work() {
+        let config: ProxiesConfig = serde_yaml::from_str("{}").unwrap();
+        assert!(config.trusted_networks().is_empty());
+    }
+}
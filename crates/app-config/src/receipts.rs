@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default tolerance for clock differences when validating receipts.
+pub const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+/// Configuration of distribution receipts.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiptsConfig {
+    /// The secret used to sign receipts using HMAC-SHA256.
+    /// If unset, receipts are returned unsigned.
+    pub signing_key: Option<String>,
+    /// The number of seconds by which the clocks of clients and the server may differ
+    /// when validating the timestamps of receipts. Defaults to [`DEFAULT_MAX_CLOCK_SKEW`].
+    pub max_clock_skew_sec: Option<u64>,
+}
+
+impl ReceiptsConfig {
+    /// Gets the tolerated difference between client and server clocks.
+    pub fn max_clock_skew(&self) -> Duration {
+        self.max_clock_skew_sec
+            .map_or(DEFAULT_MAX_CLOCK_SKEW, Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_receipts_config_works() {
+        let yaml = r#"
+            signing_key: "s3cr3t"
+            max_clock_skew_sec: 5
+        "#;
+
+        let config: ReceiptsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize receipts config");
+        assert_eq!(config.signing_key.as_deref(), Some("s3cr3t"));
+        assert_eq!(config.max_clock_skew(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn receipts_config_defaults_work() {
+        let config: ReceiptsConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize receipts config");
+        assert_eq!(config.signing_key, None);
+        assert_eq!(config.max_clock_skew(), DEFAULT_MAX_CLOCK_SKEW);
+    }
+}
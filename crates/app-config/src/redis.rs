@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+/// The default size of the chunks files are split into, keeping individual
+/// commands short such that they do not block the Redis server for long.
+pub const DEFAULT_CHUNK_SIZE_BYTES: usize = 4 * 1024 * 1024;
+
+/// The Redis-specific configuration.
+///
+/// Files are stored with an expiry matching their temporal lease, such that several
+/// instances sharing a Redis server can serve each other's files while they are live.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct RedisBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The URL of the Redis server.
+    ///
+    /// ## Example
+    /// ```text
+    /// redis://:password@127.0.0.1:6379/0
+    /// ```
+    pub connection_string: String,
+    /// The priority of the backend during distribution. Backends with lower
+    /// values are served first. Defaults to `0`.
+    #[serde(default)]
+    pub priority: u16,
+    /// The maximum number of bytes stored per key. Larger files are split into
+    /// chunks of this size. Defaults to [`DEFAULT_CHUNK_SIZE_BYTES`].
+    pub chunk_size_bytes: Option<usize>,
+}
+
+impl RedisBackendConfig {
+    /// Gets the size of the chunks files are split into.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size_bytes.unwrap_or(DEFAULT_CHUNK_SIZE_BYTES)
+    }
+
+    /// Determines whether the connection string is a Redis URL.
+    pub fn is_redis_url(&self) -> bool {
+        matches!(
+            url::Url::parse(&self.connection_string)
+                .as_ref()
+                .map(url::Url::scheme),
+            Ok("redis" | "redis+unix" | "unix")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_redis_config_works() {
+        let yaml = r#"
+            tag: redis-1
+            connection_string: "redis://127.0.0.1:6379/0"
+            priority: 10
+            chunk_size_bytes: 524288
+        "#;
+
+        let config: RedisBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize Redis config");
+        assert_eq!(config.tag, "redis-1");
+        assert_eq!(config.connection_string, "redis://127.0.0.1:6379/0");
+        assert!(config.is_redis_url());
+        assert_eq!(config.priority, 10);
+        assert_eq!(config.chunk_size(), 524288);
+    }
+
+    #[test]
+    fn redis_config_defaults_work() {
+        let yaml = r#"
+            tag: redis-1
+            connection_string: "memcache://127.0.0.1:11211"
+        "#;
+
+        let config: RedisBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize Redis config");
+        assert!(!config.is_redis_url());
+        assert_eq!(config.priority, 0);
+        assert_eq!(config.chunk_size(), DEFAULT_CHUNK_SIZE_BYTES);
+    }
+}
@@ -0,0 +1,82 @@
+use serde::{Deserialize, Serialize};
+
+/// The default size of the parts of multipart uploads.
+pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// The minimum size of the parts of multipart uploads, as mandated by S3.
+pub const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+
+/// The S3-specific configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct S3BackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The name of the bucket to store the files in.
+    pub bucket: String,
+    /// The region of the bucket, e.g. `us-east-1`.
+    pub region: String,
+    /// A custom endpoint for S3-compatible services, e.g. MinIO.
+    ///
+    /// ## Example
+    /// ```text
+    /// http://127.0.0.1:9000
+    /// ```
+    pub endpoint: Option<String>,
+    /// The access key. If unset, credentials are taken from the environment
+    /// (`AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`) or the AWS profile.
+    pub access_key: Option<String>,
+    /// The secret key.
+    pub secret_key: Option<String>,
+    /// Whether to use path-style requests (`endpoint/bucket/key`), as commonly
+    /// required by MinIO. Defaults to virtual-hosted-style requests.
+    #[serde(default)]
+    pub path_style: bool,
+    /// The size of the parts of multipart uploads, in bytes. Files smaller than this
+    /// are uploaded in a single request. Defaults to [`DEFAULT_PART_SIZE`] and is
+    /// raised to at least [`MIN_PART_SIZE`].
+    pub part_size_bytes: Option<usize>,
+    /// The priority of the backend during distribution. Backends with lower
+    /// values are served first. Defaults to `0`.
+    #[serde(default)]
+    pub priority: u16,
+}
+
+impl S3BackendConfig {
+    /// Gets the size of the parts of multipart uploads.
+    pub fn part_size(&self) -> usize {
+        self.part_size_bytes
+            .unwrap_or(DEFAULT_PART_SIZE)
+            .max(MIN_PART_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_s3_config_works() {
+        let yaml = r#"
+            tag: minio-1
+            bucket: yeet-yoink
+            region: us-east-1
+            endpoint: "http://127.0.0.1:9000"
+            access_key: minioadmin
+            secret_key: minioadmin
+            path_style: true
+            part_size_bytes: 1024
+            priority: 3
+        "#;
+
+        let config: S3BackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize S3 config");
+        assert_eq!(config.tag, "minio-1");
+        assert_eq!(config.bucket, "yeet-yoink");
+        assert_eq!(config.region, "us-east-1");
+        assert_eq!(config.endpoint.as_deref(), Some("http://127.0.0.1:9000"));
+        assert_eq!(config.access_key.as_deref(), Some("minioadmin"));
+        assert!(config.path_style);
+        assert_eq!(config.part_size(), MIN_PART_SIZE);
+        assert_eq!(config.priority, 3);
+    }
+}
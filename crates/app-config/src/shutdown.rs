@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default time to wait for in-flight downloads when shutting down.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configuration of the graceful shutdown.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// The maximum number of seconds to wait for in-flight downloads to finish after a
+    /// termination signal before the servers are stopped. Defaults to [`DEFAULT_DRAIN_TIMEOUT`].
+    pub drain_timeout_sec: Option<u64>,
+}
+
+impl ShutdownConfig {
+    /// Gets the maximum time to wait for in-flight downloads when shutting down.
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout_sec
+            .map_or(DEFAULT_DRAIN_TIMEOUT, Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_shutdown_config_works() {
+        let yaml = r#"
+            drain_timeout_sec: 120
+        "#;
+
+        let config: ShutdownConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize shutdown config");
+        assert_eq!(config.drain_timeout(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn shutdown_config_defaults_work() {
+        let config: ShutdownConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize shutdown config");
+        assert_eq!(config.drain_timeout(), DEFAULT_DRAIN_TIMEOUT);
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The default time to wait for a backend to respond to a health check.
+pub const DEFAULT_BACKEND_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The default time after which idle resumable upload sessions are discarded.
+pub const DEFAULT_RESUMABLE_SESSION_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Configuration of request timeouts.
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutsConfig {
+    /// The number of seconds a request handler may take to produce a response,
+    /// including any time spent waiting on backends. If unset, handlers are not cut off.
+    pub handler_sec: Option<u64>,
+    /// Overrides [`handler_sec`](Self::handler_sec) per route, keyed by the route's base path
+    /// (e.g. `/yoink`). A value of `0` disables the timeout for the route.
+    pub routes: HashMap<String, u64>,
+    /// The number of milliseconds to wait for each backend to respond to a health check
+    /// before it is considered unhealthy. Defaults to [`DEFAULT_BACKEND_HEALTH_CHECK_TIMEOUT`].
+    pub backend_health_check_ms: Option<u64>,
+    /// The number of seconds an upload may go without receiving data before it is aborted.
+    /// If unset, idle uploads are not cut off.
+    pub upload_idle_sec: Option<u64>,
+    /// The number of seconds an upload may take to receive its body before it is aborted.
+    /// If unset, uploads may take arbitrarily long.
+    pub upload_sec: Option<u64>,
+    /// The number of seconds a resumable upload session may go without receiving a chunk
+    /// before it is discarded. Defaults to [`DEFAULT_RESUMABLE_SESSION_TIMEOUT`].
+    pub resumable_session_sec: Option<u64>,
+}
+
+impl TimeoutsConfig {
+    /// Gets the handler timeout for the specified route base path, e.g. `/yoink`.
+    pub fn handler_timeout(&self, route: &str) -> Option<Duration> {
+        match self.routes.get(route).copied().or(self.handler_sec) {
+            None | Some(0) => None,
+            Some(seconds) => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    /// Gets the time to wait for each backend to respond to a health check.
+    pub fn backend_health_check_timeout(&self) -> Duration {
+        self.backend_health_check_ms
+            .map_or(DEFAULT_BACKEND_HEALTH_CHECK_TIMEOUT, Duration::from_millis)
+    }
+
+    /// Gets the time an upload may go without receiving data.
+    pub fn upload_idle_timeout(&self) -> Option<Duration> {
+        self.upload_idle_sec.map(Duration::from_secs)
+    }
+
+    /// Gets the time an upload may take to receive its body.
+    pub fn upload_timeout(&self) -> Option<Duration> {
+        self.upload_sec.map(Duration::from_secs)
+    }
+
+    /// Gets the time a resumable upload session may go without receiving a chunk.
+    pub fn resumable_session_timeout(&self) -> Duration {
+        self.resumable_session_sec
+            .map_or(DEFAULT_RESUMABLE_SESSION_TIMEOUT, Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_timeouts_config_works() {
+        let yaml = r#"
+            handler_sec: 30
+            routes:
+              /yoink: 10
+              /yeet: 0
+            backend_health_check_ms: 500
+            upload_idle_sec: 15
+            upload_sec: 600
+            resumable_session_sec: 900
+        "#;
+
+        let config: TimeoutsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize timeouts config");
+        assert_eq!(
+            config.handler_timeout("/yoink"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(config.handler_timeout("/yeet"), None);
+        assert_eq!(
+            config.handler_timeout("/health"),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            config.backend_health_check_timeout(),
+            Duration::from_millis(500)
+        );
+        assert_eq!(config.upload_idle_timeout(), Some(Duration::from_secs(15)));
+        assert_eq!(config.upload_timeout(), Some(Duration::from_secs(600)));
+        assert_eq!(config.resumable_session_timeout(), Duration::from_secs(900));
+    }
+
+    #[test]
+    fn timeouts_config_defaults_work() {
+        let config: TimeoutsConfig =
+            serde_yaml::from_str("{}").expect("Failed to deserialize timeouts config");
+        assert_eq!(config.handler_timeout("/yoink"), None);
+        assert_eq!(
+            config.backend_health_check_timeout(),
+            DEFAULT_BACKEND_HEALTH_CHECK_TIMEOUT
+        );
+        assert_eq!(config.upload_idle_timeout(), None);
+        assert_eq!(config.upload_timeout(), None);
+        assert_eq!(
+            config.resumable_session_timeout(),
+            DEFAULT_RESUMABLE_SESSION_TIMEOUT
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! Contains the validation of the configuration as a whole, see [`AppConfig::validate`].
+
+use crate::files::is_content_type_pattern;
+use crate::proxies::parse_network;
+use crate::AppConfig;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+/// A problem with a configuration value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigIssue {
+    /// The path of the value, e.g. `backends.s3[0].bucket`.
+    pub path: String,
+    /// The description of the problem.
+    pub message: String,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// The configuration is invalid.
+#[derive(Debug, thiserror::Error)]
+pub struct ValidationError {
+    /// The problems found, in the order of the configuration sections.
+    pub issues: Vec<ConfigIssue>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The configuration has {count} problem{plural}",
+            count = self.issues.len(),
+            plural = if self.issues.len() == 1 { "" } else { "s" }
+        )?;
+        for issue in &self.issues {
+            write!(f, "\n  - {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the problems found during validation.
+#[derive(Default)]
+struct Issues(Vec<ConfigIssue>);
+
+impl Issues {
+    /// Records a problem unless the check holds.
+    fn check<P, M>(&mut self, holds: bool, path: P, message: M)
+    where
+        P: Into<String>,
+        M: Into<String>,
+    {
+        if !holds {
+            self.0.push(ConfigIssue {
+                path: path.into(),
+                message: message.into(),
+            });
+        }
+    }
+}
+
+impl AppConfig {
+    /// Validates the configuration as a whole, reporting all problems at once.
+    ///
+    /// This covers values that are individually well-formed but unusable, such as
+    /// backends missing required settings, duplicate backend tags and numeric values
+    /// outside their sensible range.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut issues = Issues::default();
+        self.validate_base_path(&mut issues);
+        self.validate_files(&mut issues);
+        self.validate_distribution(&mut issues);
+        self.validate_downloads(&mut issues);
+        self.validate_auth(&mut issues);
+        self.validate_cors(&mut issues);
+        self.validate_proxies(&mut issues);
+        self.validate_metrics(&mut issues);
+        self.validate_backends(&mut issues);
+
+        if issues.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { issues: issues.0 })
+        }
+    }
+
+    fn validate_base_path(&self, issues: &mut Issues) {
+        // Route parameters and wildcards would swallow parts of the request paths.
+        if let Some(path) = &self.base_path {
+            issues.check(
+                path.starts_with('/') && !path.contains(['*', ':', '?', '#']),
+                "base_path",
+                "must be an absolute path such as /api/v1, without parameters or wildcards",
+            );
+        }
+    }
+
+    fn validate_files(&self, issues: &mut Issues) {
+        let files = &self.files;
+        issues.check(
+            files.lease_sec != Some(0),
+            "files.lease_sec",
+            "must be at least 1",
+        );
+        issues.check(
+            files.max_lease_sec != Some(0),
+            "files.max_lease_sec",
+            "must be at least 1",
+        );
+        issues.check(
+            files.lease() <= files.max_lease(),
+            "files.lease_sec",
+            format!(
+                "must not exceed files.max_lease_sec ({} seconds)",
+                files.max_lease().as_secs()
+            ),
+        );
+        issues.check(
+            files.max_storage_bytes != Some(0),
+            "files.max_storage_bytes",
+            "must be at least 1",
+        );
+        if let (Some(high_water), Some(capacity)) =
+            (files.storage_high_water_bytes, files.max_storage_bytes)
+        {
+            issues.check(
+                high_water <= capacity,
+                "files.storage_high_water_bytes",
+                format!("must not exceed files.max_storage_bytes ({capacity})"),
+            );
+        }
+        issues.check(
+            files.max_concurrent_uploads != Some(0),
+            "files.max_concurrent_uploads",
+            "must be at least 1",
+        );
+
+        let patterns = [
+            ("allowed_content_types", &files.allowed_content_types),
+            ("denied_content_types", &files.denied_content_types),
+        ];
+        for (name, patterns) in patterns {
+            for (index, pattern) in patterns.iter().enumerate() {
+                issues.check(
+                    is_content_type_pattern(pattern),
+                    format!("files.{name}[{index}]"),
+                    "must be a content type such as image/png, image/* or */*",
+                );
+            }
+        }
+    }
+
+    fn validate_distribution(&self, issues: &mut Issues) {
+        let distribution = &self.distribution;
+        issues.check(
+            distribution.event_buffer_size != Some(0),
+            "distribution.event_buffer_size",
+            "must be at least 1",
+        );
+        issues.check(
+            distribution.retry.max_attempts != Some(0),
+            "distribution.retry.max_attempts",
+            "must be at least 1",
+        );
+        issues.check(
+            distribution.retry.initial_backoff() <= distribution.retry.max_backoff(),
+            "distribution.retry.initial_backoff_ms",
+            "must not exceed distribution.retry.max_backoff_ms",
+        );
+    }
+
+    fn validate_downloads(&self, issues: &mut Issues) {
+        let downloads = &self.downloads;
+        issues.check(
+            downloads.rate_limit_bytes_per_sec != Some(0),
+            "downloads.rate_limit_bytes_per_sec",
+            "must be at least 1",
+        );
+        issues.check(
+            downloads.max_rate_limit_bytes_per_sec != Some(0),
+            "downloads.max_rate_limit_bytes_per_sec",
+            "must be at least 1",
+        );
+        issues.check(
+            downloads.fallback.max_attempts != Some(0),
+            "downloads.fallback.max_attempts",
+            "must be at least 1",
+        );
+        issues.check(
+            downloads.fallback.backend_timeout_ms != Some(0),
+            "downloads.fallback.backend_timeout_ms",
+            "must be at least 1",
+        );
+        issues.check(
+            downloads.fallback.deadline_ms != Some(0),
+            "downloads.fallback.deadline_ms",
+            "must be at least 1",
+        );
+    }
+
+    fn validate_auth(&self, issues: &mut Issues) {
+        let mut tokens = HashSet::new();
+        for (index, token) in self.auth.tokens.iter().enumerate() {
+            let path = format!("auth.tokens[{index}]");
+            issues.check(!token.token.is_empty(), &path, "must not be empty");
+            issues.check(
+                tokens.insert(token.token.as_str()),
+                &path,
+                "duplicates another token",
+            );
+        }
+
+        // Admin tokens must not double as file tokens, which clients get to see.
+        let mut admin_tokens = HashSet::new();
+        for (index, token) in self.auth.admin_tokens.iter().enumerate() {
+            let path = format!("auth.admin_tokens[{index}]");
+            issues.check(!token.is_empty(), &path, "must not be empty");
+            issues.check(
+                admin_tokens.insert(token.as_str()) && !tokens.contains(token.as_str()),
+                &path,
+                "duplicates another token",
+            );
+        }
+    }
+
+    fn validate_cors(&self, issues: &mut Issues) {
+        // Browsers send the bare origin, so anything else would never match.
+        for (index, origin) in self.cors.allowed_origins.iter().enumerate() {
+            let is_origin = match url::Url::parse(origin) {
+                Ok(url) => {
+                    matches!(url.scheme(), "http" | "https")
+                        && url.origin().ascii_serialization() == *origin
+                }
+                Err(_) => false,
+            };
+            issues.check(
+                is_origin,
+                format!("cors.allowed_origins[{index}]"),
+                "must be an origin such as https://app.example.com, without a path or trailing slash",
+            );
+        }
+    }
+
+    fn validate_proxies(&self, issues: &mut Issues) {
+        for (index, network) in self.proxies.trusted.iter().enumerate() {
+            issues.check(
+                parse_network(network).is_some(),
+                format!("proxies.trusted[{index}]"),
+                "must be an IP address or a network such as 10.0.0.0/8",
+            );
+        }
+    }
+
+    fn validate_metrics(&self, issues: &mut Issues) {
+        for (index, pattern) in self.metrics.content_types.iter().enumerate() {
+            issues.check(
+                is_content_type_pattern(pattern),
+                format!("metrics.content_types[{index}]"),
+                "must be a content type such as image/png, image/* or */*",
+            );
+        }
+    }
+
+    fn validate_backends(&self, issues: &mut Issues) {
+        // The tags of the backends identify them in logs, metrics and receipts.
+        #[allow(unused_mut)]
+        let mut tags: Vec<(String, &str)> = Vec::new();
+
+        #[cfg(feature = "memcache")]
+        for (index, backend) in self.backends.memcache.iter().enumerate() {
+            let path = format!("backends.memcache[{index}]");
+            issues.check(
+                !backend.connection_string.get_urls().is_empty(),
+                format!("{path}.connection_string"),
+                "is required",
+            );
+            issues.check(
+                backend.chunk_size_bytes != Some(0),
+                format!("{path}.chunk_size_bytes"),
+                "must be at least 1",
+            );
+            tags.push((path, &backend.tag));
+        }
+
+        #[cfg(feature = "filesystem")]
+        for (index, backend) in self.backends.filesystem.iter().enumerate() {
+            let path = format!("backends.filesystem[{index}]");
+            issues.check(
+                !backend.directory.as_os_str().is_empty(),
+                format!("{path}.directory"),
+                "is required",
+            );
+            tags.push((path, &backend.tag));
+        }
+
+        #[cfg(feature = "s3")]
+        for (index, backend) in self.backends.s3.iter().enumerate() {
+            let path = format!("backends.s3[{index}]");
+            issues.check(
+                !backend.bucket.is_empty(),
+                format!("{path}.bucket"),
+                "is required",
+            );
+            issues.check(
+                !backend.region.is_empty(),
+                format!("{path}.region"),
+                "is required",
+            );
+            issues.check(
+                backend.access_key.is_some() == backend.secret_key.is_some(),
+                format!("{path}.secret_key"),
+                "must be set together with access_key",
+            );
+            tags.push((path, &backend.tag));
+        }
+
+        #[cfg(feature = "http")]
+        for (index, backend) in self.backends.http.iter().enumerate() {
+            let path = format!("backends.http[{index}]");
+            let is_http_url = matches!(
+                url::Url::parse(&backend.base_url)
+                    .as_ref()
+                    .map(url::Url::scheme),
+                Ok("http" | "https")
+            );
+            issues.check(
+                is_http_url,
+                format!("{path}.base_url"),
+                "must be an http or https URL",
+            );
+            tags.push((path, &backend.tag));
+        }
+
+        #[cfg(feature = "redis")]
+        for (index, backend) in self.backends.redis.iter().enumerate() {
+            let path = format!("backends.redis[{index}]");
+            issues.check(
+                backend.is_redis_url(),
+                format!("{path}.connection_string"),
+                "must be a redis:// URL",
+            );
+            issues.check(
+                backend.chunk_size_bytes != Some(0),
+                format!("{path}.chunk_size_bytes"),
+                "must be at least 1",
+            );
+            tags.push((path, &backend.tag));
+        }
+
+        let mut seen: HashMap<&str, &str> = HashMap::new();
+        for (path, tag) in &tags {
+            issues.check(!tag.is_empty(), format!("{path}.tag"), "is required");
+            if tag.is_empty() {
+                continue;
+            }
+            if let Some(first) = seen.get(tag) {
+                issues.check(
+                    false,
+                    format!("{path}.tag"),
+                    format!("\"{tag}\" is already used by {first}"),
+                );
+            } else {
+                seen.insert(tag, path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issues(yaml: &str) -> Vec<String> {
+        let config: AppConfig = serde_yaml::from_str(yaml).expect("Failed to deserialize config");
+        match config.validate() {
+            Ok(()) => Vec::new(),
+            Err(e) => e.issues.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(issues("version: 0").is_empty());
+    }
+
+    #[test]
+    fn numeric_ranges_are_validated() {
+        let issues = issues(
+            r#"
+            version: 0
+            files:
+              lease_sec: 7200
+              max_lease_sec: 3600
+              max_storage_bytes: 100
+              storage_high_water_bytes: 200
+              max_concurrent_uploads: 0
+            distribution:
+              event_buffer_size: 0
+              retry:
+                max_attempts: 0
+            "#,
+        );
+        assert_eq!(
+            issues,
+            [
+                "files.lease_sec: must not exceed files.max_lease_sec (3600 seconds)",
+                "files.storage_high_water_bytes: must not exceed files.max_storage_bytes (100)",
+                "files.max_concurrent_uploads: must be at least 1",
+                "distribution.event_buffer_size: must be at least 1",
+                "distribution.retry.max_attempts: must be at least 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn base_paths_are_validated() {
+        assert!(issues("version: 0\nbase_path: /api/v1/").is_empty());
+        assert_eq!(
+            issues("version: 0\nbase_path: api/:version"),
+            ["base_path: must be an absolute path such as /api/v1, without parameters or wildcards"]
+        );
+    }
+
+    #[test]
+    fn content_type_patterns_are_validated() {
+        let issues = issues(
+            r#"
+            version: 0
+            files:
+              allowed_content_types: ["image/*", "text"]
+              denied_content_types: ["*/svg+xml"]
+            metrics:
+              content_types: ["image/png", "png"]
+            "#,
+        );
+        assert_eq!(
+            issues,
+            [
+                "files.allowed_content_types[1]: must be a content type such as image/png, image/* or */*",
+                "files.denied_content_types[0]: must be a content type such as image/png, image/* or */*",
+                "metrics.content_types[1]: must be a content type such as image/png, image/* or */*",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_tokens_are_rejected() {
+        let issues = issues(
+            r#"
+            version: 0
+            auth:
+              tokens:
+                - "change-me"
+                - ""
+                - "change-me"
+              admin_tokens:
+                - "change-me"
+                - "admin"
+            "#,
+        );
+        assert_eq!(
+            issues,
+            [
+                "auth.tokens[1]: must not be empty",
+                "auth.tokens[2]: duplicates another token",
+                "auth.admin_tokens[0]: duplicates another token",
+            ]
+        );
+    }
+
+    #[test]
+    fn cors_origins_are_validated() {
+        let issues = issues(
+            r#"
+            version: 0
+            cors:
+              allowed_origins:
+                - "https://app.example.com"
+                - "http://localhost:3000"
+                - "https://app.example.com/"
+                - "app.example.com"
+            "#,
+        );
+        assert_eq!(issues.len(), 2);
+        assert!(issues[0].starts_with("cors.allowed_origins[2]: must be an origin"));
+        assert!(issues[1].starts_with("cors.allowed_origins[3]: must be an origin"));
+    }
+
+    #[test]
+    fn trusted_proxies_are_validated() {
+        let issues = issues(
+            r#"
+            version: 0
+            proxies:
+              trusted:
+                - "10.0.0.0/8"
+                - "::1"
+                - "10.0.0.0/33"
+                - "proxy.local"
+            "#,
+        );
+        assert_eq!(
+            issues,
+            [
+                "proxies.trusted[2]: must be an IP address or a network such as 10.0.0.0/8",
+                "proxies.trusted[3]: must be an IP address or a network such as 10.0.0.0/8",
+            ]
+        );
+    }
+
+    #[cfg(all(feature = "filesystem", feature = "s3"))]
+    #[test]
+    fn backends_are_validated() {
+        let issues = issues(
+            r#"
+            version: 0
+            backends:
+              filesystem:
+                - tag: "shared"
+                  directory: "/var/lib/yeet-yoink/files"
+              s3:
+                - tag: "shared"
+                  bucket: ""
+                  region: "us-east-1"
+                  access_key: "minioadmin"
+                - tag: ""
+                  bucket: "yeet-yoink"
+                  region: "us-east-1"
+            "#,
+        );
+        assert_eq!(
+            issues,
+            [
+                "backends.s3[0].bucket: is required",
+                "backends.s3[0].secret_key: must be set together with access_key",
+                "backends.s3[0].tag: \"shared\" is already used by backends.filesystem[0]",
+                "backends.s3[1].tag: is required",
+            ]
+        );
+    }
+
+    #[test]
+    fn errors_list_all_issues() {
+        let error = ValidationError {
+            issues: vec![ConfigIssue {
+                path: "files.lease_sec".to_string(),
+                message: "must be at least 1".to_string(),
+            }],
+        };
+        assert_eq!(
+            error.to_string(),
+            "The configuration has 1 problem\n  - files.lease_sec: must be at least 1"
+        );
+    }
+}
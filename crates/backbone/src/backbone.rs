@@ -1,26 +1,36 @@
-use crate::file_reader::FileReader;
+use crate::expiry_queue::ExpiryQueue;
+use crate::file_index::{FileIndex, IndexedFile};
+use crate::file_reader::{FileReader, ReaderSource};
 use crate::file_record::FileRecord;
 use crate::file_writer::FileWriter;
 use crate::file_writer_guard::FileWriterGuard;
-use async_tempfile::TempFile;
+use crate::hash_index::HashIndex;
+use crate::storage_quota::{HighWaterMarkExceeded, InsufficientStorage, StorageQuota};
+use crate::tombstones::Tombstones;
+use crate::upload_progress::{ProgressTracker, UploadProgress};
+use crate::upload_session::{UploadSession, UploadSessionOptions, UploadSessions};
+use async_tempfile::{Ownership, TempFile};
 use axum::headers::ContentType;
-use backend_traits::{BackendCommand, BackendCommandSender};
+use backend_traits::{
+    BackendCommand, BackendCommandReserveError, BackendCommandSender, BackendHealth,
+    DistributionOutcome,
+};
+use file_distribution::metadata::ItemMetadata;
 use file_distribution::{BoxedFileReader, GetFileReaderError, WriteSummary};
+use metrics::backbone::{BackboneMetrics, RemovalReason};
 use rendezvous::RendezvousGuard;
 use shared_files::{SharedFileWriter, SharedTemporaryFile};
 use shortguid::ShortGuid;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{mpsc, oneshot, RwLock};
 use tokio::task::JoinHandle;
 use tokio::time::Instant;
-use tracing::info;
-
-/// The duration for which to keep each file alive.
-pub const TEMPORAL_LEASE: Duration = Duration::from_secs(5 * 60);
+use tracing::{debug, info, warn, Span};
 
 /// A local file distribution manager.
 ///
@@ -29,30 +39,310 @@ pub struct Backbone {
     inner: Arc<RwLock<Inner>>,
     sender: Sender<BackboneCommand>,
     loop_handle: JoinHandle<()>,
+    /// The sender used to communicate with the backends.
+    backend_sender: BackendCommandSender,
+    /// The duration for which to keep each file alive, unless specified otherwise.
+    temporal_lease: Duration,
+    /// The maximum size of files served from a shared read pass; `None` if disabled.
+    broadcast_max_bytes: Option<u64>,
+    /// The storage space available to buffered files; `None` if neither a capacity
+    /// nor a high-water mark is set.
+    storage_quota: Option<Arc<StorageQuota>>,
+    /// The index used to deduplicate files; `None` if disabled.
+    hash_index: Option<Arc<HashIndex>>,
+    /// The time to wait for the backends to accept a new file; `None` to wait indefinitely.
+    enqueue_timeout: Option<Duration>,
+    /// The directory files are buffered in; `None` for the system's temporary directory.
+    temp_dir: Option<PathBuf>,
+    /// The memory-backed directory small files are buffered in; `None` if disabled.
+    in_memory: Option<InMemoryBuffer>,
+    /// The index persisting files across restarts; `None` if disabled.
+    file_index: Option<Arc<FileIndex>>,
+    /// The sessions of resumable uploads.
+    upload_sessions: UploadSessions,
+    /// The guard forked for files whose removal waits for their readers.
+    cleanup_rendezvous: RendezvousGuard,
+}
+
+/// A memory-backed directory, e.g. `/dev/shm`, for buffering small files.
+#[derive(Debug)]
+struct InMemoryBuffer {
+    /// The directory the files are created in.
+    dir: PathBuf,
+    /// The maximum announced size of the files.
+    max_bytes: u64,
+}
+
+/// A completely written file that is still available.
+#[derive(Debug, Clone)]
+pub struct ExistingFile {
+    /// The ID of the file.
+    pub id: ShortGuid,
+    /// The write summary of the file.
+    pub summary: Arc<WriteSummary>,
+    /// The content type specified when the file was created.
+    pub content_type: Option<ContentType>,
+    /// The time after which the file will be inaccessible.
+    pub expires: Instant,
+}
+
+/// A snapshot of a locally buffered file, see [`Backbone::list_files`].
+#[derive(Debug, Clone)]
+pub struct LiveFile {
+    /// The ID of the file.
+    pub id: ShortGuid,
+    /// The name of the file, if known.
+    pub file_name: Option<String>,
+    /// The content type specified when the file was created.
+    pub content_type: Option<ContentType>,
+    /// The size of the file in bytes, or `None` if it is still being written.
+    pub file_size_bytes: Option<u64>,
+    /// The time the file was created.
+    pub created: Instant,
+    /// The time after which the file will be inaccessible.
+    pub expires: Instant,
 }
 
 struct Inner {
     open: HashMap<ShortGuid, FileRecord>,
+    /// The IDs of recently expired files.
+    tombstones: Tombstones,
+}
+
+impl Inner {
+    /// Gets the error for a file that is not buffered locally, telling files that
+    /// expired recently apart from unknown ones.
+    fn missing_file(&self, id: ShortGuid) -> GetFileReaderError {
+        if self.tombstones.contains(id, Instant::now()) {
+            GetFileReaderError::FileExpired(id)
+        } else {
+            GetFileReaderError::UnknownFile(id)
+        }
+    }
 }
 
 impl Backbone {
-    pub fn new(backend_sender: BackendCommandSender, cleanup_rendezvous: RendezvousGuard) -> Self {
+    pub fn new(
+        backend_sender: BackendCommandSender,
+        cleanup_rendezvous: RendezvousGuard,
+        temporal_lease: Duration,
+    ) -> Self {
         let (sender, receiver) = mpsc::channel(1024);
         let inner = Arc::new(RwLock::new(Inner {
             open: HashMap::default(),
+            tombstones: Tombstones::default(),
         }));
 
         let loop_handle = tokio::spawn(Self::command_loop(
             inner.clone(),
             receiver,
-            backend_sender,
-            cleanup_rendezvous,
+            backend_sender.clone(),
+            cleanup_rendezvous.fork(),
         ));
         Self {
             inner,
             sender,
             loop_handle,
+            backend_sender,
+            temporal_lease,
+            broadcast_max_bytes: None,
+            storage_quota: None,
+            hash_index: None,
+            enqueue_timeout: None,
+            temp_dir: None,
+            in_memory: None,
+            file_index: None,
+            upload_sessions: UploadSessions::default(),
+            cleanup_rendezvous,
+        }
+    }
+
+    /// Serves concurrent downloads of completely written files of up to `max_bytes`
+    /// from a single shared read pass rather than reading the file once per client.
+    ///
+    /// ## Remarks
+    ///
+    /// The content of a broadcast file is held in memory while it is being read.
+    pub fn with_broadcast_reads(mut self, max_bytes: u64) -> Self {
+        self.broadcast_max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Limits the storage space used by buffered files to `capacity_bytes`.
+    ///
+    /// Uploads announcing their size are rejected up front if they do not fit into the
+    /// remaining headroom; other uploads fail as soon as they exceed it.
+    pub fn with_storage_quota(self, capacity_bytes: u64) -> Self {
+        self.with_storage_limits(Some(capacity_bytes), None)
+    }
+
+    /// Rejects new files while the storage used by buffered files is at or above
+    /// `high_water_bytes`, until expiring leases free enough space.
+    ///
+    /// Files already being written are not affected.
+    pub fn with_storage_high_water_mark(self, high_water_bytes: u64) -> Self {
+        self.with_storage_limits(None, Some(high_water_bytes))
+    }
+
+    /// Applies the storage limits, retaining limits that were configured before.
+    fn with_storage_limits(
+        mut self,
+        capacity_bytes: Option<u64>,
+        high_water_bytes: Option<u64>,
+    ) -> Self {
+        let (capacity, high_water_mark) = match self.storage_quota.take() {
+            Some(quota) => (
+                capacity_bytes.or(quota.capacity),
+                high_water_bytes.or(quota.high_water_mark),
+            ),
+            None => (capacity_bytes, high_water_bytes),
+        };
+        self.storage_quota = Some(Arc::new(StorageQuota::new(capacity, high_water_mark)));
+        self
+    }
+
+    /// Deduplicates uploads by their SHA-256 hash and by client-supplied idempotency keys.
+    ///
+    /// See [`FileWriterGuard::finalize_deduplicated`] and [`Backbone::find_by_idempotency_key`].
+    pub fn with_deduplication(mut self) -> Self {
+        self.hash_index = Some(Arc::default());
+        self
+    }
+
+    /// Rejects new files if the backends do not accept them within `timeout`, e.g. because
+    /// their command queue is full, rather than waiting for the queue to drain.
+    ///
+    /// See [`NewFileError::BackendsBusy`].
+    pub fn with_enqueue_timeout(mut self, timeout: Duration) -> Self {
+        self.enqueue_timeout = Some(timeout);
+        self
+    }
+
+    /// Buffers files in `dir`, e.g. a fast NVMe mount, rather than in the system's
+    /// temporary directory.
+    pub fn with_temp_dir(mut self, dir: PathBuf) -> Self {
+        self.temp_dir = Some(dir);
+        self
+    }
+
+    /// Buffers files announced to be at most `max_bytes` in size in `dir`, which is meant
+    /// to be a memory-backed file system such as `/dev/shm`, sparing them the disk I/O.
+    ///
+    /// ## Remarks
+    ///
+    /// The directory is chosen when a file is created; files of unknown size are always
+    /// buffered in the temporary directory.
+    pub fn with_in_memory_buffer(mut self, dir: PathBuf, max_bytes: u64) -> Self {
+        self.in_memory = Some(InMemoryBuffer { dir, max_bytes });
+        self
+    }
+
+    /// Discards resumable upload sessions that did not receive a chunk for `timeout`.
+    /// Without a timeout, sessions are kept until they complete or are aborted.
+    pub fn with_upload_session_timeout(mut self, timeout: Duration) -> Self {
+        self.upload_sessions = UploadSessions::new(Some(timeout));
+        self
+    }
+
+    /// Remembers the IDs of up to `capacity` expired files for `grace`, such that reads of
+    /// them fail with [`GetFileReaderError::FileExpired`] rather than
+    /// [`GetFileReaderError::UnknownFile`].
+    ///
+    /// ## Remarks
+    ///
+    /// Expired files are still read back from the backends if they provide them.
+    pub fn with_expired_tombstones(self, grace: Duration, capacity: usize) -> Self {
+        self.inner
+            .try_write()
+            .expect("the backbone is not in use while being configured")
+            .tombstones = Tombstones::new(grace, capacity);
+        self
+    }
+
+    /// Persists the buffered files in an index at `path`, such that they survive a restart.
+    ///
+    /// The index is replayed right away: completely written files whose lease has not
+    /// expired are available again, and their leases are re-armed. The files of incomplete
+    /// uploads, expired files and files that are gone from disk are pruned from the index.
+    ///
+    /// ## Remarks
+    ///
+    /// This must be configured last, such that restored files are accounted for in the
+    /// storage quota and deduplication index. Files buffered with an index are kept on
+    /// graceful shutdown and only deleted once they are removed from the backbone.
+    pub async fn with_file_index(mut self, path: &Path) -> std::io::Result<Self> {
+        let (index, files) = FileIndex::open(path)?;
+        let index = Arc::new(index);
+        for file in files {
+            self.restore_file(&index, file).await;
+        }
+        self.file_index = Some(index);
+        Ok(self)
+    }
+
+    /// Registers a file restored from the file index, or prunes it if it cannot be restored.
+    async fn restore_file(&self, index: &Arc<FileIndex>, indexed: IndexedFile) {
+        let id = indexed.id;
+        let size = indexed.metadata.file_size_bytes.unwrap_or(0);
+        let reservation = match &self.storage_quota {
+            Some(quota) => match quota.reserve(size) {
+                Ok(reservation) => Some(Arc::new(reservation)),
+                Err(e) => {
+                    warn!(file_id = %id, "Unable to restore file {id}: {e}");
+                    index.remove(id);
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        if let Err(e) = Self::reconnect_file(&indexed.path, size).await {
+            warn!(file_id = %id, "Unable to restore file {id} from {path:?}: {e}", path = indexed.path);
+            index.remove(id);
+            return;
         }
+
+        let Some(record) = FileRecord::restored(id, indexed.path, indexed.metadata, indexed.lease)
+        else {
+            warn!(file_id = %id, "Unable to restore file {id}: its metadata is incomplete");
+            index.remove(id);
+            return;
+        };
+
+        if let (Some(hash_index), Some(summary)) = (&self.hash_index, record.get_summary().await) {
+            hash_index.register(id, summary.hashes.sha256.map(Into::into), None);
+        }
+        record.set_buffered_bytes(size);
+        let record = record
+            .with_storage_reservation(reservation)
+            .with_hash_index(self.hash_index.clone())
+            .with_file_index(Some(index.clone()));
+
+        info!(file_id = %id, "Restored file {id} from the file index");
+        self.inner.write().await.open.insert(id, record);
+        BackboneMetrics::track_file_added();
+        BackboneMetrics::track_file_completed(size);
+        self.sender.send(BackboneCommand::Restored(id)).await.ok();
+    }
+
+    /// Checks that a completely written file of `size` bytes is still present, such that it
+    /// can be read in place.
+    async fn reconnect_file(path: &Path, size: u64) -> std::io::Result<()> {
+        let found = tokio::fs::metadata(path).await?.len();
+        if found != size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("expected {size} bytes, found {found}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gets the storage space still available to buffered files, or `None` if unlimited.
+    pub fn available_storage(&self) -> Option<u64> {
+        self.storage_quota
+            .as_ref()
+            .and_then(|quota| quota.available())
     }
 
     pub async fn join(self) {
@@ -60,6 +350,8 @@ impl Backbone {
     }
 
     /// Creates a new file buffer, registers it and returns a writer to it.
+    ///
+    /// If no `temporal_lease` is specified, the backbone's default lease is used.
     pub async fn new_file(
         &self,
         id: ShortGuid,
@@ -67,16 +359,63 @@ impl Backbone {
         content_type: Option<ContentType>,
         content_md5: Option<[u8; 16]>,
         file_name: Option<String>,
+        temporal_lease: Option<Duration>,
     ) -> Result<FileWriterGuard, NewFileError> {
+        // The temporary file is named after the ID, so an existing file must be
+        // detected before the disk is touched. Resumable uploads hold their ID until
+        // all bytes were received and their part file is stored under it.
+        let session_open = self
+            .upload_sessions
+            .get(id)
+            .is_some_and(|session| !session.is_complete());
+        if session_open || self.inner.read().await.open.contains_key(&id) {
+            warn!(file_id = %id, "Rejecting file {id}: the ID is already in use");
+            return Err(NewFileError::IdAlreadyExists(id));
+        }
+
+        // Ensure the backends keep up before accepting the file.
+        let permit = match self.enqueue_timeout {
+            Some(timeout) => match self.backend_sender.reserve_timeout(timeout).await {
+                Ok(permit) => Some(permit),
+                Err(e @ BackendCommandReserveError::Full(_)) => {
+                    warn!(file_id = %id, "Rejecting file {id}: {e}");
+                    return Err(NewFileError::BackendsBusy(id, e));
+                }
+                Err(e) => {
+                    warn!(file_id = %id, "Unable to announce file {id} to the backends: {e}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Reserve the announced size before touching the disk; the reservation
+        // is held until the file is removed.
+        let reservation = match &self.storage_quota {
+            Some(quota) => {
+                quota
+                    .check_high_water_mark()
+                    .map_err(|e| NewFileError::HighWaterMarkExceeded(id, e))?;
+                Some(Arc::new(
+                    quota
+                        .reserve(expected_size.unwrap_or(0))
+                        .map_err(|e| NewFileError::InsufficientStorage(id, e))?,
+                ))
+            }
+            None => None,
+        };
+
         // We reuse the ID such that it is easier to find and debug the
         // created file if necessary.
-        let file = Self::create_new_temporary_file(id).await?;
+        let file = self.create_buffer_file(id, expected_size).await?;
         let writer = Self::create_writer_for_file(id, &file).await?;
 
         let mut inner = self.inner.write().await;
         let (sender, receiver) = oneshot::channel();
 
-        let temporal_lease = TEMPORAL_LEASE;
+        let temporal_lease = temporal_lease.unwrap_or(self.temporal_lease);
+        let progress = Arc::new(ProgressTracker::new(expected_size));
+        debug!(file_id = %id, "Using a temporal lease of {temporal_lease:?} for file {id}");
 
         // This needs to happen synchronously so that the moment we return the writer,
         // we know the entry exists.
@@ -85,18 +424,39 @@ impl Backbone {
                 // TODO: Actively mark the file as failed? This could invalidate all readers and writers.
                 drop(writer);
                 drop(file);
-                return Err(NewFileError::InternalErrorMayRetry(id));
+                return Err(NewFileError::IdAlreadyExists(id));
             }
-            Entry::Vacant(v) => v.insert(FileRecord::new(
-                id,
-                file,
-                self.sender.clone(),
-                receiver,
-                temporal_lease,
-                content_type,
-                Instant::now(),
-            )),
+            Entry::Vacant(v) => v.insert(
+                FileRecord::new(
+                    id,
+                    file,
+                    self.sender.clone(),
+                    receiver,
+                    temporal_lease,
+                    content_type,
+                    Instant::now(),
+                )
+                .with_progress(progress.clone())
+                .with_storage_reservation(reservation.clone())
+                .with_hash_index(self.hash_index.clone())
+                .with_file_index(self.file_index.clone()),
+            ),
         };
+        inner.tombstones.remove(id);
+
+        BackboneMetrics::track_file_added();
+
+        // Release the lock so that backends can access the file right away.
+        drop(inner);
+        if let Some(permit) = permit {
+            permit.send(BackendCommand::FileCreated(id, Span::current()));
+        } else if let Err(error) = self
+            .backend_sender
+            .send(BackendCommand::FileCreated(id, Span::current()))
+            .await
+        {
+            warn!(file_id = %id, "Unable to announce file {id} to the backends: {error}");
+        }
 
         let writer = FileWriter::new(&id, writer, file_name);
         Ok(FileWriterGuard::new(
@@ -105,32 +465,374 @@ impl Backbone {
             temporal_lease,
             expected_size,
             content_md5,
-        ))
+            progress,
+        )
+        .with_storage_reservation(reservation)
+        .with_hash_index(id, self.hash_index.clone()))
     }
 
-    /// Creates a new file buffer, registers it and returns a writer to it.
+    /// Opens a session for a resumable upload stored under `id` once all of its bytes
+    /// were received, see [`UploadSession`].
+    ///
+    /// The part file is buffered in the temporary directory.
+    pub async fn create_upload_session(
+        &self,
+        id: ShortGuid,
+        options: UploadSessionOptions,
+    ) -> Result<Arc<UploadSession>, NewFileError> {
+        self.upload_sessions.purge_idle();
+        if self.upload_sessions.contains(id) || self.inner.read().await.open.contains_key(&id) {
+            warn!(file_id = %id, "Rejecting upload session {id}: the ID is already in use");
+            return Err(NewFileError::IdAlreadyExists(id));
+        }
+
+        let path = self
+            .temp_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!("yeet_{id}.part"));
+        let session = UploadSession::create(id, path, options)
+            .await
+            .map_err(|e| NewFileError::FailedCreatingFile(id, async_tempfile::Error::Io(e)))?;
+        let session = Arc::new(session);
+        if !self.upload_sessions.insert(session.clone()) {
+            return Err(NewFileError::IdAlreadyExists(id));
+        }
+        Ok(session)
+    }
+
+    /// Reserves storage for the total size announced to a resumable upload session,
+    /// see [`UploadSession`]. Does nothing if no storage quota is configured.
+    pub fn reserve_upload_session(&self, session: &UploadSession) -> Result<(), NewFileError> {
+        match &self.storage_quota {
+            Some(quota) => session.reserve(quota),
+            None => Ok(()),
+        }
+    }
+
+    /// Gets an open resumable upload session.
+    pub fn upload_session(&self, id: ShortGuid) -> Option<Arc<UploadSession>> {
+        self.upload_sessions.purge_idle();
+        self.upload_sessions.get(id)
+    }
+
+    /// Closes a resumable upload session, e.g. once all of its bytes were received or
+    /// when it is aborted. The part file is deleted once the session is dropped.
+    pub fn remove_upload_session(&self, id: ShortGuid) -> Option<Arc<UploadSession>> {
+        self.upload_sessions.remove(id)
+    }
+
+    /// Gets a completely written file previously uploaded with the specified idempotency key.
+    ///
+    /// Always returns `None` if deduplication is disabled.
+    pub async fn find_by_idempotency_key(&self, key: &str) -> Option<ExistingFile> {
+        let id = self.hash_index.as_ref()?.find_by_key(key)?;
+        self.find_existing(id).await
+    }
+
+    /// Gets a completely written, locally buffered file.
+    pub async fn find_existing(&self, id: ShortGuid) -> Option<ExistingFile> {
+        let inner = self.inner.read().await;
+        let file = inner.open.get(&id)?;
+        let summary = file.get_summary().await?;
+        Some(ExistingFile {
+            id,
+            summary,
+            content_type: file.content_type.clone(),
+            expires: file.expiration_date(),
+        })
+    }
+
+    /// Gets a snapshot of all locally buffered files, including files still being written,
+    /// ordered by their creation time.
+    pub async fn list_files(&self) -> Vec<LiveFile> {
+        let inner = self.inner.read().await;
+        let mut files = Vec::with_capacity(inner.open.len());
+        for file in inner.open.values() {
+            let summary = file.get_summary().await;
+            files.push(LiveFile {
+                id: file.id,
+                file_name: summary.as_ref().and_then(|s| s.file_name.clone()),
+                content_type: file.content_type.clone(),
+                file_size_bytes: summary.map(|s| s.file_size_bytes as u64),
+                created: file.created,
+                expires: file.expiration_date(),
+            });
+        }
+
+        files.sort_by_key(|file| file.created);
+        files
+    }
+
+    /// Gets a reader to a file.
+    ///
+    /// If the file is not known locally, the backends are asked to provide it; if they do
+    /// not answer in time, [`GetFileReaderError::BackendTimeout`] is returned.
     pub async fn get_file(&self, id: ShortGuid) -> Result<BoxedFileReader, GetFileReaderError> {
+        match self.get_local_file(id).await {
+            Err(GetFileReaderError::UnknownFile(id)) => self.receive_from_backends(id).await,
+            result => result,
+        }
+    }
+
+    /// Gets a reader to a locally buffered file.
+    pub async fn get_local_file(
+        &self,
+        id: ShortGuid,
+    ) -> Result<BoxedFileReader, GetFileReaderError> {
         let inner = self.inner.read().await;
         match inner.open.get(&id) {
             None => Err(GetFileReaderError::UnknownFile(id)),
             Some(file) => {
-                let reader = file.get_reader().await?;
+                let broadcast = match self.broadcast_max_bytes {
+                    Some(max_bytes) => file.get_broadcast_reader(max_bytes).await?,
+                    None => None,
+                };
+                let reader = match broadcast {
+                    Some(reader) => ReaderSource::from(reader),
+                    None => file.get_reader().await?,
+                };
                 let reader = FileReader::new(
                     reader,
                     file.content_type.clone(),
                     file.created,
-                    file.expiration_duration,
+                    file.creation_date,
+                    file.expiration_date(),
                     file.get_summary().await,
-                );
+                )
+                .with_guard(file.track_reader());
                 Ok(BoxedFileReader::new(reader))
             }
         }
     }
 
-    async fn create_new_temporary_file(id: ShortGuid) -> Result<SharedTemporaryFile, NewFileError> {
-        SharedTemporaryFile::new_with_uuid(id.into())
+    /// Gets the upload progress of a locally buffered file.
+    pub async fn upload_progress(
+        &self,
+        id: ShortGuid,
+    ) -> Result<UploadProgress, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        match inner.open.get(&id) {
+            None => Err(inner.missing_file(id)),
+            Some(file) => Ok(file.upload_progress().await),
+        }
+    }
+
+    /// Gets the metadata record of a locally buffered file.
+    ///
+    /// Returns `None` if the file is still being written.
+    pub async fn get_metadata(
+        &self,
+        id: ShortGuid,
+    ) -> Result<Option<ItemMetadata>, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        match inner.open.get(&id) {
+            None => Err(inner.missing_file(id)),
+            Some(file) => Ok(file.get_metadata().await),
+        }
+    }
+
+    /// Extends the temporal lease of a file, keeping it available for another lease duration.
+    /// The backends are informed such that they keep their copy of the file as well.
+    ///
+    /// Returns the new expiration date of the file.
+    pub async fn extend_lease(&self, id: ShortGuid) -> Result<Instant, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        let expires = match inner.open.get(&id) {
+            None => return Err(inner.missing_file(id)),
+            Some(file) => file.extend_lease().await?,
+        };
+        drop(inner);
+
+        // Backends expiring files along with their lease need to keep them as well.
+        self.backend_sender
+            .send(BackendCommand::LeaseExtended(id, expires))
+            .await
+            .ok();
+        Ok(expires)
+    }
+
+    /// Requests to be notified once a locally buffered file was distributed to the backends.
+    ///
+    /// Must be called before writing the file completes. The returned channel is closed
+    /// without an outcome if the file is removed or its distribution is aborted.
+    pub async fn await_distribution(
+        &self,
+        id: ShortGuid,
+    ) -> Result<oneshot::Receiver<DistributionOutcome>, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        match inner.open.get(&id) {
+            None => Err(inner.missing_file(id)),
+            Some(file) => Ok(file.wait_for_distribution()),
+        }
+    }
+
+    /// Distributes a completely written, locally buffered file to the backends again, e.g. to
+    /// retry a failed distribution once a backend outage is resolved. The backends store the
+    /// file until its current expiration date.
+    ///
+    /// Returns the expiration date of the file, or `None` if it is still being written;
+    /// such files are distributed once writing completes.
+    pub async fn redistribute(&self, id: ShortGuid) -> Result<Option<Instant>, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        let Some(file) = inner.open.get(&id) else {
+            return Err(inner.missing_file(id));
+        };
+        let Some(summary) = file.get_summary().await else {
+            return Ok(None);
+        };
+        let expires = file.expiration_date();
+        drop(inner);
+
+        info!(file_id = %id, "Redistributing file {id} on request");
+        let summary = Arc::new(WriteSummary {
+            expires,
+            ..WriteSummary::clone(&summary)
+        });
+        if let Err(error) = self
+            .backend_sender
+            .send(BackendCommand::DistributeFile(
+                id,
+                summary,
+                None,
+                Span::current(),
+            ))
+            .await
+        {
+            warn!(file_id = %id, "Unable to redistribute file {id} to the backends: {error}");
+        }
+        Ok(Some(expires))
+    }
+
+    /// Removes a locally buffered file before its temporal lease expires.
+    ///
+    /// Currently open readers continue to work; new readers are rejected.
+    pub async fn remove_file(&self, id: ShortGuid) -> Result<(), GetFileReaderError> {
+        let mut inner = self.inner.write().await;
+        let Some(file) = inner.open.remove(&id) else {
+            return Err(inner.missing_file(id));
+        };
+
+        info!(file_id = %id, "Removing file {id} on request");
+        BackboneMetrics::track_removal(RemovalReason::Deleted, file.was_read());
+        file.close().await;
+        drop(inner);
+        Self::release_file(file, &self.cleanup_rendezvous);
+
+        self.backend_sender
+            .send(BackendCommand::DeleteFile(id))
             .await
-            .map_err(|e| NewFileError::FailedCreatingFile(id, e))
+            .ok();
+        Ok(())
+    }
+
+    /// Gets a presigned URL of a distributed file from the backends, valid for `expires_in`.
+    ///
+    /// Returns `None` if no backend provides one.
+    pub async fn presigned_url(&self, id: ShortGuid, expires_in: Duration) -> Option<String> {
+        let (sender, receiver) = oneshot::channel();
+        if let Err(error) = self
+            .backend_sender
+            .send(BackendCommand::PresignFile(id, expires_in, sender))
+            .await
+        {
+            warn!(file_id = %id, "Unable to request a presigned URL of file {id} from the backends: {error}");
+            return None;
+        }
+
+        receiver.await.ok().flatten()
+    }
+
+    /// Checks the health of all backends, waiting at most `timeout` for each.
+    ///
+    /// Returns `None` if the backends cannot be reached at all, e.g. while shutting down.
+    pub async fn check_backends(&self, timeout: Duration) -> Option<Vec<BackendHealth>> {
+        let (sender, receiver) = oneshot::channel();
+        if let Err(error) = self
+            .backend_sender
+            .send(BackendCommand::CheckHealth(timeout, sender))
+            .await
+        {
+            warn!("Unable to request the health of the backends: {error}");
+            return None;
+        }
+
+        receiver.await.ok()
+    }
+
+    /// Attempts to read a file back from the backends.
+    async fn receive_from_backends(
+        &self,
+        id: ShortGuid,
+    ) -> Result<BoxedFileReader, GetFileReaderError> {
+        let (sender, receiver) = oneshot::channel();
+        if let Err(error) = self
+            .backend_sender
+            .send(BackendCommand::ReceiveFile(id, sender))
+            .await
+        {
+            warn!(file_id = %id, "Unable to request file {id} from the backends: {error}");
+            return Err(self.inner.read().await.missing_file(id));
+        }
+
+        match receiver.await {
+            Ok(Ok(Some(reader))) => Ok(reader),
+            Ok(Err(_)) => Err(GetFileReaderError::BackendTimeout(id)),
+            Ok(Ok(None)) | Err(_) => Err(self.inner.read().await.missing_file(id)),
+        }
+    }
+
+    /// Selects the directory a file of the expected size is buffered in, or `None`
+    /// for the system's temporary directory.
+    fn buffer_dir(&self, expected_size: Option<u64>) -> Option<&PathBuf> {
+        match (&self.in_memory, expected_size) {
+            (Some(in_memory), Some(size)) if size <= in_memory.max_bytes => Some(&in_memory.dir),
+            _ => self.temp_dir.as_ref(),
+        }
+    }
+
+    /// Creates the file an upload is buffered in.
+    ///
+    /// Files persisted in the file index are kept on disk when they are dropped,
+    /// see [`FileRecord::discard`].
+    async fn create_buffer_file(
+        &self,
+        id: ShortGuid,
+        expected_size: Option<u64>,
+    ) -> Result<SharedTemporaryFile, NewFileError> {
+        let dir = self.buffer_dir(expected_size);
+        let Some(index) = &self.file_index else {
+            return Self::create_new_temporary_file(id, dir).await;
+        };
+
+        let path = dir
+            .cloned()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(format!("yeet_{id}"));
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+            .map_err(|e| NewFileError::FailedCreatingFile(id, async_tempfile::Error::Io(e)))?;
+        let file = SharedTemporaryFile::from_existing(path, Ownership::Borrowed)
+            .await
+            .map_err(|e| NewFileError::FailedCreatingFile(id, e))?;
+        index.created(id, file.file_path());
+        Ok(file)
+    }
+
+    async fn create_new_temporary_file(
+        id: ShortGuid,
+        dir: Option<&PathBuf>,
+    ) -> Result<SharedTemporaryFile, NewFileError> {
+        let file = match dir {
+            Some(dir) => TempFile::new_with_uuid_in(id.into(), dir)
+                .await
+                .map(SharedTemporaryFile::from),
+            None => SharedTemporaryFile::new_with_uuid(id.into()).await,
+        };
+        file.map_err(|e| NewFileError::FailedCreatingFile(id, e))
     }
 
     async fn create_writer_for_file(
@@ -148,17 +850,57 @@ impl Backbone {
         backend_sender: BackendCommandSender,
         cleanup_rendezvous: RendezvousGuard,
     ) {
-        while let Some(command) = channel.recv().await {
+        let mut expiry = ExpiryQueue::default();
+        loop {
+            let command = tokio::select! {
+                command = channel.recv() => match command {
+                    Some(command) => command,
+                    None => break,
+                },
+                _ = expiry.expired() => {
+                    Self::remove_expired_files(&inner, &mut expiry, &backend_sender, &cleanup_rendezvous).await;
+                    continue;
+                }
+            };
+
             match command {
-                BackboneCommand::RemoveWriter(id) => {
+                BackboneCommand::RemoveWriter(id, reason) => {
                     info!(file_id = %id, "Removing file {id} from bookkeeping");
-                    let mut inner = inner.write().await;
-                    inner.open.remove(&id);
+                    let removed = inner.write().await.open.remove(&id);
+                    if let Some(file) = removed {
+                        BackboneMetrics::track_removal(reason, file.was_read());
+                        Self::release_file(file, &cleanup_rendezvous);
+                        backend_sender
+                            .send(BackendCommand::FileRemoved(id))
+                            .await
+                            .ok();
+                    }
                 }
-                BackboneCommand::ReadyForDistribution(id, summary) => {
+                BackboneCommand::Restored(id) => {
+                    if let Some(file) = inner.read().await.open.get(&id) {
+                        expiry.schedule(id, file.expiration_date());
+                    }
+                }
+                BackboneCommand::ReadyForDistribution(id, summary, span) => {
                     info!(file_id = %id, "The file {id} was buffered completely and can now be distributed");
+                    // Files removed in the meantime are no longer buffered.
+                    let mut completion = None;
+                    if let Some(file) = inner.read().await.open.get(&id) {
+                        completion = file.take_distribution_waiter();
+                        file.persist().await;
+                        let bytes = summary.file_size_bytes as u64;
+                        file.set_buffered_bytes(bytes);
+                        BackboneMetrics::track_file_completed(bytes);
+
+                        let expires = file.expiration_date();
+                        let duration = expires.saturating_duration_since(Instant::now());
+                        info!(file_id = %id, "File {id} will accept new readers for {duration:?}");
+                        expiry.schedule(id, expires);
+                    }
                     backend_sender
-                        .send(BackendCommand::DistributeFile(id, summary))
+                        .send(BackendCommand::DistributeFile(
+                            id, summary, completion, span,
+                        ))
                         .await
                         .ok();
                 }
@@ -168,6 +910,83 @@ impl Backbone {
         info!("The backbone command loop stopped");
         cleanup_rendezvous.completed();
     }
+
+    /// Removes all files whose temporal lease expired.
+    ///
+    /// Files whose lease was extended in the meantime are scheduled again.
+    async fn remove_expired_files(
+        inner: &RwLock<Inner>,
+        expiry: &mut ExpiryQueue,
+        backend_sender: &BackendCommandSender,
+        cleanup_rendezvous: &RendezvousGuard,
+    ) {
+        let now = Instant::now();
+        let mut removed = Vec::new();
+        let mut inner = inner.write().await;
+        for id in expiry.take_expired(now) {
+            // Files that were removed early are no longer known; the ID may even have
+            // been reused by a file that is still being written.
+            let Some(file) = inner.open.get(&id) else {
+                continue;
+            };
+            if file.get_summary().await.is_none() {
+                continue;
+            }
+
+            let expires = file.expiration_date();
+            if expires > now {
+                expiry.schedule(id, expires);
+                continue;
+            }
+
+            info!(file_id = %id, "Read lease timed out for file {id}; removing it");
+            inner.tombstones.insert(id, now);
+            if let Some(file) = inner.open.remove(&id) {
+                BackboneMetrics::track_removal(RemovalReason::Expired, file.was_read());
+                Self::release_file(file, cleanup_rendezvous);
+                removed.push(id);
+            }
+        }
+        let tombstones = inner.tombstones.len();
+        drop(inner);
+
+        debug!(
+            "Removed {removed} expired files; {scheduled} files remain scheduled for expiry, {tombstones} are tombstoned",
+            removed = removed.len(),
+            scheduled = expiry.len()
+        );
+        for id in removed {
+            backend_sender
+                .send(BackendCommand::FileRemoved(id))
+                .await
+                .ok();
+        }
+    }
+
+    /// Drops a file record that was removed from the bookkeeping.
+    ///
+    /// If readers of the file are still alive, the record, and with it the file and
+    /// its storage reservation, is kept until the last reader is dropped.
+    fn release_file(file: FileRecord, cleanup_rendezvous: &RendezvousGuard) {
+        let readers = file.active_readers();
+        if readers == 0 {
+            BackboneMetrics::track_file_removed(file.buffered_bytes());
+            file.discard();
+            return;
+        }
+
+        let id = file.id;
+        info!(file_id = %id, "Deferring the removal of file {id} until {readers} readers are dropped");
+        let rendezvous = cleanup_rendezvous.fork();
+        tokio::spawn(async move {
+            file.readers_dropped().await;
+            debug!(file_id = %id, "The last reader of file {id} was dropped; removing it");
+            BackboneMetrics::track_file_removed(file.buffered_bytes());
+            file.discard();
+            drop(file);
+            rendezvous.completed();
+        });
+    }
 }
 
 #[derive(Debug)]
@@ -177,9 +996,12 @@ pub enum BackboneCommand {
     ///
     /// Currently open writers or readers will continue to work.
     /// When the last reference is closed, the file will be removed.
-    RemoveWriter(ShortGuid),
-    /// Marks the file ready for distribution to other backends.
-    ReadyForDistribution(ShortGuid, Arc<WriteSummary>),
+    RemoveWriter(ShortGuid, RemovalReason),
+    /// Marks the file ready for distribution to other backends. The span is that of
+    /// the file, itself a child of the span of the upload.
+    ReadyForDistribution(ShortGuid, Arc<WriteSummary>, Span),
+    /// Re-arms the lease of a file restored from the file index.
+    Restored(ShortGuid),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -188,6 +1010,1079 @@ pub enum NewFileError {
     FailedCreatingFile(ShortGuid, async_tempfile::Error),
     #[error("Failed to create a writer to the file: {1}")]
     FailedCreatingWriter(ShortGuid, async_tempfile::Error),
-    #[error("An internal error occurred; the operation may be retried")]
-    InternalErrorMayRetry(ShortGuid),
+    #[error("The file ID {0} is already in use")]
+    IdAlreadyExists(ShortGuid),
+    #[error("{1}")]
+    InsufficientStorage(ShortGuid, InsufficientStorage),
+    #[error("{1}")]
+    HighWaterMarkExceeded(ShortGuid, HighWaterMarkExceeded),
+    #[error("The backends are busy: {1}")]
+    BackendsBusy(ShortGuid, BackendCommandReserveError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_writer::FinalizationError;
+    use crate::file_writer_guard::Finalized;
+    use crate::CompletionMode;
+    use file_distribution::hash::{HashAlgorithms, HashSha256};
+    use file_distribution::FileReaderTrait;
+    use rendezvous::Rendezvous;
+    use tokio::io::AsyncReadExt;
+
+    const LEASE: Duration = Duration::from_secs(60);
+
+    struct Fixture {
+        backbone: Backbone,
+        _backend_receiver: mpsc::Receiver<BackendCommand>,
+        rendezvous: Rendezvous,
+    }
+
+    impl Fixture {
+        /// Stops the backbone and waits for its command loop to finish.
+        async fn shut_down(self) {
+            drop(self.backbone);
+            let rendezvous = self.rendezvous;
+            tokio::task::spawn_blocking(move || rendezvous.rendezvous())
+                .await
+                .expect("failed to await the rendezvous");
+        }
+
+        /// Ends the lease of the file early, then shuts down the backbone.
+        async fn remove_and_shut_down(self, id: ShortGuid) {
+            self.backbone
+                .remove_file(id)
+                .await
+                .expect("failed to remove file");
+            self.shut_down().await;
+        }
+    }
+
+    fn fixture() -> Fixture {
+        fixture_with(|backbone| backbone)
+    }
+
+    fn fixture_with(configure: impl FnOnce(Backbone) -> Backbone) -> Fixture {
+        let (sender, backend_receiver) = mpsc::channel(16);
+        let rendezvous = Rendezvous::new();
+        let backbone = configure(Backbone::new(sender.into(), rendezvous.fork_guard(), LEASE));
+        Fixture {
+            backbone,
+            _backend_receiver: backend_receiver,
+            rendezvous,
+        }
+    }
+
+    async fn store_file(backbone: &Backbone, data: &[u8]) -> ShortGuid {
+        let id = ShortGuid::new_random();
+        let mut writer = backbone
+            .new_file(id, None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+        let mut written = 0;
+        while written < data.len() {
+            written += writer
+                .write(&data[written..])
+                .await
+                .expect("failed to write");
+        }
+        writer.sync_data().await.expect("failed to sync");
+        writer
+            .finalize(CompletionMode::NoSync)
+            .await
+            .expect("failed to finalize");
+        id
+    }
+
+    /// Sleeps for the specified duration and lets pending tasks catch up.
+    async fn sleep(duration: Duration) {
+        tokio::time::sleep(duration).await;
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn file_expires_after_lease() {
+        let fixture = fixture();
+        let id = store_file(&fixture.backbone, b"data").await;
+
+        sleep(LEASE / 2).await;
+        assert!(fixture.backbone.get_local_file(id).await.is_ok());
+
+        sleep(LEASE).await;
+        assert!(matches!(
+            fixture.backbone.get_local_file(id).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_files_are_tombstoned_for_the_grace_period() {
+        let fixture = fixture_with(|backbone| backbone.with_expired_tombstones(LEASE, 16));
+        let id = store_file(&fixture.backbone, b"data").await;
+
+        sleep(LEASE * 3 / 2).await;
+        assert!(matches!(
+            fixture.backbone.get_metadata(id).await,
+            Err(GetFileReaderError::FileExpired(_))
+        ));
+        assert!(matches!(
+            fixture.backbone.extend_lease(id).await,
+            Err(GetFileReaderError::FileExpired(_))
+        ));
+        assert!(matches!(
+            fixture.backbone.get_metadata(ShortGuid::new_random()).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+
+        sleep(LEASE).await;
+        assert!(matches!(
+            fixture.backbone.get_metadata(id).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn extended_lease_keeps_file_alive() {
+        let fixture = fixture();
+        let id = store_file(&fixture.backbone, b"data").await;
+
+        sleep(LEASE * 3 / 4).await;
+        let expires = fixture
+            .backbone
+            .extend_lease(id)
+            .await
+            .expect("failed to extend lease");
+        assert_eq!(expires, Instant::now() + LEASE);
+
+        sleep(LEASE * 3 / 4).await;
+        let reader = fixture.backbone.get_local_file(id).await;
+        assert_eq!(reader.map(|r| r.expiration_date()).ok(), Some(expires));
+
+        sleep(LEASE / 2).await;
+        assert!(matches!(
+            fixture.backbone.extend_lease(id).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_files_are_removed_in_batches() {
+        let fixture = fixture();
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(store_file(&fixture.backbone, b"data").await);
+        }
+        sleep(Duration::ZERO).await;
+        assert_eq!(fixture.backbone.list_files().await.len(), 3);
+
+        sleep(LEASE / 2).await;
+        fixture
+            .backbone
+            .extend_lease(ids[1])
+            .await
+            .expect("failed to extend lease");
+
+        sleep(LEASE * 3 / 4).await;
+        let remaining: Vec<_> = fixture
+            .backbone
+            .list_files()
+            .await
+            .into_iter()
+            .map(|file| file.id)
+            .collect();
+        assert_eq!(remaining, vec![ids[1]]);
+
+        sleep(LEASE).await;
+        assert!(fixture.backbone.list_files().await.is_empty());
+
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn metadata_is_built_when_writing_completes() {
+        let fixture = fixture();
+        let id = ShortGuid::new_random();
+        let mut writer = fixture
+            .backbone
+            .new_file(id, None, Some(ContentType::text()), None, None, None)
+            .await
+            .expect("failed to create file");
+        writer.write(b"data").await.expect("failed to write");
+        sleep(Duration::ZERO).await;
+        assert_eq!(fixture.backbone.get_metadata(id).await.ok(), Some(None));
+
+        writer.sync_data().await.expect("failed to sync");
+        writer
+            .finalize(CompletionMode::NoSync)
+            .await
+            .expect("failed to finalize");
+        sleep(Duration::ZERO).await;
+
+        let metadata = fixture
+            .backbone
+            .get_metadata(id)
+            .await
+            .expect("failed to get metadata")
+            .expect("metadata missing");
+        assert_eq!(metadata.id, id.as_bytes().to_vec());
+        assert_eq!(metadata.file_size_bytes, Some(4));
+        assert_eq!(metadata.content_type, Some(ContentType::text().to_string()));
+
+        let created = metadata.created_unix_ms.expect("creation time missing");
+        let expires = metadata.expires_unix_ms.expect("expiration time missing");
+        assert!(expires >= created + LEASE.as_millis() as u64 - 1000);
+
+        sleep(LEASE * 2).await;
+        fixture.shut_down().await;
+    }
+
+    /// Uploads a file with deduplication, returning the outcome.
+    async fn store_deduplicated(
+        backbone: &Backbone,
+        data: &[u8],
+        idempotency_key: Option<&str>,
+    ) -> (ShortGuid, Finalized) {
+        let id = ShortGuid::new_random();
+        let mut writer = backbone
+            .new_file(id, None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+        writer.set_idempotency_key(idempotency_key.map(str::to_string));
+        writer.write(data).await.expect("failed to write");
+        writer.sync_data().await.expect("failed to sync");
+        let finalized = writer
+            .finalize_deduplicated(CompletionMode::NoSync)
+            .await
+            .expect("failed to finalize");
+        (id, finalized)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn identical_uploads_are_deduplicated() {
+        let fixture = fixture_with(Backbone::with_deduplication);
+        let (first, finalized) = store_deduplicated(&fixture.backbone, b"data", None).await;
+        assert!(matches!(finalized, Finalized::Stored(_)));
+
+        let (second, finalized) =
+            store_deduplicated(&fixture.backbone, b"data", Some("retry")).await;
+        assert!(matches!(finalized, Finalized::Duplicate(existing, _) if existing == first));
+        sleep(Duration::ZERO).await;
+
+        // The duplicate is discarded, the idempotency key refers to the original file.
+        assert!(matches!(
+            fixture.backbone.get_local_file(second).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+        let existing = fixture.backbone.find_by_idempotency_key("retry").await;
+        assert_eq!(existing.map(|file| file.id), Some(first));
+
+        let (_, finalized) = store_deduplicated(&fixture.backbone, b"other", None).await;
+        assert!(matches!(finalized, Finalized::Stored(_)));
+
+        // Once the original file is gone, its content is stored anew.
+        fixture
+            .backbone
+            .remove_file(first)
+            .await
+            .expect("failed to remove file");
+        assert!(fixture
+            .backbone
+            .find_by_idempotency_key("retry")
+            .await
+            .is_none());
+        let (_, finalized) = store_deduplicated(&fixture.backbone, b"data", None).await;
+        assert!(matches!(finalized, Finalized::Stored(_)));
+
+        sleep(LEASE * 2).await;
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn removed_file_is_no_longer_available() {
+        let fixture = fixture();
+        let id = store_file(&fixture.backbone, b"data").await;
+
+        // Readers opened before the removal remain usable.
+        let reader = fixture.backbone.get_local_file(id).await;
+        assert!(reader.is_ok());
+
+        fixture
+            .backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        assert!(matches!(
+            fixture.backbone.get_local_file(id).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+        assert!(matches!(
+            fixture.backbone.remove_file(id).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+
+        drop(reader);
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn live_files_are_listed_in_creation_order() {
+        let fixture = fixture();
+        let first = store_file(&fixture.backbone, b"data").await;
+        sleep(Duration::from_secs(1)).await;
+
+        let second = ShortGuid::new_random();
+        let writer = fixture
+            .backbone
+            .new_file(second, None, Some(ContentType::text()), None, None, None)
+            .await
+            .expect("failed to create file");
+
+        let files = fixture.backbone.list_files().await;
+        let ids: Vec<_> = files.iter().map(|file| file.id).collect();
+        assert_eq!(ids, [first, second]);
+        assert_eq!(files[0].file_size_bytes, Some(4));
+        assert_eq!(files[1].file_size_bytes, None);
+        assert_eq!(files[1].content_type, Some(ContentType::text()));
+        assert!(files[0].created < files[1].created);
+
+        drop(writer);
+        fixture.remove_and_shut_down(first).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn upload_progress_is_reported_while_writing() {
+        let fixture = fixture();
+        let id = ShortGuid::new_random();
+        let mut writer = fixture
+            .backbone
+            .new_file(id, Some(9), None, None, None, None)
+            .await
+            .expect("failed to create file");
+
+        let progress = |bytes_received, completed| UploadProgress {
+            bytes_received,
+            bytes_expected: Some(9),
+            completed,
+        };
+
+        let status = fixture.backbone.upload_progress(id).await.ok();
+        assert_eq!(status, Some(progress(0, false)));
+
+        writer.write(b"yeet").await.expect("failed to write");
+        let status = fixture.backbone.upload_progress(id).await.ok();
+        assert_eq!(status, Some(progress(4, false)));
+
+        writer.write(b"yoink").await.expect("failed to write");
+        let status = fixture.backbone.upload_progress(id).await.ok();
+        assert_eq!(status, Some(progress(9, false)));
+
+        writer.sync_data().await.expect("failed to sync");
+        writer
+            .finalize(CompletionMode::NoSync)
+            .await
+            .expect("failed to finalize");
+        sleep(Duration::ZERO).await;
+        let status = fixture.backbone.upload_progress(id).await.ok();
+        assert_eq!(status, Some(progress(9, true)));
+
+        sleep(LEASE * 2).await;
+        fixture.shut_down().await;
+    }
+
+    /// Opens `count` readers of the file at once, reads them concurrently and
+    /// returns the number of read passes opened on the underlying file.
+    async fn read_concurrently(
+        backbone: &Backbone,
+        id: ShortGuid,
+        count: usize,
+        data: &[u8],
+    ) -> usize {
+        let mut readers = Vec::new();
+        for _ in 0..count {
+            let reader = backbone
+                .get_local_file(id)
+                .await
+                .expect("failed to get reader");
+            assert_eq!(reader.file_size().minimum_size(), Some(data.len()));
+            readers.push(tokio::spawn(read_all(reader)));
+        }
+
+        for reader in readers {
+            let content = reader
+                .await
+                .expect("reader panicked")
+                .expect("failed to read");
+            assert_eq!(content, data);
+        }
+
+        let inner = backbone.inner.read().await;
+        inner.open.get(&id).expect("file is missing").read_passes()
+    }
+
+    async fn read_all(mut reader: BoxedFileReader) -> std::io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        let mut chunk = vec![0; 16 * 1024];
+        loop {
+            match reader.read(&mut chunk).await? {
+                0 => return Ok(content),
+                bytes_read => content.extend_from_slice(&chunk[..bytes_read]),
+            }
+        }
+    }
+
+    fn test_data() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn broadcast_reads_share_one_read_pass() {
+        let fixture = fixture_with(|backbone| backbone.with_broadcast_reads(1024 * 1024));
+        let data = test_data();
+        let id = store_file(&fixture.backbone, &data).await;
+        sleep(Duration::ZERO).await;
+
+        let passes = read_concurrently(&fixture.backbone, id, 4, &data).await;
+        assert_eq!(passes, 1);
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test]
+    async fn regular_reads_open_one_read_pass_each() {
+        let fixture = fixture();
+        let data = test_data();
+        let id = store_file(&fixture.backbone, &data).await;
+        sleep(Duration::ZERO).await;
+
+        let passes = read_concurrently(&fixture.backbone, id, 4, &data).await;
+        assert_eq!(passes, 4);
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test]
+    async fn files_committed_without_sync_are_readable() {
+        let fixture = fixture();
+        let id = ShortGuid::new_random();
+        let mut writer = fixture
+            .backbone
+            .new_file(id, None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+        writer.write(b"yeet").await.expect("failed to write");
+        writer.commit(false).await.expect("failed to commit");
+        writer
+            .finalize(CompletionMode::Sync)
+            .await
+            .expect("failed to finalize");
+        sleep(Duration::ZERO).await;
+
+        let reader = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        assert_eq!(read_all(reader).await.expect("failed to read"), b"yeet");
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test]
+    async fn duplicate_ids_are_rejected_without_touching_the_file() {
+        let fixture = fixture();
+        let id = store_file(&fixture.backbone, b"yeet").await;
+        sleep(Duration::ZERO).await;
+
+        let result = fixture
+            .backbone
+            .new_file(id, None, None, None, None, None)
+            .await;
+        assert!(matches!(result, Err(NewFileError::IdAlreadyExists(existing)) if existing == id));
+
+        let reader = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        assert_eq!(read_all(reader).await.expect("failed to read"), b"yeet");
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test]
+    async fn small_files_are_buffered_in_memory() {
+        let root = std::env::temp_dir().join(format!("yeet-yoink-{}", ShortGuid::new_random()));
+        let (disk, memory) = (root.join("disk"), root.join("memory"));
+        for dir in [&disk, &memory] {
+            std::fs::create_dir_all(dir).expect("failed to create directory");
+        }
+        let fixture = fixture_with(|backbone| {
+            backbone
+                .with_temp_dir(disk.clone())
+                .with_in_memory_buffer(memory.clone(), 16)
+        });
+        let files_in = |dir: &PathBuf| {
+            std::fs::read_dir(dir)
+                .expect("failed to list directory")
+                .count()
+        };
+
+        let mut writers = Vec::new();
+        for expected_size in [Some(16), Some(17), None] {
+            let writer = fixture
+                .backbone
+                .new_file(
+                    ShortGuid::new_random(),
+                    expected_size,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .expect("failed to create file");
+            writers.push(writer);
+        }
+        assert_eq!(files_in(&memory), 1);
+        assert_eq!(files_in(&disk), 2);
+
+        drop(writers);
+        fixture.shut_down().await;
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn broadcast_is_skipped_for_large_files() {
+        let fixture = fixture_with(|backbone| backbone.with_broadcast_reads(1024));
+        let data = test_data();
+        let id = store_file(&fixture.backbone, &data).await;
+        sleep(Duration::ZERO).await;
+
+        let passes = read_concurrently(&fixture.backbone, id, 2, &data).await;
+        assert_eq!(passes, 2);
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test]
+    async fn broadcast_reader_joining_mid_stream_catches_up() {
+        let fixture = fixture_with(|backbone| backbone.with_broadcast_reads(1024 * 1024));
+        let data = test_data();
+        let id = store_file(&fixture.backbone, &data).await;
+        sleep(Duration::ZERO).await;
+
+        let mut first = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        let mut head = vec![0; 100_000];
+        first.read_exact(&mut head).await.expect("failed to read");
+        assert_eq!(head, data[..100_000]);
+
+        let second = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        let content = read_all(second).await.expect("failed to read");
+        assert_eq!(content, data);
+
+        let tail = read_all(first).await.expect("failed to read");
+        assert_eq!(tail, data[100_000..]);
+
+        {
+            let inner = fixture.backbone.inner.read().await;
+            assert_eq!(inner.open.get(&id).map(|file| file.read_passes()), Some(1));
+        }
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn upload_exceeding_headroom_is_rejected_early() {
+        let fixture = fixture_with(|backbone| backbone.with_storage_quota(10));
+        let first = fixture
+            .backbone
+            .new_file(ShortGuid::new_random(), Some(6), None, None, None, None)
+            .await
+            .expect("failed to create file");
+        assert_eq!(fixture.backbone.available_storage(), Some(4));
+
+        let result = fixture
+            .backbone
+            .new_file(ShortGuid::new_random(), Some(5), None, None, None, None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(NewFileError::InsufficientStorage(
+                _,
+                InsufficientStorage {
+                    requested: 5,
+                    available: 4
+                }
+            ))
+        ));
+
+        drop(first);
+        sleep(Duration::ZERO).await;
+        assert_eq!(fixture.backbone.available_storage(), Some(10));
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn upload_sessions_hold_their_id_and_announced_size() {
+        let fixture = fixture_with(|backbone| backbone.with_storage_quota(10));
+        let id = ShortGuid::new_random();
+        let session = fixture
+            .backbone
+            .create_upload_session(id, UploadSessionOptions::default())
+            .await
+            .expect("failed to create session");
+
+        session.announce(4, Some(8)).expect("the chunk fits");
+        fixture
+            .backbone
+            .reserve_upload_session(&session)
+            .expect("the total fits");
+        fixture
+            .backbone
+            .reserve_upload_session(&session)
+            .expect("the total is reserved once");
+        assert_eq!(fixture.backbone.available_storage(), Some(2));
+
+        // Sessions exceeding the headroom are rejected.
+        let other = fixture
+            .backbone
+            .create_upload_session(ShortGuid::new_random(), UploadSessionOptions::default())
+            .await
+            .expect("failed to create session");
+        other.announce(4, Some(4)).expect("the chunk fits");
+        assert!(matches!(
+            fixture.backbone.reserve_upload_session(&other),
+            Err(NewFileError::InsufficientStorage(_, _))
+        ));
+
+        // The ID is taken until all bytes of the session were received.
+        let result = fixture
+            .backbone
+            .new_file(id, Some(8), None, None, None, None)
+            .await;
+        assert!(matches!(result, Err(NewFileError::IdAlreadyExists(_))));
+
+        session.record(0..8);
+        assert!(session.is_complete());
+        session.release_reservation();
+        assert_eq!(fixture.backbone.available_storage(), Some(10));
+        let writer = fixture
+            .backbone
+            .new_file(id, Some(8), None, None, None, None)
+            .await
+            .expect("failed to store the session");
+
+        drop((writer, session, other));
+        fixture.backbone.remove_upload_session(id);
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn upload_of_unknown_length_fails_when_exceeding_headroom() {
+        let fixture = fixture_with(|backbone| backbone.with_storage_quota(10));
+        let mut writer = fixture
+            .backbone
+            .new_file(ShortGuid::new_random(), None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+
+        writer.write(b"yeet").await.expect("failed to write");
+        writer.write(b"yoink").await.expect("failed to write");
+        assert_eq!(fixture.backbone.available_storage(), Some(1));
+
+        let error = writer.write(b"yy").await.expect_err("write should fail");
+        let error = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<InsufficientStorage>());
+        assert_eq!(
+            error,
+            Some(&InsufficientStorage {
+                requested: 2,
+                available: 1
+            })
+        );
+
+        // The failed upload releases its reservation.
+        writer.sync_data().await.expect("failed to sync");
+        drop(writer);
+        sleep(Duration::ZERO).await;
+        assert_eq!(fixture.backbone.available_storage(), Some(10));
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn new_files_are_rejected_above_high_water_mark() {
+        let fixture = fixture_with(|backbone| backbone.with_storage_high_water_mark(4));
+        let id = store_file(&fixture.backbone, b"data").await;
+        sleep(Duration::ZERO).await;
+
+        let result = fixture
+            .backbone
+            .new_file(ShortGuid::new_random(), None, None, None, None, None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(NewFileError::HighWaterMarkExceeded(
+                _,
+                HighWaterMarkExceeded {
+                    used: 4,
+                    high_water_mark: 4
+                }
+            ))
+        ));
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn new_files_are_rejected_while_backends_are_busy() {
+        let (sender, mut backend_receiver) = mpsc::channel(1);
+        let rendezvous = Rendezvous::new();
+        let backend_sender = BackendCommandSender::from(sender);
+        let backbone = Backbone::new(backend_sender.clone(), rendezvous.fork_guard(), LEASE)
+            .with_enqueue_timeout(Duration::from_millis(100));
+
+        // Fill the backend queue.
+        backend_sender
+            .send(BackendCommand::FileRemoved(ShortGuid::new_random()))
+            .await
+            .expect("failed to fill queue");
+
+        let result = backbone
+            .new_file(ShortGuid::new_random(), None, None, None, None, None)
+            .await;
+        assert!(matches!(
+            result,
+            Err(NewFileError::BackendsBusy(
+                _,
+                BackendCommandReserveError::Full(_)
+            ))
+        ));
+
+        backend_receiver.recv().await.expect("missing command");
+        let fixture = Fixture {
+            backbone,
+            _backend_receiver: backend_receiver,
+            rendezvous,
+        };
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn completed_files_are_redistributed_on_request() {
+        let (sender, mut backend_receiver) = mpsc::channel(16);
+        let rendezvous = Rendezvous::new();
+        let backbone = Backbone::new(sender.into(), rendezvous.fork_guard(), LEASE);
+        let id = store_file(&backbone, b"data").await;
+        sleep(LEASE / 2).await;
+
+        let expires = backbone
+            .redistribute(id)
+            .await
+            .expect("failed to redistribute")
+            .expect("the file is complete");
+        assert_eq!(
+            expires,
+            backbone.get_local_file(id).await.unwrap().expiration_date()
+        );
+
+        // The file was announced, distributed, and then distributed again.
+        let mut distributed = Vec::new();
+        while let Ok(command) = backend_receiver.try_recv() {
+            if let BackendCommand::DistributeFile(file_id, summary, _, _) = command {
+                distributed.push((file_id, summary.expires, summary.file_size_bytes));
+            }
+        }
+        assert_eq!(distributed.len(), 2);
+        assert_eq!(distributed[1], (id, expires, 4));
+
+        let incomplete = ShortGuid::new_random();
+        let writer = backbone
+            .new_file(incomplete, None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+        assert!(matches!(backbone.redistribute(incomplete).await, Ok(None)));
+        assert!(matches!(
+            backbone.redistribute(ShortGuid::new_random()).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+
+        drop(writer);
+        let fixture = Fixture {
+            backbone,
+            _backend_receiver: backend_receiver,
+            rendezvous,
+        };
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn extended_leases_are_sent_to_the_backends() {
+        let (sender, mut backend_receiver) = mpsc::channel(16);
+        let rendezvous = Rendezvous::new();
+        let backbone = Backbone::new(sender.into(), rendezvous.fork_guard(), LEASE);
+        let id = store_file(&backbone, b"data").await;
+        sleep(LEASE / 2).await;
+
+        let expires = backbone
+            .extend_lease(id)
+            .await
+            .expect("failed to extend lease");
+
+        let mut extended = Vec::new();
+        while let Ok(command) = backend_receiver.try_recv() {
+            if let BackendCommand::LeaseExtended(file_id, expires) = command {
+                extended.push((file_id, expires));
+            }
+        }
+        assert_eq!(extended, [(id, expires)]);
+
+        let fixture = Fixture {
+            backbone,
+            _backend_receiver: backend_receiver,
+            rendezvous,
+        };
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_files_are_kept_until_their_readers_are_dropped() {
+        let fixture = fixture_with(|backbone| backbone.with_storage_quota(10));
+        let id = store_file(&fixture.backbone, b"data").await;
+        let reader = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+
+        sleep(LEASE * 2).await;
+        assert!(matches!(
+            fixture.backbone.get_local_file(id).await,
+            Err(GetFileReaderError::UnknownFile(_))
+        ));
+        assert_eq!(fixture.backbone.available_storage(), Some(6));
+
+        let data = read_all(reader).await.expect("failed to read");
+        assert_eq!(data, b"data");
+        sleep(Duration::ZERO).await;
+        assert_eq!(fixture.backbone.available_storage(), Some(10));
+
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn files_are_marked_read_once_data_is_served() {
+        let fixture = fixture();
+        let id = store_file(&fixture.backbone, b"data").await;
+        let was_read = |backbone: &Backbone| {
+            let inner = backbone.inner.try_read().expect("failed to lock");
+            inner.open.get(&id).expect("file is missing").was_read()
+        };
+
+        // Opening a reader, e.g. to answer a HEAD request, does not read the file.
+        let reader = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        drop(reader);
+        assert!(!was_read(&fixture.backbone));
+
+        let reader = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        read_all(reader).await.expect("failed to read");
+        assert!(was_read(&fixture.backbone));
+
+        fixture.remove_and_shut_down(id).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn buffered_file_holds_reservation_until_removed() {
+        let fixture = fixture_with(|backbone| backbone.with_storage_quota(10));
+        let id = store_file(&fixture.backbone, b"data").await;
+        sleep(Duration::ZERO).await;
+        assert_eq!(fixture.backbone.available_storage(), Some(6));
+
+        fixture
+            .backbone
+            .remove_file(id)
+            .await
+            .expect("failed to remove file");
+        assert_eq!(fixture.backbone.available_storage(), Some(10));
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn files_not_matching_the_expected_sha256_are_rejected() {
+        let fixture = fixture();
+
+        let id = ShortGuid::new_random();
+        let mut writer = fixture
+            .backbone
+            .new_file(id, None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+        writer.set_expected_sha256(Some([0; 32]));
+        writer.select_hashes(HashAlgorithms::none());
+        writer.write(b"data").await.expect("failed to write");
+        writer.sync_data().await.expect("failed to sync");
+        assert!(matches!(
+            writer.finalize(CompletionMode::NoSync).await,
+            Err(FinalizationError::Sha256Mismatch(..))
+        ));
+
+        let id = ShortGuid::new_random();
+        let mut writer = fixture
+            .backbone
+            .new_file(id, None, None, None, None, None)
+            .await
+            .expect("failed to create file");
+        let mut sha256 = HashSha256::new();
+        sha256.update(b"data");
+        writer.set_expected_sha256(Some(sha256.finalize().into()));
+        writer.select_hashes(HashAlgorithms::none());
+        writer.write(b"data").await.expect("failed to write");
+        writer.sync_data().await.expect("failed to sync");
+        let summary = writer
+            .finalize(CompletionMode::NoSync)
+            .await
+            .expect("failed to finalize");
+        assert!(summary.hashes.sha256.is_some());
+
+        fixture.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn files_failing_to_finalize_are_deleted() {
+        let dir = std::env::temp_dir().join(format!("backbone-{}", ShortGuid::new_random()));
+        std::fs::create_dir(&dir).expect("failed to create directory");
+        let fixture = fixture_with(|backbone| backbone.with_temp_dir(dir.clone()));
+
+        let id = ShortGuid::new_random();
+        let mut writer = fixture
+            .backbone
+            .new_file(id, None, None, Some([0; 16]), None, None)
+            .await
+            .expect("failed to create file");
+        writer.write(b"data").await.expect("failed to write");
+        writer.sync_data().await.expect("failed to sync");
+        assert!(matches!(
+            writer.finalize(CompletionMode::NoSync).await,
+            Err(FinalizationError::IntegrityCheckFailed(..))
+        ));
+
+        // The file is removed from the bookkeeping asynchronously.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while fixture.backbone.get_local_file(id).await.is_ok() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the file was not removed");
+        let remaining = std::fs::read_dir(&dir)
+            .expect("failed to list directory")
+            .count();
+        assert_eq!(remaining, 0, "the temporary file was not deleted");
+
+        fixture.shut_down().await;
+        std::fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn files_survive_a_restart_with_a_file_index() {
+        let dir = std::env::temp_dir().join(format!("backbone-{}", ShortGuid::new_random()));
+        std::fs::create_dir(&dir).expect("failed to create directory");
+        let index_path = dir.join("files.idx");
+
+        let fixture = fixture_with(|backbone| backbone.with_temp_dir(dir.clone()));
+        let fixture = Fixture {
+            backbone: fixture
+                .backbone
+                .with_file_index(&index_path)
+                .await
+                .expect("failed to open file index"),
+            ..fixture
+        };
+        let id = store_file(&fixture.backbone, b"data").await;
+        sleep(LEASE / 2).await;
+        let expires = fixture
+            .backbone
+            .extend_lease(id)
+            .await
+            .expect("failed to extend lease");
+        fixture.shut_down().await;
+
+        // The file is read in place rather than being written again.
+        let path = dir.join(format!("yeet_{id}"));
+        let modified = std::time::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(modified))
+            .expect("failed to set the modification time");
+
+        // The file is kept on disk and restored with its extended lease.
+        let fixture = fixture_with(|backbone| backbone.with_temp_dir(dir.clone()));
+        let fixture = Fixture {
+            backbone: fixture
+                .backbone
+                .with_file_index(&index_path)
+                .await
+                .expect("failed to open file index"),
+            ..fixture
+        };
+        let existing = fixture
+            .backbone
+            .find_existing(id)
+            .await
+            .expect("file was not restored");
+        assert_eq!(existing.summary.file_size_bytes, 4);
+        let drift = existing
+            .expires
+            .max(expires)
+            .duration_since(existing.expires.min(expires));
+        assert!(drift < Duration::from_secs(1));
+
+        let reader = fixture
+            .backbone
+            .get_local_file(id)
+            .await
+            .expect("failed to get reader");
+        let data = read_all(reader).await.expect("failed to read");
+        assert_eq!(data, b"data");
+        let metadata = std::fs::metadata(&path).expect("failed to read file metadata");
+        assert_eq!(metadata.modified().ok(), Some(modified));
+
+        // Once the re-armed lease expires, the file is deleted.
+        sleep(LEASE * 2).await;
+        assert!(fixture.backbone.find_existing(id).await.is_none());
+        fixture.shut_down().await;
+        let remaining: Vec<_> = std::fs::read_dir(&dir)
+            .expect("failed to list directory")
+            .map(|entry| entry.expect("failed to read entry").file_name())
+            .collect();
+        assert_eq!(remaining, ["files.idx"]);
+
+        std::fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
 }
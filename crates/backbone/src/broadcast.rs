@@ -0,0 +1,137 @@
+use shortguid::ShortGuid;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tracing::{debug, warn};
+
+/// The size of the chunks read from the underlying file.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A single read pass over a completely written file whose content is shared
+/// between all concurrent readers.
+///
+/// ## Remarks
+///
+/// The content is kept in memory for as long as at least one reader (or the
+/// read pass itself) is alive. Readers that subscribe after the read pass started
+/// catch up from the shared buffer before receiving the remaining bytes.
+#[derive(Debug)]
+pub(crate) struct Broadcast {
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The chunks read so far.
+    chunks: Vec<Vec<u8>>,
+    /// Whether the read pass has completed.
+    completed: bool,
+    /// The error that terminated the read pass, if any.
+    error: Option<(ErrorKind, String)>,
+    /// The readers waiting for more data.
+    wakers: Vec<Waker>,
+}
+
+impl Broadcast {
+    /// Starts a new read pass over the file.
+    pub fn start<R>(id: ShortGuid, reader: R) -> Arc<Self>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+    {
+        let broadcast = Arc::new(Self {
+            state: Mutex::default(),
+        });
+        tokio::spawn(Self::read_pass(id, reader, broadcast.clone()));
+        broadcast
+    }
+
+    /// Creates a new reader starting at the beginning of the file.
+    pub fn subscribe(self: &Arc<Self>) -> BroadcastReader {
+        BroadcastReader {
+            broadcast: self.clone(),
+            chunk: 0,
+            offset: 0,
+        }
+    }
+
+    async fn read_pass<R>(id: ShortGuid, mut reader: R, this: Arc<Self>)
+    where
+        R: AsyncRead + Unpin,
+    {
+        debug!(file_id = %id, "Starting shared read pass over file {id}");
+        loop {
+            let mut chunk = vec![0; CHUNK_SIZE];
+            let result = reader.read(&mut chunk).await;
+
+            let mut state = this.state.lock().expect("failed to lock broadcast state");
+            match result {
+                Ok(0) => state.completed = true,
+                Ok(bytes_read) => {
+                    chunk.truncate(bytes_read);
+                    state.chunks.push(chunk);
+                }
+                Err(e) => {
+                    warn!(file_id = %id, "Shared read pass over file {id} failed: {e}");
+                    state.error = Some((e.kind(), e.to_string()));
+                }
+            }
+
+            let done = state.completed || state.error.is_some();
+            state.wakers.drain(..).for_each(Waker::wake);
+            if done {
+                debug!(file_id = %id, "Completed shared read pass over file {id}");
+                return;
+            }
+        }
+    }
+}
+
+/// A reader consuming a [`Broadcast`].
+pub(crate) struct BroadcastReader {
+    broadcast: Arc<Broadcast>,
+    /// The index of the chunk to read next.
+    chunk: usize,
+    /// The read offset within the current chunk.
+    offset: usize,
+}
+
+impl AsyncRead for BroadcastReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+        let mut state = this
+            .broadcast
+            .state
+            .lock()
+            .expect("failed to lock broadcast state");
+
+        if let Some(chunk) = state.chunks.get(this.chunk) {
+            let remaining = &chunk[this.offset..];
+            let len = remaining.len().min(buf.remaining());
+            buf.put_slice(&remaining[..len]);
+
+            this.offset += len;
+            if this.offset == chunk.len() {
+                this.chunk += 1;
+                this.offset = 0;
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        if let Some((kind, message)) = &state.error {
+            return Poll::Ready(Err(std::io::Error::new(*kind, message.clone())));
+        }
+
+        if state.completed {
+            return Poll::Ready(Ok(()));
+        }
+
+        state.wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,95 @@
+use shortguid::ShortGuid;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use tokio::time::Instant;
+
+/// The expiration dates of buffered files, ordered by time.
+///
+/// Entries are not updated when a lease is extended or a file is removed; whoever
+/// takes expired entries from the queue checks them against the current lease.
+#[derive(Debug, Default)]
+pub(crate) struct ExpiryQueue {
+    heap: BinaryHeap<Reverse<(Instant, ShortGuid)>>,
+}
+
+impl ExpiryQueue {
+    /// Schedules the file to expire at the specified time.
+    pub fn schedule(&mut self, id: ShortGuid, expires: Instant) {
+        self.heap.push(Reverse((expires, id)));
+    }
+
+    /// Gets the number of scheduled entries.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Gets the earliest scheduled expiration date, if any.
+    pub fn next_expiration(&self) -> Option<Instant> {
+        self.heap.peek().map(|Reverse((expires, _))| *expires)
+    }
+
+    /// Waits until the earliest scheduled entry expires.
+    ///
+    /// Never completes if the queue is empty.
+    pub async fn expired(&self) {
+        match self.next_expiration() {
+            Some(expires) => tokio::time::sleep_until(expires).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Takes all entries that expired at `now`, earliest first.
+    pub fn take_expired(&mut self, now: Instant) -> Vec<ShortGuid> {
+        let mut expired = Vec::new();
+        while let Some(Reverse((expires, id))) = self.heap.peek() {
+            if *expires > now {
+                break;
+            }
+            expired.push(*id);
+            self.heap.pop();
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn expired_entries_are_taken_in_order() {
+        let now = Instant::now();
+        let (first, second, third) = (
+            ShortGuid::new_random(),
+            ShortGuid::new_random(),
+            ShortGuid::new_random(),
+        );
+
+        let mut queue = ExpiryQueue::default();
+        queue.schedule(third, now + Duration::from_secs(3));
+        queue.schedule(first, now + Duration::from_secs(1));
+        queue.schedule(second, now + Duration::from_secs(2));
+        assert_eq!(queue.next_expiration(), Some(now + Duration::from_secs(1)));
+
+        assert!(queue.take_expired(now).is_empty());
+        assert_eq!(
+            queue.take_expired(now + Duration::from_secs(2)),
+            vec![first, second]
+        );
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.next_expiration(), Some(now + Duration::from_secs(3)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waiting_completes_when_the_earliest_entry_expires() {
+        let mut queue = ExpiryQueue::default();
+        let empty = tokio::time::timeout(Duration::from_secs(3600), queue.expired());
+        assert!(empty.await.is_err(), "an empty queue never expires");
+
+        let expires = Instant::now() + Duration::from_secs(5);
+        queue.schedule(ShortGuid::new_random(), expires);
+        queue.expired().await;
+        assert_eq!(Instant::now(), expires);
+    }
+}
@@ -38,7 +38,7 @@ impl FileAccessorBridge {
 impl GetFile for FileAccessorBridge {
     async fn get_file(&self, id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
         match self.get_backbone() {
-            Ok(backbone) => Ok(backbone.get_file(id).await?),
+            Ok(backbone) => Ok(backbone.get_local_file(id).await?),
             Err(GetBackboneError::BackboneUnavailable) => {
                 Err(FileAccessorError::BackboneUnavailable)
             }
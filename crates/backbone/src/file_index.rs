@@ -0,0 +1,262 @@
+//! Contains the journal persisting locally buffered files across restarts, see [`FileIndex`].
+
+use file_distribution::metadata::file_index_entry::Change;
+use file_distribution::metadata::{FileIndexEntry, ItemMetadata};
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// An append-only journal of the locally buffered files.
+///
+/// Every file is recorded when it is created, again when writing completed or its lease
+/// was extended, and once more when it is removed. Replaying the journal at startup
+/// yields the files that survived a restart; files of incomplete uploads are deleted.
+///
+/// ## Remarks
+///
+/// Entries are written synchronously but not synced to disk; they are small and written
+/// at most a few times per file. A torn entry at the end of the journal, e.g. after a
+/// crash, ends the replay.
+#[derive(Debug)]
+pub(crate) struct FileIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    journal: File,
+    /// The paths of the files created and not yet removed.
+    paths: HashMap<ShortGuid, PathBuf>,
+}
+
+/// A completely written file recovered from the index.
+#[derive(Debug)]
+pub(crate) struct IndexedFile {
+    pub id: ShortGuid,
+    /// The path the file is buffered at.
+    pub path: PathBuf,
+    /// The metadata recorded when writing completed, reflecting the latest lease.
+    pub metadata: ItemMetadata,
+    /// The duration by which the lease is extended.
+    pub lease: Duration,
+}
+
+/// The state of a file while replaying the journal.
+struct Replayed {
+    path: PathBuf,
+    stored: Option<(ItemMetadata, Duration)>,
+}
+
+impl FileIndex {
+    /// Opens the index at `path`, creating it if necessary.
+    ///
+    /// The journal is replayed and compacted such that it only retains the returned files,
+    /// i.e. completely written files whose lease has not expired and that are still on disk.
+    /// The files of incomplete uploads and expired files are deleted.
+    pub fn open(path: &Path) -> std::io::Result<(Self, Vec<IndexedFile>)> {
+        let journal = match std::fs::read(path) {
+            Ok(journal) => journal,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        let files = Self::replay(&journal, SystemTime::now());
+        info!(
+            "Restoring {count} files from the file index {path:?}",
+            count = files.len()
+        );
+
+        // Rewrite the journal with the surviving files only.
+        let compacted_path = path.with_extension("compacting");
+        let mut compacted = File::create(&compacted_path)?;
+        let mut paths = HashMap::with_capacity(files.len());
+        for file in &files {
+            let created = FileIndexEntry::created(file.id, file.path.to_string_lossy().into());
+            let stored = FileIndexEntry::stored(file.id, file.metadata.clone(), file.lease);
+            compacted.write_all(&created.serialize_length_delimited())?;
+            compacted.write_all(&stored.serialize_length_delimited())?;
+            paths.insert(file.id, file.path.clone());
+        }
+        compacted.sync_all()?;
+        drop(compacted);
+        std::fs::rename(&compacted_path, path)?;
+
+        let journal = OpenOptions::new().append(true).open(path)?;
+        let index = Self {
+            inner: Mutex::new(Inner { journal, paths }),
+        };
+        Ok((index, files))
+    }
+
+    /// Folds the journal into the files that are still alive at `now`.
+    fn replay(mut journal: &[u8], now: SystemTime) -> Vec<IndexedFile> {
+        let mut files: HashMap<ShortGuid, Replayed> = HashMap::new();
+        while !journal.is_empty() {
+            let entry = match FileIndexEntry::deserialize_length_delimited(&mut journal) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Ignoring the remainder of the file index: {e}");
+                    break;
+                }
+            };
+            let Ok(id) = ShortGuid::from_slice(&entry.id) else {
+                warn!("Ignoring a file index entry with an invalid ID");
+                continue;
+            };
+
+            match entry.change {
+                Some(Change::Created(path)) => {
+                    files.insert(
+                        id,
+                        Replayed {
+                            path: PathBuf::from(path),
+                            stored: None,
+                        },
+                    );
+                }
+                Some(Change::Stored(stored)) => {
+                    if let (Some(file), Some(metadata)) = (files.get_mut(&id), stored.metadata) {
+                        file.stored = Some((metadata, Duration::from_millis(stored.lease_ms)));
+                    }
+                }
+                Some(Change::Removed(_)) => {
+                    files.remove(&id);
+                }
+                None => {}
+            }
+        }
+
+        let now_unix_ms = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_millis() as u64);
+        let mut alive = Vec::with_capacity(files.len());
+        for (id, file) in files {
+            let Some((metadata, lease)) = file.stored else {
+                debug!(file_id = %id, "Deleting file {id}, which was not written completely");
+                delete_file(id, &file.path);
+                continue;
+            };
+            if metadata.expires_unix_ms.unwrap_or(0) <= now_unix_ms {
+                debug!(file_id = %id, "Deleting file {id}, whose lease expired");
+                delete_file(id, &file.path);
+                continue;
+            }
+            if !file.path.is_file() {
+                warn!(file_id = %id, "Dropping file {id} from the index: {path:?} no longer exists", path = file.path);
+                continue;
+            }
+            alive.push(IndexedFile {
+                id,
+                path: file.path,
+                metadata,
+                lease,
+            });
+        }
+        alive
+    }
+
+    /// Records that the file was created at `path`.
+    pub fn created(&self, id: ShortGuid, path: &Path) {
+        let mut inner = self.inner.lock().expect("failed to lock file index");
+        inner.paths.insert(id, path.to_path_buf());
+        inner.append(FileIndexEntry::created(id, path.to_string_lossy().into()));
+    }
+
+    /// Records that writing the file completed, or that its lease was extended.
+    pub fn stored(&self, id: ShortGuid, metadata: ItemMetadata, lease: Duration) {
+        let mut inner = self.inner.lock().expect("failed to lock file index");
+        inner.append(FileIndexEntry::stored(id, metadata, lease));
+    }
+
+    /// Deletes the file from disk and records its removal.
+    pub fn remove(&self, id: ShortGuid) {
+        let mut inner = self.inner.lock().expect("failed to lock file index");
+        if let Some(path) = inner.paths.remove(&id) {
+            delete_file(id, &path);
+            inner.append(FileIndexEntry::removed(id));
+        }
+    }
+}
+
+impl Inner {
+    fn append(&mut self, entry: FileIndexEntry) {
+        if let Err(e) = self.journal.write_all(&entry.serialize_length_delimited()) {
+            warn!("Failed to write to the file index: {e}");
+        }
+    }
+}
+
+/// Deletes a buffered file, ignoring files that are already gone.
+fn delete_file(id: ShortGuid, path: &Path) {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => warn!(file_id = %id, "Failed to delete file {id} at {path:?}: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(id: ShortGuid, expires_unix_ms: u64) -> ItemMetadata {
+        ItemMetadata {
+            id: Vec::from(id.as_bytes()),
+            expires_unix_ms: Some(expires_unix_ms),
+            ..ItemMetadata::default()
+        }
+    }
+
+    #[test]
+    fn replaying_keeps_live_files_only() {
+        let dir = std::env::temp_dir().join(format!("file-index-{}", ShortGuid::new_random()));
+        std::fs::create_dir(&dir).expect("failed to create directory");
+        let path = |name: &str| dir.join(name);
+        for name in ["live", "removed", "incomplete", "expired"] {
+            std::fs::write(path(name), b"data").expect("failed to write file");
+        }
+
+        let [live, removed, incomplete, expired, missing] =
+            [(); 5].map(|_| ShortGuid::new_random());
+        let lease = Duration::from_secs(60);
+        let entries = [
+            FileIndexEntry::created(live, path("live").to_string_lossy().into()),
+            FileIndexEntry::created(removed, path("removed").to_string_lossy().into()),
+            FileIndexEntry::created(incomplete, path("incomplete").to_string_lossy().into()),
+            FileIndexEntry::created(expired, path("expired").to_string_lossy().into()),
+            FileIndexEntry::created(missing, path("missing").to_string_lossy().into()),
+            FileIndexEntry::stored(live, metadata(live, 1_000), lease),
+            FileIndexEntry::stored(removed, metadata(removed, 2_000), lease),
+            FileIndexEntry::removed(removed),
+            FileIndexEntry::stored(expired, metadata(expired, 500), lease),
+            FileIndexEntry::stored(missing, metadata(missing, 2_000), lease),
+            FileIndexEntry::stored(live, metadata(live, 2_000), lease),
+        ];
+        let mut journal: Vec<u8> = entries
+            .iter()
+            .flat_map(FileIndexEntry::serialize_length_delimited)
+            .collect();
+        // A torn entry at the end, e.g. after a crash.
+        journal.extend_from_slice(&[0x20, 0x01]);
+
+        let files = FileIndex::replay(&journal, UNIX_EPOCH + Duration::from_millis(1_000));
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].id, live);
+        assert_eq!(files[0].path, path("live"));
+        assert_eq!(files[0].metadata.expires_unix_ms, Some(2_000));
+        assert_eq!(files[0].lease, lease);
+
+        // Files of incomplete uploads and expired files are deleted.
+        assert!(path("live").is_file());
+        assert!(path("removed").is_file());
+        assert!(!path("incomplete").exists());
+        assert!(!path("expired").exists());
+
+        std::fs::remove_dir_all(&dir).expect("failed to clean up");
+    }
+}
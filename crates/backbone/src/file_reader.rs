@@ -1,3 +1,5 @@
+use crate::broadcast::BroadcastReader;
+use crate::file_record::ReaderGuard;
 use axum::headers::ContentType;
 use file_distribution::{FileReaderTrait, WriteSummary};
 use metrics::transfer::{TransferMethod, TransferMetrics};
@@ -6,58 +8,113 @@ use std::borrow::Cow;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncRead, ReadBuf};
 use tokio::time::Instant;
 
 /// A read accessor for a temporary file.
 pub struct FileReader {
     /// The file reader.
-    inner: SharedTemporaryFileReader,
+    inner: ReaderSource,
     content_type: Option<String>,
     created: Instant,
-    expiration_duration: Duration,
+    creation_date: SystemTime,
+    expires: Instant,
     summary: Option<Arc<WriteSummary>>,
+    /// The number of bytes read and not yet reported to the transfer metrics.
+    bytes_read: usize,
+    /// Keeps the file record from being removed while the reader is alive.
+    guard: Option<ReaderGuard>,
+}
+
+/// The source a [`FileReader`] reads from.
+pub(crate) enum ReaderSource {
+    /// A dedicated reader of the temporary file.
+    File(SharedTemporaryFileReader),
+    /// A reader sharing a single read pass over a completely written file.
+    Broadcast(BroadcastReader),
+    /// A reader of a file restored from the file index, which is read in place.
+    Restored(tokio::fs::File),
+}
+
+impl From<SharedTemporaryFileReader> for ReaderSource {
+    fn from(reader: SharedTemporaryFileReader) -> Self {
+        Self::File(reader)
+    }
+}
+
+impl From<BroadcastReader> for ReaderSource {
+    fn from(reader: BroadcastReader) -> Self {
+        Self::Broadcast(reader)
+    }
 }
 
 impl FileReader {
-    pub fn new(
-        reader: SharedTemporaryFileReader,
+    pub(crate) fn new(
+        reader: impl Into<ReaderSource>,
         content_type: Option<ContentType>,
         created: Instant,
-        expiration_duration: Duration,
+        creation_date: SystemTime,
+        expires: Instant,
         summary: Option<Arc<WriteSummary>>,
     ) -> Self {
         Self {
-            inner: reader,
+            inner: reader.into(),
             content_type: content_type.map(|c| c.to_string()),
             created,
-            expiration_duration,
+            creation_date,
+            expires,
             summary,
+            bytes_read: 0,
+            guard: None,
         }
     }
 
+    /// Registers the reader with its file record until the reader is dropped.
+    pub(crate) fn with_guard(mut self, guard: ReaderGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
     pub fn summary(&self) -> &Option<Arc<WriteSummary>> {
         &self.summary
     }
 
     pub fn expiration_date(&self) -> Instant {
-        self.created + self.expiration_duration
+        self.expires
     }
 
     pub fn file_size(&self) -> FileSize {
-        self.inner.file_size()
+        match (&self.inner, &self.summary) {
+            (ReaderSource::File(reader), _) => reader.file_size(),
+            (ReaderSource::Broadcast(_) | ReaderSource::Restored(_), Some(summary)) => {
+                FileSize::Exactly(summary.file_size_bytes)
+            }
+            (ReaderSource::Broadcast(_) | ReaderSource::Restored(_), None) => FileSize::Error,
+        }
     }
 
     pub fn file_age(&self) -> Duration {
         Instant::now() - self.created
     }
 
-    pub fn content_type(&self) -> Option<Cow<str>> {
+    pub fn creation_date(&self) -> SystemTime {
+        self.creation_date
+    }
+
+    pub fn content_type(&self) -> Option<Cow<'_, str>> {
         self.content_type
             .as_ref()
             .map(|content_type| Cow::from(content_type.as_str()))
     }
+
+    /// Reports the bytes read so far to the transfer metrics.
+    fn track_bytes_read(&mut self) {
+        if self.bytes_read > 0 {
+            TransferMetrics::track_bytes_transferred(TransferMethod::Fetch, self.bytes_read);
+            self.bytes_read = 0;
+        }
+    }
 }
 
 impl FileReaderTrait for FileReader {
@@ -77,7 +134,11 @@ impl FileReaderTrait for FileReader {
         self.file_age()
     }
 
-    fn content_type(&self) -> Option<Cow<str>> {
+    fn creation_date(&self) -> SystemTime {
+        self.creation_date()
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
         self.content_type()
     }
 }
@@ -88,13 +149,77 @@ impl AsyncRead for FileReader {
         cx: &mut Context<'_>,
         buf: &mut ReadBuf<'_>,
     ) -> Poll<std::io::Result<()>> {
-        match Pin::new(&mut self.inner).poll_read(cx, buf) {
-            Poll::Ready(read) => {
-                let bytes_read = buf.filled().len();
-                TransferMetrics::track_bytes_transferred(TransferMethod::Fetch, bytes_read);
-                Poll::Ready(read)
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = poll {
+            let bytes_read = buf.filled().len() - filled;
+            self.bytes_read += bytes_read;
+            if bytes_read > 0 {
+                if let Some(guard) = &self.guard {
+                    guard.mark_read();
+                }
             }
-            Poll::Pending => Poll::Pending,
+
+            // Reading zero bytes into a non-empty buffer indicates the end of the file.
+            if bytes_read == 0 && buf.remaining() > 0 {
+                self.track_bytes_read();
+            }
+        }
+
+        poll
+    }
+}
+
+impl AsyncRead for ReaderSource {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ReaderSource::File(reader) => Pin::new(reader).poll_read(cx, buf),
+            ReaderSource::Broadcast(reader) => Pin::new(reader).poll_read(cx, buf),
+            ReaderSource::Restored(file) => Pin::new(file).poll_read(cx, buf),
         }
     }
 }
+
+/// Reports the bytes read if the reader is dropped before reaching the end of the file.
+impl Drop for FileReader {
+    fn drop(&mut self) {
+        self.track_bytes_read();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared_files::SharedTemporaryFile;
+    use shortguid::ShortGuid;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn bytes_read_are_reported_at_end_of_file() {
+        let file = SharedTemporaryFile::new_with_uuid(ShortGuid::new_random().into())
+            .await
+            .expect("failed to create file");
+        let mut writer = file.writer().await.expect("failed to create writer");
+        writer
+            .write_all(b"yeet yoink")
+            .await
+            .expect("failed to write");
+        writer.complete().await.expect("failed to complete");
+
+        let reader = file.reader().await.expect("failed to create reader");
+        let now = Instant::now();
+        let mut reader = FileReader::new(reader, None, now, SystemTime::now(), now, None);
+
+        let mut chunk = [0; 4];
+        reader.read_exact(&mut chunk).await.expect("failed to read");
+        assert_eq!(reader.bytes_read, 4);
+
+        while reader.read(&mut chunk).await.expect("failed to read") > 0 {}
+        assert_eq!(reader.bytes_read, 0);
+    }
+}
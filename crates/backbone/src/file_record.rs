@@ -1,16 +1,28 @@
 use crate::backbone::BackboneCommand;
+use crate::broadcast::{Broadcast, BroadcastReader};
+use crate::file_index::FileIndex;
+use crate::file_reader::ReaderSource;
 use crate::file_writer_guard::WriteResult;
-use axum::headers::ContentType;
+use crate::hash_index::HashIndex;
+use crate::storage_quota::StorageReservation;
+use crate::upload_progress::{ProgressTracker, UploadProgress};
+use axum::headers::{ContentType, Header};
+use axum::http::HeaderValue;
+use backend_traits::DistributionOutcome;
+use file_distribution::metadata::ItemMetadata;
 use file_distribution::{GetFileReaderError, WriteSummary};
-use shared_files::{SharedTemporaryFile, SharedTemporaryFileReader};
+use metrics::backbone::RemovalReason;
+use shared_files::SharedTemporaryFile;
 use shortguid::ShortGuid;
-use std::sync::Arc;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot::Receiver;
-use tokio::sync::RwLock;
+use tokio::sync::{oneshot, watch, RwLock};
 use tokio::time::Instant;
-use tracing::{info, warn};
+use tracing::{debug, info, info_span, warn, Instrument, Span};
 
 #[derive(Debug)]
 pub(crate) struct FileRecord {
@@ -20,15 +32,51 @@ pub(crate) struct FileRecord {
     pub content_type: Option<ContentType>,
     /// The time when the file was created.
     pub created: Instant,
-    /// The time after which the file will be inaccessible.
+    /// The wall-clock time when the file was created.
+    pub creation_date: SystemTime,
+    /// The duration for which the file is kept alive after writing completed
+    /// or its lease was extended.
     pub expiration_duration: Duration,
+    /// The time after which the file will be inaccessible.
+    lease: Arc<watch::Sender<Instant>>,
+    /// The progress of the upload.
+    progress: Arc<ProgressTracker>,
+    /// The currently running shared read pass, if any.
+    broadcast: Mutex<Weak<Broadcast>>,
+    /// The number of read passes opened on the underlying file.
+    read_passes: AtomicUsize,
+    /// The number of readers handed out and not yet dropped.
+    readers: Arc<watch::Sender<usize>>,
+    /// Whether any data of the file was read.
+    read: Arc<AtomicBool>,
+    /// The size of the file as reported to the buffer metrics; `0` until writing completed.
+    buffered_bytes: AtomicU64,
+    /// The storage space reserved for the file, released when the record is dropped.
+    storage_reservation: Option<Arc<StorageReservation>>,
+    /// The index the file is deduplicated with; its entries are removed when the record is dropped.
+    hash_index: Option<Arc<HashIndex>>,
+    /// The index persisting the file across restarts, if enabled.
+    file_index: Option<Arc<FileIndex>>,
+    /// Notified of the outcome of distributing the file, if requested.
+    distribution_waiter: Mutex<Option<oneshot::Sender<DistributionOutcome>>>,
     inner: Arc<RwLock<Inner>>,
 }
 
 #[derive(Debug)]
 struct Inner {
-    file: Option<SharedTemporaryFile>,
+    file: Option<BufferedFile>,
     summary: Option<Arc<WriteSummary>>,
+    /// The metadata record built when writing completed.
+    metadata: Option<ItemMetadata>,
+}
+
+/// The buffer holding the content of a file.
+#[derive(Debug)]
+enum BufferedFile {
+    /// A temporary file written by the backbone; its readers follow the writer.
+    Shared(SharedTemporaryFile),
+    /// A completely written file restored from the file index, which is read in place.
+    Restored(PathBuf),
 }
 
 impl FileRecord {
@@ -42,107 +90,413 @@ impl FileRecord {
         created: Instant,
     ) -> Self {
         let inner = Arc::new(RwLock::new(Inner {
-            file: Some(file),
+            file: Some(BufferedFile::Shared(file)),
             summary: None,
+            metadata: None,
         }));
-        let _ = tokio::spawn(Self::lifetime_handler(
+        let (lease, _) = watch::channel(created + duration);
+        let lease = Arc::new(lease);
+        let creation_date = system_time(created);
+        let metadata = MetadataTemplate {
+            content_type: content_type.as_ref().map(ContentType::to_string),
+            created: creation_date,
+        };
+        // The span is a child of the span creating the file, e.g. that of the upload request.
+        let span = info_span!("file", file_id = %id);
+        tokio::spawn(
+            Self::lifetime_handler(
+                id,
+                inner.clone(),
+                backbone_command,
+                writer_command,
+                duration,
+                lease.clone(),
+                metadata,
+            )
+            .instrument(span),
+        );
+        Self::from_parts(
             id,
-            inner.clone(),
-            backbone_command,
-            writer_command,
+            inner,
+            lease,
             duration,
-        ));
+            content_type,
+            created,
+            creation_date,
+        )
+    }
+
+    /// Recreates the record of a completely written file at `path` from its metadata, e.g. one
+    /// restored from the file index.
+    ///
+    /// Unlike [`FileRecord::new`], the file is ready for reading right away and its lease
+    /// ends at the expiration date of the metadata. The file is read in place and its size
+    /// is taken from the metadata. Returns `None` if the metadata is incomplete.
+    pub fn restored(
+        id: ShortGuid,
+        path: PathBuf,
+        metadata: ItemMetadata,
+        duration: Duration,
+    ) -> Option<Self> {
+        let unix_time = |millis| UNIX_EPOCH + Duration::from_millis(millis);
+        let creation_date = unix_time(metadata.created_unix_ms?);
+        let created = instant(creation_date);
+        let expires = instant(unix_time(metadata.expires_unix_ms?));
+        let file_size_bytes = usize::try_from(metadata.file_size_bytes?).ok()?;
+        let summary = metadata.to_summary(file_size_bytes, expires)?;
+        let content_type = metadata
+            .content_type
+            .as_deref()
+            .and_then(parse_content_type);
+
+        let inner = Arc::new(RwLock::new(Inner {
+            file: Some(BufferedFile::Restored(path)),
+            summary: Some(summary),
+            metadata: Some(metadata),
+        }));
+        let (lease, _) = watch::channel(expires);
+        Some(Self::from_parts(
+            id,
+            inner,
+            Arc::new(lease),
+            duration,
+            content_type,
+            created,
+            creation_date,
+        ))
+    }
+
+    fn from_parts(
+        id: ShortGuid,
+        inner: Arc<RwLock<Inner>>,
+        lease: Arc<watch::Sender<Instant>>,
+        duration: Duration,
+        content_type: Option<ContentType>,
+        created: Instant,
+        creation_date: SystemTime,
+    ) -> Self {
         Self {
             id,
             inner,
             content_type,
             created,
+            creation_date,
             expiration_duration: duration,
+            lease,
+            progress: Arc::default(),
+            broadcast: Mutex::default(),
+            read_passes: AtomicUsize::new(0),
+            readers: Arc::new(watch::Sender::new(0)),
+            read: Arc::default(),
+            buffered_bytes: AtomicU64::new(0),
+            storage_reservation: None,
+            hash_index: None,
+            file_index: None,
+            distribution_waiter: Mutex::default(),
         }
     }
 
+    /// Sets the tracker used to report the upload progress.
+    pub fn with_progress(mut self, progress: Arc<ProgressTracker>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Sets the storage space reserved for the file.
+    pub fn with_storage_reservation(
+        mut self,
+        reservation: Option<Arc<StorageReservation>>,
+    ) -> Self {
+        self.storage_reservation = reservation;
+        self
+    }
+
+    /// Sets the index the file is deduplicated with.
+    pub fn with_hash_index(mut self, index: Option<Arc<HashIndex>>) -> Self {
+        self.hash_index = index;
+        self
+    }
+
+    /// Sets the index persisting the file across restarts.
+    pub fn with_file_index(mut self, index: Option<Arc<FileIndex>>) -> Self {
+        self.file_index = index;
+        self
+    }
+
+    /// Gets the size of the file as reported to the buffer metrics.
+    pub fn buffered_bytes(&self) -> u64 {
+        self.buffered_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Sets the size of the file as reported to the buffer metrics.
+    pub fn set_buffered_bytes(&self, bytes: u64) {
+        self.buffered_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Gets the time after which the file will be inaccessible.
+    pub fn expiration_date(&self) -> Instant {
+        *self.lease.borrow()
+    }
+
+    /// Resets the temporal lease of the file such that it expires one lease
+    /// duration from now. Returns the new expiration date.
+    pub async fn extend_lease(&self) -> Result<Instant, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        if inner.file.is_none() {
+            return Err(GetFileReaderError::FileExpired(self.id));
+        }
+
+        let expires = Instant::now() + self.expiration_duration;
+        self.lease.send_replace(expires);
+        if let (Some(index), Some(metadata)) = (&self.file_index, &inner.metadata) {
+            let metadata = metadata.clone().with_expires(system_time(expires));
+            index.stored(self.id, metadata, self.expiration_duration);
+        }
+        info!(file_id = %self.id, "Extended the lease of file {id} by {duration:?}", id = self.id, duration = self.expiration_duration);
+        Ok(expires)
+    }
+
+    /// Records the completely written file in the file index, if enabled.
+    pub async fn persist(&self) {
+        let Some(index) = &self.file_index else {
+            return;
+        };
+        if let Some(metadata) = self.get_metadata().await {
+            index.stored(self.id, metadata, self.expiration_duration);
+        }
+    }
+
+    /// Deletes the file from disk if it is persisted in the file index, and records its removal.
+    ///
+    /// Must only be called once the file is no longer in use; files that are not
+    /// persisted are deleted when their last reference is dropped.
+    pub fn discard(&self) {
+        if let Some(index) = &self.file_index {
+            index.remove(self.id);
+        }
+    }
+
+    /// Closes the file for new readers and ends its lease early.
+    ///
+    /// Currently open readers continue to work; the file is removed from disk
+    /// when the last reader is closed.
+    pub async fn close(&self) {
+        let mut inner = self.inner.write().await;
+        inner.file.take();
+        self.lease.send_replace(Instant::now());
+        info!(file_id = %self.id, "Closed file {id}", id = self.id);
+    }
+
     /// Gets an additional reader for the file.
-    pub async fn get_reader(&self) -> Result<SharedTemporaryFileReader, GetFileReaderError> {
+    pub async fn get_reader(&self) -> Result<ReaderSource, GetFileReaderError> {
         let inner = self.inner.read().await;
-        match &inner.file {
-            None => Err(GetFileReaderError::FileExpired(self.id)),
-            Some(file) => Ok(file
+        let reader = match &inner.file {
+            None => return Err(GetFileReaderError::FileExpired(self.id)),
+            Some(BufferedFile::Shared(file)) => file
                 .reader()
                 .await
-                .map_err(|e| GetFileReaderError::FileError(self.id, e))?),
+                .map(ReaderSource::from)
+                .map_err(|e| GetFileReaderError::FileError(self.id, e))?,
+            Some(BufferedFile::Restored(path)) => tokio::fs::File::open(path)
+                .await
+                .map(ReaderSource::Restored)
+                .map_err(|e| GetFileReaderError::FileError(self.id, e.into()))?,
+        };
+        let passes = self.read_passes.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!(file_id = %self.id, "Opened read pass {passes} over file {id}", id = self.id);
+        Ok(reader)
+    }
+
+    /// Gets a reader sharing a single read pass over the file with all other
+    /// concurrent broadcast readers.
+    ///
+    /// Returns `None` if the file is not completely written yet or is larger
+    /// than `max_bytes`, in which case a regular reader should be used.
+    pub async fn get_broadcast_reader(
+        &self,
+        max_bytes: u64,
+    ) -> Result<Option<BroadcastReader>, GetFileReaderError> {
+        match self.get_summary().await {
+            Some(summary) if summary.file_size_bytes as u64 <= max_bytes => {}
+            _ => return Ok(None),
+        }
+
+        if let Some(broadcast) = self.running_broadcast() {
+            return Ok(Some(broadcast.subscribe()));
+        }
+
+        let reader = self.get_reader().await?;
+        let mut current = self.broadcast.lock().expect("failed to lock broadcast");
+
+        // Another reader may have started a read pass in the meantime.
+        let broadcast = match current.upgrade() {
+            Some(broadcast) => broadcast,
+            None => {
+                let broadcast = Broadcast::start(self.id, reader);
+                *current = Arc::downgrade(&broadcast);
+                broadcast
+            }
+        };
+        Ok(Some(broadcast.subscribe()))
+    }
+
+    /// Requests to be notified of the outcome of distributing the file to the backends.
+    ///
+    /// Only the most recent request is notified.
+    pub fn wait_for_distribution(&self) -> oneshot::Receiver<DistributionOutcome> {
+        let (sender, receiver) = oneshot::channel();
+        *self
+            .distribution_waiter
+            .lock()
+            .expect("failed to lock distribution waiter") = Some(sender);
+        receiver
+    }
+
+    /// Takes the channel to notify of the outcome of distributing the file, if any.
+    pub fn take_distribution_waiter(&self) -> Option<oneshot::Sender<DistributionOutcome>> {
+        self.distribution_waiter
+            .lock()
+            .expect("failed to lock distribution waiter")
+            .take()
+    }
+
+    /// Registers a reader of the file until the returned guard is dropped.
+    pub fn track_reader(&self) -> ReaderGuard {
+        self.readers.send_modify(|readers| *readers += 1);
+        ReaderGuard {
+            readers: self.readers.clone(),
+            read: self.read.clone(),
         }
     }
 
+    /// Determines whether any data of the file was read.
+    pub fn was_read(&self) -> bool {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of readers handed out and not yet dropped.
+    pub fn active_readers(&self) -> usize {
+        *self.readers.borrow()
+    }
+
+    /// Waits until all readers handed out are dropped.
+    pub async fn readers_dropped(&self) {
+        let mut readers = self.readers.subscribe();
+        readers.wait_for(|readers| *readers == 0).await.ok();
+    }
+
+    /// Gets the number of read passes opened on the underlying file.
+    #[cfg(test)]
+    pub fn read_passes(&self) -> usize {
+        self.read_passes.load(Ordering::Relaxed)
+    }
+
+    fn running_broadcast(&self) -> Option<Arc<Broadcast>> {
+        self.broadcast
+            .lock()
+            .expect("failed to lock broadcast")
+            .upgrade()
+    }
+
+    /// Gets the progress of the upload.
+    pub async fn upload_progress(&self) -> UploadProgress {
+        let completed = self.get_summary().await.is_some();
+        self.progress.snapshot(completed)
+    }
+
     /// Gets the file write summary or `None`, if the file writing hasn't completed yet.
     pub async fn get_summary(&self) -> Option<Arc<WriteSummary>> {
         let inner = self.inner.read().await;
         inner.summary.clone()
     }
 
-    /// Controls the lifetime of the entry in the backbone.
+    /// Gets the metadata record of the file or `None`, if the file writing hasn't completed yet.
+    ///
+    /// The expiration date reflects the current lease of the file.
+    pub async fn get_metadata(&self) -> Option<ItemMetadata> {
+        let inner = self.inner.read().await;
+        inner
+            .metadata
+            .clone()
+            .map(|metadata| metadata.with_expires(system_time(self.expiration_date())))
+    }
+
+    /// Controls the lifetime of the entry in the backbone until writing completes.
     ///
     /// This method will:
     ///
     /// - Wait until the file is buffered to disk completely,
-    /// - Apply a temporal lease to the file (keeping it alive for a certain time).
-    /// - Remove the file from the registry after the time is over.
+    /// - Apply a temporal lease to the file (keeping it alive for a certain time),
+    /// - Hand the file over to the backbone, which removes it once the lease expired.
+    ///
+    /// Files that fail to be written are removed right away.
     async fn lifetime_handler(
         id: ShortGuid,
         mut inner: Arc<RwLock<Inner>>,
         backbone_command: Sender<BackboneCommand>,
         writer_command: Receiver<WriteResult>,
         duration: Duration,
+        lease: Arc<watch::Sender<Instant>>,
+        metadata: MetadataTemplate,
     ) {
         // Before starting the timeout, wait for the write to the file to complete.
-        let summary = match writer_command.await {
-            Ok(WriteResult::Success(summary)) => {
+        let (summary, stored) = match writer_command.await {
+            Ok(WriteResult::Success(summary, stored)) => {
                 info!(file_id = %id, "File writing completed: {}", summary.hashes);
-                summary
+                (summary, stored)
+            }
+            Ok(WriteResult::Duplicate(existing)) => {
+                info!(file_id = %id, "File {id} duplicates file {existing}; discarding it");
+                Self::close_file(&mut inner).await;
+                Self::remove_writer(id, RemovalReason::Duplicate, backbone_command).await;
+                return;
             }
             Ok(WriteResult::Failed) => {
                 warn!(file_id = %id, "Writing to the file failed");
                 Self::close_file(&mut inner).await;
-                Self::remove_writer(id, backbone_command).await;
+                Self::remove_writer(id, RemovalReason::Failed, backbone_command).await;
                 return;
             }
             Err(e) => {
                 warn!(file_id = %id, "The file writer channel failed: {e}");
                 Self::close_file(&mut inner).await;
-                Self::remove_writer(id, backbone_command).await;
+                Self::remove_writer(id, RemovalReason::Failed, backbone_command).await;
                 return;
             }
         };
 
-        // Persist the write summary.
+        // Persist the write summary and start the lease. This happens under the lock
+        // so that a concurrent removal of the file cannot be overridden.
         {
             let mut inner = inner.write().await;
+            if inner.file.is_none() {
+                info!(file_id = %id, "File {id} was removed before writing completed");
+                return;
+            }
+
+            let expires = Instant::now() + duration;
+            inner.metadata = Some(
+                ItemMetadata::new(id, &summary)
+                    .with_content_type(metadata.content_type)
+                    .with_created(metadata.created)
+                    .with_expires(system_time(expires)),
+            );
             inner.summary = Some(summary.clone());
+            lease.send_replace(expires);
         }
+        let _ = stored.send(());
 
         // Indicate the file is ready for processing.
         if let Err(error) = backbone_command
-            .send(BackboneCommand::ReadyForDistribution(id, summary))
+            .send(BackboneCommand::ReadyForDistribution(
+                id,
+                summary,
+                Span::current(),
+            ))
             .await
         {
             warn!(file_id = %id, "The backbone writer channel was closed while indicating a termination for file with ID {id}: {error}");
-            return;
         }
-
-        // TODO: The lifetime handler also needs to listen to graceful shutdowns.
-        //       If that's not the case, open file entries may keep the server
-        //       alive even if the servers have already shut down.
-
-        // Keep the file open for readers.
-        Self::apply_temporal_lease(&id, duration).await;
-        info!(file_id = %id, "Read lease timed out for file {id}; removing it");
-
-        // Gracefully close the file.
-        Self::remove_writer(id, backbone_command).await;
-    }
-
-    async fn apply_temporal_lease(id: &ShortGuid, duration: Duration) {
-        info!(file_id = %id, "File {id} will accept new readers for {duration:?}");
-        tokio::time::sleep(duration).await
     }
 
     async fn close_file(inner: &mut Arc<RwLock<Inner>>) {
@@ -150,12 +504,81 @@ impl FileRecord {
         inner.file.take();
     }
 
-    async fn remove_writer(id: ShortGuid, backbone_command: Sender<BackboneCommand>) {
+    async fn remove_writer(
+        id: ShortGuid,
+        reason: RemovalReason,
+        backbone_command: Sender<BackboneCommand>,
+    ) {
         if let Err(error) = backbone_command
-            .send(BackboneCommand::RemoveWriter(id))
+            .send(BackboneCommand::RemoveWriter(id, reason))
             .await
         {
             warn!(file_id = %id, "The backbone writer channel was closed while indicating a termination for file with ID {id}: {error}");
         }
     }
 }
+
+impl Drop for FileRecord {
+    fn drop(&mut self) {
+        if let Some(index) = &self.hash_index {
+            index.remove(self.id);
+        }
+    }
+}
+
+/// Registers a reader with its [`FileRecord`] until it is dropped.
+///
+/// Removing the record is deferred until all of its readers are dropped, such that
+/// the file is accounted for as buffered while it is still being read.
+#[derive(Debug)]
+pub(crate) struct ReaderGuard {
+    readers: Arc<watch::Sender<usize>>,
+    read: Arc<AtomicBool>,
+}
+
+impl ReaderGuard {
+    /// Records that data of the file was read.
+    pub fn mark_read(&self) {
+        self.read.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        self.readers.send_modify(|readers| *readers -= 1);
+    }
+}
+
+/// The parts of the metadata record that are known when the file is created.
+#[derive(Debug)]
+struct MetadataTemplate {
+    content_type: Option<String>,
+    created: SystemTime,
+}
+
+/// Parses a content type recorded in the metadata of a file.
+fn parse_content_type(value: &str) -> Option<ContentType> {
+    let value = HeaderValue::from_str(value).ok()?;
+    ContentType::decode(&mut std::iter::once(&value)).ok()
+}
+
+/// Converts wall-clock time into a monotonic instant.
+fn instant(time: SystemTime) -> Instant {
+    let now = Instant::now();
+    let wall_clock = SystemTime::now();
+    match wall_clock.duration_since(time) {
+        Ok(elapsed) => now.checked_sub(elapsed).unwrap_or(now),
+        Err(e) => now + e.duration(),
+    }
+}
+
+/// Converts a monotonic instant into wall-clock time.
+fn system_time(instant: Instant) -> SystemTime {
+    let now = Instant::now();
+    let wall_clock = SystemTime::now();
+    if instant <= now {
+        wall_clock - (now - instant)
+    } else {
+        wall_clock + (instant - now)
+    }
+}
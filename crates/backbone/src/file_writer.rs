@@ -1,7 +1,8 @@
-use file_distribution::hash::{HashMd5, HashSha256};
-use file_distribution::{FileHashes, WriteSummary};
+use file_distribution::hash::{FileHasher, HashAlgorithms};
+use file_distribution::WriteSummary;
 use shared_files::{prelude::*, SharedTemporaryFileWriter};
 use shortguid::ShortGuid;
+use std::collections::BTreeMap;
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
 use std::time::Duration;
@@ -18,9 +19,9 @@ use tracing::debug;
 /// the [`Backbone`](crate::backbone::Backbone) is informed about it.
 pub struct FileWriter {
     inner: SharedTemporaryFileWriter,
-    md5: HashMd5,
-    sha256: HashSha256,
+    hasher: FileHasher,
     file_name: Option<String>,
+    metadata: BTreeMap<String, String>,
     file_size: usize,
 }
 
@@ -38,13 +39,29 @@ impl FileWriter {
 
         Self {
             inner,
-            md5: HashMd5::new(),
-            sha256: HashSha256::new(),
+            hasher: FileHasher::default(),
             file_name,
+            metadata: BTreeMap::default(),
             file_size: 0,
         }
     }
 
+    /// Selects the hashes to compute; all hashes are computed by default.
+    ///
+    /// ## Remarks
+    ///
+    /// This must be called before the first write, as the hashes
+    /// would otherwise be calculated over partial content.
+    pub fn select_hashes(&mut self, algorithms: HashAlgorithms) {
+        debug_assert_eq!(self.file_size, 0, "Hashes must be selected before writing");
+        self.hasher = FileHasher::new(algorithms);
+    }
+
+    /// Sets the key/value metadata attached to the file.
+    pub fn set_metadata(&mut self, metadata: BTreeMap<String, String>) {
+        self.metadata = metadata;
+    }
+
     pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<usize> {
         self.update_state(chunk);
         self.inner.write(chunk).await
@@ -54,6 +71,16 @@ impl FileWriter {
         Ok(self.inner.sync_data().await?)
     }
 
+    /// Makes the data written so far available to readers, syncing it to disk
+    /// only if `sync` is set.
+    pub async fn commit(&mut self, sync: bool) -> Result<(), SynchronizationError> {
+        if sync {
+            self.sync_data().await
+        } else {
+            Ok(self.inner.flush().await?)
+        }
+    }
+
     pub async fn finalize(
         self,
         mode: CompletionMode,
@@ -64,14 +91,12 @@ impl FileWriter {
             CompletionMode::NoSync => self.inner.complete_no_sync()?,
         }
 
-        let md5 = self.md5.finalize();
-        let sha256 = self.sha256.finalize();
-
         let summary = Arc::new(WriteSummary {
             expires: Instant::now() + expiration,
-            hashes: FileHashes::new(md5, sha256),
+            hashes: self.hasher.finalize(),
             file_name: self.file_name,
             file_size_bytes: self.file_size,
+            metadata: self.metadata,
         });
 
         Ok(summary)
@@ -79,8 +104,7 @@ impl FileWriter {
 
     fn update_state(&mut self, buf: &[u8]) {
         self.file_size += buf.len();
-        self.md5.update(buf);
-        self.sha256.update(buf);
+        self.hasher.update(buf);
     }
 }
 
@@ -88,7 +112,6 @@ pub(crate) fn err_broken_pipe<T>() -> Result<T, Error> {
     Err(Error::new(ErrorKind::BrokenPipe, "Writer closed"))
 }
 
-#[allow(dead_code)]
 pub enum CompletionMode {
     Sync,
     NoSync,
@@ -104,10 +127,14 @@ pub enum FinalizationError {
     InvalidFileLength(u64, u64),
     #[error("Integrity check failed: expected MD5 {0}, got MD5 {1}")]
     IntegrityCheckFailed(String, String),
+    #[error("Integrity check failed: expected SHA-256 {0}, got SHA-256 {1}")]
+    Sha256Mismatch(String, String),
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum SynchronizationError {
     #[error("Syncing the file to disk failed")]
     FileSyncFailed(#[from] CompleteWritingError),
+    #[error("Flushing the file failed")]
+    FileFlushFailed(#[from] std::io::Error),
 }
@@ -1,12 +1,19 @@
 use crate::file_writer::{err_broken_pipe, FileWriter, FinalizationError};
+use crate::hash_index::HashIndex;
+use crate::storage_quota::StorageReservation;
+use crate::upload_progress::ProgressTracker;
 use crate::CompletionMode;
+use file_distribution::hash::HashAlgorithms;
 use file_distribution::WriteSummary;
 use metrics::transfer::{TransferMethod, TransferMetrics};
+use shortguid::ShortGuid;
+use std::collections::BTreeMap;
 use std::io::ErrorKind;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::oneshot::Sender;
+use subtle::ConstantTimeEq;
+use tokio::sync::oneshot::{self, Sender};
 
 /// A writer guard to communicate back to the [`Backbone`](crate::backbone::Backbone);
 ///
@@ -26,24 +33,46 @@ pub struct FileWriterGuard {
     expected_size: Option<u64>,
     /// The expected MD5 hash of the content, as per `Content-MD5` header.
     expected_content_md5: Option<[u8; 16]>,
+    /// The expected SHA-256 hash of the content, as supplied by the client.
+    expected_sha256: Option<[u8; 32]>,
+    /// The upload progress shared with the backbone.
+    progress: Arc<ProgressTracker>,
+    /// The storage space reserved for the file, if a quota applies.
+    storage_reservation: Option<Arc<StorageReservation>>,
+    /// The index used to deduplicate files, if enabled, along with the ID of the file.
+    hash_index: Option<(ShortGuid, Arc<HashIndex>)>,
+    /// The client-supplied idempotency key of the upload.
+    idempotency_key: Option<String>,
 }
 
 /// A write result.
 #[derive(Debug)]
 pub enum WriteResult {
-    /// The writer succeeded.
-    Success(Arc<WriteSummary>),
+    /// The writer succeeded; the sender is notified once the summary was stored with the file.
+    Success(Arc<WriteSummary>, Sender<()>),
+    /// The writer succeeded, but the content duplicates the file with the specified ID.
+    Duplicate(ShortGuid),
     /// The writer failed.
     Failed,
 }
 
+/// The outcome of finalizing a file with deduplication.
+#[derive(Debug)]
+pub enum Finalized {
+    /// The file was stored.
+    Stored(Arc<WriteSummary>),
+    /// The content duplicates the live file with the specified ID; the new file is discarded.
+    Duplicate(ShortGuid, Arc<WriteSummary>),
+}
+
 impl FileWriterGuard {
-    pub fn new(
+    pub(crate) fn new(
         writer: FileWriter,
         sender: Sender<WriteResult>,
         expiration: Duration,
         expected_size: Option<u64>,
         content_md5: Option<[u8; 16]>,
+        progress: Arc<ProgressTracker>,
     ) -> Self {
         Self {
             inner: Some(writer),
@@ -52,13 +81,85 @@ impl FileWriterGuard {
             file_size: 0,
             expected_size,
             expected_content_md5: content_md5,
+            expected_sha256: None,
+            progress,
+            storage_reservation: None,
+            hash_index: None,
+            idempotency_key: None,
+        }
+    }
+
+    /// Sets the index used to deduplicate the file with the specified ID.
+    pub(crate) fn with_hash_index(mut self, id: ShortGuid, index: Option<Arc<HashIndex>>) -> Self {
+        self.hash_index = index.map(|index| (id, index));
+        self
+    }
+
+    /// Sets the client-supplied idempotency key under which the file is registered
+    /// once it was written completely. Only has an effect if deduplication is enabled.
+    pub fn set_idempotency_key(&mut self, key: Option<String>) {
+        self.idempotency_key = key;
+    }
+
+    /// Sets the key/value metadata the client attached to the file.
+    pub fn set_metadata(&mut self, metadata: BTreeMap<String, String>) {
+        if let Some(ref mut writer) = self.inner {
+            writer.set_metadata(metadata);
+        }
+    }
+
+    /// Sets the storage space reserved for the file; writes beyond it
+    /// attempt to grow the reservation.
+    pub(crate) fn with_storage_reservation(
+        mut self,
+        reservation: Option<Arc<StorageReservation>>,
+    ) -> Self {
+        self.storage_reservation = reservation;
+        self
+    }
+
+    /// Sets the SHA-256 hash the content is expected to have; finalizing fails if
+    /// the content does not match it.
+    ///
+    /// ## Remarks
+    ///
+    /// This must be called before [`select_hashes`](Self::select_hashes).
+    pub fn set_expected_sha256(&mut self, sha256: Option<[u8; 32]>) {
+        self.expected_sha256 = sha256;
+    }
+
+    /// Selects the hashes to compute for the file. MD5 and SHA-256 are always computed
+    /// if an expected hash was provided, as they are needed for the integrity check.
+    ///
+    /// ## Remarks
+    ///
+    /// This must be called before the first write.
+    pub fn select_hashes(&mut self, mut algorithms: HashAlgorithms) {
+        if self.expected_content_md5.is_some() {
+            algorithms = algorithms.with_md5();
+        }
+        if self.expected_sha256.is_some() {
+            algorithms = algorithms.with_sha256();
+        }
+
+        if let Some(ref mut writer) = self.inner {
+            writer.select_hashes(algorithms);
         }
     }
 
     pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<usize> {
+        // Ensure the chunk fits into the storage headroom before writing it.
+        if let Some(reservation) = &self.storage_reservation {
+            if let Err(e) = reservation.grow_to(self.file_size + chunk.len() as u64) {
+                self.fail_if_not_already_closed();
+                return Err(std::io::Error::other(e));
+            }
+        }
+
         if let Some(ref mut writer) = self.inner {
             let bytes_written = writer.write(chunk).await?;
             self.file_size += bytes_written as u64;
+            self.progress.add(bytes_written);
 
             TransferMetrics::track_bytes_transferred(TransferMethod::Store, bytes_written);
 
@@ -81,12 +182,41 @@ impl FileWriterGuard {
         }
     }
 
+    /// Completes writing the file and hands it to the backbone for distribution.
+    ///
+    /// If this fails, the file is removed from the backbone and deleted from disk
+    /// once it is no longer in use, just as if the guard was dropped.
     pub async fn finalize(
-        mut self,
+        self,
         mode: CompletionMode,
     ) -> Result<Arc<WriteSummary>, FinalizationError> {
+        match self.complete(mode, false).await? {
+            Finalized::Stored(summary) | Finalized::Duplicate(_, summary) => Ok(summary),
+        }
+    }
+
+    /// Finalizes the file like [`finalize`](Self::finalize), but discards it if a live
+    /// file with the same SHA-256 hash exists and deduplication is enabled.
+    pub async fn finalize_deduplicated(
+        self,
+        mode: CompletionMode,
+    ) -> Result<Finalized, FinalizationError> {
+        self.complete(mode, true).await
+    }
+
+    async fn complete(
+        mut self,
+        mode: CompletionMode,
+        deduplicate: bool,
+    ) -> Result<Finalized, FinalizationError> {
         if let Some(writer) = self.inner.take() {
-            let summary = writer.finalize(mode, self.expiration).await?;
+            let summary = match writer.finalize(mode, self.expiration).await {
+                Ok(summary) => summary,
+                Err(e) => {
+                    self.fail_if_not_already_closed();
+                    return Err(e);
+                }
+            };
 
             // Verify the file length if possible.
             if let Some(expected_size) = self.expected_size {
@@ -100,29 +230,64 @@ impl FileWriterGuard {
             }
 
             // Verify integrity if possible.
-            if let Some(md5) = self.expected_content_md5 {
-                if md5.ne(&summary.hashes.md5[..]) {
+            if let (Some(expected), Some(actual)) =
+                (self.expected_content_md5, summary.hashes.md5.as_ref())
+            {
+                if expected.ne(&actual[..]) {
                     self.fail_if_not_already_closed();
                     return Err(FinalizationError::IntegrityCheckFailed(
-                        hex::encode(md5),
-                        hex::encode(&summary.hashes.md5[..]),
+                        hex::encode(expected),
+                        hex::encode(&actual[..]),
                     ));
                 }
             }
 
-            self.try_signal_success(&summary)?;
-            Ok(summary)
+            // Compare in constant time so that the comparison reveals nothing about the
+            // position of the first differing byte.
+            if let (Some(expected), Some(actual)) =
+                (self.expected_sha256, summary.hashes.sha256.as_ref())
+            {
+                if !bool::from(expected[..].ct_eq(&actual[..])) {
+                    self.fail_if_not_already_closed();
+                    return Err(FinalizationError::Sha256Mismatch(
+                        hex::encode(expected),
+                        hex::encode(&actual[..]),
+                    ));
+                }
+            }
+
+            let existing = self.hash_index.take().and_then(|(id, index)| {
+                let sha256 = summary.hashes.sha256.map(Into::into);
+                index.register(id, sha256, self.idempotency_key.take())
+            });
+
+            match existing {
+                Some(existing) if deduplicate => {
+                    self.try_signal(WriteResult::Duplicate(existing))?;
+                    Ok(Finalized::Duplicate(existing, summary))
+                }
+                _ => {
+                    let (stored, summary_stored) = oneshot::channel();
+                    self.try_signal(WriteResult::Success(summary.clone(), stored))?;
+
+                    // Only report completion once the file can be read along with its summary,
+                    // such that a download immediately following the upload sees the hashes.
+                    // The sender is dropped if the file was removed in the meantime.
+                    let _ = summary_stored.await;
+                    Ok(Finalized::Stored(summary))
+                }
+            }
         } else {
             Err(FinalizationError::BackboneCommunicationFailed)
         }
     }
 
-    /// Signal a success to the backbone.
-    fn try_signal_success(mut self, summary: &Arc<WriteSummary>) -> Result<(), FinalizationError> {
+    /// Signal a successful write to the backbone.
+    fn try_signal(mut self, result: WriteResult) -> Result<(), FinalizationError> {
         // Send the hashes back to the backbone.
         match self.sender.take() {
             None => Err(FinalizationError::BackboneCommunicationFailed),
-            Some(sender) => match sender.send(WriteResult::Success(summary.clone())) {
+            Some(sender) => match sender.send(result) {
                 Ok(_) => Ok(()),
                 Err(_) => Err(FinalizationError::BackboneCommunicationFailed),
             },
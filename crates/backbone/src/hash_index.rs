@@ -0,0 +1,77 @@
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Indexes completely written files by their SHA-256 hash and by client-supplied
+/// idempotency keys, such that repeated uploads of the same content can be deduplicated.
+#[derive(Debug, Default)]
+pub(crate) struct HashIndex {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    by_sha256: HashMap<[u8; 32], ShortGuid>,
+    by_key: HashMap<String, ShortGuid>,
+}
+
+impl HashIndex {
+    /// Registers a file under its SHA-256 hash and optional idempotency key.
+    ///
+    /// Returns the ID of a previously registered file with the same hash, in which
+    /// case the new file is not registered under the hash. The idempotency key is
+    /// registered for whichever file holds the content.
+    pub fn register(
+        &self,
+        id: ShortGuid,
+        sha256: Option<[u8; 32]>,
+        idempotency_key: Option<String>,
+    ) -> Option<ShortGuid> {
+        let mut inner = self.inner.lock().expect("failed to lock hash index");
+        let existing = sha256.and_then(|sha256| {
+            let existing = *inner.by_sha256.entry(sha256).or_insert(id);
+            (existing != id).then_some(existing)
+        });
+
+        if let Some(key) = idempotency_key {
+            inner.by_key.entry(key).or_insert(existing.unwrap_or(id));
+        }
+
+        existing
+    }
+
+    /// Gets the ID of the file registered under the idempotency key.
+    pub fn find_by_key(&self, key: &str) -> Option<ShortGuid> {
+        let inner = self.inner.lock().expect("failed to lock hash index");
+        inner.by_key.get(key).copied()
+    }
+
+    /// Removes all entries referring to the file.
+    pub fn remove(&self, id: ShortGuid) {
+        let mut inner = self.inner.lock().expect("failed to lock hash index");
+        inner.by_sha256.retain(|_, entry| *entry != id);
+        inner.by_key.retain(|_, entry| *entry != id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_content_resolves_to_the_first_file() {
+        let index = HashIndex::default();
+        let (first, second) = (ShortGuid::new_random(), ShortGuid::new_random());
+
+        assert_eq!(index.register(first, Some([1; 32]), None), None);
+        assert_eq!(
+            index.register(second, Some([1; 32]), Some("retry".to_string())),
+            Some(first)
+        );
+        assert_eq!(index.find_by_key("retry"), Some(first));
+
+        index.remove(first);
+        assert_eq!(index.find_by_key("retry"), None);
+        assert_eq!(index.register(second, Some([1; 32]), None), None);
+    }
+}
@@ -3,13 +3,27 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 mod backbone;
+mod broadcast;
+mod expiry_queue;
 mod file_accessor;
+mod file_index;
 mod file_reader;
 mod file_record;
 mod file_writer;
 mod file_writer_guard;
+mod hash_index;
+mod storage_quota;
+mod tombstones;
+mod upload_progress;
+mod upload_session;
 
-pub use backbone::{Backbone, NewFileError};
+pub use backbone::{Backbone, ExistingFile, LiveFile, NewFileError};
 pub use file_accessor::FileAccessorBridge;
 pub use file_reader::FileReader;
-pub use file_writer::CompletionMode;
+pub use file_writer::{CompletionMode, FinalizationError};
+pub use file_writer_guard::Finalized;
+pub use storage_quota::{HighWaterMarkExceeded, InsufficientStorage};
+pub use upload_progress::UploadProgress;
+pub use upload_session::{
+    ChunkRangeError, UploadSession, UploadSessionOptions, UploadSessionStatus,
+};
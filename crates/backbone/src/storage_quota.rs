@@ -0,0 +1,218 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Tracks the storage space reserved by locally buffered files against a fixed capacity
+/// and a high-water mark above which no new files are accepted.
+#[derive(Debug)]
+pub(crate) struct StorageQuota {
+    /// The total number of bytes that may be buffered; `None` if unlimited.
+    pub capacity: Option<u64>,
+    /// The number of buffered bytes from which on new files are rejected; `None` if disabled.
+    pub high_water_mark: Option<u64>,
+    /// The number of bytes currently reserved by uploads and buffered files.
+    reserved: AtomicU64,
+}
+
+/// A reservation of storage space held by a file; the space is released when
+/// the reservation is dropped.
+#[derive(Debug)]
+pub(crate) struct StorageReservation {
+    quota: Arc<StorageQuota>,
+    /// The number of bytes reserved for the file.
+    bytes: AtomicU64,
+}
+
+/// The storage headroom does not suffice for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Insufficient storage: {requested} bytes requested, {available} bytes available")]
+pub struct InsufficientStorage {
+    /// The number of additional bytes requested.
+    pub requested: u64,
+    /// The number of bytes available at the time of the request.
+    pub available: u64,
+}
+
+/// The storage used by buffered files is at or above the high-water mark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("Storage above high-water mark: {used} bytes in use, new files are accepted below {high_water_mark} bytes")]
+pub struct HighWaterMarkExceeded {
+    /// The number of bytes in use at the time of the request.
+    pub used: u64,
+    /// The configured high-water mark.
+    pub high_water_mark: u64,
+}
+
+impl StorageQuota {
+    pub fn new(capacity: Option<u64>, high_water_mark: Option<u64>) -> Self {
+        Self {
+            capacity,
+            high_water_mark,
+            reserved: AtomicU64::new(0),
+        }
+    }
+
+    /// Gets the number of bytes that can still be reserved, or `None` if unlimited.
+    pub fn available(&self) -> Option<u64> {
+        self.capacity
+            .map(|capacity| capacity.saturating_sub(self.reserved.load(Ordering::Acquire)))
+    }
+
+    /// Ensures that the storage in use is below the high-water mark, if one is set.
+    pub fn check_high_water_mark(&self) -> Result<(), HighWaterMarkExceeded> {
+        let Some(high_water_mark) = self.high_water_mark else {
+            return Ok(());
+        };
+
+        let used = self.reserved.load(Ordering::Acquire);
+        if used >= high_water_mark {
+            return Err(HighWaterMarkExceeded {
+                used,
+                high_water_mark,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Reserves the specified number of bytes, returning a reservation that
+    /// releases them when dropped.
+    pub fn reserve(
+        self: &Arc<Self>,
+        bytes: u64,
+    ) -> Result<StorageReservation, InsufficientStorage> {
+        self.try_acquire(bytes)?;
+        Ok(StorageReservation {
+            quota: self.clone(),
+            bytes: AtomicU64::new(bytes),
+        })
+    }
+
+    fn try_acquire(&self, bytes: u64) -> Result<(), InsufficientStorage> {
+        let capacity = self.capacity.unwrap_or(u64::MAX);
+        self.reserved
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |reserved| {
+                reserved
+                    .checked_add(bytes)
+                    .filter(|&total| total <= capacity)
+            })
+            .map(|_| ())
+            .map_err(|reserved| InsufficientStorage {
+                requested: bytes,
+                available: capacity.saturating_sub(reserved),
+            })
+    }
+
+    fn release(&self, bytes: u64) {
+        self.reserved.fetch_sub(bytes, Ordering::AcqRel);
+    }
+}
+
+impl StorageReservation {
+    /// Grows the reservation such that it covers at least `total` bytes.
+    ///
+    /// ## Remarks
+    ///
+    /// This is only ever called from the single writer of a file.
+    pub fn grow_to(&self, total: u64) -> Result<(), InsufficientStorage> {
+        let reserved = self.bytes.load(Ordering::Acquire);
+        if total <= reserved {
+            return Ok(());
+        }
+
+        let additional = total - reserved;
+        self.quota.try_acquire(additional)?;
+        self.bytes.fetch_add(additional, Ordering::AcqRel);
+        Ok(())
+    }
+}
+
+impl Drop for StorageReservation {
+    fn drop(&mut self) {
+        self.quota.release(*self.bytes.get_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservations_are_bounded_by_capacity() {
+        let quota = Arc::new(StorageQuota::new(Some(100), None));
+        let first = quota.reserve(60).expect("failed to reserve");
+        assert_eq!(
+            quota.reserve(50).err(),
+            Some(InsufficientStorage {
+                requested: 50,
+                available: 40
+            })
+        );
+
+        assert!(first.grow_to(100).is_ok());
+        assert_eq!(quota.available(), Some(0));
+        assert!(first.grow_to(101).is_err());
+
+        drop(first);
+        assert_eq!(quota.available(), Some(100));
+        assert!(quota.reserve(50).is_ok());
+    }
+
+    #[test]
+    fn high_water_mark_rejects_new_files() {
+        let quota = Arc::new(StorageQuota::new(None, Some(10)));
+        assert_eq!(quota.available(), None);
+
+        let first = quota.reserve(4).expect("failed to reserve");
+        assert!(quota.check_high_water_mark().is_ok());
+
+        // Files already being written may grow beyond the mark.
+        assert!(first.grow_to(12).is_ok());
+        assert_eq!(
+            quota.check_high_water_mark(),
+            Err(HighWaterMarkExceeded {
+                used: 12,
+                high_water_mark: 10
+            })
+        );
+
+        drop(first);
+        assert!(quota.check_high_water_mark().is_ok());
+    }
+
+    #[test]
+    fn concurrent_reservations_never_exceed_capacity() {
+        const THREADS: u64 = 16;
+        const ATTEMPTS: u64 = 1000;
+        const CAPACITY: u64 = 100;
+
+        let quota = Arc::new(StorageQuota::new(Some(CAPACITY), None));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let quota = quota.clone();
+                std::thread::spawn(move || {
+                    let mut held = Vec::new();
+                    for _ in 0..ATTEMPTS {
+                        if let Ok(reservation) = quota.reserve(7) {
+                            assert!(quota.reserved.load(Ordering::Acquire) <= CAPACITY);
+                            held.push(reservation);
+                        }
+                    }
+                    held
+                })
+            })
+            .collect();
+
+        let held: Vec<_> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("thread panicked"))
+            .collect();
+
+        // Since reservations are never released while reserving, exactly as
+        // many fit as the capacity allows.
+        assert_eq!(held.len() as u64, CAPACITY / 7);
+        assert_eq!(quota.available(), Some(CAPACITY % 7));
+
+        drop(held);
+        assert_eq!(quota.available(), Some(CAPACITY));
+    }
+}
@@ -0,0 +1,130 @@
+use shortguid::ShortGuid;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The IDs of recently expired files, kept for a grace period such that requests for
+/// them can be told apart from requests for files that never existed.
+///
+/// The set is bounded; once it is full, the tombstones of the files that expired
+/// earliest are evicted first.
+#[derive(Debug, Default)]
+pub(crate) struct Tombstones {
+    /// The time for which a tombstone is kept after its file expired.
+    grace: Duration,
+    /// The maximum number of tombstones; `0` disables them.
+    capacity: usize,
+    /// The expiration time of each tombstoned file.
+    expired: HashMap<ShortGuid, Instant>,
+    /// The tombstones in order of expiration; entries not matching [`Self::expired`]
+    /// are stale and skipped.
+    order: VecDeque<(ShortGuid, Instant)>,
+}
+
+impl Tombstones {
+    pub fn new(grace: Duration, capacity: usize) -> Self {
+        Self {
+            grace,
+            capacity,
+            expired: HashMap::default(),
+            order: VecDeque::default(),
+        }
+    }
+
+    /// Gets the number of tombstones, including ones past their grace period.
+    pub fn len(&self) -> usize {
+        self.expired.len()
+    }
+
+    /// Records that the file expired at `now`, evicting the oldest tombstones if the set is full.
+    pub fn insert(&mut self, id: ShortGuid, now: Instant) {
+        if self.capacity == 0 || self.grace.is_zero() {
+            return;
+        }
+
+        self.purge(now);
+        while self.expired.len() >= self.capacity && self.evict_oldest() {}
+        self.expired.insert(id, now);
+        self.order.push_back((id, now));
+    }
+
+    /// Determines whether the file expired within the grace period before `now`.
+    pub fn contains(&self, id: ShortGuid, now: Instant) -> bool {
+        self.expired
+            .get(&id)
+            .is_some_and(|&expired| now.saturating_duration_since(expired) < self.grace)
+    }
+
+    /// Removes the tombstone of a file, e.g. because its ID is in use again.
+    pub fn remove(&mut self, id: ShortGuid) {
+        self.expired.remove(&id);
+    }
+
+    /// Removes the tombstones whose grace period ended at `now`.
+    fn purge(&mut self, now: Instant) {
+        while let Some(&(_, expired)) = self.order.front() {
+            if now.saturating_duration_since(expired) < self.grace {
+                break;
+            }
+            self.evict_oldest();
+        }
+    }
+
+    /// Removes the oldest tombstone, returning `false` if there was none.
+    fn evict_oldest(&mut self) -> bool {
+        let Some((id, expired)) = self.order.pop_front() else {
+            return false;
+        };
+        if self.expired.get(&id) == Some(&expired) {
+            self.expired.remove(&id);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tombstones_are_kept_for_the_grace_period() {
+        let now = Instant::now();
+        let id = ShortGuid::new_random();
+
+        let mut tombstones = Tombstones::new(Duration::from_secs(10), 8);
+        tombstones.insert(id, now);
+        assert!(tombstones.contains(id, now + Duration::from_secs(9)));
+        assert!(!tombstones.contains(id, now + Duration::from_secs(10)));
+        assert!(!tombstones.contains(ShortGuid::new_random(), now));
+
+        // Expired tombstones are purged when new ones are added.
+        tombstones.insert(ShortGuid::new_random(), now + Duration::from_secs(10));
+        assert_eq!(tombstones.len(), 1);
+    }
+
+    #[test]
+    fn the_oldest_tombstones_are_evicted_when_full() {
+        let now = Instant::now();
+        let ids: Vec<_> = (0..3).map(|_| ShortGuid::new_random()).collect();
+
+        let mut tombstones = Tombstones::new(Duration::from_secs(60), 2);
+        for (i, id) in ids.iter().enumerate() {
+            tombstones.insert(*id, now + Duration::from_secs(i as u64));
+        }
+        assert_eq!(tombstones.len(), 2);
+        assert!(!tombstones.contains(ids[0], now));
+        assert!(tombstones.contains(ids[1], now));
+        assert!(tombstones.contains(ids[2], now));
+
+        tombstones.remove(ids[1]);
+        assert!(!tombstones.contains(ids[1], now));
+    }
+
+    #[test]
+    fn tombstones_can_be_disabled() {
+        let mut tombstones = Tombstones::default();
+        let id = ShortGuid::new_random();
+        tombstones.insert(id, Instant::now());
+        assert!(!tombstones.contains(id, Instant::now()));
+    }
+}
@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Tracks the number of bytes received for an in-flight upload.
+#[derive(Debug, Default)]
+pub(crate) struct ProgressTracker {
+    /// The number of bytes written to the file so far.
+    bytes_received: AtomicU64,
+    /// The expected number of bytes as per `Content-Length` header, if known.
+    bytes_expected: Option<u64>,
+}
+
+/// A snapshot of the upload progress of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// The number of bytes received so far.
+    pub bytes_received: u64,
+    /// The total number of bytes, if known.
+    pub bytes_expected: Option<u64>,
+    /// Whether the upload has completed.
+    pub completed: bool,
+}
+
+impl ProgressTracker {
+    pub fn new(bytes_expected: Option<u64>) -> Self {
+        Self {
+            bytes_received: AtomicU64::new(0),
+            bytes_expected,
+        }
+    }
+
+    /// Registers that the specified number of bytes were written.
+    pub fn add(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Takes a snapshot of the current progress.
+    pub fn snapshot(&self, completed: bool) -> UploadProgress {
+        UploadProgress {
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_expected: self.bytes_expected,
+            completed,
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Contains the sessions of resumable uploads, see [`UploadSession`].
+
+use crate::storage_quota::{StorageQuota, StorageReservation};
+use crate::NewFileError;
+use axum::headers::ContentType;
+use file_distribution::hash::HashAlgorithms;
+use shortguid::ShortGuid;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::AsyncSeekExt;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// The parameters of a resumable upload, applied once all of its bytes were received.
+#[derive(Debug, Clone, Default)]
+pub struct UploadSessionOptions {
+    pub content_type: Option<ContentType>,
+    pub file_name: Option<String>,
+    pub temporal_lease: Option<Duration>,
+    pub hash_algorithms: HashAlgorithms,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A resumable upload whose chunks are received in any order.
+///
+/// Chunks are written at their offset into a part file; the received byte ranges are
+/// tracked such that clients can resume by sending the missing ones. Once all bytes were
+/// received, the part file is stored like a regular upload. The part file is deleted
+/// when the session is dropped.
+///
+/// Once the total size is announced, the session reserves it against the storage quota
+/// until the part file is stored.
+#[derive(Debug)]
+pub struct UploadSession {
+    id: ShortGuid,
+    path: PathBuf,
+    options: UploadSessionOptions,
+    state: Mutex<SessionState>,
+}
+
+#[derive(Debug)]
+struct SessionState {
+    received: ReceivedRanges,
+    /// The total size of the upload, once announced by a chunk.
+    total: Option<u64>,
+    last_activity: Instant,
+    /// Whether all bytes were received; only the chunk completing the upload observes this.
+    complete: bool,
+    /// The storage reserved for the announced total, if a storage quota is configured.
+    reservation: Option<StorageReservation>,
+}
+
+/// A snapshot of the state of an upload session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadSessionStatus {
+    /// The byte ranges received so far, in ascending order.
+    pub received: Vec<Range<u64>>,
+    /// The total size of the upload, if announced yet.
+    pub total: Option<u64>,
+    /// Whether this chunk completed the upload.
+    pub complete: bool,
+}
+
+impl UploadSessionStatus {
+    /// Gets the number of distinct bytes received.
+    pub fn bytes_received(&self) -> u64 {
+        self.received
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
+}
+
+/// A chunk does not fit the upload session.
+#[derive(Debug, thiserror::Error)]
+pub enum ChunkRangeError {
+    #[error("The upload was announced with {0} bytes, but the chunk announces {1} bytes")]
+    TotalMismatch(u64, u64),
+    #[error("The chunk ends at byte {0}, beyond the total of {1} bytes")]
+    BeyondTotal(u64, u64),
+}
+
+impl UploadSession {
+    /// Creates the session along with its empty part file at `path`.
+    pub(crate) async fn create(
+        id: ShortGuid,
+        path: PathBuf,
+        options: UploadSessionOptions,
+    ) -> std::io::Result<Self> {
+        tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+        debug!(file_id = %id, "Buffering chunks of resumable upload {id} to {path:?}");
+        Ok(Self {
+            id,
+            path,
+            options,
+            state: Mutex::new(SessionState {
+                received: ReceivedRanges::default(),
+                total: None,
+                last_activity: Instant::now(),
+                complete: false,
+                reservation: None,
+            }),
+        })
+    }
+
+    pub fn id(&self) -> ShortGuid {
+        self.id
+    }
+
+    /// Gets the path of the part file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn options(&self) -> &UploadSessionOptions {
+        &self.options
+    }
+
+    /// Checks that a chunk ending before byte `end` fits the announced `total` size,
+    /// recording the total if it was not known yet.
+    pub fn announce(&self, end: u64, total: Option<u64>) -> Result<(), ChunkRangeError> {
+        let mut state = self.state.lock().expect("failed to lock upload session");
+        let total = match (state.total, total) {
+            (Some(known), Some(total)) if known != total => {
+                return Err(ChunkRangeError::TotalMismatch(known, total))
+            }
+            (known, total) => known.or(total),
+        };
+        if let Some(total) = total {
+            if end > total {
+                return Err(ChunkRangeError::BeyondTotal(end, total));
+            }
+        }
+        state.total = total;
+        state.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Reserves the announced total size against the storage `quota` unless reserved already.
+    ///
+    /// Like new files, sessions are rejected while the storage in use is above the high-water mark.
+    pub(crate) fn reserve(&self, quota: &Arc<StorageQuota>) -> Result<(), NewFileError> {
+        let mut state = self.state.lock().expect("failed to lock upload session");
+        let Some(total) = state.total.filter(|_| state.reservation.is_none()) else {
+            return Ok(());
+        };
+
+        quota
+            .check_high_water_mark()
+            .map_err(|e| NewFileError::HighWaterMarkExceeded(self.id, e))?;
+        let reservation = quota
+            .reserve(total)
+            .map_err(|e| NewFileError::InsufficientStorage(self.id, e))?;
+        state.reservation = Some(reservation);
+        Ok(())
+    }
+
+    /// Releases the storage reserved for the session, e.g. when its part file is about to be
+    /// stored as a file, which reserves the space itself.
+    pub fn release_reservation(&self) {
+        let mut state = self.state.lock().expect("failed to lock upload session");
+        state.reservation = None;
+    }
+
+    /// Determines whether all bytes were received, i.e. whether the part file is being stored.
+    pub fn is_complete(&self) -> bool {
+        self.state
+            .lock()
+            .expect("failed to lock upload session")
+            .complete
+    }
+
+    /// Reopens a completed session whose part file could not be stored, such that the next
+    /// chunk received completes it again.
+    pub fn reopen(&self) {
+        let mut state = self.state.lock().expect("failed to lock upload session");
+        state.complete = false;
+        state.last_activity = Instant::now();
+    }
+
+    /// Opens the part file for writing a chunk starting at `offset`.
+    pub async fn open_at(&self, offset: u64) -> std::io::Result<File> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(file)
+    }
+
+    /// Records that the bytes in `range` were written to the part file.
+    pub fn record(&self, range: Range<u64>) -> UploadSessionStatus {
+        let mut state = self.state.lock().expect("failed to lock upload session");
+        state.received.insert(range);
+        state.last_activity = Instant::now();
+
+        let complete = !state.complete
+            && state
+                .total
+                .is_some_and(|total| state.received.covers(total));
+        state.complete |= complete;
+        UploadSessionStatus {
+            received: state.received.0.clone(),
+            total: state.total,
+            complete,
+        }
+    }
+
+    /// Takes a snapshot of the state of the session.
+    pub fn status(&self) -> UploadSessionStatus {
+        let state = self.state.lock().expect("failed to lock upload session");
+        UploadSessionStatus {
+            received: state.received.0.clone(),
+            total: state.total,
+            complete: false,
+        }
+    }
+
+    /// Determines whether no chunk was received for the specified duration.
+    fn is_idle(&self, timeout: Duration, now: Instant) -> bool {
+        let state = self.state.lock().expect("failed to lock upload session");
+        !state.complete && now.duration_since(state.last_activity) >= timeout
+    }
+}
+
+impl Drop for UploadSession {
+    fn drop(&mut self) {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                warn!(file_id = %self.id, "Failed to delete part file {path:?}: {e}", path = self.path)
+            }
+        }
+    }
+}
+
+/// The open sessions of resumable uploads.
+#[derive(Debug, Default)]
+pub(crate) struct UploadSessions {
+    sessions: Mutex<HashMap<ShortGuid, Arc<UploadSession>>>,
+    /// The time after which idle sessions are discarded; `None` to keep them indefinitely.
+    timeout: Option<Duration>,
+}
+
+impl UploadSessions {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            sessions: Mutex::default(),
+            timeout,
+        }
+    }
+
+    pub fn contains(&self, id: ShortGuid) -> bool {
+        self.lock().contains_key(&id)
+    }
+
+    /// Registers a session, returning `false` if its ID is already in use.
+    pub fn insert(&self, session: Arc<UploadSession>) -> bool {
+        let mut sessions = self.lock();
+        if sessions.contains_key(&session.id) {
+            return false;
+        }
+        sessions.insert(session.id, session);
+        true
+    }
+
+    pub fn get(&self, id: ShortGuid) -> Option<Arc<UploadSession>> {
+        self.lock().get(&id).cloned()
+    }
+
+    pub fn remove(&self, id: ShortGuid) -> Option<Arc<UploadSession>> {
+        self.lock().remove(&id)
+    }
+
+    /// Discards the sessions that did not receive a chunk within the timeout.
+    pub fn purge_idle(&self) {
+        let Some(timeout) = self.timeout else {
+            return;
+        };
+        let now = Instant::now();
+        self.lock().retain(|id, session| {
+            let idle = session.is_idle(timeout, now);
+            if idle {
+                debug!(file_id = %id, "Discarding idle resumable upload {id}");
+            }
+            !idle
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<ShortGuid, Arc<UploadSession>>> {
+        self.sessions
+            .lock()
+            .expect("failed to lock upload sessions")
+    }
+}
+
+/// The byte ranges received for an upload, kept sorted and merged.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct ReceivedRanges(Vec<Range<u64>>);
+
+impl ReceivedRanges {
+    /// Adds a range, merging it with the ranges it overlaps or adjoins.
+    fn insert(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+
+        // The ranges are disjoint and not adjacent, so every range touching the merged
+        // range also touches the inserted one.
+        let mut merged = range;
+        self.0.retain(|existing| {
+            let touches = existing.start <= merged.end && merged.start <= existing.end;
+            if touches {
+                merged.start = merged.start.min(existing.start);
+                merged.end = merged.end.max(existing.end);
+            }
+            !touches
+        });
+        let position = self
+            .0
+            .partition_point(|existing| existing.end < merged.start);
+        self.0.insert(position, merged);
+    }
+
+    /// Determines whether all bytes of an upload of `total` bytes were received.
+    fn covers(&self, total: u64) -> bool {
+        match self.0.as_slice() {
+            [] => total == 0,
+            [range] => range.start == 0 && range.end == total,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_merged_regardless_of_order() {
+        let mut ranges = ReceivedRanges::default();
+        ranges.insert(20..30);
+        ranges.insert(0..5);
+        ranges.insert(10..15);
+        assert_eq!(ranges.0, vec![0..5, 10..15, 20..30]);
+        assert!(!ranges.covers(30));
+
+        // Overlapping and adjacent chunks are merged.
+        ranges.insert(12..22);
+        ranges.insert(5..10);
+        assert_eq!(ranges.0, vec![0..30]);
+        assert!(ranges.covers(30));
+        assert!(!ranges.covers(31));
+
+        // Chunks received twice change nothing.
+        ranges.insert(3..8);
+        ranges.insert(7..7);
+        assert_eq!(ranges.0, vec![0..30]);
+    }
+
+    #[tokio::test]
+    async fn sessions_complete_once_all_bytes_were_received() {
+        let id = ShortGuid::new_random();
+        let path = std::env::temp_dir().join(format!("yeet_{id}.part"));
+        let session = UploadSession::create(id, path.clone(), UploadSessionOptions::default())
+            .await
+            .expect("failed to create session");
+
+        session
+            .announce(10, None)
+            .expect("unknown totals are accepted");
+        assert!(!session.record(5..10).complete);
+        session.announce(5, Some(10)).expect("the total fits");
+        assert!(matches!(
+            session.announce(5, Some(12)),
+            Err(ChunkRangeError::TotalMismatch(10, 12))
+        ));
+        assert!(matches!(
+            session.announce(11, None),
+            Err(ChunkRangeError::BeyondTotal(11, 10))
+        ));
+
+        let status = session.record(0..5);
+        assert!(status.complete);
+        assert_eq!(status.bytes_received(), 10);
+        assert!(
+            !session.record(0..5).complete,
+            "completion is reported once"
+        );
+
+        drop(session);
+        assert!(!path.exists(), "the part file is deleted with the session");
+    }
+}
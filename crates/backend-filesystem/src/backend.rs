@@ -0,0 +1,537 @@
+use crate::file_reader::FilesystemFileReader;
+use app_config::{filesystem::FilesystemBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{
+    Backend, DistributeEarly, DistributeFile, DistributionError, HealthCheckError, ReceiveError,
+    ReceiveFile, StoredDigest,
+};
+use backend_traits::{BackendInfo, TryCreateFromConfig};
+use file_distribution::hash::HashSha256;
+use file_distribution::metadata::ItemMetadata;
+use file_distribution::{BoxedFileReader, FileProvider, FileReaderTrait, GetFile, WriteSummary};
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::{trace, warn};
+
+/// The size of the buffer used to hash stored files, in bytes.
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+/// A backend storing files in a local directory.
+///
+/// Each file is stored under its ID, with its metadata (content type, hashes)
+/// kept in a `.meta` sidecar file next to it.
+pub struct FilesystemBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The directory to store the files in.
+    directory: PathBuf,
+    /// Files received while they were still being written, waiting for their write summary.
+    pending: Mutex<HashMap<ShortGuid, EarlyFile>>,
+}
+
+/// A file received while it was still being written.
+struct EarlyFile {
+    /// The SHA-256 hash of the data, computed while it was stored.
+    sha256: [u8; 32],
+    /// The content type of the file, if known.
+    content_type: Option<String>,
+}
+
+impl FilesystemBackend {
+    pub fn try_new(
+        config: &FilesystemBackendConfig,
+    ) -> Result<Self, FilesystemBackendConstructionError> {
+        std::fs::create_dir_all(&config.directory).map_err(|e| {
+            FilesystemBackendConstructionError::FailedToCreateDirectory(config.directory.clone(), e)
+        })?;
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            directory: config.directory.clone(),
+            pending: Mutex::default(),
+        })
+    }
+
+    /// Gets the path under which the file data is stored.
+    fn data_path(&self, id: ShortGuid) -> PathBuf {
+        self.directory.join(id.to_string())
+    }
+
+    /// Gets the path under which the file metadata is stored.
+    fn meta_path(&self, id: ShortGuid) -> PathBuf {
+        self.directory.join(format!("{id}.meta"))
+    }
+
+    /// Gets the path the file data is written to before it is complete.
+    fn partial_path(&self, id: ShortGuid) -> PathBuf {
+        self.directory.join(format!("{id}.partial"))
+    }
+
+    /// Stores the metadata of a file.
+    async fn store_metadata(
+        &self,
+        id: ShortGuid,
+        summary: &Arc<WriteSummary>,
+        content_type: Option<String>,
+    ) -> Result<(), DistributionError> {
+        let metadata = ItemMetadata::new(id, summary).with_content_type(content_type);
+        let metadata_buf = metadata
+            .serialize_to_proto()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        let path = self.meta_path(id);
+        write_synced(&path, &metadata_buf)
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+        trace!(file_id = %id, "Stored metadata in {path:?}");
+        Ok(())
+    }
+
+    fn take_pending(&self, id: ShortGuid) -> Option<EarlyFile> {
+        self.pending
+            .lock()
+            .expect("failed to lock the pending files")
+            .remove(&id)
+    }
+}
+
+#[async_trait]
+impl DistributeFile for FilesystemBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn location(&self, id: ShortGuid) -> Option<String> {
+        Some(self.data_path(id).display().to_string())
+    }
+
+    fn receiver(&self) -> Option<&dyn ReceiveFile> {
+        Some(self)
+    }
+
+    fn early_distributor(&self) -> Option<&dyn DistributeEarly> {
+        Some(self)
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<Option<StoredDigest>, DistributionError> {
+        let mut file = file_provider.get_file(id).await?;
+
+        // The metadata is written first so that it exists whenever the data does.
+        let content_type = file.content_type().map(|c| c.into_owned());
+        self.store_metadata(id, &summary, content_type).await?;
+
+        // The data is written to a temporary file first so that readers never observe
+        // incomplete files.
+        let partial = self.partial_path(id);
+        let result = async {
+            let mut target = File::create(&partial).await?;
+            tokio::io::copy(&mut file, &mut target).await?;
+            target.sync_all().await?;
+            tokio::fs::rename(&partial, self.data_path(id)).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            remove_if_exists(&partial).await.ok();
+            return Err(DistributionError::BackendSpecific(Box::new(e)));
+        }
+
+        trace!(file_id = %id, "Stored data in {path:?}", path = self.data_path(id));
+
+        // The stored data is hashed again to verify it, unless there is nothing to compare against.
+        if summary.hashes.sha256.is_none() {
+            return Ok(None);
+        }
+        match sha256_of(&self.data_path(id)).await {
+            Ok(sha256) => Ok(Some(StoredDigest::Sha256(sha256))),
+            Err(e) => {
+                warn!(file_id = %id, "Failed to hash the stored data of file {id}: {e}");
+                Ok(None)
+            }
+        }
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DistributionError> {
+        self.take_pending(id);
+        for path in [
+            self.data_path(id),
+            self.meta_path(id),
+            self.partial_path(id),
+        ] {
+            remove_if_exists(&path)
+                .await
+                .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+            trace!(file_id = %id, "Deleted {path:?}");
+        }
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<(), HealthCheckError> {
+        let metadata = tokio::fs::metadata(&self.directory).await?;
+        if !metadata.is_dir() || metadata.permissions().readonly() {
+            return Err(HealthCheckError::BackendSpecific(
+                format!(
+                    "{directory:?} is not a writable directory",
+                    directory = self.directory
+                )
+                .into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DistributeEarly for FilesystemBackend {
+    async fn distribute_early(
+        &self,
+        id: ShortGuid,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError> {
+        let mut file = file_provider.get_file(id).await?;
+        let content_type = file.content_type().map(|c| c.into_owned());
+
+        // The data is hashed while it is stored rather than read back afterwards, since
+        // the hashes of the file are only known once it was written completely.
+        let partial = self.partial_path(id);
+        let sha256 = match copy_hashed(&mut file, &partial).await {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                remove_if_exists(&partial).await.ok();
+                return Err(DistributionError::BackendSpecific(Box::new(e)));
+            }
+        };
+
+        trace!(file_id = %id, "Stored data in {partial:?} while it was written");
+        self.pending
+            .lock()
+            .expect("failed to lock the pending files")
+            .insert(
+                id,
+                EarlyFile {
+                    sha256,
+                    content_type,
+                },
+            );
+        Ok(())
+    }
+
+    async fn update_metadata(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+    ) -> Result<(), DistributionError> {
+        let Some(file) = self.take_pending(id) else {
+            return Err(DistributionError::BackendSpecific(
+                format!("File {id} was not received").into(),
+            ));
+        };
+
+        let partial = self.partial_path(id);
+        if let Some(expected) = &summary.hashes.sha256 {
+            if expected[..] != file.sha256[..] {
+                remove_if_exists(&partial).await.ok();
+                return Err(DistributionError::BackendSpecific(
+                    format!(
+                        "The stored data of file {id} has SHA-256 {actual}, expected {expected}",
+                        actual = hex::encode(file.sha256),
+                        expected = hex::encode(&expected[..])
+                    )
+                    .into(),
+                ));
+            }
+        }
+
+        // Readers never observe the data without its metadata.
+        self.store_metadata(id, &summary, file.content_type).await?;
+        tokio::fs::rename(&partial, self.data_path(id)).await?;
+        trace!(file_id = %id, "Stored data in {path:?}", path = self.data_path(id));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for FilesystemBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<Option<BoxedFileReader>, ReceiveError> {
+        let file = match File::open(self.data_path(id)).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let file_size = file.metadata().await?.len() as usize;
+
+        let metadata = match tokio::fs::read(self.meta_path(id)).await {
+            Ok(buf) => ItemMetadata::deserialize_from_proto(&buf).ok(),
+            Err(e) => {
+                warn!(file_id = %id, "Unable to read the metadata of file {id}: {e}");
+                None
+            }
+        };
+
+        let content_type = metadata.as_ref().and_then(|m| m.content_type.clone());
+        let summary = metadata
+            .and_then(|metadata| metadata.to_summary(file_size, tokio::time::Instant::now()));
+
+        let reader = FilesystemFileReader::new(file, file_size, content_type, summary);
+        Ok(Some(BoxedFileReader::new(reader)))
+    }
+}
+
+/// Writes the data to the specified file and syncs it to disk.
+async fn write_synced(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut file = File::create(path).await?;
+    file.write_all(data).await?;
+    file.sync_all().await
+}
+
+/// Copies the data into the specified file, syncs it to disk and returns its SHA-256 hash.
+async fn copy_hashed<R>(reader: &mut R, path: &Path) -> std::io::Result<[u8; 32]>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut target = File::create(path).await?;
+    let mut hasher = HashSha256::new();
+    let mut buf = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        target.write_all(&buf[..n]).await?;
+    }
+    target.sync_all().await?;
+    Ok(hasher.finalize().into())
+}
+
+/// Computes the SHA-256 hash of the specified file.
+async fn sha256_of(path: &Path) -> std::io::Result<[u8; 32]> {
+    let mut file = File::open(path).await?;
+    let mut hasher = HashSha256::new();
+    let mut buf = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// Removes the specified file, ignoring files that do not exist.
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+impl BackendInfo for FilesystemBackend {
+    fn backend_name() -> &'static str {
+        "Filesystem"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for FilesystemBackend {
+    type Error = FilesystemBackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        config
+            .backends
+            .filesystem
+            .iter()
+            .map(|config| {
+                FilesystemBackend::try_new(config)
+                    .map(|backend| Backend::wrap(backend).with_priority(config.priority))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilesystemBackendConstructionError {
+    #[error("Failed to create the storage directory {0:?}")]
+    FailedToCreateDirectory(PathBuf, #[source] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_distribution::hash::{HashBlake3, HashMd5, HashSha256};
+    use file_distribution::{BufferedFileReader, FileAccessorError, FileHashes};
+
+    struct SomeFile;
+
+    #[async_trait]
+    impl GetFile for SomeFile {
+        async fn get_file(&self, _id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
+            let reader =
+                BufferedFileReader::new("yeet").with_content_type(Some("text/plain".into()));
+            Ok(BoxedFileReader::new(reader))
+        }
+    }
+
+    fn summary() -> Arc<WriteSummary> {
+        let mut md5 = HashMd5::new();
+        md5.update(b"yeet");
+        let mut sha256 = HashSha256::new();
+        sha256.update(b"yeet");
+        let mut blake3 = HashBlake3::new();
+        blake3.update(b"yeet");
+
+        Arc::new(WriteSummary {
+            expires: tokio::time::Instant::now(),
+            hashes: FileHashes::new(md5.finalize(), sha256.finalize(), blake3.finalize()),
+            file_name: Some("yeet.txt".into()),
+            file_size_bytes: 4,
+            metadata: Default::default(),
+        })
+    }
+
+    fn backend(directory: &Path) -> FilesystemBackend {
+        FilesystemBackend::try_new(&FilesystemBackendConfig {
+            tag: "fs-1".into(),
+            directory: directory.join("files"),
+            priority: 0,
+        })
+        .expect("failed to create backend")
+    }
+
+    #[tokio::test]
+    async fn distributed_files_can_be_received_and_deleted() {
+        let directory = tempfile::tempdir().expect("failed to create directory");
+        let backend = backend(directory.path());
+        let id = ShortGuid::new_random();
+        let summary = summary();
+
+        let digest = backend
+            .distribute_file(id, summary.clone(), FileProvider::wrap(Arc::new(SomeFile)))
+            .await
+            .expect("failed to distribute file")
+            .expect("the stored data should be verifiable");
+        assert_eq!(digest.matches(&summary.hashes), Some(true));
+
+        let mut file = backend
+            .receive_file(id)
+            .await
+            .expect("failed to receive file")
+            .expect("file should exist");
+        assert_eq!(file.content_type().as_deref(), Some("text/plain"));
+
+        let received = file.summary().clone().expect("summary should exist");
+        assert_eq!(received.file_name.as_deref(), Some("yeet.txt"));
+        assert_eq!(received.file_size_bytes, 4);
+        assert_eq!(received.hashes.sha256, summary.hashes.sha256);
+
+        let mut data = String::new();
+        file.read_to_string(&mut data).await.unwrap();
+        assert_eq!(data, "yeet");
+
+        backend
+            .delete_file(id)
+            .await
+            .expect("failed to delete file");
+        assert!(backend.receive_file(id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn files_can_be_distributed_early() {
+        let directory = tempfile::tempdir().expect("failed to create directory");
+        let backend = backend(directory.path());
+        let id = ShortGuid::new_random();
+
+        backend
+            .distribute_early(id, FileProvider::wrap(Arc::new(SomeFile)))
+            .await
+            .expect("failed to distribute file");
+        assert!(backend.receive_file(id).await.unwrap().is_none());
+
+        backend
+            .update_metadata(id, summary())
+            .await
+            .expect("failed to update metadata");
+
+        let mut file = backend
+            .receive_file(id)
+            .await
+            .expect("failed to receive file")
+            .expect("file should exist");
+        assert_eq!(file.content_type().as_deref(), Some("text/plain"));
+        assert_eq!(
+            file.summary().as_ref().unwrap().hashes.sha256,
+            summary().hashes.sha256
+        );
+
+        let mut data = String::new();
+        file.read_to_string(&mut data).await.unwrap();
+        assert_eq!(data, "yeet");
+    }
+
+    #[tokio::test]
+    async fn early_files_not_matching_the_hash_are_discarded() {
+        let directory = tempfile::tempdir().expect("failed to create directory");
+        let backend = backend(directory.path());
+        let id = ShortGuid::new_random();
+
+        backend
+            .distribute_early(id, FileProvider::wrap(Arc::new(SomeFile)))
+            .await
+            .expect("failed to distribute file");
+
+        let mut sha256 = HashSha256::new();
+        sha256.update(b"yoink");
+        let summary = Arc::new(WriteSummary {
+            hashes: FileHashes::new(
+                HashMd5::new().finalize(),
+                sha256.finalize(),
+                HashBlake3::new().finalize(),
+            ),
+            ..(*summary()).clone()
+        });
+        assert!(backend.update_metadata(id, summary).await.is_err());
+        assert!(backend.receive_file(id).await.unwrap().is_none());
+        assert!(!backend.partial_path(id).exists());
+    }
+
+    #[tokio::test]
+    async fn health_check_requires_the_directory() {
+        let directory = tempfile::tempdir().expect("failed to create directory");
+        let backend = backend(directory.path());
+        backend
+            .health_check()
+            .await
+            .expect("backend should be healthy");
+
+        std::fs::remove_dir(directory.path().join("files")).expect("failed to remove directory");
+        assert!(backend.health_check().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_files_are_not_received() {
+        let directory = tempfile::tempdir().expect("failed to create directory");
+        let backend = backend(directory.path());
+        let id = ShortGuid::new_random();
+
+        assert!(backend.receive_file(id).await.unwrap().is_none());
+        backend
+            .delete_file(id)
+            .await
+            .expect("deleting unknown files should succeed");
+    }
+}
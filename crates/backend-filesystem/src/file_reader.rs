@@ -0,0 +1,78 @@
+use file_distribution::{FileReaderTrait, WriteSummary};
+use shared_files::FileSize;
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Instant;
+
+/// A file reader streaming a stored file from disk.
+pub(crate) struct FilesystemFileReader {
+    file: File,
+    file_size: usize,
+    content_type: Option<String>,
+    created: Instant,
+    creation_date: SystemTime,
+    summary: Option<Arc<WriteSummary>>,
+}
+
+impl FilesystemFileReader {
+    pub fn new(
+        file: File,
+        file_size: usize,
+        content_type: Option<String>,
+        summary: Option<Arc<WriteSummary>>,
+    ) -> Self {
+        Self {
+            file,
+            file_size,
+            content_type,
+            created: Instant::now(),
+            creation_date: SystemTime::now(),
+            summary,
+        }
+    }
+}
+
+impl FileReaderTrait for FilesystemFileReader {
+    fn summary(&self) -> &Option<Arc<WriteSummary>> {
+        &self.summary
+    }
+
+    /// Since the remaining lifetime of the file is unknown to the backend,
+    /// the file is considered to expire immediately.
+    fn expiration_date(&self) -> Instant {
+        self.created
+    }
+
+    fn file_size(&self) -> FileSize {
+        FileSize::Exactly(self.file_size)
+    }
+
+    fn file_age(&self) -> Duration {
+        Instant::now() - self.created
+    }
+
+    fn creation_date(&self) -> SystemTime {
+        self.creation_date
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
+        self.content_type
+            .as_ref()
+            .map(|content_type| Cow::from(content_type.as_str()))
+    }
+}
+
+impl AsyncRead for FilesystemFileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_read(cx, buf)
+    }
+}
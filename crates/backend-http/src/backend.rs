@@ -0,0 +1,469 @@
+use crate::file_reader::{BodyStream, HttpFileReader};
+use app_config::{http::HttpBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{
+    Backend, DistributeFile, DistributionError, HealthCheckError, ReceiveError, ReceiveFile,
+    StoredDigest,
+};
+use backend_traits::{BackendInfo, TryCreateFromConfig};
+use bytes::Bytes;
+use file_distribution::{
+    BoxedFileReader, FileHashes, FileProvider, FileReaderTrait, GetFile, WriteSummary,
+};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Body, Client, StatusCode, Url};
+use serde::Deserialize;
+use shared_files::FileSize;
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+use tracing::trace;
+
+/// The content type of files whose content type is unknown.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The header carrying the hex encoded SHA-256 hash of a file served by `/yoink/:id`.
+const SHA256_HEADER: &str = "yy-file-sha256";
+
+/// The header carrying the hex encoded MD5 hash of a file served by `/yoink/:id`.
+const MD5_HEADER: &str = "yy-file-md5";
+
+/// The number of chunks buffered between reading a file and sending it upstream.
+const UPLOAD_BUFFER_CHUNKS: usize = 4;
+
+/// A backend forwarding files to another yeet-yoink instance.
+///
+/// Files are uploaded via the upstream's `/yeet` endpoint, which assigns them a new ID.
+/// The backend keeps the mapping from local to upstream IDs in memory, so files
+/// distributed before a restart can no longer be retrieved or deleted through it.
+pub struct HttpBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The base URL of the upstream instance, ending in a slash.
+    base_url: Url,
+    /// The client, sending the bearer token with every request.
+    client: Client,
+    /// The upstream IDs of the distributed files, keyed by their local IDs.
+    remote_ids: RwLock<HashMap<ShortGuid, ShortGuid>>,
+}
+
+impl HttpBackend {
+    pub fn try_new(config: &HttpBackendConfig) -> Result<Self, HttpBackendConstructionError> {
+        let base_url = parse_base_url(&config.base_url)?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &config.token {
+            let mut value = HeaderValue::try_from(format!("Bearer {token}"))
+                .map_err(|_| HttpBackendConstructionError::InvalidToken)?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .build()
+            .map_err(HttpBackendConstructionError::FailedToCreateClient)?;
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            base_url,
+            client,
+            remote_ids: RwLock::default(),
+        })
+    }
+
+    /// Gets the URL of an endpoint of the upstream instance.
+    fn endpoint(&self, path: &str) -> Url {
+        self.base_url
+            .join(path)
+            .expect("endpoint paths are valid relative URLs")
+    }
+
+    /// Gets the URL under which the upstream instance serves the file.
+    fn file_url(&self, remote_id: ShortGuid) -> Url {
+        self.endpoint(&format!("yoink/{remote_id}"))
+    }
+
+    /// Gets the upstream ID of a file distributed by this backend.
+    fn remote_id(&self, id: ShortGuid) -> Option<ShortGuid> {
+        self.remote_ids
+            .read()
+            .expect("failed to lock the remote IDs")
+            .get(&id)
+            .copied()
+    }
+
+    /// Uploads the file to the upstream instance, returning its upstream ID.
+    async fn upload(
+        &self,
+        file: BoxedFileReader,
+        summary: &WriteSummary,
+    ) -> Result<(ShortGuid, Option<StoredDigest>), HttpBackendError> {
+        let content_type = file
+            .content_type()
+            .map_or(DEFAULT_CONTENT_TYPE.to_string(), |c| c.into_owned());
+
+        // The file reader is not `Sync`, so its chunks are handed to the request body
+        // through a channel. Read errors are forwarded to abort the request.
+        let (sender, receiver) = mpsc::channel(UPLOAD_BUFFER_CHUNKS);
+        let forward = async move {
+            let mut chunks = ReaderStream::new(file);
+            while let Some(chunk) = chunks.next().await {
+                if sender.send(chunk).await.is_err() {
+                    break;
+                }
+            }
+        };
+
+        let mut request = self
+            .client
+            .post(self.endpoint("yeet"))
+            .header(CONTENT_TYPE, content_type)
+            .header(CONTENT_LENGTH, summary.file_size_bytes)
+            .body(Body::wrap_stream(ReceiverStream::new(receiver)));
+        if let Some(file_name) = &summary.file_name {
+            request = request.query(&[("file_name", file_name)]);
+        }
+
+        let send = async {
+            let response = request.send().await?;
+            check_status(response.status())?;
+            Ok::<_, HttpBackendError>(response.json::<UploadResponse>().await?)
+        };
+
+        let (_, response) = tokio::join!(forward, send);
+        let response = response?;
+
+        // The upstream only reports hashes it was configured to compute.
+        if let (Some(expected), Some(actual)) = (summary.hashes.sha256, &response.hashes.sha256) {
+            if !hex::encode(expected).eq_ignore_ascii_case(actual) {
+                return Err(HttpBackendError::ChecksumMismatch);
+            }
+        }
+
+        let digest = response
+            .hashes
+            .sha256
+            .as_deref()
+            .and_then(|sha256| hex::decode(sha256).ok())
+            .and_then(|sha256| sha256.try_into().ok())
+            .map(StoredDigest::Sha256);
+        Ok((response.id, digest))
+    }
+}
+
+#[async_trait]
+impl DistributeFile for HttpBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn location(&self, id: ShortGuid) -> Option<String> {
+        self.remote_id(id)
+            .map(|remote_id| self.file_url(remote_id).to_string())
+    }
+
+    fn receiver(&self) -> Option<&dyn ReceiveFile> {
+        Some(self)
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<Option<StoredDigest>, DistributionError> {
+        let file = file_provider.get_file(id).await?;
+        let (remote_id, digest) = self
+            .upload(file, &summary)
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        self.remote_ids
+            .write()
+            .expect("failed to lock the remote IDs")
+            .insert(id, remote_id);
+        trace!(file_id = %id, "Stored file upstream as {remote_id}");
+        Ok(digest)
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DistributionError> {
+        let Some(remote_id) = self.remote_id(id) else {
+            return Ok(());
+        };
+
+        let response = self
+            .client
+            .delete(self.file_url(remote_id))
+            .send()
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        // Deleting files that are already gone upstream is not an error.
+        if !is_gone(response.status()) {
+            check_status(response.status())
+                .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+        }
+
+        self.remote_ids
+            .write()
+            .expect("failed to lock the remote IDs")
+            .remove(&id);
+        trace!(file_id = %id, "Deleted upstream file {remote_id}");
+        Ok(())
+    }
+
+    /// Probes the liveness of the upstream instance. Its readiness is not required,
+    /// since it may still serve files while some of its own backends are unavailable.
+    async fn health_check(&self) -> Result<(), HealthCheckError> {
+        let response = self
+            .client
+            .get(self.endpoint("livez"))
+            .send()
+            .await
+            .map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))?;
+        check_status(response.status()).map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for HttpBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<Option<BoxedFileReader>, ReceiveError> {
+        let Some(remote_id) = self.remote_id(id) else {
+            return Ok(None);
+        };
+
+        let response = self
+            .client
+            .get(self.file_url(remote_id))
+            .send()
+            .await
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        if is_gone(response.status()) {
+            return Ok(None);
+        }
+        check_status(response.status()).map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        trace!(file_id = %id, "Fetching upstream file {remote_id}");
+
+        let file_size = match response
+            .content_length()
+            .and_then(|l| usize::try_from(l).ok())
+        {
+            Some(size) => FileSize::Exactly(size),
+            None => FileSize::Error,
+        };
+
+        let summary = match file_size {
+            FileSize::Exactly(size) => summary_from_headers(response.headers(), size),
+            _ => None,
+        };
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let stream: BodyStream = Box::pin(
+            response
+                .bytes_stream()
+                .map(|chunk: reqwest::Result<Bytes>| chunk.map_err(std::io::Error::other)),
+        );
+        let reader = HttpFileReader::new(stream, file_size, content_type, summary);
+        Ok(Some(BoxedFileReader::new(reader)))
+    }
+}
+
+/// The parts of the upstream's response to `/yeet` used by the backend.
+#[derive(Debug, Deserialize)]
+struct UploadResponse {
+    /// The upstream ID of the file.
+    id: ShortGuid,
+    /// The hashes computed by the upstream.
+    #[serde(default)]
+    hashes: UploadHashes,
+}
+
+/// The hashes computed by the upstream, in hex encoding.
+#[derive(Debug, Default, Deserialize)]
+struct UploadHashes {
+    sha256: Option<String>,
+}
+
+/// Parses the base URL, ensuring that endpoint paths are appended to it.
+fn parse_base_url(base_url: &str) -> Result<Url, HttpBackendConstructionError> {
+    let mut url = Url::parse(base_url)
+        .map_err(|_| HttpBackendConstructionError::InvalidBaseUrl(base_url.to_string()))?;
+    if url.cannot_be_a_base() {
+        return Err(HttpBackendConstructionError::InvalidBaseUrl(
+            base_url.to_string(),
+        ));
+    }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    Ok(url)
+}
+
+/// Reconstructs the write summary of a file from the hash headers of `/yoink/:id`.
+fn summary_from_headers(headers: &HeaderMap, file_size_bytes: usize) -> Option<Arc<WriteSummary>> {
+    let decode = |name: &str| -> Option<Vec<u8>> {
+        match headers.get(name) {
+            Some(value) => hex::decode(value.to_str().ok()?).ok(),
+            None => Some(Vec::new()),
+        }
+    };
+
+    let md5 = decode(MD5_HEADER)?;
+    let sha256 = decode(SHA256_HEADER)?;
+    if md5.is_empty() && sha256.is_empty() {
+        return None;
+    }
+
+    Some(Arc::new(WriteSummary {
+        expires: tokio::time::Instant::now(),
+        hashes: FileHashes::try_from_slices(&md5, &sha256, &[])?,
+        file_name: None,
+        file_size_bytes,
+        metadata: Default::default(),
+    }))
+}
+
+/// Determines whether the upstream no longer has the file.
+fn is_gone(status: StatusCode) -> bool {
+    status == StatusCode::NOT_FOUND || status == StatusCode::GONE
+}
+
+fn check_status(status: StatusCode) -> Result<(), HttpBackendError> {
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(HttpBackendError::UnexpectedStatus(status.as_u16()))
+    }
+}
+
+impl BackendInfo for HttpBackend {
+    fn backend_name() -> &'static str {
+        "HTTP"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for HttpBackend {
+    type Error = HttpBackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        config
+            .backends
+            .http
+            .iter()
+            .map(|config| {
+                HttpBackend::try_new(config)
+                    .map(|backend| Backend::wrap(backend).with_priority(config.priority))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpBackendConstructionError {
+    #[error("Invalid base URL: {0}")]
+    InvalidBaseUrl(String),
+    #[error("The token is not a valid header value")]
+    InvalidToken,
+    #[error("Failed to create HTTP client")]
+    FailedToCreateClient(#[source] reqwest::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpBackendError {
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+    #[error("Unexpected response status {0}")]
+    UnexpectedStatus(u16),
+    #[error("The upstream reported a different SHA-256 hash")]
+    ChecksumMismatch,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_url_is_extended_to_a_directory() {
+        let url = parse_base_url("https://central.example.com/yeet-yoink").unwrap();
+        assert_eq!(url.as_str(), "https://central.example.com/yeet-yoink/");
+        assert_eq!(
+            url.join("yeet").unwrap().as_str(),
+            "https://central.example.com/yeet-yoink/yeet"
+        );
+
+        let url = parse_base_url("https://central.example.com").unwrap();
+        assert_eq!(
+            url.join("livez").unwrap().as_str(),
+            "https://central.example.com/livez"
+        );
+
+        assert!(parse_base_url("central.example.com").is_err());
+        assert!(parse_base_url("mailto:central@example.com").is_err());
+    }
+
+    #[test]
+    fn location_points_to_the_upstream_file() {
+        let backend = HttpBackend::try_new(&HttpBackendConfig {
+            tag: "central".to_string(),
+            base_url: "https://central.example.com/".to_string(),
+            token: Some("change-me".to_string()),
+            priority: 0,
+        })
+        .unwrap();
+
+        let id = ShortGuid::new_random();
+        assert!(backend.location(id).is_none());
+
+        let remote_id = ShortGuid::new_random();
+        backend.remote_ids.write().unwrap().insert(id, remote_id);
+        assert_eq!(
+            backend.location(id),
+            Some(format!("https://central.example.com/yoink/{remote_id}"))
+        );
+    }
+
+    #[test]
+    fn summary_is_restored_from_headers() {
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let mut headers = HeaderMap::new();
+        headers.insert(SHA256_HEADER, HeaderValue::from_static(sha256));
+
+        let summary = summary_from_headers(&headers, 0).expect("summary should be restored");
+        assert_eq!(
+            summary.hashes.sha256.map(hex::encode).as_deref(),
+            Some(sha256)
+        );
+        assert!(summary.hashes.md5.is_none());
+
+        assert!(summary_from_headers(&HeaderMap::new(), 0).is_none());
+
+        headers.insert(MD5_HEADER, HeaderValue::from_static("not hex"));
+        assert!(summary_from_headers(&headers, 0).is_none());
+    }
+
+    #[test]
+    fn upload_response_is_parsed() {
+        let json = r#"{
+            "id": "KmC6e8laTnK3dioUSMpM0Q",
+            "file_size_bytes": 10,
+            "hashes": { "sha256": "abc" }
+        }"#;
+        let response: UploadResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(response.id.to_string(), "KmC6e8laTnK3dioUSMpM0Q");
+        assert_eq!(response.hashes.sha256.as_deref(), Some("abc"));
+    }
+}
@@ -1,24 +1,28 @@
+use crate::chunks::{chunk_key, manifest_key, read_chunk, ChunkManifest};
 use crate::connection_string::MemcacheConnectionStringWrapper;
 use app_config::{
     memcache::{MemcacheBackendConfig, DEFAULT_EXPIRATION},
     AppConfig,
 };
 use async_trait::async_trait;
-use backend_traits::{Backend, DistributeFile, DistributionError};
+use backend_traits::{
+    Backend, DistributeFile, DistributionError, HealthCheckError, ReceiveError, ReceiveFile,
+    StoredDigest,
+};
 use backend_traits::{BackendInfo, TryCreateFromConfig};
-use file_distribution::protobuf::ItemMetadata;
-use file_distribution::{BoxedFileReader, FileProvider, GetFile, WriteSummary};
-use map_ok::{BoxOk, MapOk};
+use file_distribution::metadata::ItemMetadata;
+use file_distribution::{
+    BoxedFileReader, BufferedFileReader, FileProvider, FileReaderTrait, GetFile, WriteSummary,
+};
 use r2d2::Pool;
-use r2d2_memcache::memcache::{MemcacheError, ToMemcacheValue};
+use r2d2_memcache::memcache::MemcacheError;
 use r2d2_memcache::MemcacheConnectionManager;
 use shortguid::ShortGuid;
-use std::cell::Cell;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::spawn_blocking;
 use tokio_util::io::SyncIoBridge;
-use tracing::trace;
+use tracing::{debug, trace};
 
 pub struct MemcacheBackend {
     /// The tag identifying the backend.
@@ -27,6 +31,8 @@ pub struct MemcacheBackend {
     pool: Pool<MemcacheConnectionManager>,
     /// The expiration time for stored entries.
     expiration_secs: u32,
+    /// The maximum number of bytes stored per item.
+    chunk_size: usize,
 }
 
 impl MemcacheBackend {
@@ -46,10 +52,17 @@ impl MemcacheBackend {
             .map_or(DEFAULT_EXPIRATION, |secs| Duration::from_secs(secs as _))
             .as_secs()
             .min(u32::MAX as _) as u32;
+
+        let chunk_size = config.chunk_size();
+        if chunk_size == 0 {
+            return Err(MemcacheBackendConstructionError::InvalidChunkSize);
+        }
+
         Ok(Self {
             tag: config.tag.clone(),
             pool,
             expiration_secs,
+            chunk_size,
         })
     }
 }
@@ -60,17 +73,27 @@ impl DistributeFile for MemcacheBackend {
         &self.tag
     }
 
+    fn location(&self, id: ShortGuid) -> Option<String> {
+        Some(manifest_key(id))
+    }
+
+    fn receiver(&self) -> Option<&dyn ReceiveFile> {
+        Some(self)
+    }
+
     async fn distribute_file(
         &self,
         id: ShortGuid,
         summary: Arc<WriteSummary>,
         file_provider: FileProvider,
-    ) -> Result<(), DistributionError> {
-        // TODO: Sanity check the file size - don't store if too large.
-
+    ) -> Result<Option<StoredDigest>, DistributionError> {
         let expiration = self.expiration_secs;
+        let chunk_size = self.chunk_size;
         let file = file_provider.get_file(id).await?;
-        let client = self.pool.get().unwrap();
+        let client = self
+            .pool
+            .get()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
 
         let metadata = ItemMetadata::new(id, &summary);
         let metadata_buf = metadata
@@ -78,13 +101,33 @@ impl DistributeFile for MemcacheBackend {
             .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
 
         let result: Result<(), MemcacheError> = spawn_blocking(move || {
-            let file = StreamWrapper::new(summary, file);
+            // Memcached limits the size of items, so files are stored in chunks.
+            let mut file = SyncIoBridge::new(file);
+            let mut buf = Vec::with_capacity(chunk_size.min(summary.file_size_bytes));
+            let mut manifest = ChunkManifest {
+                chunks: 0,
+                file_size_bytes: 0,
+            };
+            while read_chunk(&mut file, chunk_size, &mut buf)? {
+                let key = chunk_key(id, manifest.chunks);
+                client.set(&key, buf.as_slice(), expiration)?;
+                trace!(
+                    "Stored {len} bytes under key {key} with expiration {expiration}",
+                    len = buf.len()
+                );
+                manifest.chunks += 1;
+                manifest.file_size_bytes += buf.len() as u64;
+            }
 
-            let key = format!("data-{}", id);
-            client.set(&key, file, expiration)?;
-            trace!("Stored data under key {key} with expiration {expiration}");
+            // The manifest is stored last so that readers never observe a partial file.
+            let key = manifest_key(id);
+            client.set(&key, manifest.to_bytes().as_slice(), expiration)?;
+            trace!(
+                "Stored manifest of {chunks} chunks under key {key} with expiration {expiration}",
+                chunks = manifest.chunks
+            );
 
-            let key = format!("meta-{}", id);
+            let key = meta_key(id);
             client.set(&key, metadata_buf.as_ref(), expiration)?;
             trace!("Stored metadata under key {key} with expiration {expiration}");
 
@@ -93,51 +136,115 @@ impl DistributeFile for MemcacheBackend {
         .await?;
 
         match result {
-            Ok(()) => Ok(()),
+            Ok(()) => Ok(None),
             Err(e) => Err(DistributionError::BackendSpecific(Box::new(e))),
         }
     }
-}
 
-struct StreamWrapper {
-    summary: Arc<WriteSummary>,
-    bridge: Cell<Option<SyncIoBridge<BoxedFileReader>>>,
-}
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DistributionError> {
+        let client = self
+            .pool
+            .get()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
 
-impl StreamWrapper {
-    pub fn new(summary: Arc<WriteSummary>, reader: BoxedFileReader) -> StreamWrapper {
-        Self {
-            summary,
-            bridge: Cell::new(Some(SyncIoBridge::new(reader))),
-        }
-    }
-}
+        let result: Result<(), MemcacheError> = spawn_blocking(move || {
+            let manifest: Option<Vec<u8>> = client.get(&manifest_key(id))?;
+            let chunks = manifest
+                .and_then(|buf| ChunkManifest::from_bytes(&buf))
+                .map_or(0, |manifest| manifest.chunks);
+
+            // The manifest is deleted first so that readers never observe a partial file.
+            let keys = [manifest_key(id), meta_key(id)]
+                .into_iter()
+                .chain((0..chunks).map(|index| chunk_key(id, index)));
+            for key in keys {
+                client.delete(&key)?;
+                trace!("Deleted key {key}");
+            }
+            Ok(())
+        })
+        .await?;
 
-impl<W> ToMemcacheValue<W> for StreamWrapper
-where
-    W: std::io::Write,
-{
-    fn get_flags(&self) -> u32 {
-        0_u32
+        result.map_err(|e| DistributionError::BackendSpecific(Box::new(e)))
     }
 
-    fn get_length(&self) -> usize {
-        self.summary.file_size_bytes
+    async fn health_check(&self) -> Result<(), HealthCheckError> {
+        let client = self
+            .pool
+            .get()
+            .map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))?;
+
+        let result: Result<_, MemcacheError> = spawn_blocking(move || client.version()).await?;
+        let versions = result.map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))?;
+        trace!("Memcached servers report versions {versions:?}");
+        Ok(())
     }
+}
 
-    fn write_to(&self, stream: &mut W) -> std::io::Result<()> {
-        if let Some(mut bridge) = self.bridge.take() {
-            std::io::copy(&mut bridge, stream)?;
-            Ok(())
-        } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Source already read to end",
-            ))
-        }
+#[async_trait]
+impl ReceiveFile for MemcacheBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<Option<BoxedFileReader>, ReceiveError> {
+        let client = self
+            .pool
+            .get()
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+
+        let result: Result<_, MemcacheError> = spawn_blocking(move || {
+            let key = manifest_key(id);
+            let manifest: Option<Vec<u8>> = client.get(&key)?;
+            trace!("Fetched manifest from key {key}");
+            let Some(manifest) = manifest.and_then(|buf| ChunkManifest::from_bytes(&buf)) else {
+                return Ok(None);
+            };
+
+            let mut data = Vec::with_capacity(manifest.file_size_bytes as usize);
+            for index in 0..manifest.chunks {
+                let key = chunk_key(id, index);
+                let chunk: Option<Vec<u8>> = client.get(&key)?;
+                trace!("Fetched data from key {key}");
+
+                // Chunks may have been evicted independently of each other.
+                let Some(chunk) = chunk else {
+                    debug!(file_id = %id, "Chunk {index} of file {id} is missing");
+                    return Ok(None);
+                };
+                data.extend_from_slice(&chunk);
+            }
+
+            if data.len() as u64 != manifest.file_size_bytes {
+                debug!(file_id = %id, "The chunks of file {id} do not match its manifest");
+                return Ok(None);
+            }
+
+            let key = meta_key(id);
+            let metadata: Option<Vec<u8>> = client.get(&key)?;
+            trace!("Fetched metadata from key {key}");
+
+            Ok(Some((data, metadata)))
+        })
+        .await?;
+
+        let result = result.map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        let Some((data, metadata)) = result else {
+            return Ok(None);
+        };
+
+        let reader = BufferedFileReader::new(data);
+        let summary = metadata
+            .and_then(|buf| ItemMetadata::deserialize_from_proto(&buf).ok())
+            .and_then(|metadata| {
+                metadata.to_summary(reader.file_size_bytes(), reader.expiration_date())
+            });
+
+        Ok(Some(BoxedFileReader::new(reader.with_summary(summary))))
     }
 }
 
+/// Gets the key under which the file metadata is stored.
+fn meta_key(id: ShortGuid) -> String {
+    format!("meta-{}", id)
+}
+
 impl BackendInfo for MemcacheBackend {
     fn backend_name() -> &'static str {
         "Memcached"
@@ -159,9 +266,13 @@ impl TryCreateFromConfig for MemcacheBackend {
 
         configs
             .iter()
-            .map(MemcacheBackend::try_new)
-            .box_ok()
-            .map_ok(Backend::from)
+            .map(|config| {
+                MemcacheBackend::try_new(config).map(|backend| {
+                    Backend::wrap(backend)
+                        .with_priority(config.priority)
+                        .with_fallback_content_type(config.fallback_content_type.clone())
+                })
+            })
             .collect()
     }
 }
@@ -170,4 +281,6 @@ impl TryCreateFromConfig for MemcacheBackend {
 pub enum MemcacheBackendConstructionError {
     #[error("Failed to create pool")]
     FailedToCreatePool(r2d2::Error),
+    #[error("The chunk size must be at least one byte")]
+    InvalidChunkSize,
 }
@@ -3,6 +3,7 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 mod backend;
+mod chunks;
 mod connection_string;
 
 pub use backend::{MemcacheBackend, MemcacheBackendConstructionError};
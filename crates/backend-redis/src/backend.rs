@@ -0,0 +1,334 @@
+use crate::chunks::{chunk_key, manifest_key, meta_key, read_chunk, ChunkManifest};
+use app_config::{redis::RedisBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{
+    Backend, DistributeFile, DistributionError, HealthCheckError, ReceiveError, ReceiveFile,
+    StoredDigest,
+};
+use backend_traits::{BackendInfo, TryCreateFromConfig};
+use file_distribution::metadata::ItemMetadata;
+use file_distribution::{
+    BoxedFileReader, BufferedFileReader, FileProvider, FileReaderTrait, GetFile, WriteSummary,
+};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisError, RedisResult};
+use shortguid::ShortGuid;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+use tracing::{debug, trace};
+
+pub struct RedisBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The client used to connect to the server.
+    client: redis::Client,
+    /// The connection, established on first use; it reconnects by itself.
+    connection: OnceCell<ConnectionManager>,
+    /// The maximum number of bytes stored per key.
+    chunk_size: usize,
+}
+
+impl RedisBackend {
+    pub fn try_new(config: &RedisBackendConfig) -> Result<Self, RedisBackendConstructionError> {
+        let client = redis::Client::open(config.connection_string.as_str())
+            .map_err(RedisBackendConstructionError::InvalidConnectionString)?;
+
+        let chunk_size = config.chunk_size();
+        if chunk_size == 0 {
+            return Err(RedisBackendConstructionError::InvalidChunkSize);
+        }
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            client,
+            connection: OnceCell::new(),
+            chunk_size,
+        })
+    }
+
+    /// Gets the connection to the server, connecting first if needed.
+    ///
+    /// Connecting lazily keeps an unavailable server from blocking the startup.
+    async fn connection(&self) -> RedisResult<ConnectionManager> {
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl DistributeFile for RedisBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn location(&self, id: ShortGuid) -> Option<String> {
+        Some(manifest_key(id))
+    }
+
+    fn receiver(&self) -> Option<&dyn ReceiveFile> {
+        Some(self)
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<Option<StoredDigest>, DistributionError> {
+        let expiration_ms = expiration_ms(summary.expires, Instant::now());
+        let mut file = file_provider.get_file(id).await?;
+        let mut connection = self.connection().await.map_err(distribution_error)?;
+
+        let metadata = ItemMetadata::new(id, &summary);
+        let metadata_buf = metadata
+            .serialize_to_proto()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        let mut buf = Vec::with_capacity(self.chunk_size.min(summary.file_size_bytes));
+        let mut manifest = ChunkManifest {
+            chunks: 0,
+            file_size_bytes: 0,
+        };
+        while read_chunk(&mut file, self.chunk_size, &mut buf).await? {
+            let key = chunk_key(id, manifest.chunks);
+            let _: () = connection
+                .pset_ex(&key, buf.as_slice(), expiration_ms)
+                .await
+                .map_err(distribution_error)?;
+            trace!(
+                "Stored {len} bytes under key {key} with expiration {expiration_ms} ms",
+                len = buf.len()
+            );
+            manifest.chunks += 1;
+            manifest.file_size_bytes += buf.len() as u64;
+        }
+
+        let key = meta_key(id);
+        let _: () = connection
+            .pset_ex(&key, metadata_buf.as_ref(), expiration_ms)
+            .await
+            .map_err(distribution_error)?;
+        trace!("Stored metadata under key {key} with expiration {expiration_ms} ms");
+
+        // The manifest is stored last so that readers never observe a partial file.
+        let key = manifest_key(id);
+        let _: () = connection
+            .pset_ex(&key, manifest.to_bytes().as_slice(), expiration_ms)
+            .await
+            .map_err(distribution_error)?;
+        trace!(
+            "Stored manifest of {chunks} chunks under key {key} with expiration {expiration_ms} ms",
+            chunks = manifest.chunks
+        );
+
+        Ok(None)
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DistributionError> {
+        let mut connection = self.connection().await.map_err(distribution_error)?;
+        let keys = stored_keys(&mut connection, id)
+            .await
+            .map_err(distribution_error)?;
+        let _: () = connection.del(&keys).await.map_err(distribution_error)?;
+        trace!("Deleted keys {keys:?}");
+        Ok(())
+    }
+
+    async fn extend_lease(&self, id: ShortGuid, expires: Instant) -> Result<(), DistributionError> {
+        let expiration_ms = expiration_ms(expires, Instant::now());
+        let mut connection = self.connection().await.map_err(distribution_error)?;
+        let keys = stored_keys(&mut connection, id)
+            .await
+            .map_err(distribution_error)?;
+
+        let mut pipe = redis::pipe();
+        for key in &keys {
+            pipe.cmd("PEXPIRE").arg(key).arg(expiration_ms).ignore();
+        }
+        let _: () = pipe
+            .query_async(&mut connection)
+            .await
+            .map_err(distribution_error)?;
+        trace!("Extended expiration of keys {keys:?} to {expiration_ms} ms");
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<(), HealthCheckError> {
+        let mut connection = self
+            .connection()
+            .await
+            .map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))?;
+
+        let pong: String = redis::cmd("PING")
+            .query_async(&mut connection)
+            .await
+            .map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))?;
+        trace!("Redis server answered {pong}");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for RedisBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<Option<BoxedFileReader>, ReceiveError> {
+        let mut connection = self.connection().await.map_err(receive_error)?;
+
+        let key = manifest_key(id);
+        let (manifest, metadata): (Option<Vec<u8>>, Option<Vec<u8>>) = redis::pipe()
+            .get(&key)
+            .get(meta_key(id))
+            .query_async(&mut connection)
+            .await
+            .map_err(receive_error)?;
+        trace!("Fetched manifest from key {key}");
+        let Some(manifest) = manifest.and_then(|buf| ChunkManifest::from_bytes(&buf)) else {
+            return Ok(None);
+        };
+
+        let mut data = Vec::with_capacity(manifest.file_size_bytes as usize);
+        if manifest.chunks > 0 {
+            let keys: Vec<String> = (0..manifest.chunks)
+                .map(|index| chunk_key(id, index))
+                .collect();
+            let chunks: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+                .arg(&keys)
+                .query_async(&mut connection)
+                .await
+                .map_err(receive_error)?;
+            trace!("Fetched {count} chunks of file {id}", count = chunks.len());
+
+            for (index, chunk) in chunks.into_iter().enumerate() {
+                // Chunks may have been evicted independently of each other.
+                let Some(chunk) = chunk else {
+                    debug!(file_id = %id, "Chunk {index} of file {id} is missing");
+                    return Ok(None);
+                };
+                data.extend_from_slice(&chunk);
+            }
+        }
+
+        if data.len() as u64 != manifest.file_size_bytes {
+            debug!(file_id = %id, "The chunks of file {id} do not match its manifest");
+            return Ok(None);
+        }
+
+        let metadata = metadata.and_then(|buf| ItemMetadata::deserialize_from_proto(&buf).ok());
+        let content_type = metadata.as_ref().and_then(|m| m.content_type.clone());
+        let reader = BufferedFileReader::new(data).with_content_type(content_type);
+        let summary = metadata.and_then(|metadata| {
+            metadata.to_summary(reader.file_size_bytes(), reader.expiration_date())
+        });
+
+        Ok(Some(BoxedFileReader::new(reader.with_summary(summary))))
+    }
+}
+
+/// Gets all keys a file is stored under. Chunks beyond the first are only known from the manifest.
+async fn stored_keys(
+    connection: &mut ConnectionManager,
+    id: ShortGuid,
+) -> RedisResult<Vec<String>> {
+    let manifest: Option<Vec<u8>> = connection.get(manifest_key(id)).await?;
+    let chunks = manifest
+        .and_then(|buf| ChunkManifest::from_bytes(&buf))
+        .map_or(0, |manifest| manifest.chunks);
+
+    Ok([manifest_key(id), meta_key(id)]
+        .into_iter()
+        .chain((0..chunks.max(1)).map(|index| chunk_key(id, index)))
+        .collect())
+}
+
+/// Gets the number of milliseconds until the lease of a file ends, at least one,
+/// since Redis rejects non-positive expiration times.
+fn expiration_ms(expires: Instant, now: Instant) -> u64 {
+    let remaining = expires.saturating_duration_since(now);
+    u64::try_from(remaining.as_millis())
+        .unwrap_or(u64::MAX)
+        .max(1)
+}
+
+fn distribution_error(e: RedisError) -> DistributionError {
+    DistributionError::BackendSpecific(Box::new(e))
+}
+
+fn receive_error(e: RedisError) -> ReceiveError {
+    ReceiveError::BackendSpecific(Box::new(e))
+}
+
+impl BackendInfo for RedisBackend {
+    fn backend_name() -> &'static str {
+        "Redis"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for RedisBackend {
+    type Error = RedisBackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        let configs = &config.backends.redis;
+        if configs.is_empty() {
+            return Ok(Vec::default());
+        }
+
+        configs
+            .iter()
+            .map(|config| {
+                RedisBackend::try_new(config)
+                    .map(|backend| Backend::wrap(backend).with_priority(config.priority))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedisBackendConstructionError {
+    #[error("Invalid connection string: {0}")]
+    InvalidConnectionString(RedisError),
+    #[error("The chunk size must be at least one byte")]
+    InvalidChunkSize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn entries_expire_with_the_lease() {
+        let now = Instant::now();
+        assert_eq!(expiration_ms(now + Duration::from_secs(300), now), 300_000);
+        assert_eq!(expiration_ms(now, now), 1);
+        assert_eq!(expiration_ms(now, now + Duration::from_secs(1)), 1);
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected() {
+        let config = RedisBackendConfig {
+            tag: "redis-1".to_string(),
+            connection_string: "redis://127.0.0.1:6379/0".to_string(),
+            priority: 0,
+            chunk_size_bytes: Some(0),
+        };
+        assert!(matches!(
+            RedisBackend::try_new(&config),
+            Err(RedisBackendConstructionError::InvalidChunkSize)
+        ));
+
+        let config = RedisBackendConfig {
+            connection_string: "memcache://127.0.0.1:11211".to_string(),
+            ..config
+        };
+        assert!(matches!(
+            RedisBackend::try_new(&config),
+            Err(RedisBackendConstructionError::InvalidConnectionString(_))
+        ));
+    }
+}
@@ -0,0 +1,106 @@
+use shortguid::ShortGuid;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Describes how a file is split into chunks; stored under the [`manifest_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkManifest {
+    /// The number of chunks, stored under [`chunk_key`] with indexes `0..chunks`.
+    pub chunks: u32,
+    /// The total size of the file in bytes.
+    pub file_size_bytes: u64,
+}
+
+impl ChunkManifest {
+    /// The size of the encoded manifest.
+    const ENCODED_LEN: usize = 12;
+
+    /// Encodes the manifest as the big-endian chunk count followed by the file size.
+    pub fn to_bytes(self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        bytes[..4].copy_from_slice(&self.chunks.to_be_bytes());
+        bytes[4..].copy_from_slice(&self.file_size_bytes.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a manifest, returning `None` if the bytes are malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; Self::ENCODED_LEN] = bytes.try_into().ok()?;
+        Some(Self {
+            chunks: u32::from_be_bytes(bytes[..4].try_into().ok()?),
+            file_size_bytes: u64::from_be_bytes(bytes[4..].try_into().ok()?),
+        })
+    }
+}
+
+/// Gets the key under which the chunk manifest of a file is stored.
+pub fn manifest_key(id: ShortGuid) -> String {
+    format!("{id}:manifest")
+}
+
+/// Gets the key under which the metadata of a file is stored.
+pub fn meta_key(id: ShortGuid) -> String {
+    format!("{id}:meta")
+}
+
+/// Gets the key under which a chunk of a file is stored.
+///
+/// The first chunk is stored under the ID itself, which holds the entire file unless
+/// it exceeds the chunk size.
+pub fn chunk_key(id: ShortGuid, index: u32) -> String {
+    match index {
+        0 => id.to_string(),
+        _ => format!("{id}:{index}"),
+    }
+}
+
+/// Reads the next chunk of at most `chunk_size` bytes into `buf`.
+///
+/// Returns `false` once the reader is exhausted.
+pub async fn read_chunk<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    chunk_size: usize,
+    buf: &mut Vec<u8>,
+) -> std::io::Result<bool> {
+    buf.clear();
+    reader.take(chunk_size as u64).read_to_end(buf).await?;
+    Ok(!buf.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_roundtrip_works() {
+        let manifest = ChunkManifest {
+            chunks: 3,
+            file_size_bytes: 10_000_000,
+        };
+        assert_eq!(
+            ChunkManifest::from_bytes(&manifest.to_bytes()),
+            Some(manifest)
+        );
+        assert_eq!(ChunkManifest::from_bytes(b"yeet"), None);
+    }
+
+    #[tokio::test]
+    async fn files_are_split_into_chunks() {
+        let data: Vec<u8> = (0..10).collect();
+        let mut reader = data.as_slice();
+        let mut chunks = Vec::new();
+        let mut buf = Vec::new();
+        while read_chunk(&mut reader, 4, &mut buf).await.unwrap() {
+            chunks.push(buf.clone());
+        }
+        assert_eq!(chunks, vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+    }
+
+    #[test]
+    fn the_first_chunk_is_stored_under_the_id() {
+        let id = ShortGuid::new_random();
+        assert_eq!(chunk_key(id, 0), id.to_string());
+        assert_eq!(chunk_key(id, 2), format!("{id}:2"));
+        assert_eq!(manifest_key(id), format!("{id}:manifest"));
+        assert_eq!(meta_key(id), format!("{id}:meta"));
+    }
+}
@@ -0,0 +1,464 @@
+use crate::file_reader::{ObjectStream, S3FileReader};
+use app_config::{s3::S3BackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{
+    Backend, DistributeFile, DistributionError, HealthCheckError, ReceiveError, ReceiveFile,
+    StoredDigest,
+};
+use backend_traits::{BackendInfo, TryCreateFromConfig};
+use file_distribution::{
+    BoxedFileReader, FileHashes, FileProvider, FileReaderTrait, GetFile, WriteSummary,
+};
+use http::{HeaderMap, HeaderValue};
+use s3::creds::Credentials;
+use s3::error::S3Error;
+use s3::{Bucket, Region};
+use shared_files::FileSize;
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_stream::StreamExt;
+use tracing::{trace, warn};
+
+/// The object metadata key under which the SHA-256 hash of a file is stored.
+const SHA256_METADATA_KEY: &str = "sha256";
+
+/// The content type of files whose content type is unknown.
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The key probed by health checks; it is never written.
+const HEALTH_CHECK_KEY: &str = "yeet-yoink-health-check";
+
+/// A backend storing files in an S3-compatible bucket.
+///
+/// Each file is stored as an object keyed by its ID, with its content type set on
+/// the object and its SHA-256 hash stored as object metadata.
+pub struct S3Backend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The bucket to store the files in.
+    bucket: Box<Bucket>,
+    /// The size of the parts of multipart uploads.
+    part_size: usize,
+}
+
+impl S3Backend {
+    pub fn try_new(config: &S3BackendConfig) -> Result<Self, S3BackendConstructionError> {
+        let region = match &config.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: config.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => config
+                .region
+                .parse()
+                .map_err(|_| S3BackendConstructionError::InvalidRegion(config.region.clone()))?,
+        };
+
+        let credentials = Credentials::new(
+            config.access_key.as_deref(),
+            config.secret_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| S3BackendConstructionError::InvalidCredentials(Box::new(e)))?;
+
+        let mut bucket = Bucket::new(&config.bucket, region, credentials)
+            .map_err(S3BackendConstructionError::FailedToCreateBucket)?;
+        if config.path_style {
+            bucket.set_path_style();
+        }
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            bucket,
+            part_size: config.part_size(),
+        })
+    }
+
+    /// Uploads the file, using a multipart upload if it exceeds the part size.
+    async fn upload<R>(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        reader: &mut R,
+        content_type: &str,
+    ) -> Result<Option<StoredDigest>, S3BackendError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let first_part = read_part(reader, self.part_size).await?;
+        if first_part.len() < self.part_size {
+            let response = bucket
+                .put_object_with_content_type(key, &first_part, content_type)
+                .await?;
+            check_status(response.status_code())?;
+            return Ok(response
+                .headers()
+                .get("etag")
+                .and_then(|etag| digest_from_etag(etag)));
+        }
+
+        let upload = bucket.initiate_multipart_upload(key, content_type).await?;
+        let result = async {
+            let mut parts = Vec::new();
+            let mut part = first_part;
+            while !part.is_empty() {
+                let part_number = parts.len() as u32 + 1;
+                trace!(
+                    "Uploading part {part_number} of {key} ({} bytes)",
+                    part.len()
+                );
+                parts.push(
+                    bucket
+                        .put_multipart_chunk(
+                            part,
+                            &upload.key,
+                            part_number,
+                            &upload.upload_id,
+                            content_type,
+                        )
+                        .await?,
+                );
+                part = read_part(reader, self.part_size).await?;
+            }
+
+            let response = bucket
+                .complete_multipart_upload(&upload.key, &upload.upload_id, parts)
+                .await?;
+            check_status(response.status_code())
+        }
+        .await;
+
+        if result.is_err() {
+            if let Err(e) = bucket.abort_upload(&upload.key, &upload.upload_id).await {
+                warn!("Failed to abort the multipart upload of {key}: {e}");
+            }
+        }
+
+        // The ETags of multipart uploads are not the MD5 of the object.
+        result.map(|_| None)
+    }
+}
+
+#[async_trait]
+impl DistributeFile for S3Backend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn location(&self, id: ShortGuid) -> Option<String> {
+        Some(format!(
+            "s3://{bucket}/{key}",
+            bucket = self.bucket.name(),
+            key = object_key(id)
+        ))
+    }
+
+    async fn presigned_url(
+        &self,
+        id: ShortGuid,
+        expires_in: Duration,
+    ) -> Result<Option<String>, ReceiveError> {
+        let key = object_key(id);
+        let (_, status) = self
+            .bucket
+            .head_object(&key)
+            .await
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        if status == 404 {
+            return Ok(None);
+        }
+        check_status(status).map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+
+        let url = self
+            .bucket
+            .presign_get(&key, presign_expiry_secs(expires_in), None)
+            .await
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        trace!(file_id = %id, "Presigned object {key} for {expires_in:?}");
+        Ok(Some(url))
+    }
+
+    fn receiver(&self) -> Option<&dyn ReceiveFile> {
+        Some(self)
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<Option<StoredDigest>, DistributionError> {
+        let mut file = file_provider.get_file(id).await?;
+        let content_type = file
+            .content_type()
+            .map_or(DEFAULT_CONTENT_TYPE.to_string(), |c| c.into_owned());
+
+        // The metadata is sent with the request creating the object.
+        let mut headers = HeaderMap::new();
+        if let Some(sha256) = summary.hashes.sha256 {
+            let value = HeaderValue::from_str(&hex::encode(sha256))
+                .expect("hex encoding produces valid header values");
+            headers.insert(
+                http::HeaderName::try_from(format!("x-amz-meta-{SHA256_METADATA_KEY}"))
+                    .expect("metadata key is a valid header name"),
+                value,
+            );
+        }
+
+        let bucket = self
+            .bucket
+            .with_extra_headers(headers)
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        let key = object_key(id);
+        let digest = self
+            .upload(&bucket, &key, &mut file, &content_type)
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+        trace!(file_id = %id, "Stored object {key} in bucket {bucket}", bucket = bucket.name());
+        Ok(digest)
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DistributionError> {
+        let key = object_key(id);
+        let response = self
+            .bucket
+            .delete_object(&key)
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        // Deleting objects that don't exist is not an error.
+        match response.status_code() {
+            404 => Ok(()),
+            status => {
+                check_status(status).map_err(|e| DistributionError::BackendSpecific(Box::new(e)))
+            }
+        }?;
+
+        trace!(file_id = %id, "Deleted object {key}");
+        Ok(())
+    }
+
+    /// Requests the metadata of a well-known key. Any response other than a server
+    /// error shows that the bucket is reachable, even if the object does not exist.
+    async fn health_check(&self) -> Result<(), HealthCheckError> {
+        let (_, status) = self
+            .bucket
+            .head_object(HEALTH_CHECK_KEY)
+            .await
+            .map_err(|e| HealthCheckError::BackendSpecific(Box::new(e)))?;
+        if status >= 500 {
+            return Err(HealthCheckError::BackendSpecific(Box::new(
+                S3BackendError::UnexpectedStatus(status),
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for S3Backend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<Option<BoxedFileReader>, ReceiveError> {
+        let key = object_key(id);
+        let (head, status) = self
+            .bucket
+            .head_object(&key)
+            .await
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        if status == 404 {
+            return Ok(None);
+        }
+        check_status(status).map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+
+        let response = self
+            .bucket
+            .get_object_stream(&key)
+            .await
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        check_status(response.status_code)
+            .map_err(|e| ReceiveError::BackendSpecific(Box::new(e)))?;
+        trace!(file_id = %id, "Fetched object {key}");
+
+        let file_size = match head.content_length.and_then(|l| usize::try_from(l).ok()) {
+            Some(size) => FileSize::Exactly(size),
+            None => FileSize::Error,
+        };
+
+        let summary = match file_size {
+            FileSize::Exactly(size) => head
+                .metadata
+                .as_ref()
+                .and_then(|metadata| summary_from_metadata(metadata, size)),
+            _ => None,
+        };
+
+        let stream: ObjectStream = Box::pin(
+            response
+                .bytes
+                .map(|chunk| chunk.map_err(std::io::Error::other)),
+        );
+        let reader = S3FileReader::new(stream, file_size, head.content_type, summary);
+        Ok(Some(BoxedFileReader::new(reader)))
+    }
+}
+
+/// The longest validity of presigned URLs supported by S3.
+const MAX_PRESIGN_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Converts the validity of a presigned URL to seconds, clamped to the range supported by S3.
+fn presign_expiry_secs(expires_in: Duration) -> u32 {
+    expires_in
+        .clamp(Duration::from_secs(1), MAX_PRESIGN_EXPIRY)
+        .as_secs() as u32
+}
+
+/// Gets the key under which the file is stored.
+fn object_key(id: ShortGuid) -> String {
+    id.to_string()
+}
+
+/// Reconstructs the write summary of a file from its object metadata.
+fn summary_from_metadata(
+    metadata: &HashMap<String, String>,
+    file_size_bytes: usize,
+) -> Option<Arc<WriteSummary>> {
+    let sha256 = hex::decode(metadata.get(SHA256_METADATA_KEY)?).ok()?;
+    Some(Arc::new(WriteSummary {
+        expires: tokio::time::Instant::now(),
+        hashes: FileHashes::try_from_slices(&[], &sha256, &[])?,
+        file_name: None,
+        file_size_bytes,
+        metadata: Default::default(),
+    }))
+}
+
+/// Reads up to `part_size` bytes, returning fewer only at the end of the file.
+async fn read_part<R>(reader: &mut R, part_size: usize) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut part = Vec::with_capacity(part_size);
+    reader.take(part_size as u64).read_to_end(&mut part).await?;
+    Ok(part)
+}
+
+/// Parses the `ETag` of an object created by a single request, which is the MD5 digest
+/// of the object unless it is encrypted with a customer or KMS key.
+fn digest_from_etag(etag: &str) -> Option<StoredDigest> {
+    let digest = hex::decode(etag.trim_matches('"')).ok()?;
+    digest.try_into().ok().map(StoredDigest::Md5)
+}
+
+fn check_status(status: u16) -> Result<(), S3BackendError> {
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(S3BackendError::UnexpectedStatus(status))
+    }
+}
+
+impl BackendInfo for S3Backend {
+    fn backend_name() -> &'static str {
+        "S3"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for S3Backend {
+    type Error = S3BackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        config
+            .backends
+            .s3
+            .iter()
+            .map(|config| {
+                S3Backend::try_new(config)
+                    .map(|backend| Backend::wrap(backend).with_priority(config.priority))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum S3BackendConstructionError {
+    #[error("Invalid region: {0}")]
+    InvalidRegion(String),
+    #[error("Invalid credentials")]
+    InvalidCredentials(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to create bucket")]
+    FailedToCreateBucket(#[source] S3Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum S3BackendError {
+    #[error(transparent)]
+    Request(#[from] S3Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Unexpected response status {0}")]
+    UnexpectedStatus(u16),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn read_part_fills_parts_until_end_of_file() {
+        let mut reader: &[u8] = b"yeet-yoink";
+        assert_eq!(read_part(&mut reader, 4).await.unwrap(), b"yeet");
+        assert_eq!(read_part(&mut reader, 4).await.unwrap(), b"-yoi");
+        assert_eq!(read_part(&mut reader, 4).await.unwrap(), b"nk");
+        assert!(read_part(&mut reader, 4).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn etags_are_parsed_as_md5_digests() {
+        let etag = "\"9f53c0b7e3c3ff1d8e9d8bd1a9d5cb1a\"";
+        assert_eq!(
+            digest_from_etag(etag)
+                .map(|digest| digest.to_string())
+                .as_deref(),
+            Some("md5:9f53c0b7e3c3ff1d8e9d8bd1a9d5cb1a")
+        );
+        assert_eq!(
+            digest_from_etag("\"9f53c0b7e3c3ff1d8e9d8bd1a9d5cb1a-3\""),
+            None
+        );
+        assert_eq!(digest_from_etag("not-hex"), None);
+    }
+
+    #[test]
+    fn presign_expiry_is_clamped() {
+        assert_eq!(presign_expiry_secs(Duration::ZERO), 1);
+        assert_eq!(presign_expiry_secs(Duration::from_secs(300)), 300);
+        assert_eq!(
+            presign_expiry_secs(Duration::from_secs(30 * 24 * 60 * 60)),
+            604_800
+        );
+    }
+
+    #[test]
+    fn summary_is_restored_from_metadata() {
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let metadata = HashMap::from([(SHA256_METADATA_KEY.to_string(), sha256.to_string())]);
+
+        let summary = summary_from_metadata(&metadata, 0).expect("summary should be restored");
+        assert_eq!(
+            summary.hashes.sha256.map(hex::encode).as_deref(),
+            Some(sha256)
+        );
+        assert!(summary.hashes.md5.is_none());
+
+        assert!(summary_from_metadata(&HashMap::new(), 0).is_none());
+    }
+}
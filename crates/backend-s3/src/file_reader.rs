@@ -0,0 +1,83 @@
+use bytes::Bytes;
+use file_distribution::{FileReaderTrait, WriteSummary};
+use shared_files::FileSize;
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Instant;
+use tokio_stream::Stream;
+use tokio_util::io::StreamReader;
+
+/// The stream of an object's contents.
+pub(crate) type ObjectStream = Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>;
+
+/// A file reader streaming an object from S3.
+pub(crate) struct S3FileReader {
+    inner: StreamReader<ObjectStream, Bytes>,
+    file_size: FileSize,
+    content_type: Option<String>,
+    created: Instant,
+    creation_date: SystemTime,
+    summary: Option<Arc<WriteSummary>>,
+}
+
+impl S3FileReader {
+    pub fn new(
+        stream: ObjectStream,
+        file_size: FileSize,
+        content_type: Option<String>,
+        summary: Option<Arc<WriteSummary>>,
+    ) -> Self {
+        Self {
+            inner: StreamReader::new(stream),
+            file_size,
+            content_type,
+            created: Instant::now(),
+            creation_date: SystemTime::now(),
+            summary,
+        }
+    }
+}
+
+impl FileReaderTrait for S3FileReader {
+    fn summary(&self) -> &Option<Arc<WriteSummary>> {
+        &self.summary
+    }
+
+    /// Since the remaining lifetime of the file is unknown to the backend,
+    /// the file is considered to expire immediately.
+    fn expiration_date(&self) -> Instant {
+        self.created
+    }
+
+    fn file_size(&self) -> FileSize {
+        self.file_size
+    }
+
+    fn file_age(&self) -> Duration {
+        Instant::now() - self.created
+    }
+
+    fn creation_date(&self) -> SystemTime {
+        self.creation_date
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
+        self.content_type
+            .as_ref()
+            .map(|content_type| Cow::from(content_type.as_str()))
+    }
+}
+
+impl AsyncRead for S3FileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
@@ -1,13 +1,108 @@
-use file_distribution::WriteSummary;
+use crate::{BackendHealth, ReceiveTimeout};
+use file_distribution::{BoxedFileReader, WriteSummary};
 use shortguid::ShortGuid;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc::error::SendError;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Permit, Sender};
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+use tracing::Span;
 
 pub enum BackendCommand {
-    DistributeFile(ShortGuid, Arc<WriteSummary>),
+    /// A file was created and is being written. The span is that of the upload,
+    /// such that the distribution can be traced back to it.
+    FileCreated(ShortGuid, Span),
+    /// Distributes a file to all backends. The outcome is sent once all backends
+    /// finished if a completion channel is provided. The span is that of the upload,
+    /// such that the distribution can be traced back to it.
+    DistributeFile(
+        ShortGuid,
+        Arc<WriteSummary>,
+        Option<oneshot::Sender<DistributionOutcome>>,
+        Span,
+    ),
+    /// Attempts to read a file back from the backends. The first backend
+    /// knowing the file provides the reader; `None` is sent if no backend does,
+    /// and [`ReceiveTimeout`] if backends that might know it did not answer in time.
+    ReceiveFile(
+        ShortGuid,
+        oneshot::Sender<Result<Option<BoxedFileReader>, ReceiveTimeout>>,
+    ),
+    /// Requests a presigned URL of a file valid for the specified duration. The first
+    /// backend providing one answers; `None` is sent if no backend does.
+    PresignFile(ShortGuid, Duration, oneshot::Sender<Option<String>>),
+    /// A file was removed from the backbone, either because its lease expired
+    /// or writing it failed.
+    FileRemoved(ShortGuid),
+    /// A file was deleted explicitly and should be removed from the backends.
+    DeleteFile(ShortGuid),
+    /// The lease of a file was extended until the specified time, e.g. by a keepalive.
+    LeaseExtended(ShortGuid, Instant),
+    /// Checks the health of all backends, waiting at most the specified duration for each.
+    CheckHealth(Duration, oneshot::Sender<Vec<BackendHealth>>),
 }
 
+/// The outcome of distributing a file to the backends.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DistributionOutcome {
+    /// The result of each backend asked to store the file, keyed by the backend tag.
+    pub backends: BTreeMap<String, BackendResult>,
+}
+
+/// The result of distributing a file to a single backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendResult {
+    /// The backend stored the file.
+    Stored,
+    /// The backend failed to store the file.
+    Failed,
+}
+
+impl DistributionOutcome {
+    /// Records the result of a backend.
+    pub fn insert<T: Into<String>>(&mut self, tag: T, result: BackendResult) {
+        self.backends.insert(tag.into(), result);
+    }
+
+    /// Gets the tags of the backends that stored the file.
+    pub fn succeeded(&self) -> Vec<&str> {
+        self.with_result(BackendResult::Stored)
+    }
+
+    /// Gets the tags of the backends that failed to store the file.
+    pub fn failed(&self) -> Vec<&str> {
+        self.with_result(BackendResult::Failed)
+    }
+
+    fn with_result(&self, result: BackendResult) -> Vec<&str> {
+        self.backends
+            .iter()
+            .filter(|(_, r)| **r == result)
+            .map(|(tag, _)| tag.as_str())
+            .collect()
+    }
+}
+
+impl BackendResult {
+    /// Gets the name of the result, e.g. `stored`.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            BackendResult::Stored => "stored",
+            BackendResult::Failed => "failed",
+        }
+    }
+}
+
+impl Display for BackendResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone)]
 pub struct BackendCommandSender {
     sender: Sender<BackendCommand>,
 }
@@ -16,6 +111,20 @@ impl BackendCommandSender {
     pub async fn send(&self, command: BackendCommand) -> Result<(), BackendCommandSendError> {
         Ok(self.sender.send(command).await?)
     }
+
+    /// Reserves a slot in the command queue, waiting at most `timeout` for one to free up.
+    ///
+    /// The reserved slot is used by sending a command through the returned permit.
+    pub async fn reserve_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Permit<'_, BackendCommand>, BackendCommandReserveError> {
+        match tokio::time::timeout(timeout, self.sender.reserve()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(BackendCommandReserveError::Closed),
+            Err(_) => Err(BackendCommandReserveError::Full(timeout)),
+        }
+    }
 }
 
 impl From<Sender<BackendCommand>> for BackendCommandSender {
@@ -27,3 +136,43 @@ impl From<Sender<BackendCommand>> for BackendCommandSender {
 #[derive(Debug, thiserror::Error)]
 #[error(transparent)]
 pub struct BackendCommandSendError(#[from] SendError<BackendCommand>);
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendCommandReserveError {
+    #[error("The backend command queue is closed")]
+    Closed,
+    #[error("The backend command queue remained full for {0:?}")]
+    Full(Duration),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test(start_paused = true)]
+    async fn reserving_a_full_queue_times_out() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let sender = BackendCommandSender::from(sender);
+        let timeout = Duration::from_millis(100);
+
+        sender
+            .reserve_timeout(timeout)
+            .await
+            .expect("queue should have capacity")
+            .send(BackendCommand::FileRemoved(ShortGuid::new_random()));
+        assert!(matches!(
+            sender.reserve_timeout(timeout).await,
+            Err(BackendCommandReserveError::Full(_))
+        ));
+
+        receiver.recv().await.expect("missing command");
+        assert!(sender.reserve_timeout(timeout).await.is_ok());
+
+        drop(receiver);
+        assert!(matches!(
+            sender.reserve_timeout(timeout).await,
+            Err(BackendCommandReserveError::Closed)
+        ));
+    }
+}
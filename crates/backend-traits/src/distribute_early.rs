@@ -0,0 +1,30 @@
+use crate::DistributionError;
+use async_trait::async_trait;
+use file_distribution::{FileProvider, WriteSummary};
+use shortguid::ShortGuid;
+use std::sync::Arc;
+
+/// Trait for backends that do not depend on the file hashes and can therefore
+/// start receiving a file while it is still being written.
+#[async_trait]
+pub trait DistributeEarly: Send + Sync {
+    /// Handles a file that is still being written.
+    ///
+    /// Readers obtained from the `file_provider` block until more data is written
+    /// and end when the file was written completely.
+    async fn distribute_early(
+        &self,
+        id: ShortGuid,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError>;
+
+    /// Attaches the write summary (e.g. the hashes) to a file that was
+    /// distributed early, once writing it has completed.
+    async fn update_metadata(
+        &self,
+        _id: ShortGuid,
+        _summary: Arc<WriteSummary>,
+    ) -> Result<(), DistributionError> {
+        Ok(())
+    }
+}
@@ -1,9 +1,17 @@
+use crate::receive_file::FallbackContentType;
+use crate::{
+    BackendInfo, DistributeEarly, HealthCheckError, ReceiveError, ReceiveFile, StoredDigest,
+};
 use async_trait::async_trait;
-use file_distribution::{FileAccessorError, FileProvider, WriteSummary};
+use file_distribution::{
+    BoxedFileReader, FileAccessorError, FileProvider, FileReaderTrait, WriteSummary,
+};
 use shortguid::ShortGuid;
 use std::error::Error;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
 
 /// Main trait for file distribution to a backend.
 #[async_trait]
@@ -11,13 +19,72 @@ pub trait DistributeFile: Send + Sync {
     /// Gets the tag of the backend.
     fn tag(&self) -> &str;
 
+    /// Gets a backend-specific description of where a distributed file is stored,
+    /// e.g. a key or path. Returns `None` if the backend cannot describe it.
+    fn location(&self, _id: ShortGuid) -> Option<String> {
+        None
+    }
+
+    /// Gets a time-limited URL from which clients can download a distributed file directly,
+    /// valid for `expires_in`.
+    ///
+    /// Returns `Ok(None)` if the backend does not support presigned URLs or does not
+    /// know the file, in which case downloads are streamed through the server.
+    async fn presigned_url(
+        &self,
+        _id: ShortGuid,
+        _expires_in: Duration,
+    ) -> Result<Option<String>, ReceiveError> {
+        Ok(None)
+    }
+
+    /// Gets access to the backend's ability to serve files back, if it supports it.
+    fn receiver(&self) -> Option<&dyn ReceiveFile> {
+        None
+    }
+
+    /// Gets access to the backend's ability to receive files before they are hashed,
+    /// if it supports it.
+    fn early_distributor(&self) -> Option<&dyn DistributeEarly> {
+        None
+    }
+
     /// Handles a file that is ready for distribution.
+    ///
+    /// Backends able to verify the stored object return its digest, which is compared
+    /// against the hashes of the file to detect corruption; others return `Ok(None)`.
     async fn distribute_file(
         &self,
         id: ShortGuid,
         summary: Arc<WriteSummary>,
         file_provider: FileProvider,
-    ) -> Result<(), DistributionError>;
+    ) -> Result<Option<StoredDigest>, DistributionError>;
+
+    /// Removes a distributed file from the backend.
+    ///
+    /// The default implementation does nothing, leaving the file to expire.
+    async fn delete_file(&self, _id: ShortGuid) -> Result<(), DistributionError> {
+        Ok(())
+    }
+
+    /// Keeps a distributed file until `expires`, since its lease was extended.
+    ///
+    /// The default implementation does nothing, for backends that do not expire files
+    /// along with their lease.
+    async fn extend_lease(
+        &self,
+        _id: ShortGuid,
+        _expires: Instant,
+    ) -> Result<(), DistributionError> {
+        Ok(())
+    }
+
+    /// Probes whether the backend is reachable, e.g. by a lightweight request to its server.
+    ///
+    /// The default implementation reports the backend as healthy.
+    async fn health_check(&self) -> Result<(), HealthCheckError> {
+        Ok(())
+    }
 }
 
 /// [`Backend`] is a wrapper struct that holds a dynamically dispatched [`DistributeFile`] instance.
@@ -28,7 +95,7 @@ pub trait DistributeFile: Send + Sync {
 /// use std::sync::Arc;
 /// use async_trait::async_trait;
 /// use shortguid::ShortGuid;
-/// use backend_traits::{DistributeFile, DistributionError, Backend};
+/// use backend_traits::{DistributeFile, DistributionError, Backend, StoredDigest};
 /// use file_distribution::{FileProvider, WriteSummary};
 ///
 /// struct PostgresBackend;
@@ -37,9 +104,9 @@ pub trait DistributeFile: Send + Sync {
 /// impl DistributeFile for PostgresBackend {
 ///     fn tag(&self) -> &str { "postgres" }
 ///
-///     async fn distribute_file(&self, id: ShortGuid, summary: Arc<WriteSummary>, file_accessor: FileProvider) -> Result<(), DistributionError> {
+///     async fn distribute_file(&self, id: ShortGuid, summary: Arc<WriteSummary>, file_accessor: FileProvider) -> Result<Option<StoredDigest>, DistributionError> {
 ///         // ...
-/// #       Ok(())
+/// #       Ok(None)
 ///     }
 /// }
 ///
@@ -49,23 +116,43 @@ pub trait DistributeFile: Send + Sync {
 /// impl DistributeFile for MySqlBackend {
 ///     fn tag(&self) -> &str { "mysql" }
 ///
-///     async fn distribute_file(&self, id: ShortGuid, summary: Arc<WriteSummary>, file_accessor: FileProvider) -> Result<(), DistributionError> {
+///     async fn distribute_file(&self, id: ShortGuid, summary: Arc<WriteSummary>, file_accessor: FileProvider) -> Result<Option<StoredDigest>, DistributionError> {
 ///         // ...
-/// #        Ok(())
+/// #        Ok(None)
 ///     }
 /// }
 ///
 /// let postgres_backend = Backend::wrap(PostgresBackend);
-/// let my_sql_backend = Backend::wrap(MySqlBackend);
+/// let my_sql_backend = Backend::wrap(MySqlBackend).with_priority(10);
+///
+/// assert_eq!(postgres_backend.priority(), 0);
+/// assert_eq!(my_sql_backend.priority(), 10);
 /// ```
-pub struct Backend(Box<dyn DistributeFile>);
+pub struct Backend {
+    inner: Box<dyn DistributeFile>,
+    priority: BackendPriority,
+    fallback_content_type: Option<String>,
+    /// The name and version of the backend implementation, if known.
+    info: Option<(&'static str, &'static str)>,
+}
+
+/// The priority of a backend. Backends with lower values are served first.
+pub type BackendPriority = u16;
+
+/// The priority used for backends that don't specify one.
+pub const DEFAULT_PRIORITY: BackendPriority = 0;
 
 impl Backend {
     pub fn new<T>(b: Box<T>) -> Self
     where
         T: DistributeFile + 'static,
     {
-        Backend(b)
+        Backend {
+            inner: b,
+            priority: DEFAULT_PRIORITY,
+            fallback_content_type: None,
+            info: None,
+        }
     }
 
     pub fn wrap<T>(b: T) -> Self
@@ -74,13 +161,71 @@ impl Backend {
     {
         Self::new(Box::new(b))
     }
+
+    /// Sets the priority of the backend. Lower values are served first.
+    pub fn with_priority(mut self, priority: BackendPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Gets the priority of the backend. Lower values are served first.
+    pub fn priority(&self) -> BackendPriority {
+        self.priority
+    }
+
+    /// Records the name and version of the backend implementation `T`.
+    pub fn with_info<T: BackendInfo>(mut self) -> Self {
+        self.info = Some((T::backend_name(), T::backend_version()));
+        self
+    }
+
+    /// Gets the name of the backend implementation, e.g. `S3`, if known.
+    pub fn backend_name(&self) -> Option<&'static str> {
+        self.info.map(|(name, _)| name)
+    }
+
+    /// Gets the version of the backend implementation, if known.
+    pub fn backend_version(&self) -> Option<&'static str> {
+        self.info.map(|(_, version)| version)
+    }
+
+    /// Sets the content type to report for files read back from the backend
+    /// when the backend did not preserve it.
+    pub fn with_fallback_content_type(mut self, content_type: Option<String>) -> Self {
+        self.fallback_content_type = content_type;
+        self
+    }
+
+    /// Attempts to read a file back from the backend.
+    ///
+    /// Returns `Ok(None)` if the backend does not support reading files or does not know the file.
+    /// If the backend did not preserve the content type, the configured fallback is reported.
+    pub async fn receive_file(
+        &self,
+        id: ShortGuid,
+    ) -> Result<Option<BoxedFileReader>, ReceiveError> {
+        let Some(receiver) = self.inner.receiver() else {
+            return Ok(None);
+        };
+
+        let Some(reader) = receiver.receive_file(id).await? else {
+            return Ok(None);
+        };
+
+        match &self.fallback_content_type {
+            Some(fallback) if reader.content_type().is_none() => {
+                Ok(Some(FallbackContentType::wrap(reader, fallback.clone())))
+            }
+            _ => Ok(Some(reader)),
+        }
+    }
 }
 
 impl Deref for Backend {
     type Target = dyn DistributeFile;
 
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &*self.inner
     }
 }
 
@@ -96,11 +241,95 @@ where
 #[derive(Debug, thiserror::Error)]
 pub enum DistributionError {
     #[error(transparent)]
-    BackendSpecific(Box<dyn Error>),
+    BackendSpecific(Box<dyn Error + Send + Sync>),
     #[error(transparent)]
     FileAccessor(#[from] FileAccessorError),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Join(#[from] tokio::task::JoinError),
+    #[error("The circuit breaker of the backend is open")]
+    CircuitOpen,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_distribution::BufferedFileReader;
+
+    struct StubBackend {
+        content_type: Option<String>,
+    }
+
+    #[async_trait]
+    impl DistributeFile for StubBackend {
+        fn tag(&self) -> &str {
+            "stub"
+        }
+
+        fn receiver(&self) -> Option<&dyn ReceiveFile> {
+            Some(self)
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<Option<StoredDigest>, DistributionError> {
+            Ok(None)
+        }
+    }
+
+    #[async_trait]
+    impl ReceiveFile for StubBackend {
+        async fn receive_file(
+            &self,
+            _id: ShortGuid,
+        ) -> Result<Option<BoxedFileReader>, ReceiveError> {
+            let reader =
+                BufferedFileReader::new("data").with_content_type(self.content_type.clone());
+            Ok(Some(BoxedFileReader::new(reader)))
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_content_type_is_served() {
+        let backend = Backend::wrap(StubBackend { content_type: None })
+            .with_fallback_content_type(Some("application/pdf".to_string()));
+
+        let reader = backend
+            .receive_file(ShortGuid::new_random())
+            .await
+            .expect("receiving should succeed")
+            .expect("file should exist");
+        assert_eq!(reader.content_type().as_deref(), Some("application/pdf"));
+    }
+
+    #[tokio::test]
+    async fn stored_content_type_is_preferred() {
+        let backend = Backend::wrap(StubBackend {
+            content_type: Some("text/plain".to_string()),
+        })
+        .with_fallback_content_type(Some("application/pdf".to_string()));
+
+        let reader = backend
+            .receive_file(ShortGuid::new_random())
+            .await
+            .expect("receiving should succeed")
+            .expect("file should exist");
+        assert_eq!(reader.content_type().as_deref(), Some("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn missing_content_type_without_fallback() {
+        let backend = Backend::wrap(StubBackend { content_type: None });
+
+        let reader = backend
+            .receive_file(ShortGuid::new_random())
+            .await
+            .expect("receiving should succeed")
+            .expect("file should exist");
+        assert_eq!(reader.content_type(), None);
+    }
 }
@@ -0,0 +1,31 @@
+use std::error::Error;
+use std::time::Duration;
+
+/// The outcome of the health check of a single backend, see
+/// [`DistributeFile::health_check`](crate::DistributeFile::health_check).
+#[derive(Debug)]
+pub struct BackendHealth {
+    /// The tag of the backend.
+    pub tag: String,
+    /// The reason the backend is unhealthy, or `None` if it is healthy.
+    pub error: Option<HealthCheckError>,
+}
+
+impl BackendHealth {
+    /// Determines whether the backend is healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HealthCheckError {
+    #[error(transparent)]
+    BackendSpecific(Box<dyn Error + Send + Sync>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+    #[error("The health check did not complete within {0:?}")]
+    TimedOut(Duration),
+}
@@ -4,12 +4,25 @@
 
 mod backend_command;
 mod backend_info;
+mod distribute_early;
 mod distribute_file;
 mod from_config;
+mod health_check;
+mod receive_file;
 mod registration;
+mod stored_digest;
 
-pub use backend_command::{BackendCommand, BackendCommandSendError, BackendCommandSender};
+pub use backend_command::{
+    BackendCommand, BackendCommandReserveError, BackendCommandSendError, BackendCommandSender,
+    BackendResult, DistributionOutcome,
+};
 pub use backend_info::BackendInfo;
-pub use distribute_file::{Backend, DistributeFile, DistributionError};
+pub use distribute_early::DistributeEarly;
+pub use distribute_file::{
+    Backend, BackendPriority, DistributeFile, DistributionError, DEFAULT_PRIORITY,
+};
 pub use from_config::TryCreateFromConfig;
+pub use health_check::{BackendHealth, HealthCheckError};
+pub use receive_file::{ReceiveError, ReceiveFile, ReceiveTimeout};
 pub use registration::{BackendRegistration, RegisterBackendError};
+pub use stored_digest::StoredDigest;
@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use file_distribution::{BoxedFileReader, FileReaderTrait, WriteSummary};
+use shared_files::FileSize;
+use shortguid::ShortGuid;
+use std::borrow::Cow;
+use std::error::Error;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Instant;
+
+/// Trait for backends that are able to serve distributed files back.
+#[async_trait]
+pub trait ReceiveFile: Send + Sync {
+    /// Attempts to read a file from the backend.
+    ///
+    /// Returns `Ok(None)` if the backend does not know the file.
+    async fn receive_file(&self, id: ShortGuid) -> Result<Option<BoxedFileReader>, ReceiveError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiveError {
+    #[error(transparent)]
+    BackendSpecific(Box<dyn Error + Send + Sync>),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// The backends did not provide a file in time.
+#[derive(Debug, thiserror::Error)]
+#[error("The backends did not provide the file in time")]
+pub struct ReceiveTimeout;
+
+/// Wraps a reader that has no content type information and provides a fallback.
+pub(crate) struct FallbackContentType {
+    inner: BoxedFileReader,
+    content_type: String,
+}
+
+impl FallbackContentType {
+    pub fn wrap(inner: BoxedFileReader, content_type: String) -> BoxedFileReader {
+        BoxedFileReader::new(Self {
+            inner,
+            content_type,
+        })
+    }
+}
+
+impl FileReaderTrait for FallbackContentType {
+    fn summary(&self) -> &Option<Arc<WriteSummary>> {
+        self.inner.summary()
+    }
+
+    fn expiration_date(&self) -> Instant {
+        self.inner.expiration_date()
+    }
+
+    fn file_size(&self) -> FileSize {
+        self.inner.file_size()
+    }
+
+    fn file_age(&self) -> Duration {
+        self.inner.file_age()
+    }
+
+    fn creation_date(&self) -> SystemTime {
+        self.inner.creation_date()
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
+        Some(Cow::from(self.content_type.as_str()))
+    }
+}
+
+impl AsyncRead for FallbackContentType {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
@@ -12,4 +12,7 @@ pub trait BackendRegistration {
 pub enum RegisterBackendError {
     #[error(transparent)]
     TryCreateFromConfig(Box<dyn Error>),
+    /// Backend tags are used in logs and as metric labels and must therefore be unique.
+    #[error("A backend with the tag \"{0}\" is already registered")]
+    DuplicateTag(String),
 }
@@ -0,0 +1,85 @@
+use file_distribution::FileHashes;
+use std::fmt::{Display, Formatter};
+
+/// A digest of a file as stored by a backend, reported by
+/// [`DistributeFile::distribute_file`](crate::DistributeFile::distribute_file) such that
+/// the distribution can be verified against the hashes computed while the file was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoredDigest {
+    /// The MD5 digest of the stored object, e.g. an S3 `ETag`.
+    Md5([u8; 16]),
+    /// The SHA-256 hash of the stored object.
+    Sha256([u8; 32]),
+}
+
+impl StoredDigest {
+    /// Compares the digest against the hashes of the file.
+    ///
+    /// Returns `None` if the corresponding hash was not computed for the file.
+    pub fn matches(&self, hashes: &FileHashes) -> Option<bool> {
+        match self {
+            StoredDigest::Md5(digest) => hashes.md5.map(|md5| md5.0 == *digest),
+            StoredDigest::Sha256(digest) => hashes.sha256.map(|sha256| sha256[..] == digest[..]),
+        }
+    }
+
+    /// Gets the name of the hash algorithm.
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            StoredDigest::Md5(_) => "md5",
+            StoredDigest::Sha256(_) => "sha256",
+        }
+    }
+
+    /// Gets the raw digest.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            StoredDigest::Md5(digest) => digest,
+            StoredDigest::Sha256(digest) => digest,
+        }
+    }
+}
+
+impl Display for StoredDigest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{algorithm}:{digest}",
+            algorithm = self.algorithm(),
+            digest = hex::encode(self.as_bytes())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_distribution::hash::{HashBlake3, HashMd5, HashSha256};
+
+    fn hashes(data: &[u8]) -> FileHashes {
+        let mut md5 = HashMd5::new();
+        md5.update(data);
+        let mut sha256 = HashSha256::new();
+        sha256.update(data);
+        let mut blake3 = HashBlake3::new();
+        blake3.update(data);
+        FileHashes::new(md5.finalize(), sha256.finalize(), blake3.finalize())
+    }
+
+    #[test]
+    fn digests_are_compared_against_the_file_hashes() {
+        let hashes = hashes(b"yeet");
+        let md5 = StoredDigest::Md5(hashes.md5.unwrap().0);
+        let sha256 = StoredDigest::Sha256(hashes.sha256.unwrap().into());
+        assert_eq!(md5.matches(&hashes), Some(true));
+        assert_eq!(sha256.matches(&hashes), Some(true));
+
+        let corrupted = StoredDigest::Md5([0; 16]);
+        assert_eq!(corrupted.matches(&hashes), Some(false));
+        assert_eq!(corrupted.to_string(), format!("md5:{}", "00".repeat(16)));
+
+        let mut unhashed = hashes.clone();
+        unhashed.sha256 = None;
+        assert_eq!(sha256.matches(&unhashed), None);
+    }
+}
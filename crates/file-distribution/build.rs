@@ -4,6 +4,12 @@ fn main() {
     let mut config = prost_build::Config::new();
     config.protoc_arg("--experimental_allow_proto3_optional");
 
+    // Journal entries are short-lived; boxing the metadata would not pay off.
+    config.enum_attribute(
+        "types.FileIndexEntry.change",
+        "#[allow(clippy::large_enum_variant)]",
+    );
+
     config
         .compile_protos(&["../../proto/metadata.proto"], &proto_includes)
         .expect("Failed to compile protocol buffers");
@@ -0,0 +1,100 @@
+use crate::{FileReaderTrait, WriteSummary};
+use bytes::Bytes;
+use shared_files::FileSize;
+use std::borrow::Cow;
+use std::io::Cursor;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Instant;
+
+/// A file reader over an in-memory buffer, e.g. for files received from a backend.
+pub struct BufferedFileReader {
+    data: Cursor<Bytes>,
+    content_type: Option<String>,
+    created: Instant,
+    creation_date: SystemTime,
+    expires: Instant,
+    summary: Option<Arc<WriteSummary>>,
+}
+
+impl BufferedFileReader {
+    /// Creates a new reader over the specified data.
+    ///
+    /// Since the remaining lifetime of the file is unknown to the backend,
+    /// the file is considered to expire immediately unless specified otherwise.
+    pub fn new<B: Into<Bytes>>(data: B) -> Self {
+        let now = Instant::now();
+        Self {
+            data: Cursor::new(data.into()),
+            content_type: None,
+            created: now,
+            creation_date: SystemTime::now(),
+            expires: now,
+            summary: None,
+        }
+    }
+
+    /// Sets the content type of the file.
+    pub fn with_content_type(mut self, content_type: Option<String>) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    /// Sets the write summary of the file.
+    pub fn with_summary(mut self, summary: Option<Arc<WriteSummary>>) -> Self {
+        self.summary = summary;
+        self
+    }
+
+    /// Gets the size of the buffered file, in bytes.
+    pub fn file_size_bytes(&self) -> usize {
+        self.data.get_ref().len()
+    }
+
+    /// Sets the instant at which the file expires.
+    pub fn with_expiration_date(mut self, expires: Instant) -> Self {
+        self.expires = expires;
+        self
+    }
+}
+
+impl FileReaderTrait for BufferedFileReader {
+    fn summary(&self) -> &Option<Arc<WriteSummary>> {
+        &self.summary
+    }
+
+    fn expiration_date(&self) -> Instant {
+        self.expires
+    }
+
+    fn file_size(&self) -> FileSize {
+        FileSize::Exactly(self.file_size_bytes())
+    }
+
+    fn file_age(&self) -> Duration {
+        Instant::now() - self.created
+    }
+
+    fn creation_date(&self) -> SystemTime {
+        self.creation_date
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
+        self.content_type
+            .as_ref()
+            .map(|content_type| Cow::from(content_type.as_str()))
+    }
+}
+
+impl AsyncRead for BufferedFileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.data).poll_read(cx, buf)
+    }
+}
@@ -1,18 +1,95 @@
-use crate::hash::{Md5Digest, Sha256Digest};
+use crate::hash::{Blake3Digest, Crc32cDigest, Md5Digest, Sha256Digest};
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display, Formatter};
 
 /// The calculated hashes of a file.
+///
+/// The fields of the built-in algorithms are `None` if they were not requested for the file;
+/// [`digests`](Self::digests) lists all computed digests by algorithm name.
 #[derive(Clone)]
 pub struct FileHashes {
     /// The MD5 digest.
-    pub md5: Md5Digest,
+    pub md5: Option<Md5Digest>,
     /// The SHA-256 hash.
-    pub sha256: Sha256Digest,
+    pub sha256: Option<Sha256Digest>,
+    /// The BLAKE3 hash.
+    pub blake3: Option<Blake3Digest>,
+    /// The CRC32C checksum.
+    pub crc32c: Option<Crc32cDigest>,
+    /// The digests of the [registered](crate::hash::register_hasher) algorithms, by name.
+    pub registered: BTreeMap<String, Vec<u8>>,
 }
 
 impl FileHashes {
-    pub fn new(md5: Md5Digest, sha256: Sha256Digest) -> Self {
-        Self { md5, sha256 }
+    pub fn new(md5: Md5Digest, sha256: Sha256Digest, blake3: Blake3Digest) -> Self {
+        Self {
+            md5: Some(md5),
+            sha256: Some(sha256),
+            blake3: Some(blake3),
+            crc32c: None,
+            registered: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the CRC32C checksum.
+    pub fn with_crc32c(mut self, crc32c: Option<Crc32cDigest>) -> Self {
+        self.crc32c = crc32c;
+        self
+    }
+
+    /// Sets the digests of the registered algorithms.
+    pub fn with_registered(mut self, registered: BTreeMap<String, Vec<u8>>) -> Self {
+        self.registered = registered;
+        self
+    }
+
+    /// Gets the hex encoded digests of all computed algorithms, keyed by algorithm name.
+    ///
+    /// The CRC32C checksum is encoded in big-endian order.
+    pub fn digests(&self) -> BTreeMap<String, String> {
+        let mut digests: BTreeMap<String, String> = self
+            .registered
+            .iter()
+            .map(|(name, digest)| (name.clone(), hex::encode(digest)))
+            .collect();
+        if let Some(md5) = &self.md5 {
+            digests.insert("md5".to_string(), hex::encode(md5.as_slice()));
+        }
+        if let Some(sha256) = &self.sha256 {
+            digests.insert("sha256".to_string(), hex::encode(sha256));
+        }
+        if let Some(blake3) = &self.blake3 {
+            digests.insert("blake3".to_string(), blake3.to_hex().to_string());
+        }
+        if let Some(crc32c) = &self.crc32c {
+            digests.insert("crc32c".to_string(), format!("{crc32c:08x}"));
+        }
+        digests
+    }
+
+    /// Reconstructs the hashes from their raw bytes, e.g. as stored by a backend.
+    /// Empty slices indicate digests that were not computed.
+    /// Returns `None` if any of the digests has an invalid length.
+    pub fn try_from_slices(md5: &[u8], sha256: &[u8], blake3: &[u8]) -> Option<Self> {
+        let md5 = match md5 {
+            [] => None,
+            md5 => Some(md5::Digest(md5.try_into().ok()?)),
+        };
+        let sha256 = match sha256 {
+            [] => None,
+            sha256 => Some(Sha256Digest::from(<[u8; 32]>::try_from(sha256).ok()?)),
+        };
+        let blake3 = match blake3 {
+            [] => None,
+            blake3 => Some(Blake3Digest::from(<[u8; 32]>::try_from(blake3).ok()?)),
+        };
+        Some(Self {
+            md5,
+            sha256,
+            blake3,
+            crc32c: None,
+            registered: BTreeMap::new(),
+        })
     }
 }
 
@@ -24,11 +101,27 @@ impl Debug for FileHashes {
 
 impl Display for FileHashes {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "MD5 {md5:x}, SHA256 {sha256:x}",
-            md5 = self.md5,
-            sha256 = self.sha256
-        )
+        let mut hashes = Vec::new();
+        if let Some(md5) = &self.md5 {
+            hashes.push(format!("MD5 {md5:x}"));
+        }
+        if let Some(sha256) = &self.sha256 {
+            hashes.push(format!("SHA256 {sha256:x}"));
+        }
+        if let Some(blake3) = &self.blake3 {
+            hashes.push(format!("BLAKE3 {}", blake3.to_hex()));
+        }
+        if let Some(crc32c) = &self.crc32c {
+            hashes.push(format!("CRC32C {crc32c:08x}"));
+        }
+        for (name, digest) in &self.registered {
+            hashes.push(format!("{name} {}", hex::encode(digest)));
+        }
+
+        if hashes.is_empty() {
+            write!(f, "no hashes")
+        } else {
+            write!(f, "{}", hashes.join(", "))
+        }
     }
 }
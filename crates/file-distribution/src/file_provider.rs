@@ -32,6 +32,8 @@ pub enum GetFileReaderError {
     FileExpired(ShortGuid),
     #[error("Failed to open the file for ID {0}: {1}")]
     FileError(ShortGuid, async_tempfile::Error),
+    #[error("The backends did not provide the file for ID {0} in time")]
+    BackendTimeout(ShortGuid),
 }
 
 impl FileProvider {
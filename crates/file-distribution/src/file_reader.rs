@@ -4,7 +4,7 @@ use std::borrow::Cow;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use tokio::io::{AsyncRead, ReadBuf};
 use tokio::time::Instant;
 
@@ -13,7 +13,9 @@ pub trait FileReaderTrait: AsyncRead + Send + Unpin {
     fn expiration_date(&self) -> Instant;
     fn file_size(&self) -> FileSize;
     fn file_age(&self) -> Duration;
-    fn content_type(&self) -> Option<Cow<str>>;
+    /// Gets the wall-clock time the file was created, which does not change between calls.
+    fn creation_date(&self) -> SystemTime;
+    fn content_type(&self) -> Option<Cow<'_, str>>;
 }
 
 pub struct BoxedFileReader(Box<dyn FileReaderTrait>);
@@ -31,7 +33,10 @@ impl FileReaderTrait for BoxedFileReader {
     fn file_age(&self) -> Duration {
         self.0.file_age()
     }
-    fn content_type(&self) -> Option<Cow<str>> {
+    fn creation_date(&self) -> SystemTime {
+        self.0.creation_date()
+    }
+    fn content_type(&self) -> Option<Cow<'_, str>> {
         self.0.content_type()
     }
 }
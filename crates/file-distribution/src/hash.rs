@@ -1,6 +1,9 @@
+use crate::FileHashes;
 use sha2::digest::consts::U32;
 use sha2::digest::generic_array::GenericArray;
 use sha2::Digest;
+use std::str::FromStr;
+use std::sync::RwLock;
 
 /// An MD5 hash.
 pub struct HashMd5(md5::Context);
@@ -8,12 +11,277 @@ pub struct HashMd5(md5::Context);
 /// A SHA-256 hash.
 pub struct HashSha256(sha2::Sha256);
 
+/// A BLAKE3 hash.
+pub struct HashBlake3(blake3::Hasher);
+
+/// A CRC32C (Castagnoli) checksum.
+pub struct HashCrc32c(u32);
+
 /// Alias for a SHA-256 hash digest.
 pub type Md5Digest = md5::Digest;
 
 /// Alias for a SHA-256 hash digest.
 pub type Sha256Digest = GenericArray<u8, U32>;
 
+/// Alias for a BLAKE3 hash digest.
+pub type Blake3Digest = blake3::Hash;
+
+/// Alias for a CRC32C checksum.
+pub type Crc32cDigest = u32;
+
+/// A streaming hash algorithm.
+///
+/// The built-in algorithms implement this trait; additional ones can be computed for
+/// every file by registering them via [`register_hasher`].
+pub trait Hasher: Send + Sync {
+    /// Feeds the next chunk of the file to the hasher.
+    fn update(&mut self, chunk: &[u8]);
+
+    /// Completes the digest, returning its bytes.
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+/// Creates a hasher of a registered algorithm for each file.
+pub type HasherFactory = fn() -> Box<dyn Hasher>;
+
+/// The names of the built-in algorithms, as selected via [`HashAlgorithms`];
+/// they cannot be registered.
+pub const BUILT_IN_ALGORITHMS: [&str; 4] = ["md5", "sha256", "blake3", "crc32c"];
+
+/// The algorithms registered via [`register_hasher`], computed for every file.
+static REGISTERED_HASHERS: HasherRegistry = HasherRegistry::new();
+
+/// A set of algorithms computed in addition to the built-in ones.
+///
+/// Files are hashed with the algorithms of the global registry, see [`register_hasher`];
+/// a separate registry can be passed to [`FileHasher::with_registry`].
+#[derive(Debug, Default)]
+pub struct HasherRegistry {
+    hashers: RwLock<Vec<(&'static str, HasherFactory)>>,
+}
+
+impl HasherRegistry {
+    /// Creates an empty registry.
+    pub const fn new() -> Self {
+        Self {
+            hashers: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Registers an algorithm whose digest is reported under the specified `name`.
+    ///
+    /// The name must not be empty, nor be that of a built-in or already registered algorithm.
+    pub fn register(
+        &self,
+        name: &'static str,
+        factory: HasherFactory,
+    ) -> Result<(), RegisterHasherError> {
+        let mut hashers = self
+            .hashers
+            .write()
+            .expect("failed to lock the registered hashers");
+        let taken = BUILT_IN_ALGORITHMS
+            .iter()
+            .chain(hashers.iter().map(|(registered, _)| registered))
+            .any(|registered| registered.eq_ignore_ascii_case(name));
+        if name.is_empty() || taken {
+            return Err(RegisterHasherError(name.to_string()));
+        }
+
+        hashers.push((name, factory));
+        Ok(())
+    }
+
+    /// Gets the names of the registered algorithms, in the order of their registration.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.hashers
+            .read()
+            .expect("failed to lock the registered hashers")
+            .iter()
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    /// Creates a hasher of each registered algorithm.
+    fn create_hashers(&self) -> Vec<(&'static str, Box<dyn Hasher>)> {
+        self.hashers
+            .read()
+            .expect("failed to lock the registered hashers")
+            .iter()
+            .map(|(name, factory)| (*name, factory()))
+            .collect()
+    }
+}
+
+/// Registers an algorithm computed for every file in addition to the selected built-in
+/// ones; its digest is reported under the specified `name`.
+///
+/// ## Remarks
+///
+/// Algorithms should be registered at startup, before files are written; files that
+/// are already being written are not hashed with algorithms registered later.
+pub fn register_hasher(
+    name: &'static str,
+    factory: HasherFactory,
+) -> Result<(), RegisterHasherError> {
+    REGISTERED_HASHERS.register(name, factory)
+}
+
+/// Gets the names of the registered algorithms, in the order of their registration.
+pub fn registered_hashers() -> Vec<&'static str> {
+    REGISTERED_HASHERS.names()
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("The hash algorithm name {0:?} is empty or already in use")]
+pub struct RegisterHasherError(pub String);
+
+/// Selects the hash algorithms computed for a file.
+///
+/// The default selection computes MD5, SHA-256 and BLAKE3; the CRC32C checksum is opt-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashAlgorithms {
+    pub md5: bool,
+    pub sha256: bool,
+    pub blake3: bool,
+    pub crc32c: bool,
+}
+
+impl HashAlgorithms {
+    /// Selects all supported algorithms.
+    pub const fn all() -> Self {
+        Self {
+            md5: true,
+            sha256: true,
+            blake3: true,
+            crc32c: true,
+        }
+    }
+
+    /// Selects no algorithm.
+    pub const fn none() -> Self {
+        Self {
+            md5: false,
+            sha256: false,
+            blake3: false,
+            crc32c: false,
+        }
+    }
+
+    /// Additionally selects MD5.
+    pub fn with_md5(mut self) -> Self {
+        self.md5 = true;
+        self
+    }
+
+    /// Additionally selects SHA-256.
+    pub fn with_sha256(mut self) -> Self {
+        self.sha256 = true;
+        self
+    }
+}
+
+impl Default for HashAlgorithms {
+    fn default() -> Self {
+        Self {
+            md5: true,
+            sha256: true,
+            blake3: true,
+            crc32c: false,
+        }
+    }
+}
+
+/// Parses a comma-separated list of algorithm names, e.g. `sha256,blake3`.
+impl FromStr for HashAlgorithms {
+    type Err = UnknownHashAlgorithm;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut algorithms = Self::none();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name.to_ascii_lowercase().as_str() {
+                "md5" => algorithms.md5 = true,
+                "sha256" | "sha-256" => algorithms.sha256 = true,
+                "blake3" => algorithms.blake3 = true,
+                "crc32c" => algorithms.crc32c = true,
+                _ => return Err(UnknownHashAlgorithm(name.to_string())),
+            }
+        }
+        Ok(algorithms)
+    }
+}
+
+/// Computes the selected hashes of a file while its content is streamed through it,
+/// along with those of all [registered](register_hasher) algorithms.
+pub struct FileHasher {
+    md5: Option<HashMd5>,
+    sha256: Option<HashSha256>,
+    blake3: Option<HashBlake3>,
+    crc32c: Option<HashCrc32c>,
+    registered: Vec<(&'static str, Box<dyn Hasher>)>,
+}
+
+impl FileHasher {
+    /// Creates a hasher computing the selected `algorithms` and the registered ones.
+    pub fn new(algorithms: HashAlgorithms) -> Self {
+        Self::with_registry(algorithms, &REGISTERED_HASHERS)
+    }
+
+    /// Creates a hasher computing the selected `algorithms` and those of the `registry`.
+    pub fn with_registry(algorithms: HashAlgorithms, registry: &HasherRegistry) -> Self {
+        let registered = registry.create_hashers();
+        Self {
+            md5: algorithms.md5.then(HashMd5::new),
+            sha256: algorithms.sha256.then(HashSha256::new),
+            blake3: algorithms.blake3.then(HashBlake3::new),
+            crc32c: algorithms.crc32c.then(HashCrc32c::new),
+            registered,
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        if let Some(md5) = &mut self.md5 {
+            md5.update(chunk);
+        }
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(chunk);
+        }
+        if let Some(blake3) = &mut self.blake3 {
+            blake3.update(chunk);
+        }
+        if let Some(crc32c) = &mut self.crc32c {
+            crc32c.update(chunk);
+        }
+        for (_, hasher) in &mut self.registered {
+            hasher.update(chunk);
+        }
+    }
+
+    pub fn finalize(self) -> FileHashes {
+        FileHashes {
+            md5: self.md5.map(HashMd5::finalize),
+            sha256: self.sha256.map(HashSha256::finalize),
+            blake3: self.blake3.map(HashBlake3::finalize),
+            crc32c: self.crc32c.map(HashCrc32c::finalize),
+            registered: self
+                .registered
+                .into_iter()
+                .map(|(name, hasher)| (name.to_string(), hasher.finalize()))
+                .collect(),
+        }
+    }
+}
+
+impl Default for FileHasher {
+    fn default() -> Self {
+        Self::new(HashAlgorithms::default())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown hash algorithm: {0}")]
+pub struct UnknownHashAlgorithm(pub String);
+
 impl HashMd5 {
     pub fn new() -> Self {
         Self(md5::Context::new())
@@ -44,6 +312,34 @@ impl HashSha256 {
     }
 }
 
+impl HashBlake3 {
+    pub fn new() -> Self {
+        Self(blake3::Hasher::new())
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0.update(chunk);
+    }
+
+    pub fn finalize(self) -> Blake3Digest {
+        self.0.finalize()
+    }
+}
+
+impl HashCrc32c {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, chunk);
+    }
+
+    pub fn finalize(self) -> Crc32cDigest {
+        self.0
+    }
+}
+
 impl Default for HashMd5 {
     fn default() -> Self {
         Self::new()
@@ -55,3 +351,103 @@ impl Default for HashSha256 {
         Self::new()
     }
 }
+
+impl Default for HashBlake3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for HashCrc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for HashMd5 {
+    fn update(&mut self, chunk: &[u8]) {
+        HashMd5::update(self, chunk)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Vec::from(HashMd5::finalize(*self).as_slice())
+    }
+}
+
+impl Hasher for HashSha256 {
+    fn update(&mut self, chunk: &[u8]) {
+        HashSha256::update(self, chunk)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Vec::from(HashSha256::finalize(*self).as_slice())
+    }
+}
+
+impl Hasher for HashBlake3 {
+    fn update(&mut self, chunk: &[u8]) {
+        HashBlake3::update(self, chunk)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Vec::from(HashBlake3::finalize(*self).as_bytes().as_slice())
+    }
+}
+
+impl Hasher for HashCrc32c {
+    fn update(&mut self, chunk: &[u8]) {
+        HashCrc32c::update(self, chunk)
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        Vec::from(HashCrc32c::finalize(*self).to_be_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A checksum XOR-ing all bytes, standing in for a custom algorithm.
+    #[derive(Default)]
+    struct HashXor(u8);
+
+    impl Hasher for HashXor {
+        fn update(&mut self, chunk: &[u8]) {
+            self.0 = chunk.iter().fold(self.0, |xor, byte| xor ^ byte);
+        }
+
+        fn finalize(self: Box<Self>) -> Vec<u8> {
+            vec![self.0]
+        }
+    }
+
+    #[test]
+    fn registered_hashers_are_computed() {
+        let registry = HasherRegistry::new();
+        registry
+            .register("xor", || Box::<HashXor>::default())
+            .expect("failed to register");
+        assert!(registry
+            .register("XOR", || Box::<HashXor>::default())
+            .is_err());
+        assert!(registry
+            .register("sha256", || Box::<HashXor>::default())
+            .is_err());
+        assert_eq!(registry.names(), ["xor"]);
+
+        // Other hashers are not affected by the registry.
+        assert!(FileHasher::new(HashAlgorithms::none())
+            .finalize()
+            .registered
+            .is_empty());
+
+        let mut hasher = FileHasher::with_registry(HashAlgorithms::none(), &registry);
+        hasher.update(&[0b1010, 0b0110]);
+        hasher.update(&[0b0001]);
+        let hashes = hasher.finalize();
+        assert!(hashes.sha256.is_none());
+        assert_eq!(hashes.registered.get("xor"), Some(&vec![0b1101]));
+        assert_eq!(hashes.digests().get("xor").map(String::as_str), Some("0d"));
+    }
+}
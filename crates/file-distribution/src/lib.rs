@@ -2,13 +2,15 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+mod buffered_file_reader;
 mod file_hashes;
 mod file_provider;
 mod file_reader;
 pub mod hash;
-pub mod protobuf;
+pub mod metadata;
 mod write_summary;
 
+pub use buffered_file_reader::BufferedFileReader;
 pub use file_hashes::FileHashes;
 pub use file_provider::{FileAccessorError, FileProvider, GetFile, GetFileReaderError};
 pub use file_reader::{BoxedFileReader, FileReaderTrait};
@@ -0,0 +1,195 @@
+use crate::{FileHashes, WriteSummary};
+use bytes::{Bytes, BytesMut};
+use prost::Message;
+use shortguid::ShortGuid;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+
+include!(concat!(env!("OUT_DIR"), "/types.rs"));
+
+impl ItemMetadata {
+    pub fn new(id: ShortGuid, summary: &Arc<WriteSummary>) -> Self {
+        Self {
+            id: Vec::from(id.as_bytes()),
+            file_name: summary.file_name.clone(),
+            hashes: Some((&summary.hashes).into()),
+            content_type: None,
+            file_size_bytes: Some(summary.file_size_bytes as u64),
+            created_unix_ms: None,
+            expires_unix_ms: None,
+            metadata: summary
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }
+    }
+
+    /// Sets the content type of the file.
+    pub fn with_content_type(mut self, content_type: Option<String>) -> Self {
+        self.content_type = content_type;
+        self
+    }
+
+    /// Sets the time the file was created.
+    pub fn with_created(mut self, created: SystemTime) -> Self {
+        self.created_unix_ms = Some(unix_millis(created));
+        self
+    }
+
+    /// Sets the time the file expires.
+    pub fn with_expires(mut self, expires: SystemTime) -> Self {
+        self.expires_unix_ms = Some(unix_millis(expires));
+        self
+    }
+
+    pub fn serialize_to_proto(&self) -> Result<Bytes, prost::EncodeError> {
+        let mut metadata_buf = BytesMut::new();
+        self.encode(&mut metadata_buf)?;
+        Ok(metadata_buf.freeze())
+    }
+
+    pub fn deserialize_from_proto(buf: &[u8]) -> Result<Self, prost::DecodeError> {
+        Self::decode(buf)
+    }
+
+    /// Reconstructs a write summary from the metadata, e.g. for files read back from a backend.
+    /// Returns `None` if the stored hashes are missing or invalid.
+    pub fn to_summary(
+        &self,
+        file_size_bytes: usize,
+        expires: Instant,
+    ) -> Option<Arc<WriteSummary>> {
+        let hashes = self.hashes.as_ref()?;
+        Some(Arc::new(WriteSummary {
+            expires,
+            hashes: FileHashes::try_from_slices(&hashes.md5, &hashes.sha256, &hashes.blake3)?
+                .with_crc32c(hashes.crc32c)
+                .with_registered(
+                    hashes
+                        .registered
+                        .iter()
+                        .map(|(name, digest)| (name.clone(), digest.clone()))
+                        .collect(),
+                ),
+            file_name: self.file_name.clone(),
+            file_size_bytes,
+            metadata: self
+                .metadata
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        }))
+    }
+}
+
+impl From<&FileHashes> for Hashes {
+    fn from(hashes: &FileHashes) -> Self {
+        Self {
+            md5: hashes
+                .md5
+                .map_or_else(Vec::new, |md5| Vec::from(md5.as_slice())),
+            sha256: hashes
+                .sha256
+                .map_or_else(Vec::new, |sha256| Vec::from(sha256.as_slice())),
+            blake3: hashes
+                .blake3
+                .map_or_else(Vec::new, |blake3| Vec::from(blake3.as_bytes().as_slice())),
+            crc32c: hashes.crc32c,
+            registered: hashes
+                .registered
+                .iter()
+                .map(|(name, digest)| (name.clone(), digest.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl FileIndexEntry {
+    /// Creates an entry recording that the file was created at `path`.
+    pub fn created(id: ShortGuid, path: String) -> Self {
+        Self::with_change(id, file_index_entry::Change::Created(path))
+    }
+
+    /// Creates an entry recording that writing the file completed, or that its lease was extended.
+    pub fn stored(id: ShortGuid, metadata: ItemMetadata, lease: Duration) -> Self {
+        Self::with_change(
+            id,
+            file_index_entry::Change::Stored(IndexedFile {
+                metadata: Some(metadata),
+                lease_ms: lease.as_millis() as u64,
+            }),
+        )
+    }
+
+    /// Creates an entry recording that the file was removed.
+    pub fn removed(id: ShortGuid) -> Self {
+        Self::with_change(id, file_index_entry::Change::Removed(true))
+    }
+
+    fn with_change(id: ShortGuid, change: file_index_entry::Change) -> Self {
+        Self {
+            id: Vec::from(id.as_bytes()),
+            change: Some(change),
+        }
+    }
+
+    /// Serializes the entry prefixed with its length, such that entries can be appended to a journal.
+    pub fn serialize_length_delimited(&self) -> Vec<u8> {
+        self.encode_length_delimited_to_vec()
+    }
+
+    /// Deserializes the next length-prefixed entry from `buf`, advancing it past the entry.
+    pub fn deserialize_length_delimited(buf: &mut &[u8]) -> Result<Self, prost::DecodeError> {
+        Self::decode_length_delimited(buf)
+    }
+}
+
+/// Gets the number of milliseconds since the Unix epoch, or zero for earlier times.
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{HashBlake3, HashCrc32c, HashMd5, HashSha256};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn metadata_roundtrip_works() {
+        let summary = Arc::new(WriteSummary {
+            expires: Instant::now(),
+            hashes: FileHashes::new(
+                HashMd5::new().finalize(),
+                HashSha256::new().finalize(),
+                HashBlake3::new().finalize(),
+            )
+            .with_crc32c(Some(HashCrc32c::new().finalize())),
+            file_name: Some("yeet.txt".to_string()),
+            file_size_bytes: 42,
+            metadata: BTreeMap::from([("tenant".to_string(), "acme".to_string())]),
+        });
+
+        let created = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+        let metadata = ItemMetadata::new(ShortGuid::new_random(), &summary)
+            .with_content_type(Some("text/plain".to_string()))
+            .with_created(created)
+            .with_expires(created + Duration::from_secs(60));
+
+        let bytes = metadata.serialize_to_proto().expect("failed to serialize");
+        let decoded = ItemMetadata::deserialize_from_proto(&bytes).expect("failed to deserialize");
+        assert_eq!(decoded, metadata);
+        assert_eq!(decoded.file_size_bytes, Some(42));
+        assert_eq!(decoded.created_unix_ms, Some(1_700_000_000_000));
+        assert_eq!(decoded.expires_unix_ms, Some(1_700_000_060_000));
+
+        let restored = decoded
+            .to_summary(42, Instant::now())
+            .expect("failed to restore the summary");
+        assert_eq!(restored.hashes.crc32c, Some(0));
+        assert_eq!(restored.metadata, summary.metadata);
+    }
+}
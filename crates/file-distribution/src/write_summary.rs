@@ -1,8 +1,9 @@
 use crate::FileHashes;
+use std::collections::BTreeMap;
 use tokio::time::Instant;
 
 /// A write result.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct WriteSummary {
     /// The instant at which the file will expire.
     pub expires: Instant,
@@ -12,4 +13,6 @@ pub struct WriteSummary {
     pub file_name: Option<String>,
     /// The file size in bytes.
     pub file_size_bytes: usize,
+    /// The key/value metadata attached to the file by the client.
+    pub metadata: BTreeMap<String, String>,
 }
@@ -0,0 +1,249 @@
+//! Contains local file buffer metrics, notably [`BackboneMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, LabelValueEncoder};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::{Registry, Unit};
+use std::fmt::{Display, Formatter, Write};
+use std::time::Duration;
+
+lazy_static! {
+    static ref BUFFERED_FILES: Gauge = Gauge::default();
+    static ref BUFFERED_SIZE: Gauge = Gauge::default();
+    static ref FILES_REMOVED: Family<RemovalLabels, Counter> = Family::default();
+    static ref FILES_EXPIRED_UNREAD: Counter = Counter::default();
+    static ref CORRUPTED_DOWNLOADS: Counter = Counter::default();
+    static ref SYNC_DURATION: Family<SyncLabels, Histogram, fn() -> Histogram> =
+        Family::new_with_constructor(sync_duration_histogram);
+}
+
+/// The smallest bucket of the sync duration histogram, in seconds.
+const SYNC_DURATION_BUCKET_START: f64 = 0.001;
+
+/// The growth factor between consecutive sync duration buckets.
+const SYNC_DURATION_BUCKET_FACTOR: f64 = 4.0;
+
+/// The number of sync duration buckets, ranging from 1 ms to about 16 s.
+const SYNC_DURATION_BUCKET_COUNT: u16 = 8;
+
+/// Creates a histogram for sync durations.
+fn sync_duration_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(
+        SYNC_DURATION_BUCKET_START,
+        SYNC_DURATION_BUCKET_FACTOR,
+        SYNC_DURATION_BUCKET_COUNT,
+    ))
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RemovalLabels {
+    /// The reason the file was removed.
+    reason: RemovalReason,
+}
+
+/// The reason a file was removed from the local buffer.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// The temporal lease of the file expired.
+    Expired,
+    /// The file was deleted on request.
+    Deleted,
+    /// Writing the file failed.
+    Failed,
+    /// The file duplicated another buffered file.
+    Duplicate,
+}
+
+impl EncodeLabelValue for RemovalReason {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for RemovalReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemovalReason::Expired => write!(f, "expired"),
+            RemovalReason::Deleted => write!(f, "deleted"),
+            RemovalReason::Failed => write!(f, "failed"),
+            RemovalReason::Duplicate => write!(f, "duplicate"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct SyncLabels {
+    /// The operation that was timed.
+    operation: SyncOperation,
+}
+
+/// An operation persisting written data to disk.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum SyncOperation {
+    /// A chunk of an upload was synced to disk.
+    Sync,
+    /// An upload was completed, including its final sync if any.
+    Finalize,
+}
+
+impl EncodeLabelValue for SyncOperation {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for SyncOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncOperation::Sync => write!(f, "sync"),
+            SyncOperation::Finalize => write!(f, "finalize"),
+        }
+    }
+}
+
+/// Register the local file buffer metrics with the registry.
+pub(crate) fn register_backbone_metrics(registry: &mut Registry) {
+    registry.register(
+        "buffered_files",
+        "Number of files buffered locally, including files still being written",
+        BUFFERED_FILES.clone(),
+    );
+
+    registry.register_with_unit(
+        "buffered_size",
+        "Number of bytes of completely written files buffered locally",
+        Unit::Bytes,
+        BUFFERED_SIZE.clone(),
+    );
+
+    registry.register(
+        "buffered_files_removed",
+        "Number of files removed from the local buffer, by reason",
+        FILES_REMOVED.clone(),
+    );
+
+    registry.register(
+        "buffered_files_expired_unread",
+        "Number of files whose lease expired before any of their data was read",
+        FILES_EXPIRED_UNREAD.clone(),
+    );
+
+    registry.register_with_unit(
+        "buffered_file_sync_duration",
+        "Duration of syncing and finalizing buffered files, by operation",
+        Unit::Seconds,
+        SYNC_DURATION.clone(),
+    );
+
+    registry.register(
+        "buffered_file_corrupted_downloads",
+        "Number of downloads whose content did not match the hash of the buffered file",
+        CORRUPTED_DOWNLOADS.clone(),
+    );
+}
+
+/// Local file buffer metrics.
+#[derive(Default)]
+pub struct BackboneMetrics;
+
+impl BackboneMetrics {
+    /// Tracks a file registered with the local buffer.
+    pub fn track_file_added() {
+        BUFFERED_FILES.inc();
+    }
+
+    /// Tracks a completely written file of the specified size.
+    pub fn track_file_completed(bytes: u64) {
+        BUFFERED_SIZE.inc_by(bytes as _);
+    }
+
+    /// Tracks a file removed from the local buffer, releasing `bytes` of completely
+    /// written data; `0` if the file was not completely written.
+    pub fn track_file_removed(bytes: u64) {
+        BUFFERED_FILES.dec();
+        BUFFERED_SIZE.dec_by(bytes as _);
+    }
+
+    /// Tracks the reason a file was removed from the local buffer.
+    ///
+    /// Expired files that were never read are counted separately as well.
+    pub fn track_removal(reason: RemovalReason, read: bool) {
+        FILES_REMOVED.get_or_create(&RemovalLabels { reason }).inc();
+        if reason == RemovalReason::Expired && !read {
+            FILES_EXPIRED_UNREAD.inc();
+        }
+    }
+
+    /// Tracks the duration of syncing or finalizing a buffered file.
+    pub fn track_sync(operation: SyncOperation, duration: Duration) {
+        SYNC_DURATION
+            .get_or_create(&SyncLabels { operation })
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Tracks a download whose content did not match the hash of the file.
+    pub fn track_corrupted_download() {
+        CORRUPTED_DOWNLOADS.inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metrics;
+
+    #[test]
+    fn gauges_follow_buffered_files() {
+        BackboneMetrics::track_file_added();
+        BackboneMetrics::track_file_added();
+        BackboneMetrics::track_file_completed(1024);
+        assert_eq!(BUFFERED_FILES.get(), 2);
+        assert_eq!(BUFFERED_SIZE.get(), 1024);
+
+        let encoded = Metrics::get().encode();
+        assert!(encoded.contains("buffered_files 2"));
+        assert!(encoded.contains("buffered_size_bytes 1024"));
+
+        BackboneMetrics::track_file_removed(1024);
+        BackboneMetrics::track_file_removed(0);
+        assert_eq!(BUFFERED_FILES.get(), 0);
+        assert_eq!(BUFFERED_SIZE.get(), 0);
+    }
+
+    #[test]
+    fn removals_are_counted_by_reason() {
+        BackboneMetrics::track_removal(RemovalReason::Expired, false);
+        BackboneMetrics::track_removal(RemovalReason::Expired, true);
+        BackboneMetrics::track_removal(RemovalReason::Deleted, false);
+
+        let encoded = Metrics::get().encode();
+        assert!(encoded.contains("buffered_files_removed_total{reason=\"expired\"} 2"));
+        assert!(encoded.contains("buffered_files_removed_total{reason=\"deleted\"} 1"));
+        assert!(encoded.contains("buffered_files_expired_unread_total 1"));
+    }
+
+    #[test]
+    fn sync_durations_are_tracked_by_operation() {
+        BackboneMetrics::track_sync(SyncOperation::Sync, Duration::from_millis(2));
+        BackboneMetrics::track_sync(SyncOperation::Finalize, Duration::from_secs(20));
+
+        let encoded = Metrics::get().encode();
+        assert!(encoded.contains(
+            "buffered_file_sync_duration_seconds_bucket{le=\"0.004\",operation=\"sync\"} 1"
+        ));
+        assert!(encoded.contains(
+            "buffered_file_sync_duration_seconds_bucket{le=\"+Inf\",operation=\"finalize\"} 1"
+        ));
+    }
+
+    #[test]
+    fn corrupted_downloads_are_counted() {
+        BackboneMetrics::track_corrupted_download();
+
+        let encoded = Metrics::get().encode();
+        assert!(encoded.contains("buffered_file_corrupted_downloads_total 1"));
+    }
+}
@@ -0,0 +1,299 @@
+//! Contains backend distribution metrics, notably [`BackendMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::encoding::LabelValueEncoder;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::{Registry, Unit};
+use std::fmt::{Display, Formatter, Write};
+use std::time::Duration;
+
+lazy_static! {
+    static ref DISTRIBUTION_COUNT: Family<Labels, Counter> = Family::default();
+    static ref DISTRIBUTION_DURATION: Family<Labels, Counter<f64>> = Family::default();
+    static ref DISTRIBUTION_RETRIES: Family<BackendLabels, Counter> = Family::default();
+    static ref DISTRIBUTION_ABANDONED: Family<BackendLabels, Counter> = Family::default();
+    static ref DISTRIBUTION_SHORT_CIRCUITED: Family<BackendLabels, Counter> = Family::default();
+    static ref CIRCUIT_STATE: Family<BackendLabels, Gauge> = Family::default();
+    static ref CIRCUIT_TRANSITIONS: Family<CircuitLabels, Counter> = Family::default();
+    static ref VERIFICATIONS: Family<VerificationLabels, Counter> = Family::default();
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct BackendLabels {
+    /// The tag of the backend.
+    backend: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct Labels {
+    /// The tag of the backend.
+    backend: String,
+    /// The outcome of the distribution.
+    outcome: DistributionOutcome,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CircuitLabels {
+    /// The tag of the backend.
+    backend: String,
+    /// The state the circuit breaker transitioned to.
+    state: CircuitState,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct VerificationLabels {
+    /// The tag of the backend.
+    backend: String,
+    /// The result of comparing the stored object against the file.
+    result: VerificationResult,
+}
+
+/// The outcome of distributing a file to a backend.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum DistributionOutcome {
+    Success,
+    Failure,
+}
+
+impl EncodeLabelValue for DistributionOutcome {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for DistributionOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DistributionOutcome::Success => write!(f, "success"),
+            DistributionOutcome::Failure => write!(f, "failure"),
+        }
+    }
+}
+
+impl<T, E> From<&Result<T, E>> for DistributionOutcome {
+    fn from(value: &Result<T, E>) -> Self {
+        match value {
+            Ok(_) => DistributionOutcome::Success,
+            Err(_) => DistributionOutcome::Failure,
+        }
+    }
+}
+
+/// The result of verifying a file stored by a backend against its hashes.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum VerificationResult {
+    /// The digest of the stored object matches the file.
+    Verified,
+    /// The digest of the stored object differs from the file.
+    Mismatch,
+    /// The backend did not report a digest, or the file lacks the matching hash.
+    Unverified,
+}
+
+impl EncodeLabelValue for VerificationResult {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for VerificationResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerificationResult::Verified => write!(f, "verified"),
+            VerificationResult::Mismatch => write!(f, "mismatch"),
+            VerificationResult::Unverified => write!(f, "unverified"),
+        }
+    }
+}
+
+/// The state of the circuit breaker of a backend.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Distributions are attempted.
+    Closed,
+    /// Distributions are skipped until the cooldown has passed.
+    Open,
+    /// A single distribution is attempted to test whether the backend recovered.
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// Gets the value of the state gauge; `0` when closed, `1` when open and `2` when half-open.
+    fn gauge_value(&self) -> i64 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::Open => 1,
+            CircuitState::HalfOpen => 2,
+        }
+    }
+}
+
+impl EncodeLabelValue for CircuitState {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for CircuitState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CircuitState::Closed => write!(f, "closed"),
+            CircuitState::Open => write!(f, "open"),
+            CircuitState::HalfOpen => write!(f, "half_open"),
+        }
+    }
+}
+
+/// Register the backend distribution metric families with the registry.
+pub(crate) fn register_backend_metrics(registry: &mut Registry) {
+    registry.register(
+        "backend_distributions",
+        "Number of file distributions to backends",
+        DISTRIBUTION_COUNT.clone(),
+    );
+
+    registry.register_with_unit(
+        "backend_distribution_duration",
+        "Duration of file distributions to backends",
+        Unit::Seconds,
+        DISTRIBUTION_DURATION.clone(),
+    );
+
+    registry.register(
+        "backend_distribution_retries",
+        "Number of retried file distributions to backends",
+        DISTRIBUTION_RETRIES.clone(),
+    );
+
+    registry.register(
+        "backend_distributions_abandoned",
+        "Number of file distributions to backends given up after exhausting all attempts",
+        DISTRIBUTION_ABANDONED.clone(),
+    );
+
+    registry.register(
+        "backend_distributions_short_circuited",
+        "Number of file distributions to backends skipped while their circuit breaker was open",
+        DISTRIBUTION_SHORT_CIRCUITED.clone(),
+    );
+
+    registry.register(
+        "backend_circuit_state",
+        "State of the circuit breaker of backends (0 = closed, 1 = open, 2 = half-open)",
+        CIRCUIT_STATE.clone(),
+    );
+
+    registry.register(
+        "backend_circuit_transitions",
+        "Number of transitions of the circuit breaker of backends by target state",
+        CIRCUIT_TRANSITIONS.clone(),
+    );
+
+    registry.register(
+        "backend_distribution_verifications",
+        "Number of files stored by backends, by the result of verifying them against their hashes",
+        VERIFICATIONS.clone(),
+    );
+}
+
+/// Backend distribution metrics.
+#[derive(Default)]
+pub struct BackendMetrics;
+
+impl BackendMetrics {
+    /// Tracks one distribution of a file to the backend with the specified tag.
+    pub fn track_distribution<T, O>(backend: T, outcome: O, elapsed: Duration)
+    where
+        T: AsRef<str>,
+        O: Into<DistributionOutcome>,
+    {
+        let labels = Labels {
+            backend: backend.as_ref().to_string(),
+            outcome: outcome.into(),
+        };
+
+        DISTRIBUTION_COUNT.get_or_create(&labels).inc();
+        DISTRIBUTION_DURATION
+            .get_or_create(&labels)
+            .inc_by(elapsed.as_secs_f64());
+    }
+
+    /// Tracks a retry of a failed distribution to the backend with the specified tag.
+    pub fn track_retry<T>(backend: T)
+    where
+        T: AsRef<str>,
+    {
+        let labels = BackendLabels {
+            backend: backend.as_ref().to_string(),
+        };
+        DISTRIBUTION_RETRIES.get_or_create(&labels).inc();
+    }
+
+    /// Tracks a distribution to the backend with the specified tag that was
+    /// given up after all attempts failed.
+    pub fn track_abandoned<T>(backend: T)
+    where
+        T: AsRef<str>,
+    {
+        let labels = BackendLabels {
+            backend: backend.as_ref().to_string(),
+        };
+        DISTRIBUTION_ABANDONED.get_or_create(&labels).inc();
+    }
+
+    /// Tracks a distribution to the backend with the specified tag that was
+    /// skipped because its circuit breaker was open.
+    pub fn track_short_circuit<T>(backend: T)
+    where
+        T: AsRef<str>,
+    {
+        let labels = BackendLabels {
+            backend: backend.as_ref().to_string(),
+        };
+        DISTRIBUTION_SHORT_CIRCUITED.get_or_create(&labels).inc();
+    }
+
+    /// Tracks the verification of a file stored by the backend with the specified tag.
+    pub fn track_verification<T>(backend: T, result: VerificationResult)
+    where
+        T: AsRef<str>,
+    {
+        let labels = VerificationLabels {
+            backend: backend.as_ref().to_string(),
+            result,
+        };
+        VERIFICATIONS.get_or_create(&labels).inc();
+    }
+
+    /// Tracks the circuit breaker of the backend with the specified tag, which starts closed.
+    pub fn track_circuit<T>(backend: T)
+    where
+        T: AsRef<str>,
+    {
+        let labels = BackendLabels {
+            backend: backend.as_ref().to_string(),
+        };
+        CIRCUIT_STATE
+            .get_or_create(&labels)
+            .set(CircuitState::Closed.gauge_value());
+    }
+
+    /// Tracks a transition of the circuit breaker of the backend with the specified tag.
+    pub fn track_circuit_transition<T>(backend: T, state: CircuitState)
+    where
+        T: AsRef<str>,
+    {
+        let backend = backend.as_ref().to_string();
+        CIRCUIT_STATE
+            .get_or_create(&BackendLabels {
+                backend: backend.clone(),
+            })
+            .set(state.gauge_value());
+        CIRCUIT_TRANSITIONS
+            .get_or_create(&CircuitLabels { backend, state })
+            .inc();
+    }
+}
@@ -7,8 +7,10 @@ use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::{Registry, Unit};
 use std::fmt::{Display, Formatter, Write};
+use std::sync::RwLock;
 use std::time::Duration;
 
 lazy_static! {
@@ -17,6 +19,32 @@ lazy_static! {
     static ref TRACK_ENDPOINT: Family<Labels, Counter> = Family::default();
     static ref TRACK_DURATION: Family<Labels, Counter<f64>> = Family::default();
     static ref TRACK_IN_FLIGHT: Family<InFlightLabels, Gauge> = Family::default();
+    static ref TRACK_REQUEST_SIZE: Family<SizeLabels, Histogram, fn() -> Histogram> =
+        Family::new_with_constructor(body_size_histogram);
+    static ref TRACK_RESPONSE_SIZE: Family<SizeLabels, Histogram, fn() -> Histogram> =
+        Family::new_with_constructor(body_size_histogram);
+    static ref NORMALIZATION: RwLock<LabelNormalization> = RwLock::default();
+}
+
+/// The path label of requests not matching any route template.
+const UNMATCHED_PATH: &str = "unmatched";
+
+/// The smallest bucket of the body size histograms, in bytes.
+const BODY_SIZE_BUCKET_START: f64 = 1024.0;
+
+/// The growth factor between consecutive body size buckets.
+const BODY_SIZE_BUCKET_FACTOR: f64 = 4.0;
+
+/// The number of body size buckets, ranging from 1 KiB to 1 GiB.
+const BODY_SIZE_BUCKET_COUNT: u16 = 11;
+
+/// Creates a histogram for body sizes.
+fn body_size_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(
+        BODY_SIZE_BUCKET_START,
+        BODY_SIZE_BUCKET_FACTOR,
+        BODY_SIZE_BUCKET_COUNT,
+    ))
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -25,15 +53,61 @@ struct Labels {
     method: HttpMethod,
     // Or just a plain string.
     path: String,
-    /// The HTTP status code.
-    status: u16,
+    /// The HTTP status code or status class.
+    status: String,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 struct InFlightLabels {
+    method: HttpMethod,
     path: String,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct SizeLabels {
+    method: HttpMethod,
+    path: String,
+}
+
+/// Controls how the labels of HTTP metrics are derived, bounding their cardinality.
+#[derive(Clone, Debug, Default)]
+pub struct LabelNormalization {
+    /// Whether to report status classes such as `2xx` rather than exact status codes.
+    pub status_classes: bool,
+    /// Whether paths are reported as the template of the route they matched, e.g. `/yoink/:id`,
+    /// or as `unmatched`; otherwise, only the first path segment is used.
+    pub route_templates: bool,
+}
+
+impl LabelNormalization {
+    /// Gets the path label of a request path matching the route `template`, if any.
+    fn path(&self, path: &str, template: Option<&str>) -> String {
+        if !self.route_templates {
+            return route_base(path).to_string();
+        }
+
+        template.unwrap_or(UNMATCHED_PATH).to_string()
+    }
+
+    /// Gets the status label of a status code.
+    fn status(&self, status: u16) -> String {
+        if self.status_classes && status >= 100 {
+            format!("{class}xx", class = status / 100)
+        } else {
+            status.to_string()
+        }
+    }
+}
+
+/// Gets the base path of a route, such that e.g. `/yoink/4d6DOAMKQ5uhlE6eXKM_dQ`
+/// is treated as `/yoink`.
+pub fn route_base(path: &str) -> &str {
+    match path.get(1..).and_then(|rest| rest.find('/')) {
+        None => path,
+        Some(pos) => &path[0..(pos + 1)],
+    }
+}
+
 /// The HTTP method to track.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum HttpMethod {
@@ -119,6 +193,20 @@ pub(crate) fn register_http_requests(registry: &mut Registry) {
         "Number of requests that are currently in flight",
         TRACK_IN_FLIGHT.clone(),
     );
+
+    registry.register_with_unit(
+        "http_request_size",
+        "Size of HTTP request bodies received",
+        Unit::Bytes,
+        TRACK_REQUEST_SIZE.clone(),
+    );
+
+    registry.register_with_unit(
+        "http_response_size",
+        "Size of HTTP response bodies sent",
+        Unit::Bytes,
+        TRACK_RESPONSE_SIZE.clone(),
+    );
 }
 
 /// HTTP call metrics. Can be cheaply cloned.
@@ -127,43 +215,153 @@ pub(crate) fn register_http_requests(registry: &mut Registry) {
 pub struct HttpMetrics;
 
 impl HttpMetrics {
-    /// Tracks one call to the specified HTTP path and method.
-    pub fn track<P, M>(path: P, method: M, status: u16, elapsed: Duration)
+    /// Sets how the labels of HTTP metrics are derived.
+    pub fn set_label_normalization(normalization: LabelNormalization) {
+        *NORMALIZATION
+            .write()
+            .expect("failed to lock the label normalization") = normalization;
+    }
+
+    /// Tracks one call to the specified HTTP path and method, served by the route
+    /// matching `template`, if any.
+    pub fn track<P, M>(path: P, template: Option<&str>, method: M, status: u16, elapsed: Duration)
     where
         P: AsRef<str>,
         M: Into<HttpMethod>,
     {
-        let method = method.into();
-        TRACK_ENDPOINT
-            .get_or_create(&Labels {
-                method: method.clone(),
-                path: path.as_ref().to_string(),
-                status,
-            })
-            .inc();
+        let labels = {
+            let normalization = Self::normalization();
+            Labels {
+                method: method.into(),
+                path: normalization.path(path.as_ref(), template),
+                status: normalization.status(status),
+            }
+        };
 
+        TRACK_ENDPOINT.get_or_create(&labels).inc();
         TRACK_DURATION
-            .get_or_create(&Labels {
-                method,
-                path: path.as_ref().to_string(),
-                status,
-            })
+            .get_or_create(&labels)
             .inc_by(elapsed.as_secs_f64());
     }
 
-    pub fn inc_in_flight<P: AsRef<str>>(path: P) {
+    /// Tracks the start of a request to the specified HTTP path and method, served by
+    /// the route matching `template`, if any.
+    pub fn inc_in_flight<P, M>(path: P, template: Option<&str>, method: M)
+    where
+        P: AsRef<str>,
+        M: Into<HttpMethod>,
+    {
         TRACK_IN_FLIGHT
             .get_or_create(&InFlightLabels {
-                path: path.as_ref().to_string(),
+                method: method.into(),
+                path: Self::normalization().path(path.as_ref(), template),
             })
             .inc();
     }
 
-    pub fn dec_in_flight<P: AsRef<str>>(path: P) {
+    /// Tracks the end of a request to the specified HTTP path and method, served by
+    /// the route matching `template`, if any.
+    pub fn dec_in_flight<P, M>(path: P, template: Option<&str>, method: M)
+    where
+        P: AsRef<str>,
+        M: Into<HttpMethod>,
+    {
         TRACK_IN_FLIGHT
             .get_or_create(&InFlightLabels {
-                path: path.as_ref().to_string(),
+                method: method.into(),
+                path: Self::normalization().path(path.as_ref(), template),
             })
             .dec();
     }
+
+    /// Tracks the size of a request body received on the route matching `template`, e.g. `/yoink/:id`,
+    /// using the specified HTTP method.
+    pub fn track_request_size<M>(template: &str, method: M, bytes: usize)
+    where
+        M: Into<HttpMethod>,
+    {
+        TRACK_REQUEST_SIZE
+            .get_or_create(&SizeLabels {
+                method: method.into(),
+                path: Self::normalization().path(template, Some(template)),
+            })
+            .observe(bytes as f64);
+    }
+
+    /// Tracks the size of a response body sent on the route matching `template`, e.g. `/yoink/:id`,
+    /// using the specified HTTP method.
+    pub fn track_response_size<M>(template: &str, method: M, bytes: usize)
+    where
+        M: Into<HttpMethod>,
+    {
+        TRACK_RESPONSE_SIZE
+            .get_or_create(&SizeLabels {
+                method: method.into(),
+                path: Self::normalization().path(template, Some(template)),
+            })
+            .observe(bytes as f64);
+    }
+
+    fn normalization() -> std::sync::RwLockReadGuard<'static, LabelNormalization> {
+        NORMALIZATION
+            .read()
+            .expect("failed to lock the label normalization")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_templates() -> LabelNormalization {
+        LabelNormalization {
+            status_classes: true,
+            route_templates: true,
+        }
+    }
+
+    #[test]
+    fn paths_use_first_segment_by_default() {
+        let normalization = LabelNormalization::default();
+        assert_eq!(
+            normalization.path("/yoink/4d6DOAMKQ5uhlE6eXKM_dQ", Some("/yoink/:id")),
+            "/yoink"
+        );
+        assert_eq!(normalization.path("/yeet", Some("/yeet")), "/yeet");
+        assert_eq!(normalization.path("/", None), "/");
+    }
+
+    #[test]
+    fn paths_are_reported_as_matched_templates() {
+        let normalization = with_templates();
+        assert_eq!(
+            normalization.path("/yoink/4d6DOAMKQ5uhlE6eXKM_dQ", Some("/yoink/:id")),
+            "/yoink/:id"
+        );
+        assert_eq!(normalization.path("/yeet", Some("/yeet")), "/yeet");
+        assert_eq!(normalization.path("/wp-admin", None), UNMATCHED_PATH);
+    }
+
+    #[test]
+    fn requests_in_flight_are_tracked_per_method() {
+        HttpMetrics::inc_in_flight("/in-flight", None, Method::GET);
+        HttpMetrics::inc_in_flight("/in-flight", None, Method::POST);
+        HttpMetrics::inc_in_flight("/in-flight", None, Method::POST);
+        HttpMetrics::dec_in_flight("/in-flight", None, Method::GET);
+
+        let metrics = crate::Metrics::get().encode();
+        assert!(metrics.contains("http_requests_in_flight{method=\"GET\",path=\"/in-flight\"} 0"));
+        assert!(metrics.contains("http_requests_in_flight{method=\"POST\",path=\"/in-flight\"} 2"));
+    }
+
+    #[test]
+    fn status_codes_are_bucketed_into_classes() {
+        assert_eq!(LabelNormalization::default().status(404), "404");
+
+        let normalization = with_templates();
+        assert_eq!(normalization.status(201), "2xx");
+        assert_eq!(normalization.status(404), "4xx");
+        assert_eq!(normalization.status(504), "5xx");
+        assert_eq!(normalization.status(0), "0");
+    }
 }
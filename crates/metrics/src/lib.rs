@@ -4,6 +4,8 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod backbone;
+pub mod backend;
 pub mod http;
 pub mod transfer;
 
@@ -50,6 +52,8 @@ impl Metrics {
         let mut metrics = <Registry>::default();
         http::register_http_requests(&mut metrics);
         transfer::register_transfer_metrics(&mut metrics);
+        backend::register_backend_metrics(&mut metrics);
+        backbone::register_backbone_metrics(&mut metrics);
 
         Self { metrics }
     }
@@ -5,12 +5,15 @@ use prometheus_client::encoding::LabelValueEncoder;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::{Registry, Unit};
 use std::fmt::{Display, Formatter, Write};
 
 lazy_static! {
     static ref TRANSFER_SIZES: Family<Labels, Counter> = Family::default();
     static ref TRANSFER_COUNT: Family<Labels, Counter> = Family::default();
+    static ref UPLOADS_IN_FLIGHT: Family<UploadLabels, Gauge> = Family::default();
+    static ref UPLOADS_BY_CONTENT_TYPE: Family<ContentTypeLabels, Counter> = Family::default();
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -40,6 +43,40 @@ impl Display for TransferMethod {
     }
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct UploadLabels {
+    state: UploadState,
+}
+
+/// The state of an upload with respect to the concurrency limit.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum UploadState {
+    /// The upload waits for a free slot.
+    Queued,
+    /// The upload is being written.
+    Active,
+}
+
+impl EncodeLabelValue for UploadState {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for UploadState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadState::Queued => write!(f, "queued"),
+            UploadState::Active => write!(f, "active"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ContentTypeLabels {
+    content_type: String,
+}
+
 /// Register the `http_requests` metric family with the registry.
 pub(crate) fn register_transfer_metrics(registry: &mut Registry) {
     registry.register_with_unit(
@@ -54,6 +91,18 @@ pub(crate) fn register_transfer_metrics(registry: &mut Registry) {
         "Number of transfers initiated",
         TRANSFER_COUNT.clone(),
     );
+
+    registry.register(
+        "uploads_in_flight",
+        "Number of uploads being written or waiting for a free slot, by state",
+        UPLOADS_IN_FLIGHT.clone(),
+    );
+
+    registry.register(
+        "uploads_by_content_type",
+        "Number of files stored, by normalized content type",
+        UPLOADS_BY_CONTENT_TYPE.clone(),
+    );
 }
 
 /// HTTP call metrics. Can be cheaply cloned.
@@ -79,4 +128,53 @@ impl TransferMetrics {
             })
             .inc_by(bytes as _);
     }
+
+    /// Tracks an upload entering the specified state.
+    pub fn inc_uploads(state: UploadState) {
+        UPLOADS_IN_FLIGHT
+            .get_or_create(&UploadLabels { state })
+            .inc();
+    }
+
+    /// Tracks an upload leaving the specified state.
+    pub fn dec_uploads(state: UploadState) {
+        UPLOADS_IN_FLIGHT
+            .get_or_create(&UploadLabels { state })
+            .dec();
+    }
+
+    /// Tracks a stored file of the specified content type.
+    ///
+    /// The label must be normalized by the caller, e.g. by mapping unknown content types
+    /// to `other`, to keep the number of time series bounded.
+    pub fn track_content_type<T: Into<String>>(content_type: T) {
+        UPLOADS_BY_CONTENT_TYPE
+            .get_or_create(&ContentTypeLabels {
+                content_type: content_type.into(),
+            })
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Metrics;
+
+    #[test]
+    fn uploads_in_flight_are_tracked_per_state() {
+        TransferMetrics::inc_uploads(UploadState::Queued);
+        TransferMetrics::dec_uploads(UploadState::Queued);
+
+        let metrics = Metrics::get().encode();
+        assert!(metrics.contains("uploads_in_flight{state=\"queued\"} 0"));
+    }
+
+    #[test]
+    fn uploads_are_tracked_per_content_type() {
+        TransferMetrics::track_content_type("image/png");
+
+        let metrics = Metrics::get().encode();
+        assert!(metrics.contains("uploads_by_content_type_total{content_type=\"image/png\"} 1"));
+    }
 }